@@ -0,0 +1,106 @@
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use dicom::core::{DataElement, Tag, VR};
+use dicom::object::InMemDicomObject;
+use uuid::Uuid;
+
+/// A string attribute with padding removed. Empty values count as absent.
+pub fn text(object: &InMemDicomObject, tag: Tag) -> Option<String> {
+    object
+        .get(tag)
+        .and_then(|element| element.to_str().ok())
+        .map(|value| value.trim_end_matches('\0').trim().to_owned())
+        .filter(|value| !value.is_empty())
+}
+
+/// A person name with its `^` component separators shown as spaces.
+pub fn person_name(object: &InMemDicomObject, tag: Tag) -> Option<String> {
+    text(object, tag).map(|name| {
+        name.split('^')
+            .filter(|component| !component.trim().is_empty())
+            .map(|component| component.trim())
+            .collect::<Vec<_>>()
+            .join(" ")
+    })
+}
+
+pub fn strings(object: &InMemDicomObject, tag: Tag) -> Vec<String> {
+    object
+        .get(tag)
+        .and_then(|element| element.strings().ok())
+        .map(|values| {
+            values
+                .iter()
+                .map(|value| value.trim_end_matches('\0').trim().to_owned())
+                .filter(|value| !value.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub fn date(object: &InMemDicomObject, tag: Tag) -> Option<NaiveDate> {
+    object
+        .get(tag)?
+        .to_date()
+        .ok()?
+        .to_naive_date()
+        .ok()
+}
+
+pub fn time(object: &InMemDicomObject, tag: Tag) -> Option<NaiveTime> {
+    object
+        .get(tag)?
+        .to_time()
+        .ok()?
+        .to_naive_time()
+        .ok()
+}
+
+/// Combines a DA and a TM attribute, e.g. StudyDate and StudyTime.
+pub fn date_time(object: &InMemDicomObject, date_tag: Tag, time_tag: Tag) -> Option<NaiveDateTime> {
+    Some(date(object, date_tag)?.and_time(time(object, time_tag)?))
+}
+
+/// The attribute from `object`, or an empty one of the same VR when it is
+/// absent, for copying into a new dataset where the attribute is required.
+pub fn copy_or_empty(object: &InMemDicomObject, tag: Tag, vr: VR) -> DataElement<InMemDicomObject> {
+    match object.get(tag) {
+        Some(element) => element.to_owned(),
+        None => DataElement::empty(tag, vr),
+    }
+}
+
+/// A new UID under the `2.25` root, derived from a random UUID.
+pub fn new_uid() -> String {
+    format!("2.25.{}", Uuid::new_v4().as_u128())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom::dictionary_std::tags;
+
+    #[test]
+    fn person_name_replaces_component_separators() {
+        let object = InMemDicomObject::from_element_iter([DataElement::new(
+            tags::PATIENT_NAME,
+            VR::PN,
+            "KHAN^AYESHA^^",
+        )]);
+        assert_eq!(person_name(&object, tags::PATIENT_NAME).as_deref(), Some("KHAN AYESHA"));
+    }
+
+    #[test]
+    fn empty_values_are_absent() {
+        let object = InMemDicomObject::from_element_iter([DataElement::empty(tags::ACCESSION_NUMBER, VR::SH)]);
+        assert_eq!(text(&object, tags::ACCESSION_NUMBER), None);
+        assert_eq!(text(&object, tags::PATIENT_ID), None);
+    }
+
+    #[test]
+    fn new_uids_are_valid() {
+        let uid = new_uid();
+        assert!(uid.len() <= 64);
+        assert!(uid.chars().all(|c| c.is_ascii_digit() || c == '.'));
+        assert_ne!(uid, new_uid());
+    }
+}
@@ -0,0 +1,48 @@
+use dicom::{
+    core::{smallvec::smallvec, value::DataSetSequence, DataElement, DicomValue, Length, Tag, VR},
+    dictionary_std::tags,
+    object::InMemDicomObject,
+};
+
+use crate::attributes::text;
+
+/// A coded concept as used in SR document titles and content items.
+pub struct Code {
+    pub value: &'static str,
+    pub scheme: &'static str,
+    pub meaning: &'static str,
+}
+
+/// Key Object Selection document title for key images (CID 7010).
+pub const OF_INTEREST: Code = Code {
+    value: "113000",
+    scheme: "DCM",
+    meaning: "Of Interest",
+};
+
+/// Whether the document title of an SR, its ConceptNameCodeSequence, is `code`.
+pub fn has_title(sr: &InMemDicomObject, code: &Code) -> bool {
+    sr.get(tags::CONCEPT_NAME_CODE_SEQUENCE)
+        .and_then(|title| title.items())
+        .and_then(|title| title.first())
+        .map(|title| {
+            text(title, tags::CODE_VALUE).as_deref() == Some(code.value)
+                && text(title, tags::CODING_SCHEME_DESIGNATOR).as_deref() == Some(code.scheme)
+        })
+        .unwrap_or(false)
+}
+
+pub fn code_sequence(tag: Tag, code: &Code) -> DataElement<InMemDicomObject> {
+    DataElement::new(
+        tag,
+        VR::SQ,
+        DicomValue::Sequence(DataSetSequence::new(
+            smallvec![InMemDicomObject::from_element_iter([
+                DataElement::new(tags::CODE_VALUE, VR::SH, code.value),
+                DataElement::new(tags::CODING_SCHEME_DESIGNATOR, VR::SH, code.scheme),
+                DataElement::new(tags::CODE_MEANING, VR::LO, code.meaning),
+            ])],
+            Length::UNDEFINED,
+        )),
+    )
+}
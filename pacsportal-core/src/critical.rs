@@ -0,0 +1,120 @@
+use chrono::NaiveDateTime;
+use dicom::{
+    core::{DataElement, VR},
+    dictionary_std::{tags, uids},
+    object::InMemDicomObject,
+};
+
+use crate::attributes::{new_uid, text};
+use crate::codes::{self, Code};
+
+/// Local coding scheme for the codes below. DICOM reserves designators
+/// starting with "99" for private use.
+pub const CODING_SCHEME: &str = "99SCH";
+
+/// Document title of a report flagged as containing a critical finding.
+pub const CRITICAL_REPORT: Code = Code {
+    value: "CRIT001",
+    scheme: CODING_SCHEME,
+    meaning: "Critical result report",
+};
+
+/// Document title of the SR recording that the referring physician was
+/// informed of a critical result. Stored in the same study as the report.
+pub const CRITICAL_ACKNOWLEDGEMENT: Code = Code {
+    value: "CRIT002",
+    scheme: CODING_SCHEME,
+    meaning: "Critical result acknowledgement",
+};
+
+/// QIDO parameters matching SR instances with the given document title.
+pub fn title_query(code: &Code) -> String {
+    format!(
+        "ConceptNameCodeSequence.CodeValue={}&ConceptNameCodeSequence.CodingSchemeDesignator={}",
+        code.value, code.scheme
+    )
+}
+
+/// Builds the SR recording that `username` acknowledged the critical
+/// `report` at `now`. It belongs to the same patient and study as the report.
+pub fn acknowledgement(report: &InMemDicomObject, username: &str, now: NaiveDateTime) -> InMemDicomObject {
+    let mut ack = InMemDicomObject::from_element_iter([
+        DataElement::new(tags::SOP_CLASS_UID, VR::UI, uids::BASIC_TEXT_SR_STORAGE),
+        DataElement::new(tags::SOP_INSTANCE_UID, VR::UI, new_uid()),
+        DataElement::new(tags::SERIES_INSTANCE_UID, VR::UI, new_uid()),
+        DataElement::new(tags::MODALITY, VR::CS, "SR"),
+        DataElement::new(tags::SERIES_NUMBER, VR::IS, "1"),
+        DataElement::new(tags::INSTANCE_NUMBER, VR::IS, "1"),
+        DataElement::new(tags::CONTENT_DATE, VR::DA, now.format("%Y%m%d").to_string()),
+        DataElement::new(tags::CONTENT_TIME, VR::TM, now.format("%H%M%S").to_string()),
+        DataElement::new(tags::CONTENT_CREATOR_NAME, VR::PN, username.to_owned()),
+        DataElement::new(tags::COMPLETION_FLAG, VR::CS, "COMPLETE"),
+        DataElement::new(tags::VERIFICATION_FLAG, VR::CS, "UNVERIFIED"),
+        DataElement::new(tags::VALUE_TYPE, VR::CS, "TEXT"),
+        DataElement::new(
+            tags::TEXT_VALUE,
+            VR::UT,
+            format!(
+                "Critical result report {} acknowledged by {} at {}.",
+                text(report, tags::SOP_INSTANCE_UID).unwrap_or_default(),
+                username,
+                now.format("%Y-%m-%d %H:%M:%S")
+            ),
+        ),
+        codes::code_sequence(tags::CONCEPT_NAME_CODE_SEQUENCE, &CRITICAL_ACKNOWLEDGEMENT),
+    ]);
+    for tag in [
+        tags::PATIENT_NAME,
+        tags::PATIENT_ID,
+        tags::PATIENT_BIRTH_DATE,
+        tags::PATIENT_SEX,
+        tags::STUDY_INSTANCE_UID,
+        tags::STUDY_DATE,
+        tags::STUDY_TIME,
+        tags::STUDY_ID,
+        tags::ACCESSION_NUMBER,
+        tags::REFERRING_PHYSICIAN_NAME,
+    ] {
+        if let Some(element) = report.get(tag) {
+            ack.put(element.clone());
+        }
+    }
+    ack
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn acknowledgement_is_filed_with_the_report() {
+        let report = InMemDicomObject::from_element_iter([
+            DataElement::new(tags::SOP_INSTANCE_UID, VR::UI, "1.2.3.9"),
+            DataElement::new(tags::STUDY_INSTANCE_UID, VR::UI, "1.2.3"),
+            DataElement::new(tags::PATIENT_ID, VR::LO, "SCH-001"),
+        ]);
+        let now = NaiveDate::from_ymd_opt(2023, 7, 25).unwrap().and_hms_opt(11, 5, 0).unwrap();
+        let ack = acknowledgement(&report, "root", now);
+
+        assert_eq!(text(&ack, tags::STUDY_INSTANCE_UID).as_deref(), Some("1.2.3"));
+        assert_eq!(text(&ack, tags::PATIENT_ID).as_deref(), Some("SCH-001"));
+        assert_eq!(text(&ack, tags::CONTENT_CREATOR_NAME).as_deref(), Some("root"));
+        assert_eq!(text(&ack, tags::CONTENT_TIME).as_deref(), Some("110500"));
+        assert_ne!(text(&ack, tags::SOP_INSTANCE_UID).as_deref(), Some("1.2.3.9"));
+        let title = ack
+            .get(tags::CONCEPT_NAME_CODE_SEQUENCE)
+            .and_then(|title| title.items())
+            .and_then(|title| title.first())
+            .unwrap();
+        assert_eq!(text(title, tags::CODE_VALUE).as_deref(), Some(CRITICAL_ACKNOWLEDGEMENT.value));
+    }
+
+    #[test]
+    fn title_query_matches_code_and_scheme() {
+        assert_eq!(
+            title_query(&CRITICAL_REPORT),
+            "ConceptNameCodeSequence.CodeValue=CRIT001&ConceptNameCodeSequence.CodingSchemeDesignator=99SCH"
+        );
+    }
+}
@@ -0,0 +1,36 @@
+//! CSV for the exports, as read by spreadsheets (RFC 4180).
+
+/// Quotes a field when it holds a separator, a quote or a line break.
+/// Fields starting with a formula character are prefixed with `'` so that
+/// spreadsheets show them instead of evaluating them.
+pub fn field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@']) && value.parse::<f64>().is_err() {
+        format!("'{}", value)
+    } else {
+        value.to_owned()
+    };
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+/// One line of fields, ended with CRLF.
+pub fn row<S: AsRef<str>>(fields: impl IntoIterator<Item = S>) -> String {
+    let mut line = fields.into_iter().map(|value| field(value.as_ref())).collect::<Vec<_>>().join(",");
+    line.push_str("\r\n");
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_only_where_needed() {
+        assert_eq!(row(["CT", "KHAN, AYESHA", "said \"no\""]), "CT,\"KHAN, AYESHA\",\"said \"\"no\"\"\"\r\n");
+        assert_eq!(field("=HYPERLINK(\"x\")"), "\"'=HYPERLINK(\"\"x\"\")\"");
+        assert_eq!(field("-12.5"), "-12.5");
+    }
+}
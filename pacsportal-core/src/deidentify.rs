@@ -0,0 +1,644 @@
+//! De-identification of instances for teaching and research, following the
+//! Basic Application Level Confidentiality Profile of DICOM PS3.15 Annex E
+//! and those of its options a radiologist may pick when exporting.
+//!
+//! UIDs are remapped deterministically from a secret, so that references
+//! between instances survive, and a study exported twice with the same
+//! secret, or two studies of one patient, stay linked to each other.
+
+use dicom::core::smallvec::SmallVec;
+use dicom::core::value::{DataSetSequence, PrimitiveValue, Value};
+use dicom::core::{DataElement, DicomValue, Length, Tag, VR};
+use dicom::dictionary_std::tags;
+use dicom::object::meta::FileMetaTableBuilder;
+use dicom::object::{InMemDicomObject, OpenFileOptions};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::attributes::text;
+use crate::codes::Code;
+
+/// DeidentificationMethod of every exported instance.
+pub const METHOD: &str = "PS3.15 Basic Application Level Confidentiality Profile";
+
+/// The options of the profile offered in the portal. Everything not
+/// retained is removed, emptied or replaced as the profile requires.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Options {
+    /// Retain Longitudinal Temporal Information with Full Dates.
+    pub retain_dates: bool,
+    /// Retain UIDs, instead of remapping them.
+    pub retain_uids: bool,
+    /// Retain Patient Characteristics: age, sex, size, weight and the like.
+    pub retain_patient_characteristics: bool,
+    /// Retain Device Identity: station names and serial numbers.
+    pub retain_device_identity: bool,
+    /// Retain Institution Identity.
+    pub retain_institution_identity: bool,
+}
+
+impl Options {
+    /// The DeidentificationMethodCodeSequence of the profile and its options
+    /// in use (CID 7050).
+    pub fn codes(&self) -> Vec<Code> {
+        let mut codes = vec![code("113100", "Basic Application Confidentiality Profile")];
+        if self.retain_dates {
+            codes.push(code("113106", "Retain Longitudinal Temporal Information Full Dates Option"));
+        }
+        if self.retain_patient_characteristics {
+            codes.push(code("113108", "Retain Patient Characteristics Option"));
+        }
+        if self.retain_device_identity {
+            codes.push(code("113109", "Retain Device Identity Option"));
+        }
+        if self.retain_uids {
+            codes.push(code("113110", "Retain UIDs Option"));
+        }
+        if self.retain_institution_identity {
+            codes.push(code("113112", "Retain Institution Identity Option"));
+        }
+        codes
+    }
+}
+
+/// The query of a de-identified download, naming the options chosen and
+/// the pseudonym, if any, e.g. `retain_dates=true&pseudonym=CASE%2012`.
+pub fn query(options: &Options, pseudonym: &str) -> String {
+    let mut params: Vec<String> = [
+        ("retain_dates", options.retain_dates),
+        ("retain_uids", options.retain_uids),
+        ("retain_patient_characteristics", options.retain_patient_characteristics),
+        ("retain_device_identity", options.retain_device_identity),
+        ("retain_institution_identity", options.retain_institution_identity),
+    ]
+    .into_iter()
+    .filter(|(_, chosen)| *chosen)
+    .map(|(name, _)| format!("{}=true", name))
+    .collect();
+    if !pseudonym.trim().is_empty() {
+        let encoded: String = pseudonym
+            .trim()
+            .bytes()
+            .map(|byte| match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => char::from(byte).to_string(),
+                _ => format!("%{:02X}", byte),
+            })
+            .collect();
+        params.push(format!("pseudonym={}", encoded));
+    }
+    params.join("&")
+}
+
+fn code(value: &'static str, meaning: &'static str) -> Code {
+    Code { value, scheme: "DCM", meaning }
+}
+
+/// What the profile does to an attribute, in the letters of PS3.15 Table E.1-1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    /// D: replace with a dummy value.
+    Dummy,
+    /// Z: replace with a zero length value.
+    Empty,
+    /// X: remove.
+    Remove,
+    /// U: replace with a consistently remapped UID.
+    Uid,
+}
+
+/// Which option, when chosen, keeps an attribute as it is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Retained {
+    Never,
+    Dates,
+    Uids,
+    Patient,
+    Device,
+    Institution,
+}
+
+/// The attributes of Table E.1-1 found in the studies of the archive. Of
+/// those not listed, only the ones [`Deidentifier::kept_unlisted`] allows
+/// are kept, and private attributes, curves and overlay comments are always
+/// removed.
+const ATTRIBUTES: [(Tag, Action, Retained); 136] = [
+    // patient
+    (tags::PATIENT_NAME, Action::Dummy, Retained::Never),
+    (tags::PATIENT_ID, Action::Dummy, Retained::Never),
+    (tags::ISSUER_OF_PATIENT_ID, Action::Remove, Retained::Never),
+    (tags::OTHER_PATIENT_I_DS_SEQUENCE, Action::Remove, Retained::Never),
+    (Tag(0x0010, 0x1000), Action::Remove, Retained::Never), // OtherPatientIDs, retired
+    (tags::OTHER_PATIENT_NAMES, Action::Remove, Retained::Never),
+    (tags::PATIENT_BIRTH_DATE, Action::Empty, Retained::Never),
+    (tags::PATIENT_BIRTH_TIME, Action::Remove, Retained::Never),
+    (tags::PATIENT_BIRTH_NAME, Action::Remove, Retained::Never),
+    (tags::PATIENT_MOTHER_BIRTH_NAME, Action::Remove, Retained::Never),
+    (tags::PATIENT_ADDRESS, Action::Remove, Retained::Never),
+    (tags::PATIENT_TELEPHONE_NUMBERS, Action::Remove, Retained::Never),
+    (Tag(0x0010, 0x1090), Action::Remove, Retained::Never), // MedicalRecordLocator, retired
+    (tags::PATIENT_COMMENTS, Action::Remove, Retained::Never),
+    (tags::ADDITIONAL_PATIENT_HISTORY, Action::Remove, Retained::Never),
+    (tags::PATIENT_INSURANCE_PLAN_CODE_SEQUENCE, Action::Remove, Retained::Never),
+    (tags::PATIENT_RELIGIOUS_PREFERENCE, Action::Remove, Retained::Never),
+    (tags::MILITARY_RANK, Action::Remove, Retained::Never),
+    (tags::BRANCH_OF_SERVICE, Action::Remove, Retained::Never),
+    (tags::COUNTRY_OF_RESIDENCE, Action::Remove, Retained::Never),
+    (tags::REGION_OF_RESIDENCE, Action::Remove, Retained::Never),
+    (tags::OCCUPATION, Action::Remove, Retained::Never),
+    (tags::RESPONSIBLE_PERSON, Action::Remove, Retained::Never),
+    (tags::MEDICAL_ALERTS, Action::Remove, Retained::Never),
+    (tags::ALLERGIES, Action::Remove, Retained::Never),
+    (tags::REFERENCED_PATIENT_SEQUENCE, Action::Remove, Retained::Never),
+    (tags::CURRENT_PATIENT_LOCATION, Action::Remove, Retained::Never),
+    (tags::PATIENT_INSTITUTION_RESIDENCE, Action::Remove, Retained::Never),
+    (tags::PATIENT_STATE, Action::Remove, Retained::Never),
+    (tags::PATIENT_TRANSPORT_ARRANGEMENTS, Action::Remove, Retained::Never),
+    (tags::SPECIAL_NEEDS, Action::Remove, Retained::Never),
+    (tags::LAST_MENSTRUAL_DATE, Action::Remove, Retained::Never),
+    (tags::PATIENT_AGE, Action::Remove, Retained::Patient),
+    (tags::PATIENT_SEX, Action::Empty, Retained::Patient),
+    (tags::PATIENT_SIZE, Action::Remove, Retained::Patient),
+    (tags::PATIENT_WEIGHT, Action::Remove, Retained::Patient),
+    (tags::ETHNIC_GROUP, Action::Remove, Retained::Patient),
+    (tags::SMOKING_STATUS, Action::Remove, Retained::Patient),
+    (tags::PREGNANCY_STATUS, Action::Remove, Retained::Patient),
+    // visit, order and study identification
+    (tags::ACCESSION_NUMBER, Action::Empty, Retained::Never),
+    (tags::STUDY_ID, Action::Empty, Retained::Never),
+    (tags::ADMISSION_ID, Action::Remove, Retained::Never),
+    (Tag(0x0038, 0x0011), Action::Remove, Retained::Never), // IssuerOfAdmissionID, retired
+    (tags::SERVICE_EPISODE_ID, Action::Remove, Retained::Never),
+    (tags::REQUESTED_PROCEDURE_ID, Action::Remove, Retained::Never),
+    (tags::SCHEDULED_PROCEDURE_STEP_ID, Action::Remove, Retained::Never),
+    (tags::PERFORMED_PROCEDURE_STEP_ID, Action::Remove, Retained::Never),
+    (tags::PLACER_ORDER_NUMBER_IMAGING_SERVICE_REQUEST, Action::Remove, Retained::Never),
+    (tags::FILLER_ORDER_NUMBER_IMAGING_SERVICE_REQUEST, Action::Remove, Retained::Never),
+    (tags::REFERENCED_STUDY_SEQUENCE, Action::Remove, Retained::Never),
+    (tags::REFERENCED_PERFORMED_PROCEDURE_STEP_SEQUENCE, Action::Remove, Retained::Never),
+    (tags::REQUEST_ATTRIBUTES_SEQUENCE, Action::Remove, Retained::Never),
+    // people
+    (tags::REFERRING_PHYSICIAN_NAME, Action::Empty, Retained::Never),
+    (tags::REFERRING_PHYSICIAN_ADDRESS, Action::Remove, Retained::Never),
+    (tags::REFERRING_PHYSICIAN_TELEPHONE_NUMBERS, Action::Remove, Retained::Never),
+    (tags::REFERRING_PHYSICIAN_IDENTIFICATION_SEQUENCE, Action::Remove, Retained::Never),
+    (tags::CONSULTING_PHYSICIAN_NAME, Action::Remove, Retained::Never),
+    (tags::PHYSICIANS_OF_RECORD, Action::Remove, Retained::Never),
+    (tags::PERFORMING_PHYSICIAN_NAME, Action::Remove, Retained::Never),
+    (tags::NAME_OF_PHYSICIANS_READING_STUDY, Action::Remove, Retained::Never),
+    (tags::OPERATORS_NAME, Action::Remove, Retained::Never),
+    (tags::REQUESTING_PHYSICIAN, Action::Remove, Retained::Never),
+    (tags::REQUESTING_SERVICE, Action::Remove, Retained::Never),
+    (tags::SCHEDULED_PERFORMING_PHYSICIAN_NAME, Action::Remove, Retained::Never),
+    (tags::PERSON_NAME, Action::Dummy, Retained::Never),
+    (tags::PERSON_ADDRESS, Action::Remove, Retained::Never),
+    (tags::PERSON_TELEPHONE_NUMBERS, Action::Remove, Retained::Never),
+    (tags::PERSON_IDENTIFICATION_CODE_SEQUENCE, Action::Remove, Retained::Never),
+    (tags::VERIFYING_OBSERVER_NAME, Action::Dummy, Retained::Never),
+    (tags::VERIFYING_ORGANIZATION, Action::Remove, Retained::Never),
+    (tags::CONTENT_CREATOR_NAME, Action::Empty, Retained::Never),
+    (tags::AUTHOR_OBSERVER_SEQUENCE, Action::Remove, Retained::Never),
+    (tags::PARTICIPANT_SEQUENCE, Action::Remove, Retained::Never),
+    // free text, which may name anyone
+    (tags::STUDY_DESCRIPTION, Action::Remove, Retained::Never),
+    (tags::SERIES_DESCRIPTION, Action::Remove, Retained::Never),
+    (tags::IMAGE_COMMENTS, Action::Remove, Retained::Never),
+    (tags::FRAME_COMMENTS, Action::Remove, Retained::Never),
+    (tags::DERIVATION_DESCRIPTION, Action::Remove, Retained::Never),
+    (tags::PROTOCOL_NAME, Action::Remove, Retained::Never),
+    (tags::REQUESTED_PROCEDURE_DESCRIPTION, Action::Remove, Retained::Never),
+    (tags::PERFORMED_PROCEDURE_STEP_DESCRIPTION, Action::Remove, Retained::Never),
+    (tags::REASON_FOR_THE_REQUESTED_PROCEDURE, Action::Remove, Retained::Never),
+    (tags::ADMITTING_DIAGNOSES_DESCRIPTION, Action::Remove, Retained::Never),
+    (Tag(0x0038, 0x0040), Action::Remove, Retained::Never), // DischargeDiagnosisDescription, retired
+    (Tag(0x0008, 0x4000), Action::Remove, Retained::Never), // IdentifyingComments, retired
+    (Tag(0x0032, 0x4000), Action::Remove, Retained::Never), // StudyComments, retired
+    (tags::VISIT_COMMENTS, Action::Remove, Retained::Never),
+    (Tag(0x4008, 0x010B), Action::Remove, Retained::Never), // InterpretationText, retired
+    (Tag(0x4008, 0x0300), Action::Remove, Retained::Never), // Impressions, retired
+    (Tag(0x4008, 0x4000), Action::Remove, Retained::Never), // ResultsComments, retired
+    (Tag(0x0018, 0x4000), Action::Remove, Retained::Never), // AcquisitionComments, retired
+    (tags::TEXT_VALUE, Action::Remove, Retained::Never),
+    (tags::CONTENT_SEQUENCE, Action::Remove, Retained::Never),
+    (tags::ORIGINAL_ATTRIBUTES_SEQUENCE, Action::Remove, Retained::Never),
+    (tags::MODIFIED_ATTRIBUTES_SEQUENCE, Action::Remove, Retained::Never),
+    // dates and times
+    (tags::STUDY_DATE, Action::Empty, Retained::Dates),
+    (tags::STUDY_TIME, Action::Empty, Retained::Dates),
+    (tags::SERIES_DATE, Action::Remove, Retained::Dates),
+    (tags::SERIES_TIME, Action::Remove, Retained::Dates),
+    (tags::ACQUISITION_DATE, Action::Remove, Retained::Dates),
+    (tags::ACQUISITION_TIME, Action::Remove, Retained::Dates),
+    (tags::ACQUISITION_DATE_TIME, Action::Remove, Retained::Dates),
+    (tags::CONTENT_DATE, Action::Empty, Retained::Dates),
+    (tags::CONTENT_TIME, Action::Empty, Retained::Dates),
+    (tags::INSTANCE_CREATION_DATE, Action::Remove, Retained::Dates),
+    (tags::INSTANCE_CREATION_TIME, Action::Remove, Retained::Dates),
+    (tags::PERFORMED_PROCEDURE_STEP_START_DATE, Action::Remove, Retained::Dates),
+    (tags::PERFORMED_PROCEDURE_STEP_START_TIME, Action::Remove, Retained::Dates),
+    (tags::VERIFICATION_DATE_TIME, Action::Dummy, Retained::Dates),
+    (tags::ADMITTING_DATE, Action::Remove, Retained::Dates),
+    (tags::ADMITTING_TIME, Action::Remove, Retained::Dates),
+    // device and institution
+    (tags::STATION_NAME, Action::Remove, Retained::Device),
+    (tags::DEVICE_SERIAL_NUMBER, Action::Remove, Retained::Device),
+    (tags::PLATE_ID, Action::Remove, Retained::Device),
+    (tags::DETECTOR_ID, Action::Remove, Retained::Device),
+    (tags::GANTRY_ID, Action::Remove, Retained::Device),
+    (tags::SOFTWARE_VERSIONS, Action::Remove, Retained::Device),
+    (tags::PERFORMED_STATION_NAME, Action::Remove, Retained::Device),
+    (tags::SCHEDULED_STATION_NAME, Action::Remove, Retained::Device),
+    (tags::DEVICE_UID, Action::Uid, Retained::Device),
+    (tags::INSTITUTION_NAME, Action::Remove, Retained::Institution),
+    (tags::INSTITUTION_ADDRESS, Action::Remove, Retained::Institution),
+    (tags::INSTITUTIONAL_DEPARTMENT_NAME, Action::Remove, Retained::Institution),
+    (tags::INSTITUTION_CODE_SEQUENCE, Action::Remove, Retained::Institution),
+    // UIDs
+    (tags::STUDY_INSTANCE_UID, Action::Uid, Retained::Uids),
+    (tags::SERIES_INSTANCE_UID, Action::Uid, Retained::Uids),
+    (tags::SOP_INSTANCE_UID, Action::Uid, Retained::Uids),
+    (tags::REFERENCED_SOP_INSTANCE_UID, Action::Uid, Retained::Uids),
+    (tags::FRAME_OF_REFERENCE_UID, Action::Uid, Retained::Uids),
+    (tags::REFERENCED_FRAME_OF_REFERENCE_UID, Action::Uid, Retained::Uids),
+    (tags::SYNCHRONIZATION_FRAME_OF_REFERENCE_UID, Action::Uid, Retained::Uids),
+    (tags::INSTANCE_CREATOR_UID, Action::Uid, Retained::Uids),
+    (tags::IRRADIATION_EVENT_UID, Action::Uid, Retained::Uids),
+    (tags::CONCATENATION_UID, Action::Uid, Retained::Uids),
+    (tags::DIMENSION_ORGANIZATION_UID, Action::Uid, Retained::Uids),
+    (tags::UID, Action::Uid, Retained::Uids),
+];
+
+/// Text attributes outside Table E.1-1 that cannot name anyone: the parts
+/// of codes and the make of the device.
+const KEPT_TEXT: [Tag; 7] = [
+    tags::CODE_VALUE,
+    tags::CODING_SCHEME_DESIGNATOR,
+    tags::CODING_SCHEME_VERSION,
+    tags::CODE_MEANING,
+    tags::MANUFACTURER,
+    tags::MANUFACTURER_MODEL_NAME,
+    tags::WINDOW_CENTER_WIDTH_EXPLANATION,
+];
+
+/// Maps UIDs, and patient IDs to pseudonyms, the same way for everyone
+/// holding the same secret.
+#[derive(Debug, Clone)]
+pub struct UidMap {
+    namespace: Uuid,
+}
+
+impl UidMap {
+    pub fn new(secret: &str) -> UidMap {
+        UidMap {
+            namespace: Uuid::new_v5(&Uuid::NAMESPACE_OID, secret.as_bytes()),
+        }
+    }
+
+    /// A map of its own, consistent only within one export.
+    pub fn random() -> UidMap {
+        UidMap::new(&Uuid::new_v4().to_string())
+    }
+
+    /// The UID replacing `uid`, under the `2.25` root like [`crate::attributes::new_uid`].
+    pub fn uid(&self, uid: &str) -> String {
+        let uid = uid.trim_end_matches('\0').trim();
+        format!("2.25.{}", Uuid::new_v5(&self.namespace, uid.as_bytes()).as_u128())
+    }
+
+    /// A pseudonym for a patient ID, e.g. `ANON-3F2A19C4`.
+    pub fn pseudonym(&self, patient_id: &str) -> String {
+        let patient_id = format!("patient:{}", patient_id.trim_end_matches('\0').trim());
+        let hash = Uuid::new_v5(&self.namespace, patient_id.as_bytes()).simple().to_string();
+        format!("ANON-{}", hash[..8].to_uppercase())
+    }
+}
+
+/// Whether `pseudonym` may stand in as a PatientName and PatientID: at most
+/// 64 characters, none of them control characters or value separators.
+pub fn is_pseudonym(pseudonym: &str) -> bool {
+    !pseudonym.trim().is_empty() && pseudonym.chars().count() <= 64 && !pseudonym.chars().any(|c| c.is_control() || c == '\\')
+}
+
+/// Why an instance is not exported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Withheld {
+    /// The pixels may show who the patient is, which the Basic profile does
+    /// not clean: BurnedInAnnotation is YES, or is absent on an ultrasound
+    /// or a secondary capture, which are mostly screenshots.
+    BurnedInAnnotation,
+    /// The instance could not be read or written.
+    Invalid(String),
+}
+
+impl std::fmt::Display for Withheld {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Withheld::BurnedInAnnotation => write!(f, "the pixel data may show burned-in patient details"),
+            Withheld::Invalid(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Applies the profile with `options`. The patient is named `pseudonym`
+/// when one is given, or else by the pseudonym [`UidMap::pseudonym`] makes
+/// of the patient ID.
+#[derive(Debug, Clone)]
+pub struct Deidentifier {
+    pub options: Options,
+    pub uids: UidMap,
+    pub pseudonym: Option<String>,
+}
+
+impl Deidentifier {
+    /// De-identifies a dataset, without its file meta group.
+    pub fn dataset(&self, object: &InMemDicomObject) -> Result<InMemDicomObject, Withheld> {
+        let burned_in = text(object, tags::BURNED_IN_ANNOTATION).map(|value| value.to_uppercase());
+        let modality = text(object, tags::MODALITY).unwrap_or_default();
+        if burned_in.as_deref() == Some("YES") || (burned_in.is_none() && ["US", "SC", "OT"].contains(&modality.as_str())) {
+            return Err(Withheld::BurnedInAnnotation);
+        }
+
+        let pseudonym = match &self.pseudonym {
+            Some(pseudonym) => pseudonym.trim().to_owned(),
+            None => self.uids.pseudonym(&text(object, tags::PATIENT_ID).unwrap_or_default()),
+        };
+        let mut deidentified = self.items(object, &pseudonym);
+        deidentified.put(DataElement::new(tags::PATIENT_NAME, VR::PN, pseudonym.as_str()));
+        deidentified.put(DataElement::new(tags::PATIENT_ID, VR::LO, pseudonym.as_str()));
+        deidentified.put(DataElement::new(tags::PATIENT_IDENTITY_REMOVED, VR::CS, "YES"));
+        deidentified.put(DataElement::new(tags::DEIDENTIFICATION_METHOD, VR::LO, METHOD));
+        deidentified.put(code_sequence(tags::DEIDENTIFICATION_METHOD_CODE_SEQUENCE, &self.options.codes()));
+        deidentified.put(DataElement::new(
+            tags::LONGITUDINAL_TEMPORAL_INFORMATION_MODIFIED,
+            VR::CS,
+            if self.options.retain_dates { "UNMODIFIED" } else { "REMOVED" },
+        ));
+        Ok(deidentified)
+    }
+
+    /// De-identifies a Part 10 file, keeping its transfer syntax.
+    pub fn file(&self, file: &[u8]) -> Result<Vec<u8>, Withheld> {
+        let invalid = |e: &dyn std::fmt::Display| Withheld::Invalid(e.to_string());
+        let original = OpenFileOptions::new().from_reader(file).map_err(|e| invalid(&e))?;
+        let transfer_syntax = original.meta().transfer_syntax().to_owned();
+        let deidentified = self
+            .dataset(&original)?
+            .with_meta(FileMetaTableBuilder::new().transfer_syntax(transfer_syntax))
+            .map_err(|e| invalid(&e))?;
+        let mut bytes = Vec::new();
+        deidentified.write_all(&mut bytes).map_err(|e| invalid(&e))?;
+        Ok(bytes)
+    }
+
+    fn retained(&self, retained: Retained) -> bool {
+        match retained {
+            Retained::Never => false,
+            Retained::Dates => self.options.retain_dates,
+            Retained::Uids => self.options.retain_uids,
+            Retained::Patient => self.options.retain_patient_characteristics,
+            Retained::Device => self.options.retain_device_identity,
+            Retained::Institution => self.options.retain_institution_identity,
+        }
+    }
+
+    /// Whether an attribute outside Table E.1-1 is kept: one whose value can
+    /// only describe the instance, such as codes, numbers, UIDs of classes
+    /// and pixel data, or is in [`KEPT_TEXT`]. Other text, which may name or
+    /// locate the patient, is removed, and so are dates and times unless
+    /// they are retained.
+    fn kept_unlisted(&self, tag: Tag, vr: VR) -> bool {
+        match vr {
+            VR::AT | VR::CS | VR::DS | VR::FD | VR::FL | VR::IS | VR::SL | VR::SS | VR::SV | VR::UL | VR::US | VR::UV | VR::UI => true,
+            VR::OB | VR::OD | VR::OF | VR::OL | VR::OV | VR::OW => true,
+            VR::DA | VR::DT | VR::TM => self.options.retain_dates,
+            _ => KEPT_TEXT.contains(&tag),
+        }
+    }
+
+    /// The elements of `object` the profile keeps, with sequence items
+    /// de-identified the same way.
+    fn items(&self, object: &InMemDicomObject, pseudonym: &str) -> InMemDicomObject {
+        InMemDicomObject::from_element_iter(object.iter().filter_map(|element| {
+            let tag = element.header().tag;
+            let vr = element.header().vr;
+            // private attributes, curves, and overlay data and comments
+            if tag.group() % 2 == 1
+                || (0x5000..=0x50FF).contains(&tag.group())
+                || ((0x6000..=0x60FF).contains(&tag.group()) && [0x3000, 0x4000].contains(&tag.element()))
+            {
+                return None;
+            }
+            let listed = ATTRIBUTES.iter().find(|(listed, _, _)| *listed == tag);
+            let action = listed.filter(|(_, _, retained)| !self.retained(*retained)).map(|(_, action, _)| *action);
+            match (action, element.value()) {
+                (Some(Action::Remove), _) => None,
+                (Some(Action::Empty), _) => Some(DataElement::empty(tag, vr)),
+                (Some(Action::Dummy), _) => Some(DataElement::new(tag, vr, dummy(tag, vr, pseudonym))),
+                (Some(Action::Uid), _) => {
+                    let uids = element.strings().map(|uids| uids.iter().map(|uid| self.uids.uid(uid)).collect()).unwrap_or_default();
+                    Some(DataElement::new(tag, vr, PrimitiveValue::Strs(uids)))
+                }
+                (None, Value::Sequence(sequence)) => {
+                    let items: SmallVec<[InMemDicomObject; 2]> = sequence.items().iter().map(|item| self.items(item, pseudonym)).collect();
+                    Some(DataElement::new(tag, VR::SQ, DicomValue::Sequence(DataSetSequence::new(items, Length::UNDEFINED))))
+                }
+                // listed, and retained by an option
+                (None, _) if listed.is_some() || self.kept_unlisted(tag, vr) => Some(element.clone()),
+                (None, _) => None,
+            }
+        }))
+    }
+}
+
+/// A dummy value of the VR. Only the patient is named by the pseudonym;
+/// observers and other people all become `ANONYMIZED`.
+fn dummy(tag: Tag, vr: VR, pseudonym: &str) -> PrimitiveValue {
+    let value = match vr {
+        VR::DA => "19000101",
+        VR::TM => "000000",
+        VR::DT => "19000101000000",
+        _ if tag == tags::PATIENT_NAME || tag == tags::PATIENT_ID => pseudonym,
+        _ => "ANONYMIZED",
+    };
+    PrimitiveValue::from(value)
+}
+
+/// A sequence of one item for each code.
+fn code_sequence(tag: Tag, codes: &[Code]) -> DataElement<InMemDicomObject> {
+    let items: SmallVec<[InMemDicomObject; 2]> = codes
+        .iter()
+        .map(|code| {
+            InMemDicomObject::from_element_iter([
+                DataElement::new(tags::CODE_VALUE, VR::SH, code.value),
+                DataElement::new(tags::CODING_SCHEME_DESIGNATOR, VR::SH, code.scheme),
+                DataElement::new(tags::CODE_MEANING, VR::LO, code.meaning),
+            ])
+        })
+        .collect();
+    DataElement::new(tag, VR::SQ, DicomValue::Sequence(DataSetSequence::new(items, Length::UNDEFINED)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom::core::smallvec::smallvec;
+    use dicom::dictionary_std::uids;
+    use dicom::object::meta::FileMetaTableBuilder;
+
+    fn ct() -> InMemDicomObject {
+        InMemDicomObject::from_element_iter([
+            DataElement::new(tags::SOP_CLASS_UID, VR::UI, uids::CT_IMAGE_STORAGE),
+            DataElement::new(tags::SOP_INSTANCE_UID, VR::UI, "1.2.3.1.1"),
+            DataElement::new(tags::STUDY_INSTANCE_UID, VR::UI, "1.2.3"),
+            DataElement::new(tags::SERIES_INSTANCE_UID, VR::UI, "1.2.3.1"),
+            DataElement::new(tags::PATIENT_NAME, VR::PN, "KHAN^AYESHA"),
+            DataElement::new(tags::PATIENT_ID, VR::LO, "SCH-001"),
+            DataElement::new(tags::PATIENT_BIRTH_DATE, VR::DA, "19800101"),
+            DataElement::new(tags::PATIENT_SEX, VR::CS, "F"),
+            DataElement::new(tags::ACCESSION_NUMBER, VR::SH, "ACC-1"),
+            DataElement::new(tags::STUDY_DATE, VR::DA, "20230724"),
+            DataElement::new(tags::MODALITY, VR::CS, "CT"),
+            DataElement::new(tags::INSTITUTION_NAME, VR::LO, "Shaukat Khanum"),
+            DataElement::new(tags::SLICE_THICKNESS, VR::DS, "5"),
+            DataElement::new(Tag(0x0019, 0x1010), VR::LO, "private"),
+            DataElement::new(
+                tags::REFERENCED_IMAGE_SEQUENCE,
+                VR::SQ,
+                DicomValue::Sequence(DataSetSequence::new(
+                    smallvec![InMemDicomObject::from_element_iter([
+                        DataElement::new(tags::REFERENCED_SOP_CLASS_UID, VR::UI, uids::CT_IMAGE_STORAGE),
+                        DataElement::new(tags::REFERENCED_SOP_INSTANCE_UID, VR::UI, "1.2.3.1.2"),
+                    ])],
+                    Length::UNDEFINED,
+                )),
+            ),
+        ])
+    }
+
+    fn deidentifier(options: Options) -> Deidentifier {
+        Deidentifier {
+            options,
+            uids: UidMap::new("secret"),
+            pseudonym: None,
+        }
+    }
+
+    #[test]
+    fn applies_the_basic_profile() {
+        let object = deidentifier(Options::default()).dataset(&ct()).unwrap();
+        let pseudonym = UidMap::new("secret").pseudonym("SCH-001");
+        assert!(pseudonym.starts_with("ANON-") && pseudonym.len() == 13);
+        assert_eq!(text(&object, tags::PATIENT_NAME), Some(pseudonym.clone()));
+        assert_eq!(text(&object, tags::PATIENT_ID), Some(pseudonym));
+        assert_eq!(text(&object, tags::PATIENT_BIRTH_DATE), None);
+        assert!(object.get(tags::PATIENT_BIRTH_DATE).is_some());
+        assert_eq!(text(&object, tags::PATIENT_SEX), None);
+        assert_eq!(text(&object, tags::ACCESSION_NUMBER), None);
+        assert_eq!(text(&object, tags::STUDY_DATE), None);
+        assert!(object.get(tags::INSTITUTION_NAME).is_none());
+        assert!(object.get(Tag(0x0019, 0x1010)).is_none());
+        assert_eq!(text(&object, tags::SLICE_THICKNESS).as_deref(), Some("5"));
+        assert_eq!(text(&object, tags::PATIENT_IDENTITY_REMOVED).as_deref(), Some("YES"));
+        assert_eq!(text(&object, tags::LONGITUDINAL_TEMPORAL_INFORMATION_MODIFIED).as_deref(), Some("REMOVED"));
+
+        // UIDs are remapped the same way everywhere, SOP classes are kept
+        let map = UidMap::new("secret");
+        assert_eq!(text(&object, tags::STUDY_INSTANCE_UID), Some(map.uid("1.2.3")));
+        let reference = &object.get(tags::REFERENCED_IMAGE_SEQUENCE).unwrap().items().unwrap()[0];
+        assert_eq!(text(reference, tags::REFERENCED_SOP_INSTANCE_UID), Some(map.uid("1.2.3.1.2")));
+        assert_eq!(text(reference, tags::REFERENCED_SOP_CLASS_UID).as_deref(), Some(uids::CT_IMAGE_STORAGE));
+        assert_ne!(map.uid("1.2.3"), UidMap::new("other").uid("1.2.3"));
+        assert!(map.uid("1.2.3").len() <= 64);
+    }
+
+    #[test]
+    fn removes_whatever_may_identify_the_patient() {
+        let mut object = ct();
+        for (tag, vr, value) in [
+            (Tag(0x0010, 0x1000), VR::LO, "MRN-778"),
+            (Tag(0x0010, 0x1090), VR::LO, "Shelf 12"),
+            (tags::CURRENT_PATIENT_LOCATION, VR::LO, "Ward 4, bed 2"),
+            (tags::PATIENT_STATE, VR::LO, "Sedated"),
+            (tags::PERSON_ADDRESS, VR::ST, "12 Mall Road, Lahore"),
+            (tags::PERSON_TELEPHONE_NUMBERS, VR::LO, "042-111-155-555"),
+            // not in ATTRIBUTES, but text that may name anyone
+            (tags::NAMES_OF_INTENDED_RECIPIENTS_OF_RESULTS, VR::PN, "KHAN^AYESHA"),
+            (tags::TIMEZONE_OFFSET_FROM_UTC, VR::SH, "+0500"),
+            (tags::MANUFACTURER, VR::LO, "SIEMENS"),
+            (tags::VERIFYING_OBSERVER_NAME, VR::PN, "JILANI^WASAY"),
+            (tags::PERSON_NAME, VR::PN, "KHAN^IMRAN"),
+        ] {
+            object.put(DataElement::new(tag, vr, value));
+        }
+        let object = deidentifier(Options::default()).dataset(&object).unwrap();
+        for tag in [
+            Tag(0x0010, 0x1000),
+            Tag(0x0010, 0x1090),
+            tags::CURRENT_PATIENT_LOCATION,
+            tags::PATIENT_STATE,
+            tags::PERSON_ADDRESS,
+            tags::PERSON_TELEPHONE_NUMBERS,
+            tags::NAMES_OF_INTENDED_RECIPIENTS_OF_RESULTS,
+            tags::TIMEZONE_OFFSET_FROM_UTC,
+        ] {
+            assert!(object.get(tag).is_none(), "{} is kept", tag);
+        }
+        assert_eq!(text(&object, tags::MANUFACTURER).as_deref(), Some("SIEMENS"));
+        assert_eq!(text(&object, tags::MODALITY).as_deref(), Some("CT"));
+        assert_eq!(text(&object, tags::SOP_CLASS_UID).as_deref(), Some(uids::CT_IMAGE_STORAGE));
+        // only the patient goes by the pseudonym
+        assert_eq!(text(&object, tags::VERIFYING_OBSERVER_NAME).as_deref(), Some("ANONYMIZED"));
+        assert_eq!(text(&object, tags::PERSON_NAME).as_deref(), Some("ANONYMIZED"));
+    }
+
+    #[test]
+    fn options_retain_attributes() {
+        let options = Options {
+            retain_dates: true,
+            retain_uids: true,
+            retain_patient_characteristics: true,
+            retain_institution_identity: true,
+            ..Options::default()
+        };
+        let deidentifier = Deidentifier {
+            pseudonym: Some(String::from("TEACHING^CASE 12")),
+            ..deidentifier(options)
+        };
+        let object = deidentifier.dataset(&ct()).unwrap();
+        assert_eq!(text(&object, tags::PATIENT_NAME).as_deref(), Some("TEACHING^CASE 12"));
+        assert_eq!(text(&object, tags::STUDY_DATE).as_deref(), Some("20230724"));
+        assert_eq!(text(&object, tags::STUDY_INSTANCE_UID).as_deref(), Some("1.2.3"));
+        assert_eq!(text(&object, tags::PATIENT_SEX).as_deref(), Some("F"));
+        assert_eq!(text(&object, tags::INSTITUTION_NAME).as_deref(), Some("Shaukat Khanum"));
+        assert_eq!(text(&object, tags::PATIENT_BIRTH_DATE), None);
+        let methods = object.get(tags::DEIDENTIFICATION_METHOD_CODE_SEQUENCE).unwrap().items().unwrap();
+        assert_eq!(methods.len(), 5);
+        assert_eq!(text(&methods[0], tags::CODE_VALUE).as_deref(), Some("113100"));
+    }
+
+    #[test]
+    fn withholds_burned_in_annotation_and_rewrites_files() {
+        let mut screenshot = ct();
+        screenshot.put(DataElement::new(tags::MODALITY, VR::CS, "US"));
+        assert_eq!(deidentifier(Options::default()).dataset(&screenshot), Err(Withheld::BurnedInAnnotation));
+        screenshot.put(DataElement::new(tags::BURNED_IN_ANNOTATION, VR::CS, "NO"));
+        assert!(deidentifier(Options::default()).dataset(&screenshot).is_ok());
+
+        let mut file = Vec::new();
+        ct().with_meta(FileMetaTableBuilder::new().transfer_syntax(uids::EXPLICIT_VR_LITTLE_ENDIAN))
+            .unwrap()
+            .write_all(&mut file)
+            .unwrap();
+        let deidentified = deidentifier(Options::default()).file(&file).unwrap();
+        let reread = OpenFileOptions::new().from_reader(&deidentified[..]).unwrap();
+        assert_eq!(reread.meta().media_storage_sop_instance_uid(), UidMap::new("secret").uid("1.2.3.1.1"));
+        assert_eq!(text(&reread, tags::PATIENT_ID), Some(UidMap::new("secret").pseudonym("SCH-001")));
+        assert!(is_pseudonym("TEACHING^CASE 12") && !is_pseudonym("A\\B") && !is_pseudonym(" "));
+        let options = Options {
+            retain_dates: true,
+            ..Options::default()
+        };
+        assert_eq!(query(&options, " TEACHING^CASE 12 "), "retain_dates=true&pseudonym=TEACHING%5ECASE%2012");
+        assert_eq!(query(&Options::default(), ""), "");
+    }
+}
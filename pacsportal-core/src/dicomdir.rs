@@ -0,0 +1,379 @@
+//! The DICOMDIR of a file-set handed to patients on CD or USB (PS3.10 and
+//! the Basic Directory IOD of PS3.3 F.3), listing every file by patient,
+//! study and series so that the viewer on the disc can find them.
+
+use dicom::core::value::{DataSetSequence, PrimitiveValue};
+use dicom::core::{DataElement, DicomValue, Length, Tag, VR};
+use dicom::dictionary_std::{tags, uids};
+use dicom::encoding::TransferSyntaxIndex;
+use dicom::object::{DefaultDicomObject, FileMetaTableBuilder, InMemDicomObject};
+use dicom::transfer_syntax::TransferSyntaxRegistry;
+
+use crate::attributes::{copy_or_empty, new_uid, text};
+
+/// Name of the file-set, at most 16 characters.
+pub const FILE_SET_ID: &str = "PACSPORTAL";
+
+/// Attributes copied from each file for its records.
+const KEYS: [Tag; 20] = [
+    tags::SPECIFIC_CHARACTER_SET,
+    tags::PATIENT_NAME,
+    tags::PATIENT_ID,
+    tags::STUDY_DATE,
+    tags::STUDY_TIME,
+    tags::STUDY_DESCRIPTION,
+    tags::STUDY_INSTANCE_UID,
+    tags::STUDY_ID,
+    tags::ACCESSION_NUMBER,
+    tags::MODALITY,
+    tags::SERIES_INSTANCE_UID,
+    tags::SERIES_NUMBER,
+    tags::INSTANCE_NUMBER,
+    tags::CONTENT_DATE,
+    tags::CONTENT_TIME,
+    tags::COMPLETION_FLAG,
+    tags::VERIFICATION_FLAG,
+    tags::CONCEPT_NAME_CODE_SEQUENCE,
+    tags::PRESENTATION_CREATION_DATE,
+    tags::PRESENTATION_CREATION_TIME,
+];
+
+/// The path of a file in the file-set: ISO 9660 names of up to eight upper
+/// case letters and digits.
+pub fn file_id(series: usize, instance: usize) -> Vec<String> {
+    vec![String::from("DICOM"), format!("SE{:06}", series), format!("IM{:06}", instance)]
+}
+
+/// A file of the file-set, with what its directory records need.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub file_id: Vec<String>,
+    keys: InMemDicomObject,
+    sop_class_uid: String,
+    sop_instance_uid: String,
+    transfer_syntax: String,
+}
+
+impl Entry {
+    pub fn new(file_id: Vec<String>, file: &DefaultDicomObject) -> Self {
+        let keys = InMemDicomObject::from_element_iter(KEYS.iter().filter_map(|tag| file.get(*tag).cloned()));
+        let meta = file.meta();
+        let uid = |value: &str| value.trim_end_matches(['\0', ' ']).to_owned();
+        Entry {
+            file_id,
+            keys,
+            sop_class_uid: uid(meta.media_storage_sop_class_uid()),
+            sop_instance_uid: uid(meta.media_storage_sop_instance_uid()),
+            transfer_syntax: uid(meta.transfer_syntax()),
+        }
+    }
+
+    /// The path of the file in the ZIP, e.g. `DICOM/SE000001/IM000001`.
+    pub fn path(&self) -> String {
+        self.file_id.join("/")
+    }
+
+    fn record(&self, record_type: &str, keys: &[(Tag, VR)]) -> InMemDicomObject {
+        let mut record = InMemDicomObject::from_element_iter([
+            DataElement::new(tags::OFFSET_OF_THE_NEXT_DIRECTORY_RECORD, VR::UL, PrimitiveValue::from(0u32)),
+            DataElement::new(tags::RECORD_IN_USE_FLAG, VR::US, PrimitiveValue::from(0xFFFFu16)),
+            DataElement::new(tags::OFFSET_OF_REFERENCED_LOWER_LEVEL_DIRECTORY_ENTITY, VR::UL, PrimitiveValue::from(0u32)),
+            DataElement::new(tags::DIRECTORY_RECORD_TYPE, VR::CS, record_type),
+        ]);
+        if let Some(charset) = self.keys.get(tags::SPECIFIC_CHARACTER_SET) {
+            record.put(charset.clone());
+        }
+        for (tag, vr) in keys {
+            record.put(copy_or_empty(&self.keys, *tag, *vr));
+        }
+        record
+    }
+
+    fn patient(&self) -> InMemDicomObject {
+        self.record("PATIENT", &[(tags::PATIENT_NAME, VR::PN), (tags::PATIENT_ID, VR::LO)])
+    }
+
+    fn study(&self) -> InMemDicomObject {
+        self.record(
+            "STUDY",
+            &[
+                (tags::STUDY_DATE, VR::DA),
+                (tags::STUDY_TIME, VR::TM),
+                (tags::STUDY_DESCRIPTION, VR::LO),
+                (tags::STUDY_INSTANCE_UID, VR::UI),
+                (tags::STUDY_ID, VR::SH),
+                (tags::ACCESSION_NUMBER, VR::SH),
+            ],
+        )
+    }
+
+    fn series(&self) -> InMemDicomObject {
+        self.record(
+            "SERIES",
+            &[(tags::MODALITY, VR::CS), (tags::SERIES_INSTANCE_UID, VR::UI), (tags::SERIES_NUMBER, VR::IS)],
+        )
+    }
+
+    /// The record of the file itself, of the type its modality calls for.
+    fn instance(&self) -> InMemDicomObject {
+        let mut record = match text(&self.keys, tags::MODALITY).as_deref() {
+            Some("SR") => self.record(
+                "SR DOCUMENT",
+                &[
+                    (tags::INSTANCE_NUMBER, VR::IS),
+                    (tags::COMPLETION_FLAG, VR::CS),
+                    (tags::VERIFICATION_FLAG, VR::CS),
+                    (tags::CONTENT_DATE, VR::DA),
+                    (tags::CONTENT_TIME, VR::TM),
+                    (tags::CONCEPT_NAME_CODE_SEQUENCE, VR::SQ),
+                ],
+            ),
+            Some("KO") => self.record(
+                "KEY OBJECT DOC",
+                &[
+                    (tags::INSTANCE_NUMBER, VR::IS),
+                    (tags::CONTENT_DATE, VR::DA),
+                    (tags::CONTENT_TIME, VR::TM),
+                    (tags::CONCEPT_NAME_CODE_SEQUENCE, VR::SQ),
+                ],
+            ),
+            Some("PR") => self.record(
+                "PRESENTATION",
+                &[
+                    (tags::INSTANCE_NUMBER, VR::IS),
+                    (tags::PRESENTATION_CREATION_DATE, VR::DA),
+                    (tags::PRESENTATION_CREATION_TIME, VR::TM),
+                ],
+            ),
+            _ => self.record("IMAGE", &[(tags::INSTANCE_NUMBER, VR::IS)]),
+        };
+        record.put(DataElement::new(
+            tags::REFERENCED_FILE_ID,
+            VR::CS,
+            PrimitiveValue::Strs(self.file_id.iter().cloned().collect()),
+        ));
+        record.put(DataElement::new(tags::REFERENCED_SOP_CLASS_UID_IN_FILE, VR::UI, self.sop_class_uid.as_str()));
+        record.put(DataElement::new(tags::REFERENCED_SOP_INSTANCE_UID_IN_FILE, VR::UI, self.sop_instance_uid.as_str()));
+        record.put(DataElement::new(tags::REFERENCED_TRANSFER_SYNTAX_UID_IN_FILE, VR::UI, self.transfer_syntax.as_str()));
+        record
+    }
+}
+
+/// A directory record and the records of the entity below it.
+struct Node {
+    key: String,
+    record: InMemDicomObject,
+    children: Vec<Node>,
+}
+
+fn child(nodes: &mut Vec<Node>, key: String, record: impl FnOnce() -> InMemDicomObject) -> &mut Node {
+    let index = match nodes.iter().position(|node| node.key == key) {
+        Some(index) => index,
+        None => {
+            nodes.push(Node {
+                key,
+                record: record(),
+                children: Vec::new(),
+            });
+            nodes.len() - 1
+        }
+    };
+    &mut nodes[index]
+}
+
+/// A record in the order of the Directory Record Sequence, naming the
+/// records its offsets point to by index.
+struct Flat {
+    record: InMemDicomObject,
+    next: Option<usize>,
+    lower: Option<usize>,
+}
+
+/// Appends `nodes` and their children depth first, returning the index of
+/// the first of `nodes`.
+fn flatten(nodes: Vec<Node>, records: &mut Vec<Flat>) -> Option<usize> {
+    let mut first = None;
+    let mut previous: Option<usize> = None;
+    for node in nodes {
+        let index = records.len();
+        records.push(Flat {
+            record: node.record,
+            next: None,
+            lower: None,
+        });
+        match previous {
+            Some(previous) => records[previous].next = Some(index),
+            None => first = Some(index),
+        }
+        previous = Some(index);
+        records[index].lower = flatten(node.children, records);
+    }
+    first
+}
+
+fn put_offset(object: &mut InMemDicomObject, tag: Tag, offset: u32) {
+    object.put(DataElement::new(tag, VR::UL, PrimitiveValue::from(offset)));
+}
+
+fn write(records: &[Flat], offsets: &[u32], first: Option<usize>, last: Option<usize>, instance_uid: &str) -> Result<Vec<u8>, String> {
+    let offset_of = |index: Option<usize>| index.map(|index| offsets[index]).unwrap_or(0);
+    let items = records
+        .iter()
+        .map(|flat| {
+            let mut record = flat.record.clone();
+            put_offset(&mut record, tags::OFFSET_OF_THE_NEXT_DIRECTORY_RECORD, offset_of(flat.next));
+            put_offset(&mut record, tags::OFFSET_OF_REFERENCED_LOWER_LEVEL_DIRECTORY_ENTITY, offset_of(flat.lower));
+            record
+        })
+        .collect::<Vec<_>>();
+    let mut root = InMemDicomObject::from_element_iter([
+        DataElement::new(tags::FILE_SET_ID, VR::CS, FILE_SET_ID),
+        DataElement::new(tags::FILE_SET_CONSISTENCY_FLAG, VR::US, PrimitiveValue::from(0u16)),
+        DataElement::new(
+            tags::DIRECTORY_RECORD_SEQUENCE,
+            VR::SQ,
+            DicomValue::Sequence(DataSetSequence::new(items, Length::UNDEFINED)),
+        ),
+    ]);
+    put_offset(&mut root, tags::OFFSET_OF_THE_FIRST_DIRECTORY_RECORD_OF_THE_ROOT_DIRECTORY_ENTITY, offset_of(first));
+    put_offset(&mut root, tags::OFFSET_OF_THE_LAST_DIRECTORY_RECORD_OF_THE_ROOT_DIRECTORY_ENTITY, offset_of(last));
+
+    let meta = FileMetaTableBuilder::new()
+        .media_storage_sop_class_uid(uids::MEDIA_STORAGE_DIRECTORY_STORAGE)
+        .media_storage_sop_instance_uid(instance_uid)
+        .transfer_syntax(uids::EXPLICIT_VR_LITTLE_ENDIAN);
+    let file = root.with_meta(meta).map_err(|e| e.to_string())?;
+    let mut bytes = Vec::new();
+    file.write_all(&mut bytes).map_err(|e| e.to_string())?;
+    Ok(bytes)
+}
+
+/// Start of an item in explicit VR little endian: tag (FFFE,E000).
+const ITEM: [u8; 4] = [0xFE, 0xFF, 0x00, 0xE0];
+
+/// The DICOMDIR file of `entries`, one patient, study and series record
+/// for each that the files belong to.
+///
+/// Records point at each other by byte offsets within the file. The offsets
+/// are fixed-size UL values, so the file is written once to find where each
+/// record starts and again with the offsets filled in.
+pub fn dicomdir(entries: &[Entry]) -> Result<Vec<u8>, String> {
+    let mut patients: Vec<Node> = Vec::new();
+    for entry in entries {
+        let key = |tag: Tag| text(&entry.keys, tag).unwrap_or_default();
+        let patient = child(&mut patients, key(tags::PATIENT_ID), || entry.patient());
+        let study = child(&mut patient.children, key(tags::STUDY_INSTANCE_UID), || entry.study());
+        let series = child(&mut study.children, key(tags::SERIES_INSTANCE_UID), || entry.series());
+        series.children.push(Node {
+            key: entry.path(),
+            record: entry.instance(),
+            children: Vec::new(),
+        });
+    }
+    let last_patient = patients.len().checked_sub(1);
+    let mut records = Vec::new();
+    let first = flatten(patients, &mut records);
+    // the last root record is the last patient, which follows the records below the others
+    let last = last_patient.and_then(|last| {
+        let mut index = first?;
+        for _ in 0..last {
+            index = records[index].next?;
+        }
+        Some(index)
+    });
+
+    // both writes need the same UID, whose length would otherwise move the records
+    let instance_uid = new_uid();
+    let draft = write(&records, &vec![0; records.len()], first, last, &instance_uid)?;
+    let sequence = [0x04, 0x00, 0x20, 0x12, b'S', b'Q'];
+    let start = draft
+        .windows(sequence.len())
+        .position(|window| window == sequence)
+        .ok_or_else(|| String::from("The Directory Record Sequence was not written"))?;
+    // the sequence header is 12 bytes; each record is an item of undefined
+    // length, i.e. item tag and length, its elements, and an item delimiter
+    let ts = TransferSyntaxRegistry
+        .get(uids::EXPLICIT_VR_LITTLE_ENDIAN)
+        .ok_or_else(|| String::from("Explicit VR Little Endian is not supported"))?;
+    let mut offsets = Vec::with_capacity(records.len());
+    let mut offset = start + 12;
+    for flat in &records {
+        offsets.push(u32::try_from(offset).map_err(|e| e.to_string())?);
+        let mut encoded = Vec::new();
+        flat.record.write_dataset_with_ts(&mut encoded, ts).map_err(|e| e.to_string())?;
+        offset += 8 + encoded.len() + 8;
+    }
+
+    let bytes = write(&records, &offsets, first, last, &instance_uid)?;
+    let points_at_items = offsets
+        .iter()
+        .all(|offset| bytes.get(*offset as usize..*offset as usize + 4) == Some(&ITEM[..]));
+    if bytes.len() != draft.len() || !points_at_items {
+        return Err(String::from("The directory record offsets could not be worked out"));
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom::object::OpenFileOptions;
+
+    fn file(series: &str, modality: &str, sop_uid: &str) -> DefaultDicomObject {
+        let object = InMemDicomObject::from_element_iter([
+            DataElement::new(tags::SOP_CLASS_UID, VR::UI, uids::CT_IMAGE_STORAGE),
+            DataElement::new(tags::SOP_INSTANCE_UID, VR::UI, sop_uid),
+            DataElement::new(tags::PATIENT_ID, VR::LO, "SCH-001"),
+            DataElement::new(tags::PATIENT_NAME, VR::PN, "KHAN^AYESHA"),
+            DataElement::new(tags::STUDY_INSTANCE_UID, VR::UI, "1.2.3"),
+            DataElement::new(tags::SERIES_INSTANCE_UID, VR::UI, series),
+            DataElement::new(tags::MODALITY, VR::CS, modality),
+        ]);
+        object
+            .with_meta(FileMetaTableBuilder::new().transfer_syntax(uids::EXPLICIT_VR_LITTLE_ENDIAN))
+            .unwrap()
+    }
+
+    fn record_at(bytes: &[u8], records: &[InMemDicomObject], offset: u32) -> Option<InMemDicomObject> {
+        if offset == 0 {
+            return None;
+        }
+        assert_eq!(&bytes[offset as usize..offset as usize + 4], &ITEM);
+        // records are read back in order, so the nth item from the start is the nth record
+        let index = bytes[..offset as usize].windows(4).filter(|window| *window == ITEM).count();
+        records.get(index).cloned()
+    }
+
+    fn offset(object: &InMemDicomObject, tag: Tag) -> u32 {
+        object.get(tag).unwrap().to_int::<u32>().unwrap()
+    }
+
+    #[test]
+    fn records_point_at_each_other() {
+        let entries = [
+            Entry::new(file_id(1, 1), &file("1.2.3.1", "CT", "1.2.3.1.1")),
+            Entry::new(file_id(1, 2), &file("1.2.3.1", "CT", "1.2.3.1.2")),
+            Entry::new(file_id(2, 1), &file("1.2.3.2", "SR", "1.2.3.2.1")),
+        ];
+        assert_eq!(entries[2].path(), "DICOM/SE000002/IM000001");
+        let bytes = dicomdir(&entries).unwrap();
+        let dicomdir = OpenFileOptions::new().from_reader(&bytes[..]).unwrap();
+        assert_eq!(dicomdir.meta().media_storage_sop_class_uid().trim_end_matches('\0'), uids::MEDIA_STORAGE_DIRECTORY_STORAGE);
+        let records = dicomdir.get(tags::DIRECTORY_RECORD_SEQUENCE).unwrap().items().unwrap().to_vec();
+        let record_type = |record: &InMemDicomObject| text(record, tags::DIRECTORY_RECORD_TYPE).unwrap();
+        assert_eq!(records.iter().map(record_type).collect::<Vec<_>>(), ["PATIENT", "STUDY", "SERIES", "IMAGE", "IMAGE", "SERIES", "SR DOCUMENT"]);
+
+        let patient = record_at(&bytes, &records, offset(&dicomdir, tags::OFFSET_OF_THE_FIRST_DIRECTORY_RECORD_OF_THE_ROOT_DIRECTORY_ENTITY)).unwrap();
+        assert_eq!(offset(&dicomdir, tags::OFFSET_OF_THE_LAST_DIRECTORY_RECORD_OF_THE_ROOT_DIRECTORY_ENTITY), offset(&dicomdir, tags::OFFSET_OF_THE_FIRST_DIRECTORY_RECORD_OF_THE_ROOT_DIRECTORY_ENTITY));
+        assert_eq!(record_type(&patient), "PATIENT");
+        let study = record_at(&bytes, &records, offset(&patient, tags::OFFSET_OF_REFERENCED_LOWER_LEVEL_DIRECTORY_ENTITY)).unwrap();
+        let series = record_at(&bytes, &records, offset(&study, tags::OFFSET_OF_REFERENCED_LOWER_LEVEL_DIRECTORY_ENTITY)).unwrap();
+        let next_series = record_at(&bytes, &records, offset(&series, tags::OFFSET_OF_THE_NEXT_DIRECTORY_RECORD)).unwrap();
+        assert_eq!(text(&next_series, tags::SERIES_INSTANCE_UID).as_deref(), Some("1.2.3.2"));
+        let report = record_at(&bytes, &records, offset(&next_series, tags::OFFSET_OF_REFERENCED_LOWER_LEVEL_DIRECTORY_ENTITY)).unwrap();
+        assert_eq!(record_type(&report), "SR DOCUMENT");
+        assert_eq!(text(&report, tags::REFERENCED_SOP_INSTANCE_UID_IN_FILE).as_deref(), Some("1.2.3.2.1"));
+        assert_eq!(report.get(tags::REFERENCED_FILE_ID).unwrap().strings().unwrap().len(), 3);
+        assert_eq!(offset(&report, tags::OFFSET_OF_THE_NEXT_DIRECTORY_RECORD), 0);
+    }
+}
@@ -0,0 +1,154 @@
+//! Exports of the search results for billing reconciliation.
+
+use serde::{Deserialize, Serialize};
+
+use crate::csv;
+use crate::model::{Priority, Study};
+use crate::worklist::is_reported;
+use crate::xlsx;
+
+/// The columns of the search table, then the study UID, report status and
+/// priority.
+pub const COLUMNS: [&str; 11] = [
+    "Patient ID",
+    "Name",
+    "Accession",
+    "Modality",
+    "Description",
+    "Source AE",
+    "Date",
+    "Time",
+    "Study Instance UID",
+    "Report",
+    "Priority",
+];
+
+/// The text typed into the column headers of the search table, whether
+/// studies of only reports, presentation states and key images are hidden,
+/// and the least urgent priority shown.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextFilters {
+    pub id: String,
+    pub name: String,
+    pub accession: String,
+    pub modality: String,
+    pub description: String,
+    pub source_ae: String,
+    pub images_only: bool,
+    pub priority: Option<Priority>,
+}
+
+impl TextFilters {
+    /// Whether a study is shown; names and descriptions match regardless of
+    /// case, modalities are matched in upper case.
+    pub fn matches(&self, study: &Study) -> bool {
+        let text = |value: &Option<String>| value.clone().unwrap_or_default();
+        text(&study.patient.id).contains(&self.id)
+            && text(&study.patient.name).to_lowercase().contains(&self.name.to_lowercase())
+            && text(&study.accession).contains(&self.accession)
+            && study.modalities_label().contains(&self.modality.to_uppercase())
+            && text(&study.description).to_lowercase().contains(&self.description.to_lowercase())
+            && text(&study.source_ae).contains(&self.source_ae)
+            && (!self.images_only || study.has_images())
+            && self.priority.iter().all(|priority| study.urgency() <= *priority)
+    }
+}
+
+pub fn report_status(study: &Study) -> &'static str {
+    if is_reported(study) {
+        "Reported"
+    } else {
+        "Unreported"
+    }
+}
+
+/// The header and a row for every study the filters show.
+pub fn rows(studies: &[Study], filters: &TextFilters) -> Vec<Vec<String>> {
+    let mut rows = vec![COLUMNS.iter().map(|column| column.to_string()).collect()];
+    rows.extend(studies.iter().filter(|study| filters.matches(study)).map(|study| {
+        vec![
+            study.patient.id.clone().unwrap_or_default(),
+            study.patient.name.clone().unwrap_or_default(),
+            study.accession.clone().unwrap_or_default(),
+            study.modalities_label(),
+            study.description.clone().unwrap_or_default(),
+            study.source_ae.clone().unwrap_or_default(),
+            study.date_label(),
+            study.time_label(),
+            study.study_uid.clone().unwrap_or_default(),
+            report_status(study).to_owned(),
+            study.urgency().label().to_owned(),
+        ]
+    }));
+    rows
+}
+
+pub fn csv(rows: &[Vec<String>]) -> String {
+    rows.iter().map(csv::row).collect()
+}
+
+pub fn xlsx(rows: &[Vec<String>]) -> zip::result::ZipResult<Vec<u8>> {
+    xlsx::workbook("Studies", rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom::core::{DataElement, PrimitiveValue, VR};
+    use dicom::dictionary_std::tags;
+    use dicom::object::InMemDicomObject;
+
+    fn study(id: &str, name: &str, modalities: &[&str]) -> Study {
+        Study::from_dicom(&InMemDicomObject::from_element_iter([
+            DataElement::new(tags::STUDY_INSTANCE_UID, VR::UI, format!("1.2.{}", id)),
+            DataElement::new(tags::PATIENT_ID, VR::LO, id),
+            DataElement::new(tags::PATIENT_NAME, VR::PN, name),
+            DataElement::new(tags::MODALITIES_IN_STUDY, VR::CS, PrimitiveValue::Strs(modalities.iter().map(|m| m.to_string()).collect())),
+            DataElement::new(tags::STUDY_DATE, VR::DA, "20230724"),
+        ]))
+    }
+
+    #[test]
+    fn exports_the_filtered_studies() {
+        let studies = [study("SCH-001", "KHAN^AYESHA", &["CT", "SR"]), study("SCH-002", "JILANI^WASAY", &["MR"])];
+        let filters = TextFilters {
+            name: String::from("Khan"),
+            modality: String::from("ct"),
+            ..TextFilters::default()
+        };
+        let rows = rows(&studies, &filters);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1][0], "SCH-001");
+        assert_eq!(rows[1][8], "1.2.SCH-001");
+        assert_eq!(rows[1][9], "Reported");
+        assert_eq!(rows[1][10], "ROUTINE");
+        assert!(csv(&rows).starts_with("Patient ID,Name,Accession,Modality,"));
+        assert!(csv(&rows).contains("\r\nSCH-001,KHAN AYESHA,,\"CT, SR\",,,2023-07-24,"));
+    }
+
+    #[test]
+    fn hides_studies_without_images() {
+        let studies = [study("SCH-001", "KHAN^AYESHA", &["CT", "SR"]), study("SCH-002", "KHAN^AYESHA", &["SR", "KO"])];
+        let filters = TextFilters {
+            images_only: true,
+            ..TextFilters::default()
+        };
+        assert_eq!(rows(&studies, &TextFilters::default()).len(), 3);
+        let rows = rows(&studies, &filters);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1][0], "SCH-001");
+    }
+
+    #[test]
+    fn shows_studies_as_urgent_as_asked() {
+        let mut studies = [study("SCH-001", "KHAN^AYESHA", &["CT"]), study("SCH-002", "KHAN^AYESHA", &["MR"]), study("SCH-003", "KHAN^AYESHA", &["US"])];
+        studies[0].priority = Some(Priority::Stat);
+        studies[2].priority = Some(Priority::Low);
+        let shown = |priority| rows(&studies, &TextFilters { priority, ..TextFilters::default() }).len() - 1;
+        assert_eq!(shown(Some(Priority::Stat)), 1);
+        assert_eq!(shown(Some(Priority::High)), 1);
+        assert_eq!(shown(Some(Priority::Routine)), 2);
+        assert_eq!(shown(None), 3);
+    }
+}
@@ -0,0 +1,187 @@
+use chrono::NaiveDateTime;
+use dicom::{
+    core::{smallvec::SmallVec, value::DataSetSequence, DataElement, DicomValue, Length, Tag, VR},
+    dictionary_std::{tags, uids},
+    object::InMemDicomObject,
+};
+
+use crate::attributes::{new_uid, text};
+use crate::codes::{self, OF_INTEREST};
+
+/// An image referenced by a Key Object Selection document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyImage {
+    pub study_uid: String,
+    pub series_uid: String,
+    pub sop_class_uid: String,
+    pub sop_uid: String,
+}
+
+fn sequence(tag: Tag, items: impl IntoIterator<Item = InMemDicomObject>) -> DataElement<InMemDicomObject> {
+    DataElement::new(
+        tag,
+        VR::SQ,
+        DicomValue::Sequence(DataSetSequence::new(
+            items.into_iter().collect::<SmallVec<_>>(),
+            Length::UNDEFINED,
+        )),
+    )
+}
+
+fn referenced_sop(image: &KeyImage) -> InMemDicomObject {
+    InMemDicomObject::from_element_iter([
+        DataElement::new(tags::REFERENCED_SOP_CLASS_UID, VR::UI, image.sop_class_uid.clone()),
+        DataElement::new(tags::REFERENCED_SOP_INSTANCE_UID, VR::UI, image.sop_uid.clone()),
+    ])
+}
+
+/// Builds a Key Object Selection Document (TID 2010) titled "Of Interest"
+/// referencing `images`, all of which belong to the study in `study_details`.
+/// `now` is written as the content date and time.
+pub fn key_object_selection(study_details: &InMemDicomObject, images: &[KeyImage], now: NaiveDateTime) -> InMemDicomObject {
+    let mut kos = InMemDicomObject::from_element_iter([
+        DataElement::new(tags::SOP_CLASS_UID, VR::UI, uids::KEY_OBJECT_SELECTION_DOCUMENT_STORAGE),
+        DataElement::new(tags::SOP_INSTANCE_UID, VR::UI, new_uid()),
+        DataElement::new(tags::SERIES_INSTANCE_UID, VR::UI, new_uid()),
+        DataElement::new(tags::MODALITY, VR::CS, "KO"),
+        DataElement::new(tags::SERIES_NUMBER, VR::IS, "1"),
+        DataElement::new(tags::INSTANCE_NUMBER, VR::IS, "1"),
+        DataElement::new(tags::CONTENT_DATE, VR::DA, now.format("%Y%m%d").to_string()),
+        DataElement::new(tags::CONTENT_TIME, VR::TM, now.format("%H%M%S").to_string()),
+        DataElement::new(tags::VALUE_TYPE, VR::CS, "CONTAINER"),
+        DataElement::new(tags::CONTINUITY_OF_CONTENT, VR::CS, "SEPARATE"),
+        codes::code_sequence(tags::CONCEPT_NAME_CODE_SEQUENCE, &OF_INTEREST),
+        sequence(
+            tags::CONTENT_TEMPLATE_SEQUENCE,
+            [InMemDicomObject::from_element_iter([
+                DataElement::new(tags::MAPPING_RESOURCE, VR::CS, "DCMR"),
+                DataElement::new(tags::TEMPLATE_IDENTIFIER, VR::CS, "2010"),
+            ])],
+        ),
+    ]);
+    for tag in [
+        tags::PATIENT_NAME,
+        tags::PATIENT_ID,
+        tags::PATIENT_BIRTH_DATE,
+        tags::PATIENT_SEX,
+        tags::STUDY_INSTANCE_UID,
+        tags::STUDY_DATE,
+        tags::STUDY_TIME,
+        tags::STUDY_ID,
+        tags::ACCESSION_NUMBER,
+        tags::REFERRING_PHYSICIAN_NAME,
+    ] {
+        if let Some(element) = study_details.get(tag) {
+            kos.put(element.clone());
+        }
+    }
+
+    // evidence is grouped by study and series, as required by the SR Document General module
+    let mut series_uids: Vec<&str> = Vec::new();
+    for image in images {
+        if !series_uids.contains(&image.series_uid.as_str()) {
+            series_uids.push(&image.series_uid);
+        }
+    }
+    let referenced_series = series_uids.iter().map(|series_uid| {
+        InMemDicomObject::from_element_iter([
+            DataElement::new(tags::SERIES_INSTANCE_UID, VR::UI, series_uid.to_string()),
+            sequence(
+                tags::REFERENCED_SOP_SEQUENCE,
+                images
+                    .iter()
+                    .filter(|image| image.series_uid == *series_uid)
+                    .map(referenced_sop),
+            ),
+        ])
+    });
+    let study_uid = images.first().map(|image| image.study_uid.clone()).unwrap_or_default();
+    kos.put(sequence(
+        tags::CURRENT_REQUESTED_PROCEDURE_EVIDENCE_SEQUENCE,
+        [InMemDicomObject::from_element_iter([
+            DataElement::new(tags::STUDY_INSTANCE_UID, VR::UI, study_uid),
+            sequence(tags::REFERENCED_SERIES_SEQUENCE, referenced_series),
+        ])],
+    ));
+
+    kos.put(sequence(
+        tags::CONTENT_SEQUENCE,
+        images.iter().map(|image| {
+            InMemDicomObject::from_element_iter([
+                DataElement::new(tags::RELATIONSHIP_TYPE, VR::CS, "CONTAINS"),
+                DataElement::new(tags::VALUE_TYPE, VR::CS, "IMAGE"),
+                sequence(tags::REFERENCED_SOP_SEQUENCE, [referenced_sop(image)]),
+            ])
+        }),
+    ));
+    kos
+}
+
+/// Instances referenced in the evidence sequence of a KOS document.
+pub fn key_images(kos: &InMemDicomObject) -> Vec<KeyImage> {
+    let mut images = Vec::new();
+    let studies = kos
+        .get(tags::CURRENT_REQUESTED_PROCEDURE_EVIDENCE_SEQUENCE)
+        .and_then(|evidence| evidence.items());
+    for study in studies.into_iter().flatten() {
+        let study_uid = text(study, tags::STUDY_INSTANCE_UID).unwrap_or_default();
+        let series = study
+            .get(tags::REFERENCED_SERIES_SEQUENCE)
+            .and_then(|series| series.items());
+        for series in series.into_iter().flatten() {
+            let series_uid = text(series, tags::SERIES_INSTANCE_UID).unwrap_or_default();
+            let instances = series
+                .get(tags::REFERENCED_SOP_SEQUENCE)
+                .and_then(|instances| instances.items());
+            for instance in instances.into_iter().flatten() {
+                if let Some(sop_uid) = text(instance, tags::REFERENCED_SOP_INSTANCE_UID) {
+                    images.push(KeyImage {
+                        study_uid: study_uid.clone(),
+                        series_uid: series_uid.clone(),
+                        sop_class_uid: text(instance, tags::REFERENCED_SOP_CLASS_UID).unwrap_or_default(),
+                        sop_uid,
+                    });
+                }
+            }
+        }
+    }
+    images
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn image(series_uid: &str, sop_uid: &str) -> KeyImage {
+        KeyImage {
+            study_uid: String::from("1.2.3"),
+            series_uid: series_uid.to_owned(),
+            sop_class_uid: String::from(uids::CT_IMAGE_STORAGE),
+            sop_uid: sop_uid.to_owned(),
+        }
+    }
+
+    #[test]
+    fn key_images_round_trip_through_the_evidence_sequence() {
+        let study_details = InMemDicomObject::from_element_iter([
+            DataElement::new(tags::STUDY_INSTANCE_UID, VR::UI, "1.2.3"),
+            DataElement::new(tags::PATIENT_ID, VR::LO, "SCH-001"),
+        ]);
+        let images = vec![image("1.2.3.1", "1.2.3.1.1"), image("1.2.3.2", "1.2.3.2.1"), image("1.2.3.1", "1.2.3.1.2")];
+        let now = NaiveDate::from_ymd_opt(2023, 7, 25).unwrap().and_hms_opt(10, 30, 0).unwrap();
+        let kos = key_object_selection(&study_details, &images, now);
+
+        assert_eq!(text(&kos, tags::MODALITY).as_deref(), Some("KO"));
+        assert_eq!(text(&kos, tags::PATIENT_ID).as_deref(), Some("SCH-001"));
+        assert_eq!(text(&kos, tags::CONTENT_DATE).as_deref(), Some("20230725"));
+        assert_eq!(kos.get(tags::CONTENT_SEQUENCE).and_then(|items| items.items()).map(|items| items.len()), Some(3));
+
+        // evidence is grouped by series, so the order differs from the selection
+        let mut parsed = key_images(&kos);
+        let mut expected = images.clone();
+        parsed.sort_by(|a, b| a.sop_uid.cmp(&b.sop_uid));
+        expected.sort_by(|a, b| a.sop_uid.cmp(&b.sop_uid));
+        assert_eq!(parsed, expected);
+    }
+}
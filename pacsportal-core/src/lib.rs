@@ -0,0 +1,22 @@
+//! DICOM models and SR construction for the portal, free of any browser or
+//! Yew dependency so that they can be tested natively with `cargo test`.
+
+pub mod attributes;
+pub mod codes;
+pub mod critical;
+pub mod csv;
+pub mod deidentify;
+pub mod dicomdir;
+pub mod export;
+pub mod kos;
+pub mod macros;
+pub mod modality;
+pub mod model;
+pub mod period;
+pub mod redact;
+pub mod report;
+pub mod search;
+pub mod stow;
+pub mod turnaround;
+pub mod worklist;
+pub mod xlsx;
@@ -0,0 +1,207 @@
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+/// Who a macro belongs to. Personal macros take precedence over department
+/// macros sharing the same trigger.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MacroScope {
+    User(String),
+    Department(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Macro {
+    pub trigger: String, // stored without the leading '.', typed as ".trigger"
+    pub phrase: String,
+    pub scope: MacroScope,
+}
+
+/// Patient details substituted into `{sex}` and `{age}` placeholders.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PatientContext {
+    pub sex: Option<String>,
+    pub age: Option<String>,
+}
+
+impl PatientContext {
+    /// `sex` is the DICOM PatientSex code (M/F/O), `birth_date` and
+    /// `study_date` are used to compute the age at the time of the study.
+    pub fn new(sex: Option<&str>, birth_date: Option<NaiveDate>, study_date: Option<NaiveDate>) -> Self {
+        let sex = sex.and_then(|code| match code.trim() {
+            "M" => Some(String::from("male")),
+            "F" => Some(String::from("female")),
+            "O" => Some(String::from("other")),
+            _ => None,
+        });
+        let age = match (birth_date, study_date) {
+            (Some(birth_date), Some(study_date)) => age_at(birth_date, study_date),
+            _ => None,
+        };
+        PatientContext { sex, age }
+    }
+}
+
+/// Age phrased the way it is written in a report, e.g. "45-year-old",
+/// "8-month-old" or "12-day-old".
+fn age_at(birth_date: NaiveDate, on: NaiveDate) -> Option<String> {
+    if on < birth_date {
+        return None;
+    }
+    let mut months = (on.year() - birth_date.year()) * 12 + on.month() as i32 - birth_date.month() as i32;
+    if on.day() < birth_date.day() {
+        months -= 1;
+    }
+    if months >= 24 {
+        Some(format!("{}-year-old", months / 12))
+    } else if months >= 1 {
+        Some(format!("{}-month-old", months))
+    } else {
+        Some(format!("{}-day-old", on.signed_duration_since(birth_date).num_days()))
+    }
+}
+
+/// The macros in `all` usable by `username`, personal ones first so that
+/// they shadow department macros with the same trigger.
+pub fn available_from(all: &[Macro], username: &str, department: &str) -> Vec<Macro> {
+    let personal = all
+        .iter()
+        .filter(|m| m.scope == MacroScope::User(username.to_owned()));
+    let departmental = all
+        .iter()
+        .filter(|m| m.scope == MacroScope::Department(department.to_owned()));
+    let mut macros: Vec<Macro> = Vec::new();
+    for m in personal.chain(departmental) {
+        if !macros.iter().any(|existing| existing.trigger == m.trigger) {
+            macros.push(m.clone());
+        }
+    }
+    macros
+}
+
+/// Replaces the `{sex}` and `{age}` placeholders in a phrase. Unknown values
+/// are left as the placeholder so the radiologist notices and fills them in.
+pub fn expand(phrase: &str, patient: &PatientContext) -> String {
+    let mut expanded = phrase.to_owned();
+    if let Some(sex) = &patient.sex {
+        expanded = expanded.replace("{sex}", sex);
+    }
+    if let Some(age) = &patient.age {
+        expanded = expanded.replace("{age}", age);
+    }
+    expanded
+}
+
+/// Looks for a ".trigger" typed immediately before `caret`, terminated by a
+/// space or newline. Returns the new text and caret position if one was
+/// expanded. `caret` is a char index.
+pub fn expand_at_caret(
+    text: &str,
+    caret: usize,
+    macros: &[Macro],
+    patient: &PatientContext,
+) -> Option<(String, usize)> {
+    let chars: Vec<char> = text.chars().collect();
+    if caret == 0 || caret > chars.len() || !chars[caret - 1].is_whitespace() {
+        return None;
+    }
+    let token_end = caret - 1;
+    let token_start = chars[..token_end]
+        .iter()
+        .rposition(|c| c.is_whitespace())
+        .map(|idx| idx + 1)
+        .unwrap_or(0);
+    let token: String = chars[token_start..token_end].iter().collect();
+    let trigger = token.strip_prefix('.')?;
+    let found = macros.iter().find(|m| m.trigger == trigger)?;
+    let phrase = expand(&found.phrase, patient);
+
+    let before: String = chars[..token_start].iter().collect();
+    let after: String = chars[token_end..].iter().collect();
+    let new_caret = token_start + phrase.chars().count() + 1;
+    Some((format!("{}{}{}", before, phrase, after), new_caret))
+}
+
+/// Textarea selection offsets are in UTF-16 code units; these convert them to
+/// and from char indices.
+pub fn char_index(text: &str, utf16_offset: u32) -> usize {
+    let mut units = 0;
+    for (idx, c) in text.chars().enumerate() {
+        if units >= utf16_offset as usize {
+            return idx;
+        }
+        units += c.len_utf16();
+    }
+    text.chars().count()
+}
+
+pub fn utf16_offset(text: &str, char_index: usize) -> u32 {
+    text.chars()
+        .take(char_index)
+        .map(|c| c.len_utf16() as u32)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user_macro(trigger: &str, phrase: &str) -> Macro {
+        Macro {
+            trigger: trigger.to_owned(),
+            phrase: phrase.to_owned(),
+            scope: MacroScope::User(String::from("radiologist")),
+        }
+    }
+
+    #[test]
+    fn age_is_phrased_by_magnitude() {
+        let birth = NaiveDate::from_ymd_opt(1978, 3, 15).unwrap();
+        assert_eq!(age_at(birth, NaiveDate::from_ymd_opt(2023, 3, 14).unwrap()).as_deref(), Some("44-year-old"));
+        assert_eq!(age_at(birth, NaiveDate::from_ymd_opt(2023, 3, 15).unwrap()).as_deref(), Some("45-year-old"));
+        assert_eq!(age_at(birth, NaiveDate::from_ymd_opt(1979, 1, 20).unwrap()).as_deref(), Some("10-month-old"));
+        assert_eq!(age_at(birth, NaiveDate::from_ymd_opt(1978, 3, 27).unwrap()).as_deref(), Some("12-day-old"));
+        assert_eq!(age_at(birth, NaiveDate::from_ymd_opt(1977, 1, 1).unwrap()), None);
+    }
+
+    #[test]
+    fn unknown_placeholders_are_left_in_place() {
+        let patient = PatientContext::new(Some("F"), None, None);
+        assert_eq!(expand("A {age} {sex}.", &patient), "A {age} female.");
+    }
+
+    #[test]
+    fn trigger_before_caret_is_expanded() {
+        let macros = [user_macro("nad", "No acute disease in this {sex}.")];
+        let patient = PatientContext::new(Some("M"), None, None);
+        let (text, caret) = expand_at_caret("Chest: .nad ", 12, &macros, &patient).unwrap();
+        assert_eq!(text, "Chest: No acute disease in this male. ");
+        assert_eq!(caret, text.chars().count());
+        assert_eq!(expand_at_caret("Chest: .xyz ", 12, &macros, &patient), None);
+        assert_eq!(expand_at_caret("Chest: .nad", 11, &macros, &patient), None);
+    }
+
+    #[test]
+    fn personal_macros_shadow_department_macros() {
+        let department = Macro {
+            trigger: String::from("nad"),
+            phrase: String::from("Department phrase"),
+            scope: MacroScope::Department(String::from("Radiology")),
+        };
+        let other_user = Macro {
+            scope: MacroScope::User(String::from("root")),
+            ..user_macro("cxr", "Other user's phrase")
+        };
+        let all = [department, user_macro("nad", "Personal phrase"), other_user];
+        let available = available_from(&all, "radiologist", "Radiology");
+        assert_eq!(available.len(), 1);
+        assert_eq!(available[0].phrase, "Personal phrase");
+    }
+
+    #[test]
+    fn utf16_offsets_round_trip() {
+        let text = "Größe 😀 ok";
+        let caret = utf16_offset(text, 8);
+        assert_eq!(caret, 9);
+        assert_eq!(char_index(text, caret), 8);
+    }
+}
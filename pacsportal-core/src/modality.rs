@@ -0,0 +1,74 @@
+//! The modalities offered as filter buttons on the search page, in a stable
+//! order: the configured one, or the order below for modalities discovered
+//! in the archive.
+
+use std::collections::HashSet;
+
+/// The usual order of the buttons; imaging first, roughly by volume, then
+/// the modalities of documents and other non-image objects.
+pub const ORDER: [&str; 22] = [
+    "CR", "DX", "DR", "CT", "MR", "US", "MG", "XA", "RF", "NM", "PT", "OT", "ES", "IO", "PX", "OP", "BMD", "ECG", "DOC",
+    "SR", "PR", "KO",
+];
+
+/// Structured reports, presentation states and key object selections,
+/// which carry no pixel data of their own.
+pub const NON_IMAGE: [&str; 3] = ["SR", "PR", "KO"];
+
+/// The buttons when neither a configured list nor the archive says otherwise.
+pub const DEFAULT: [&str; 13] = ["CR", "DX", "DR", "CT", "MR", "US", "MG", "XA", "RF", "NM", "PT", "OT", "SR"];
+
+pub fn is_non_image(modality: &str) -> bool {
+    NON_IMAGE.contains(&modality)
+}
+
+/// A configured list such as `CT, MR,US MG`, in its own order, upper-cased
+/// and without repeats. Anything that is not a code string of at most 16
+/// letters and digits is dropped.
+pub fn parse(list: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    list.split(|c: char| c == ',' || c.is_whitespace())
+        .map(|modality| modality.trim().to_uppercase())
+        .filter(|modality| !modality.is_empty() && modality.len() <= 16 && modality.chars().all(|c| c.is_ascii_alphanumeric()))
+        .filter(|modality| seen.insert(modality.clone()))
+        .collect()
+}
+
+/// Modalities found in the archive, without repeats, in the order of
+/// [`ORDER`] and then alphabetically.
+pub fn ordered<I, S>(modalities: I) -> Vec<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut found: Vec<String> = modalities
+        .into_iter()
+        .map(|modality| modality.as_ref().trim().to_uppercase())
+        .filter(|modality| !modality.is_empty())
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    found.sort_by_key(|modality| {
+        let position = ORDER.iter().position(|known| known == modality).unwrap_or(ORDER.len());
+        (position, modality.clone())
+    });
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn configured_order_is_kept() {
+        assert_eq!(parse("mr, CT,,US  mg\nCT x-ray"), ["MR", "CT", "US", "MG"]);
+        assert!(parse(" , ").is_empty());
+    }
+
+    #[test]
+    fn discovered_modalities_have_a_stable_order() {
+        assert_eq!(ordered(["SR", "ct", "XC", "MR", "CT", "AU", "CR"]), ["CR", "CT", "MR", "SR", "AU", "XC"]);
+        assert_eq!(ordered(DEFAULT), DEFAULT);
+        assert!(is_non_image("KO") && !is_non_image("CT"));
+    }
+}
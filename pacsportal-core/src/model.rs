@@ -0,0 +1,328 @@
+use std::collections::HashMap;
+
+use chrono::{NaiveDate, NaiveTime};
+use dicom::dictionary_std::tags;
+use dicom::object::InMemDicomObject;
+use serde::{Deserialize, Serialize};
+
+use crate::attributes::{self, person_name, text};
+use crate::modality;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Patient {
+    pub id: Option<String>,
+    pub name: Option<String>,
+    pub birth_date: Option<NaiveDate>,
+    pub sex: Option<String>,
+}
+
+impl Patient {
+    pub fn from_dicom(object: &InMemDicomObject) -> Self {
+        Patient {
+            id: text(object, tags::PATIENT_ID),
+            name: person_name(object, tags::PATIENT_NAME),
+            birth_date: attributes::date(object, tags::PATIENT_BIRTH_DATE),
+            sex: text(object, tags::PATIENT_SEX),
+        }
+    }
+}
+
+/// Urgency of the request behind a study, most urgent first so that
+/// sorting by it puts STAT studies on top.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Stat,
+    High,
+    Routine,
+    Low,
+}
+
+impl Priority {
+    pub const ALL: [Priority; 4] = [Priority::Stat, Priority::High, Priority::Routine, Priority::Low];
+
+    /// Parses the DICOM priority terms, e.g. of Requested Procedure Priority.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_uppercase().as_str() {
+            "STAT" => Some(Priority::Stat),
+            "HIGH" => Some(Priority::High),
+            "ROUTINE" | "MEDIUM" => Some(Priority::Routine),
+            "LOW" => Some(Priority::Low),
+            _ => None,
+        }
+    }
+
+    /// The priority of the request of a study, from Requested Procedure
+    /// Priority or else the Scheduled Procedure Step Priority of the
+    /// worklist entry, at the top level or in the Request Attributes Sequence.
+    pub fn from_dicom(object: &InMemDicomObject) -> Option<Self> {
+        let of = |object: &InMemDicomObject| {
+            [tags::REQUESTED_PROCEDURE_PRIORITY, tags::SCHEDULED_PROCEDURE_STEP_PRIORITY]
+                .into_iter()
+                .find_map(|tag| text(object, tag).and_then(|value| Priority::parse(&value)))
+        };
+        of(object).or_else(|| object.get(tags::REQUEST_ATTRIBUTES_SEQUENCE)?.items()?.iter().find_map(of))
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Priority::Stat => "STAT",
+            Priority::High => "HIGH",
+            Priority::Routine => "ROUTINE",
+            Priority::Low => "LOW",
+        }
+    }
+}
+
+/// The study-level attributes shown in the search table, parsed from a QIDO
+/// result. Attributes a modality failed to send are `None` instead of
+/// panicking, and are listed in `missing` so the row can be flagged.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Study {
+    pub study_uid: Option<String>,
+    pub patient: Patient,
+    pub accession: Option<String>,
+    pub modalities: Vec<String>,
+    pub description: Option<String>,
+    pub source_ae: Option<String>,
+    pub referring_physician: Option<String>,
+    pub date: Option<NaiveDate>,
+    pub time: Option<NaiveTime>,
+    pub series_count: Option<u32>,
+    pub instance_count: Option<u32>,
+    pub priority: Option<Priority>,
+    pub missing: Vec<&'static str>,
+}
+
+/// Gives studies the priority set for them in the metadata store, which
+/// wins over the one of the request.
+pub fn set_priorities(studies: &mut [Study], priorities: &HashMap<String, Priority>) {
+    for study in studies {
+        if let Some(priority) = study.study_uid.as_ref().and_then(|study_uid| priorities.get(study_uid)) {
+            study.priority = Some(*priority);
+        }
+    }
+}
+
+impl Study {
+    pub fn from_dicom(object: &InMemDicomObject) -> Self {
+        let study_uid = text(object, tags::STUDY_INSTANCE_UID);
+        let patient = Patient::from_dicom(object);
+        let accession = text(object, tags::ACCESSION_NUMBER);
+        let modalities = attributes::strings(object, tags::MODALITIES_IN_STUDY);
+        let date = attributes::date(object, tags::STUDY_DATE);
+        let time = attributes::time(object, tags::STUDY_TIME);
+
+        let mut missing = Vec::new();
+        if study_uid.is_none() {
+            missing.push("Study Instance UID");
+        }
+        if patient.id.is_none() {
+            missing.push("Patient ID");
+        }
+        if patient.name.is_none() {
+            missing.push("Patient Name");
+        }
+        if accession.is_none() {
+            missing.push("Accession Number");
+        }
+        if modalities.is_empty() {
+            missing.push("Modalities in Study");
+        }
+        if date.is_none() {
+            missing.push("Study Date");
+        }
+        if time.is_none() {
+            missing.push("Study Time");
+        }
+
+        Study {
+            study_uid,
+            patient,
+            accession,
+            modalities,
+            description: text(object, tags::STUDY_DESCRIPTION),
+            source_ae: text(object, tags::SOURCE_APPLICATION_ENTITY_TITLE),
+            referring_physician: person_name(object, tags::REFERRING_PHYSICIAN_NAME),
+            date,
+            time,
+            series_count: text(object, tags::NUMBER_OF_STUDY_RELATED_SERIES).and_then(|n| n.parse().ok()),
+            instance_count: text(object, tags::NUMBER_OF_STUDY_RELATED_INSTANCES).and_then(|n| n.parse().ok()),
+            priority: Priority::from_dicom(object),
+            missing,
+        }
+    }
+
+    pub fn is_malformed(&self) -> bool {
+        !self.missing.is_empty()
+    }
+
+    pub fn modalities_label(&self) -> String {
+        self.modalities.join(", ")
+    }
+
+    /// The priority to order and filter by; unknown counts as routine.
+    pub fn urgency(&self) -> Priority {
+        self.priority.unwrap_or(Priority::Routine)
+    }
+
+    /// Whether the study has images, as far as its modalities tell; studies
+    /// without any are given the benefit of the doubt.
+    pub fn has_images(&self) -> bool {
+        self.modalities.is_empty() || self.modalities.iter().any(|modality| !modality::is_non_image(modality))
+    }
+
+    pub fn date_label(&self) -> String {
+        self.date
+            .map(|date| date.format("%Y-%m-%d").to_string())
+            .unwrap_or_default()
+    }
+
+    pub fn time_label(&self) -> String {
+        self.time
+            .map(|time| time.format("%H:%M:%S").to_string())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Series {
+    pub series_uid: Option<String>,
+    pub number: Option<i32>,
+    pub modality: Option<String>,
+    pub description: Option<String>,
+    pub instance_count: Option<u32>,
+}
+
+impl Series {
+    pub fn from_dicom(object: &InMemDicomObject) -> Self {
+        Series {
+            series_uid: text(object, tags::SERIES_INSTANCE_UID),
+            number: text(object, tags::SERIES_NUMBER).and_then(|n| n.parse().ok()),
+            modality: text(object, tags::MODALITY),
+            description: text(object, tags::SERIES_DESCRIPTION),
+            instance_count: text(object, tags::NUMBER_OF_SERIES_RELATED_INSTANCES).and_then(|n| n.parse().ok()),
+        }
+    }
+
+    /// Structured reports, key object selections and presentation states
+    /// carry no pixel data of their own.
+    pub fn is_image_series(&self) -> bool {
+        !self.modality.as_deref().is_some_and(modality::is_non_image)
+    }
+
+    pub fn label(&self) -> String {
+        [
+            self.number.map(|n| n.to_string()),
+            self.modality.clone(),
+            self.description.clone(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instance {
+    pub study_uid: Option<String>,
+    pub series_uid: Option<String>,
+    pub sop_class_uid: Option<String>,
+    pub sop_uid: Option<String>,
+    pub number: Option<i32>,
+}
+
+impl Instance {
+    pub fn from_dicom(object: &InMemDicomObject) -> Self {
+        Instance {
+            study_uid: text(object, tags::STUDY_INSTANCE_UID),
+            series_uid: text(object, tags::SERIES_INSTANCE_UID),
+            sop_class_uid: text(object, tags::SOP_CLASS_UID),
+            sop_uid: text(object, tags::SOP_INSTANCE_UID),
+            number: text(object, tags::INSTANCE_NUMBER).and_then(|n| n.parse().ok()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom::core::value::DataSetSequence;
+    use dicom::core::{dicom_value, DataElement, VR};
+
+    #[test]
+    fn complete_study_is_not_malformed() {
+        let object = InMemDicomObject::from_element_iter([
+            DataElement::new(tags::STUDY_INSTANCE_UID, VR::UI, "1.2.3"),
+            DataElement::new(tags::PATIENT_ID, VR::LO, "SCH-001"),
+            DataElement::new(tags::PATIENT_NAME, VR::PN, "KHAN^AYESHA"),
+            DataElement::new(tags::ACCESSION_NUMBER, VR::SH, "A100"),
+            DataElement::new(tags::MODALITIES_IN_STUDY, VR::CS, dicom_value!(Strs, ["CT", "SR"])),
+            DataElement::new(tags::STUDY_DATE, VR::DA, "20230724"),
+            DataElement::new(tags::STUDY_TIME, VR::TM, "090543"),
+            DataElement::new(tags::NUMBER_OF_STUDY_RELATED_SERIES, VR::IS, "3"),
+        ]);
+        let study = Study::from_dicom(&object);
+        assert!(!study.is_malformed());
+        assert_eq!(study.patient.name.as_deref(), Some("KHAN AYESHA"));
+        assert_eq!(study.modalities_label(), "CT, SR");
+        assert_eq!(study.date_label(), "2023-07-24");
+        assert_eq!(study.time_label(), "09:05:43");
+        assert_eq!(study.series_count, Some(3));
+        assert!(study.has_images());
+    }
+
+    #[test]
+    fn missing_attributes_are_reported() {
+        let object = InMemDicomObject::from_element_iter([
+            DataElement::new(tags::STUDY_INSTANCE_UID, VR::UI, "1.2.3"),
+            DataElement::empty(tags::PATIENT_ID, VR::LO),
+            DataElement::new(tags::STUDY_DATE, VR::DA, "not a date"),
+        ]);
+        let study = Study::from_dicom(&object);
+        assert!(study.is_malformed());
+        assert!(study.missing.contains(&"Patient ID"));
+        assert!(study.missing.contains(&"Study Date"));
+        assert!(!study.missing.contains(&"Study Instance UID"));
+        assert_eq!(study.date_label(), "");
+        assert!(study.has_images());
+    }
+
+    #[test]
+    fn priority_of_the_request() {
+        assert_eq!(Priority::parse(" stat"), Some(Priority::Stat));
+        assert_eq!(Priority::parse("MEDIUM"), Some(Priority::Routine));
+        assert_eq!(Priority::parse("soon"), None);
+        assert!(Priority::Stat < Priority::Routine);
+
+        let top = InMemDicomObject::from_element_iter([DataElement::new(tags::REQUESTED_PROCEDURE_PRIORITY, VR::CS, "HIGH")]);
+        assert_eq!(Study::from_dicom(&top).priority, Some(Priority::High));
+        let request = InMemDicomObject::from_element_iter([DataElement::new(tags::REQUESTED_PROCEDURE_PRIORITY, VR::CS, "STAT")]);
+        let nested = InMemDicomObject::from_element_iter([DataElement::new(
+            tags::REQUEST_ATTRIBUTES_SEQUENCE,
+            VR::SQ,
+            DataSetSequence::from(vec![request]),
+        )]);
+        assert_eq!(Study::from_dicom(&nested).priority, Some(Priority::Stat));
+        assert_eq!(Study::from_dicom(&InMemDicomObject::new_empty()).priority, None);
+
+        let scheduled = InMemDicomObject::from_element_iter([DataElement::new(tags::SCHEDULED_PROCEDURE_STEP_PRIORITY, VR::CS, "MEDIUM")]);
+        let mut studies = vec![Study::from_dicom(&scheduled), Study::from_dicom(&top)];
+        assert_eq!(studies[0].urgency(), Priority::Routine);
+        studies[1].study_uid = Some(String::from("1.2.3"));
+        set_priorities(&mut studies, &HashMap::from([(String::from("1.2.3"), Priority::Stat)]));
+        assert_eq!(studies[1].priority, Some(Priority::Stat));
+    }
+
+    #[test]
+    fn series_label_skips_absent_parts() {
+        let object = InMemDicomObject::from_element_iter([
+            DataElement::new(tags::SERIES_NUMBER, VR::IS, "2"),
+            DataElement::new(tags::MODALITY, VR::CS, "CT"),
+        ]);
+        let series = Series::from_dicom(&object);
+        assert_eq!(series.label(), "2 CT");
+        assert!(series.is_image_series());
+    }
+}
@@ -0,0 +1,320 @@
+//! The study date range of the search page: presets such as "Last shift",
+//! shortcuts typed as `-3d`, and how a range becomes a QIDO query.
+//!
+//! QIDO matches `StudyDate` and `StudyTime` separately, so a time of day can
+//! only be queried within a single day. Ranges over several days that start
+//! or end at a time are queried by date and narrowed by [`Period::contains`].
+
+use std::fmt;
+
+use chrono::{Datelike, Days, Duration, Months, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+
+use crate::model::Study;
+
+/// The presets when none are configured.
+pub const DEFAULT_PRESETS: &str = "today,yesterday,week,shift=20-08,24h,3d,1w,1m,1y,any";
+
+/// The first day of "any" date.
+pub fn earliest() -> NaiveDate {
+    NaiveDate::from_ymd_opt(1990, 1, 1).unwrap_or_default()
+}
+
+fn end_of_day() -> NaiveTime {
+    NaiveTime::from_hms_opt(23, 59, 59).unwrap_or_default()
+}
+
+/// From `start` to `end`, both included, to the second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Period {
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+}
+
+impl Period {
+    /// Whole days from `start` to `end`.
+    pub fn days(start: NaiveDate, end: NaiveDate) -> Self {
+        Period {
+            start: start.and_time(NaiveTime::MIN),
+            end: end.and_time(end_of_day()),
+        }
+    }
+
+    pub fn start_date(&self) -> NaiveDate {
+        self.start.date()
+    }
+
+    pub fn end_date(&self) -> NaiveDate {
+        self.end.date()
+    }
+
+    pub fn is_whole_days(&self) -> bool {
+        self.start.time() == NaiveTime::MIN && self.end.time() == end_of_day()
+    }
+
+    /// The QIDO matching keys, e.g. `StudyDate=20230701-20230724` or, within
+    /// a day, `StudyDate=20230724&StudyTime=080000-115959`.
+    pub fn query(&self) -> String {
+        let dates = format!("StudyDate={}-{}", self.start.format("%Y%m%d"), self.end.format("%Y%m%d"));
+        if self.is_whole_days() || self.start_date() != self.end_date() {
+            dates
+        } else {
+            format!(
+                "StudyDate={}&StudyTime={}-{}",
+                self.start.format("%Y%m%d"),
+                self.start.format("%H%M%S"),
+                self.end.format("%H%M%S")
+            )
+        }
+    }
+
+    /// Whether a study found by [`Period::query`] lies in the period. Studies
+    /// without a date or time cannot be placed and are kept.
+    pub fn contains(&self, study: &Study) -> bool {
+        match (study.date, study.time) {
+            (Some(date), Some(time)) => {
+                let at = date.and_time(time.with_nanosecond(0).unwrap_or(time));
+                self.start <= at && at <= self.end
+            }
+            (Some(date), None) => self.start_date() <= date && date <= self.end_date(),
+            _ => true,
+        }
+    }
+}
+
+/// A button of the date bar, worked out from the time it is pressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    Today,
+    Yesterday,
+    /// Since Monday.
+    ThisWeek,
+    /// The latest shift to have started, from `start` to `end` o'clock.
+    Shift { start: u32, end: u32 },
+    Hours(u32),
+    /// Today and the `n` days before it, as the old 1D and 3D buttons.
+    Days(u32),
+    Weeks(u32),
+    Months(u32),
+    Years(u32),
+    Any,
+}
+
+impl Preset {
+    /// One preset of a configured list: `today`, `yesterday`, `week`,
+    /// `shift=20-08`, `24h`, `3d`, `1w`, `1m`, `1y` or `any`.
+    pub fn parse(spec: &str) -> Option<Self> {
+        let spec = spec.trim().to_lowercase();
+        match spec.as_str() {
+            "today" => return Some(Preset::Today),
+            "yesterday" => return Some(Preset::Yesterday),
+            "week" => return Some(Preset::ThisWeek),
+            "any" => return Some(Preset::Any),
+            _ => {}
+        }
+        if let Some(hours) = spec.strip_prefix("shift=") {
+            let (start, end) = hours.split_once('-')?;
+            let (start, end) = (start.trim().parse().ok()?, end.trim().parse().ok()?);
+            return (start < 24 && end < 24 && start != end).then_some(Preset::Shift { start, end });
+        }
+        let unit = spec.chars().last()?;
+        let count: u32 = spec[..spec.len() - unit.len_utf8()].parse().ok().filter(|count| *count > 0)?;
+        match unit {
+            'h' => Some(Preset::Hours(count)),
+            'd' => Some(Preset::Days(count)),
+            'w' => Some(Preset::Weeks(count)),
+            'm' => Some(Preset::Months(count)),
+            'y' => Some(Preset::Years(count)),
+            _ => None,
+        }
+    }
+
+    /// A configured list such as [`DEFAULT_PRESETS`]; unknown entries are left out.
+    pub fn parse_list(list: &str) -> Vec<Self> {
+        list.split(',').filter_map(Preset::parse).collect()
+    }
+
+    /// The preset as [`Preset::parse`] reads it.
+    pub fn spec(&self) -> String {
+        match self {
+            Preset::Today => String::from("today"),
+            Preset::Yesterday => String::from("yesterday"),
+            Preset::ThisWeek => String::from("week"),
+            Preset::Shift { start, end } => format!("shift={:02}-{:02}", start, end),
+            Preset::Hours(hours) => format!("{}h", hours),
+            Preset::Days(days) => format!("{}d", days),
+            Preset::Weeks(weeks) => format!("{}w", weeks),
+            Preset::Months(months) => format!("{}m", months),
+            Preset::Years(years) => format!("{}y", years),
+            Preset::Any => String::from("any"),
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Preset::Today => String::from("Today"),
+            Preset::Yesterday => String::from("Yesterday"),
+            Preset::ThisWeek => String::from("This week"),
+            Preset::Shift { start, end } => format!("Last shift {:02}:00–{:02}:00", start, end),
+            Preset::Hours(hours) => format!("Last {}h", hours),
+            Preset::Days(days) => format!("{}D", days),
+            Preset::Weeks(weeks) => format!("{}W", weeks),
+            Preset::Months(months) => format!("{}M", months),
+            Preset::Years(years) => format!("{}Y", years),
+            Preset::Any => String::from("Any"),
+        }
+    }
+
+    /// The period as of `now`. Calendar arithmetic that would leave chrono's
+    /// range falls back to [`earliest`].
+    pub fn period(&self, now: NaiveDateTime) -> Period {
+        let today = now.date();
+        let days_before = |days: u64| today.checked_sub_days(Days::new(days)).unwrap_or_else(earliest);
+        let months_before = |months: u32| today.checked_sub_months(Months::new(months)).unwrap_or_else(earliest);
+        let until_now = |start: NaiveDateTime| Period { start, end: now };
+        match *self {
+            Preset::Today => Period::days(today, today),
+            Preset::Yesterday => Period::days(days_before(1), days_before(1)),
+            Preset::ThisWeek => Period::days(days_before(u64::from(today.weekday().num_days_from_monday())), today),
+            Preset::Shift { start, end } => {
+                let start_time = NaiveTime::from_hms_opt(start, 0, 0).unwrap_or_default();
+                let started = if now.time() >= start_time { today } else { days_before(1) };
+                let hours = (end + 24 - start) % 24;
+                let start = started.and_time(start_time);
+                Period {
+                    start,
+                    end: start + Duration::hours(i64::from(hours)) - Duration::seconds(1),
+                }
+            }
+            Preset::Hours(hours) => until_now(
+                now.checked_sub_signed(Duration::hours(i64::from(hours)))
+                    .unwrap_or_else(|| earliest().and_time(NaiveTime::MIN)),
+            ),
+            Preset::Days(days) => Period::days(days_before(u64::from(days)), today),
+            Preset::Weeks(weeks) => Period::days(days_before(7 * u64::from(weeks)), today),
+            Preset::Months(months) => Period::days(months_before(months), today),
+            Preset::Years(years) => Period::days(months_before(years.saturating_mul(12)), today),
+            Preset::Any => Period::days(earliest(), today),
+        }
+    }
+}
+
+/// Why typed dates were not understood.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invalid(pub String);
+
+impl fmt::Display for Invalid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\"{}\" is not a date. Try 2023-07-24, -3d, -12h, today or yesterday.",
+            self.0
+        )
+    }
+}
+
+/// A date as typed or from a date input: `2023-07-24` or `20230724`.
+pub fn parse_date(input: &str) -> Result<NaiveDate, Invalid> {
+    let input = input.trim();
+    NaiveDate::parse_from_str(input, "%Y-%m-%d")
+        .or_else(|_| NaiveDate::parse_from_str(input, "%Y%m%d"))
+        .map_err(|_| Invalid(input.to_owned()))
+}
+
+/// What was typed into the shortcut box: a relative shortcut such as `-3d`
+/// (since three days ago), `-12h` or `-2w`, a preset such as `today` or
+/// `week`, a date, or two dates joined by `..`.
+pub fn parse(input: &str, now: NaiveDateTime) -> Result<Period, Invalid> {
+    let trimmed = input.trim();
+    let invalid = || Invalid(trimmed.to_owned());
+    if let Some(relative) = trimmed.strip_prefix('-') {
+        return match Preset::parse(relative).ok_or_else(invalid)? {
+            preset @ (Preset::Hours(_) | Preset::Days(_) | Preset::Weeks(_) | Preset::Months(_) | Preset::Years(_)) => {
+                Ok(preset.period(now))
+            }
+            _ => Err(invalid()),
+        };
+    }
+    if let Some((start, end)) = trimmed.split_once("..") {
+        let (start, end) = (parse_date(start)?, parse_date(end)?);
+        return if start <= end { Ok(Period::days(start, end)) } else { Err(invalid()) };
+    }
+    match Preset::parse(trimmed) {
+        Some(preset @ (Preset::Today | Preset::Yesterday | Preset::ThisWeek | Preset::Any)) => Ok(preset.period(now)),
+        _ => parse_date(trimmed).map(|date| Period::days(date, date)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom::core::{DataElement, VR};
+    use dicom::dictionary_std::tags;
+    use dicom::object::InMemDicomObject;
+
+    fn at(date: &str, time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(&format!("{} {}", date, time), "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn study(date: &str, time: &str) -> Study {
+        let mut object = InMemDicomObject::from_element_iter([DataElement::new(tags::STUDY_DATE, VR::DA, date)]);
+        if !time.is_empty() {
+            object.put(DataElement::new(tags::STUDY_TIME, VR::TM, time));
+        }
+        Study::from_dicom(&object)
+    }
+
+    #[test]
+    fn presets_are_worked_out_from_now() {
+        // a Wednesday morning
+        let now = at("2023-07-26", "09:30:00");
+        assert_eq!(Preset::Today.period(now), Period::days(now.date(), now.date()));
+        assert_eq!(Preset::Yesterday.period(now).query(), "StudyDate=20230725-20230725");
+        assert_eq!(Preset::ThisWeek.period(now).start_date().to_string(), "2023-07-24");
+        assert_eq!(Preset::Days(3).period(now).query(), "StudyDate=20230723-20230726");
+        assert_eq!(Preset::Months(1).period(now).start_date().to_string(), "2023-06-26");
+
+        let shift = Preset::Shift { start: 20, end: 8 };
+        assert_eq!(shift.period(now), Period { start: at("2023-07-25", "20:00:00"), end: at("2023-07-26", "07:59:59") });
+        // during the shift, it is the one under way
+        assert_eq!(shift.period(at("2023-07-26", "21:00:00")).start, at("2023-07-26", "20:00:00"));
+        assert_eq!(shift.period(at("2023-07-26", "03:00:00")).start, at("2023-07-25", "20:00:00"));
+        let day_shift = Preset::Shift { start: 8, end: 20 }.period(now);
+        assert_eq!(day_shift.query(), "StudyDate=20230726&StudyTime=080000-195959");
+
+        let hours = Preset::Hours(24).period(now);
+        assert_eq!(hours.query(), "StudyDate=20230725-20230726");
+        assert!(hours.contains(&study("20230725", "100000")));
+        assert!(!hours.contains(&study("20230725", "090000")));
+        assert!(hours.contains(&study("20230725", "")));
+    }
+
+    #[test]
+    fn presets_are_configurable() {
+        let presets = Preset::parse_list(DEFAULT_PRESETS);
+        assert_eq!(presets.len(), 10);
+        assert_eq!(presets.iter().map(Preset::spec).collect::<Vec<_>>().join(","), DEFAULT_PRESETS);
+        assert_eq!(
+            Preset::parse_list("today, shift=07-19 ,12h,fortnight,0d,2w"),
+            [Preset::Today, Preset::Shift { start: 7, end: 19 }, Preset::Hours(12), Preset::Weeks(2)]
+        );
+        assert_eq!(Preset::parse("shift=20-20"), None);
+        assert_eq!(Preset::parse("shift=25-08"), None);
+        assert_eq!(Preset::Shift { start: 20, end: 8 }.label(), "Last shift 20:00–08:00");
+    }
+
+    #[test]
+    fn typed_dates_are_parsed_without_panicking() {
+        let now = at("2023-07-26", "09:30:00");
+        assert_eq!(parse("-3d", now), Ok(Preset::Days(3).period(now)));
+        assert_eq!(parse(" -12H ", now).map(|period| period.start), Ok(at("2023-07-25", "21:30:00")));
+        assert_eq!(parse("yesterday", now), Ok(Preset::Yesterday.period(now)));
+        assert_eq!(parse("2023-07-01..20230704", now).map(|period| period.query()), Ok(String::from("StudyDate=20230701-20230704")));
+        assert_eq!(parse("20230701", now).map(|period| period.end_date().to_string()), Ok(String::from("2023-07-01")));
+        for invalid in ["", "-", "-3x", "-3ü", "-today", "2023-02-30", "2023-07-04..2023-07-01", "soon"] {
+            assert_eq!(parse(invalid, now), Err(Invalid(invalid.trim().to_owned())), "{}", invalid);
+        }
+        assert!(parse_date("").is_err());
+        assert_eq!(parse("-999999999y", now).map(|period| period.start_date()), Ok(earliest()));
+        assert_eq!(parse("-4000000000h", now).map(|period| period.start_date()), Ok(earliest()));
+    }
+}
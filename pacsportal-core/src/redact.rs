@@ -0,0 +1,123 @@
+//! Redaction of patient data for diagnostics. Anything that goes to a log
+//! passes through here, so that logs on shared reading room terminals never
+//! show who a study belongs to.
+
+use dicom::core::dictionary::{DataDictionary, DataDictionaryEntry};
+use dicom::core::value::Value;
+use dicom::core::{Tag, VR};
+use dicom::dictionary_std::{tags, StandardDataDictionary};
+use dicom::object::InMemDicomObject;
+
+/// What a redacted value is shown as.
+pub const REDACTED: &str = "[redacted]";
+
+/// Attributes which identify a patient or a study even though they are not
+/// person names. UIDs are included: with access to the archive, they lead
+/// straight to the patient. So is report text, which often names people.
+const IDENTIFIERS: [Tag; 16] = [
+    tags::PATIENT_ID,
+    tags::ISSUER_OF_PATIENT_ID,
+    Tag(0x0010, 0x1000), // OtherPatientIDs, retired
+    tags::OTHER_PATIENT_I_DS_SEQUENCE,
+    tags::PATIENT_BIRTH_DATE,
+    tags::PATIENT_ADDRESS,
+    tags::PATIENT_TELEPHONE_NUMBERS,
+    Tag(0x0010, 0x1090), // MedicalRecordLocator, retired
+    tags::ACCESSION_NUMBER,
+    tags::STUDY_ID,
+    tags::ADMISSION_ID,
+    tags::STUDY_INSTANCE_UID,
+    tags::SERIES_INSTANCE_UID,
+    tags::SOP_INSTANCE_UID,
+    tags::REFERENCED_SOP_INSTANCE_UID,
+    tags::TEXT_VALUE,
+];
+
+/// Whether values of the attribute must not be logged: person names (PN)
+/// and the identifiers above.
+pub fn is_sensitive(tag: Tag, vr: VR) -> bool {
+    vr == VR::PN || IDENTIFIERS.contains(&tag)
+}
+
+/// The DICOM keyword of a tag, or `(gggg,eeee)` for private and unknown ones.
+pub fn keyword(tag: Tag) -> String {
+    StandardDataDictionary
+        .by_tag(tag)
+        .map(|entry| entry.alias().to_owned())
+        .unwrap_or_else(|| tag.to_string())
+}
+
+/// One line describing a data set for a log, e.g.
+/// `Modality=CT PatientName=[redacted] ReferencedSeriesSequence=[2 items]`.
+pub fn summary(object: &InMemDicomObject) -> String {
+    object
+        .iter()
+        .map(|element| {
+            let tag = element.header().tag;
+            let value = match element.value() {
+                _ if is_sensitive(tag, element.header().vr) => String::from(REDACTED),
+                Value::Sequence(sequence) => format!("[{} items]", sequence.items().len()),
+                Value::PixelSequence(_) => String::from("[pixel data]"),
+                Value::Primitive(_) => element.to_str().map(|value| value.trim().to_owned()).unwrap_or_default(),
+            };
+            format!("{}={}", keyword(tag), value)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Redacts the sensitive parameters of a QIDO-RS query string, which name
+/// attributes by keyword, e.g. `PatientName=DOE*&Modality=CT` becomes
+/// `PatientName=[redacted]&Modality=CT`.
+pub fn query(query: &str) -> String {
+    query
+        .split('&')
+        .filter(|parameter| !parameter.is_empty())
+        .map(|parameter| match parameter.split_once('=') {
+            Some((key, _)) if is_sensitive_keyword(key) => format!("{}={}", key, REDACTED),
+            _ => parameter.to_owned(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn is_sensitive_keyword(key: &str) -> bool {
+    // fuzzymatching, includefield and the like are no attributes
+    match StandardDataDictionary.parse_tag(key) {
+        Some(tag) => is_sensitive(tag, StandardDataDictionary.by_tag(tag).map(|entry| entry.vr()).unwrap_or(VR::UN)),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom::core::{DataElement, PrimitiveValue};
+
+    #[test]
+    fn names_and_identifiers_are_redacted() {
+        let object = InMemDicomObject::from_element_iter([
+            DataElement::new(tags::PATIENT_NAME, VR::PN, PrimitiveValue::from("DOE^JANE")),
+            DataElement::new(tags::PATIENT_ID, VR::LO, PrimitiveValue::from("MRN0042")),
+            DataElement::new(tags::REFERRING_PHYSICIAN_NAME, VR::PN, PrimitiveValue::from("HOUSE^GREGORY")),
+            DataElement::new(tags::STUDY_INSTANCE_UID, VR::UI, PrimitiveValue::from("1.2.3.4")),
+            DataElement::new(tags::MODALITY, VR::CS, PrimitiveValue::from("CT")),
+            DataElement::new(tags::STUDY_DATE, VR::DA, PrimitiveValue::from("20230914")),
+        ]);
+        let summary = summary(&object);
+        assert!(!summary.contains("DOE") && !summary.contains("HOUSE"));
+        assert!(!summary.contains("MRN0042") && !summary.contains("1.2.3.4"));
+        assert!(summary.contains("PatientName=[redacted]"));
+        assert!(summary.contains("StudyInstanceUID=[redacted]"));
+        assert!(summary.contains("Modality=CT"));
+        assert!(summary.contains("StudyDate=20230914"));
+    }
+
+    #[test]
+    fn query_parameters_are_redacted() {
+        assert_eq!(
+            query("PatientName=DOE*&fuzzymatching=true&ModalitiesInStudy=CT&00100020=MRN0042&StudyDate=20230901-20230914"),
+            "PatientName=[redacted]&fuzzymatching=true&ModalitiesInStudy=CT&00100020=[redacted]&StudyDate=20230901-20230914"
+        );
+    }
+}
@@ -0,0 +1,242 @@
+use chrono::{Local, NaiveDateTime};
+use dicom::{
+    core::{smallvec::smallvec, value::DataSetSequence, DataElement, DicomValue, Length, VR},
+    dictionary_std::{tags, uids},
+    object::InMemDicomObject,
+};
+
+use crate::attributes::{self, copy_or_empty, new_uid, person_name, text};
+use crate::codes;
+use crate::critical::CRITICAL_REPORT;
+
+/// A report read back from the archive, i.e. the WADO-RS metadata of an SR.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub sop_uid: Option<String>,
+    pub text: Option<String>,
+    pub content_date_time: Option<NaiveDateTime>,
+    pub verifying_observer: Option<String>,
+    pub is_critical: bool,
+}
+
+impl Report {
+    pub fn from_dicom(sr: &InMemDicomObject) -> Self {
+        let verifying_observer = sr
+            .get(tags::VERIFYING_OBSERVER_SEQUENCE)
+            .and_then(|observers| observers.items())
+            .and_then(|observers| observers.first())
+            .and_then(|observer| person_name(observer, tags::VERIFYING_OBSERVER_NAME));
+        Report {
+            sop_uid: text(sr, tags::SOP_INSTANCE_UID),
+            text: report_text(sr),
+            content_date_time: attributes::date_time(sr, tags::CONTENT_DATE, tags::CONTENT_TIME),
+            verifying_observer,
+            is_critical: codes::has_title(sr, &CRITICAL_REPORT),
+        }
+    }
+}
+
+/// The report text of an SR, either at the root of the document or in its
+/// content items.
+pub fn report_text(sr: &InMemDicomObject) -> Option<String> {
+    if let Some(text) = sr.get(tags::TEXT_VALUE) {
+        return text.to_str().ok().map(|text| text.into_owned());
+    }
+    let items = sr.get(tags::CONTENT_SEQUENCE)?.items()?;
+    let texts: Vec<String> = items
+        .iter()
+        .filter_map(|item| item.get(tags::TEXT_VALUE))
+        .filter_map(|text| text.to_str().ok())
+        .map(|text| text.into_owned())
+        .collect();
+    if texts.is_empty() {
+        None
+    } else {
+        Some(texts.join("\n"))
+    }
+}
+
+/// Builds the Basic Text SR stored when a radiologist saves a report. Patient
+/// and study attributes are copied from the QIDO result of the study; those
+/// missing there are written empty.
+pub struct ReportBuilder<'a> {
+    study_details: &'a InMemDicomObject,
+    text: String,
+    verifying_observer: String,
+    organization: String,
+    critical: bool,
+    at: NaiveDateTime,
+}
+
+impl<'a> ReportBuilder<'a> {
+    pub fn new(study_details: &'a InMemDicomObject, text: impl Into<String>, verifying_observer: impl Into<String>) -> Self {
+        ReportBuilder {
+            study_details,
+            text: text.into(),
+            verifying_observer: verifying_observer.into(),
+            organization: String::from("South City Hospital"),
+            critical: false,
+            at: Local::now().naive_local(),
+        }
+    }
+
+    /// Flags the report as containing a critical finding. The flag is the
+    /// document title so that critical reports can be found with QIDO.
+    pub fn critical(mut self, critical: bool) -> Self {
+        self.critical = critical;
+        self
+    }
+
+    pub fn organization(mut self, organization: impl Into<String>) -> Self {
+        self.organization = organization.into();
+        self
+    }
+
+    /// Content and verification date and time, now by default.
+    pub fn at(mut self, at: NaiveDateTime) -> Self {
+        self.at = at;
+        self
+    }
+
+    pub fn build(self) -> InMemDicomObject {
+        let study_details = self.study_details;
+        let mut sr = InMemDicomObject::from_element_iter([
+            DataElement::new(tags::SOP_CLASS_UID, VR::UI, uids::BASIC_TEXT_SR_STORAGE),
+            DataElement::new(tags::SOP_INSTANCE_UID, VR::UI, new_uid()),
+            copy_or_empty(study_details, tags::STUDY_DATE, VR::DA),
+            copy_or_empty(study_details, tags::STUDY_TIME, VR::TM),
+            DataElement::new(tags::CONTENT_DATE, VR::DA, self.at.format("%Y%m%d").to_string()),
+            DataElement::new(tags::CONTENT_TIME, VR::TM, self.at.format("%H%M%S").to_string()),
+            copy_or_empty(study_details, tags::ACCESSION_NUMBER, VR::SH),
+            DataElement::new(tags::MODALITY, VR::CS, "SR"),
+            copy_or_empty(study_details, tags::MANUFACTURER, VR::LO),
+            copy_or_empty(study_details, tags::REFERRING_PHYSICIAN_NAME, VR::PN),
+            copy_or_empty(study_details, tags::PATIENT_NAME, VR::PN),
+            copy_or_empty(study_details, tags::PATIENT_ID, VR::LO),
+            copy_or_empty(study_details, tags::PATIENT_BIRTH_DATE, VR::DA),
+            copy_or_empty(study_details, tags::PATIENT_SEX, VR::CS),
+            copy_or_empty(study_details, tags::STUDY_INSTANCE_UID, VR::UI),
+            DataElement::new(tags::SERIES_INSTANCE_UID, VR::UI, new_uid()),
+            copy_or_empty(study_details, tags::STUDY_ID, VR::SH),
+            DataElement::new(tags::SERIES_NUMBER, VR::IS, "1"),
+            DataElement::new(tags::INSTANCE_NUMBER, VR::IS, "1"),
+            DataElement::new(
+                tags::VERIFYING_OBSERVER_SEQUENCE,
+                VR::SQ,
+                DicomValue::Sequence(DataSetSequence::new(
+                    smallvec![InMemDicomObject::from_element_iter([
+                        DataElement::new(tags::VERIFYING_ORGANIZATION, VR::LO, self.organization.clone()),
+                        DataElement::new(
+                            tags::VERIFICATION_DATE_TIME,
+                            VR::DT,
+                            self.at.format("%Y%m%d%H%M%S").to_string()
+                        ),
+                        DataElement::new(tags::VERIFYING_OBSERVER_NAME, VR::PN, self.verifying_observer.clone()),
+                        DataElement::new(
+                            tags::VERIFYING_OBSERVER_IDENTIFICATION_CODE_SEQUENCE,
+                            VR::SQ,
+                            DicomValue::Sequence(DataSetSequence::empty())
+                        )
+                    ])],
+                    Length::UNDEFINED,
+                )),
+            ),
+            DataElement::new(tags::COMPLETION_FLAG, VR::CS, "COMPLETE"),
+            DataElement::new(tags::VERIFICATION_FLAG, VR::CS, "VERIFIED"),
+            DataElement::new(tags::VALUE_TYPE, VR::CS, "TEXT"),
+            DataElement::new(tags::TEXT_VALUE, VR::UT, self.text.clone()),
+        ]);
+
+        let report_text = InMemDicomObject::from_element_iter([
+            DataElement::new(tags::RELATIONSHIP_TYPE, VR::CS, "CONTAINS"),
+            DataElement::new(tags::VALUE_TYPE, VR::CS, "TEXT"),
+            DataElement::new(tags::TEXT_VALUE, VR::UT, self.text.clone()),
+            DataElement::new(tags::CONTINUITY_OF_CONTENT, VR::CS, "SEPARATE"),
+        ]);
+        sr.put(DataElement::new(
+            tags::CONTENT_SEQUENCE,
+            VR::SQ,
+            DicomValue::Sequence(DataSetSequence::new(smallvec![report_text], Length::UNDEFINED)),
+        ));
+
+        if self.critical {
+            sr.put(codes::code_sequence(tags::CONCEPT_NAME_CODE_SEQUENCE, &CRITICAL_REPORT));
+        }
+        sr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn study_details() -> InMemDicomObject {
+        InMemDicomObject::from_element_iter([
+            DataElement::new(tags::STUDY_INSTANCE_UID, VR::UI, "1.2.3"),
+            DataElement::new(tags::PATIENT_ID, VR::LO, "SCH-001"),
+            DataElement::new(tags::PATIENT_NAME, VR::PN, "KHAN^AYESHA"),
+            DataElement::new(tags::STUDY_DATE, VR::DA, "20230724"),
+        ])
+    }
+
+    fn at() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2023, 7, 25).unwrap().and_hms_opt(10, 30, 0).unwrap()
+    }
+
+    #[test]
+    fn builds_basic_text_sr_for_the_study() {
+        let details = study_details();
+        let sr = ReportBuilder::new(&details, "No acute findings.", "DR WASAY JILANI")
+            .at(at())
+            .build();
+        assert_eq!(text(&sr, tags::SOP_CLASS_UID).as_deref(), Some(uids::BASIC_TEXT_SR_STORAGE));
+        assert_eq!(text(&sr, tags::STUDY_INSTANCE_UID).as_deref(), Some("1.2.3"));
+        assert_eq!(text(&sr, tags::PATIENT_ID).as_deref(), Some("SCH-001"));
+        assert_eq!(text(&sr, tags::MODALITY).as_deref(), Some("SR"));
+        assert_eq!(text(&sr, tags::CONTENT_DATE).as_deref(), Some("20230725"));
+        assert_ne!(text(&sr, tags::SOP_INSTANCE_UID), text(&sr, tags::SERIES_INSTANCE_UID));
+
+        let report = Report::from_dicom(&sr);
+        assert_eq!(report.text.as_deref(), Some("No acute findings."));
+        assert_eq!(report.verifying_observer.as_deref(), Some("DR WASAY JILANI"));
+        assert_eq!(report.content_date_time, Some(at()));
+        assert!(!report.is_critical);
+    }
+
+    #[test]
+    fn missing_study_attributes_are_written_empty() {
+        let details = study_details();
+        let sr = ReportBuilder::new(&details, "", "DR WASAY JILANI").build();
+        let sex = sr.get(tags::PATIENT_SEX).expect("PatientSex should be present");
+        assert_eq!(sex.vr(), VR::CS);
+        assert_eq!(text(&sr, tags::PATIENT_SEX), None);
+        assert!(sr.get(tags::REFERRING_PHYSICIAN_NAME).is_some());
+        assert!(sr.get(tags::STUDY_ID).is_some());
+    }
+
+    #[test]
+    fn critical_flag_is_the_document_title() {
+        let details = study_details();
+        let sr = ReportBuilder::new(&details, "Large left pneumothorax.", "DR WASAY JILANI")
+            .critical(true)
+            .build();
+        assert!(Report::from_dicom(&sr).is_critical);
+    }
+
+    #[test]
+    fn report_text_falls_back_to_content_items() {
+        let sr = InMemDicomObject::from_element_iter([DataElement::new(
+            tags::CONTENT_SEQUENCE,
+            VR::SQ,
+            DicomValue::Sequence(DataSetSequence::new(
+                smallvec![
+                    InMemDicomObject::from_element_iter([DataElement::new(tags::TEXT_VALUE, VR::UT, "Findings")]),
+                    InMemDicomObject::from_element_iter([DataElement::new(tags::TEXT_VALUE, VR::UT, "Impression")]),
+                ],
+                Length::UNDEFINED,
+            )),
+        )]);
+        assert_eq!(report_text(&sr).as_deref(), Some("Findings\nImpression"));
+    }
+}
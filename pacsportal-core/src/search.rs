@@ -0,0 +1,326 @@
+//! What the search page remembers: the order of the results, the studies
+//! that arrived since it last looked, and the saved searches of a user, one
+//! of which may be their default view.
+
+use std::collections::HashSet;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+
+use crate::export::TextFilters;
+use crate::model::Study;
+use crate::period::{Period, Preset};
+use crate::worklist::acquired_at;
+
+/// The order of the search results.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Sort {
+    /// STAT studies first, then by priority; otherwise as the archive
+    /// returned them.
+    #[default]
+    Priority,
+    /// As the archive returned them.
+    Archive,
+    Newest,
+    Oldest,
+    Name,
+}
+
+impl Sort {
+    pub const ALL: [Sort; 5] = [Sort::Priority, Sort::Archive, Sort::Newest, Sort::Oldest, Sort::Name];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Sort::Priority => "STAT first",
+            Sort::Archive => "Archive order",
+            Sort::Newest => "Newest first",
+            Sort::Oldest => "Oldest first",
+            Sort::Name => "Name",
+        }
+    }
+
+    pub fn key(&self) -> &'static str {
+        match self {
+            Sort::Priority => "priority",
+            Sort::Archive => "archive",
+            Sort::Newest => "newest",
+            Sort::Oldest => "oldest",
+            Sort::Name => "name",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        Sort::ALL.into_iter().find(|sort| sort.key() == key)
+    }
+
+    /// Sorts stably; studies without a date or name go last.
+    pub fn apply(&self, studies: &mut [Study]) {
+        match self {
+            Sort::Priority => studies.sort_by_key(Study::urgency),
+            Sort::Archive => {}
+            Sort::Newest => studies.sort_by_key(|study| std::cmp::Reverse(acquired_at(study))),
+            Sort::Oldest => studies.sort_by_key(|study| (acquired_at(study).is_none(), acquired_at(study))),
+            Sort::Name => studies.sort_by_key(|study| {
+                let name = study.patient.name.as_ref().map(|name| name.to_uppercase());
+                (name.is_none(), name)
+            }),
+        }
+    }
+}
+
+/// The attributes the search page asks for beyond those QIDO-RS returns
+/// anyway: the columns of the table and the priority of the request.
+pub const INCLUDE_FIELDS: &str = "includefield=StudyDescription&includefield=SourceApplicationEntityTitle\
+                                  &includefield=RequestedProcedurePriority&includefield=ScheduledProcedureStepPriority";
+
+/// The QIDO matching keys of a search of the archive, before the
+/// [`INCLUDE_FIELDS`].
+pub fn study_query(period: &Period, modalities: &[String]) -> String {
+    let modalities: String = modalities.iter().map(|modality| format!("&ModalitiesInStudy={}", modality)).collect();
+    format!("{}{}", period.query(), modalities)
+}
+
+/// The studies of `fetched` that are not among `loaded`, i.e. that arrived
+/// since the last search. Studies without a UID cannot be told apart and
+/// never count as new.
+pub fn arrived<'a>(loaded: &[Study], fetched: &'a [Study]) -> Vec<&'a Study> {
+    let known: HashSet<&str> = loaded.iter().filter_map(|study| study.study_uid.as_deref()).collect();
+    fetched
+        .iter()
+        .filter(|study| study.study_uid.as_deref().is_some_and(|uid| !known.contains(uid)))
+        .collect()
+}
+
+/// The dates of a saved search: a preset is worked out anew each time it is
+/// used, so that "3D" stays the last three days.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Dates {
+    Preset(String),
+    Fixed { start: NaiveDate, end: NaiveDate },
+}
+
+impl Dates {
+    pub fn of(period: &Period, preset: Option<Preset>) -> Self {
+        match preset {
+            Some(preset) => Dates::Preset(preset.spec()),
+            None => Dates::Fixed {
+                start: period.start_date(),
+                end: period.end_date(),
+            },
+        }
+    }
+
+    pub fn preset(&self) -> Option<Preset> {
+        match self {
+            Dates::Preset(spec) => Preset::parse(spec),
+            Dates::Fixed { .. } => None,
+        }
+    }
+
+    /// The period as of `now`; a preset that no longer parses means today.
+    pub fn period(&self, now: NaiveDateTime) -> Period {
+        match self {
+            Dates::Preset(_) => self.preset().unwrap_or(Preset::Today).period(now),
+            Dates::Fixed { start, end } => Period::days(*start, *end),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedSearch {
+    pub name: String,
+    pub dates: Dates,
+    #[serde(default)]
+    pub modalities: Vec<String>,
+    #[serde(default)]
+    pub filters: TextFilters,
+    #[serde(default)]
+    pub sort: Sort,
+}
+
+impl SavedSearch {
+    pub fn query(&self, now: NaiveDateTime) -> String {
+        study_query(&self.dates.period(now), &self.modalities)
+    }
+
+    /// How many of the studies the archive found for [`SavedSearch::query`]
+    /// the search shows.
+    pub fn count(&self, studies: &[Study], now: NaiveDateTime) -> usize {
+        let period = self.dates.period(now);
+        studies
+            .iter()
+            .filter(|study| period.contains(study) && self.filters.matches(study))
+            .count()
+    }
+}
+
+/// The saved searches of a user, in the order they were first saved.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SavedSearches {
+    #[serde(default)]
+    pub searches: Vec<SavedSearch>,
+    /// The name of the search applied when the user logs in.
+    #[serde(default)]
+    pub default: Option<String>,
+}
+
+impl SavedSearches {
+    fn position(&self, name: &str) -> Option<usize> {
+        self.searches.iter().position(|search| search.name.eq_ignore_ascii_case(name.trim()))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&SavedSearch> {
+        self.position(name).map(|index| &self.searches[index])
+    }
+
+    /// Saves a search, replacing the one of the same name, ignoring case,
+    /// in its place.
+    pub fn save(&mut self, mut search: SavedSearch) {
+        search.name = search.name.trim().to_owned();
+        match self.position(&search.name) {
+            Some(index) => {
+                if self.default.as_deref().is_some_and(|default| default.eq_ignore_ascii_case(&search.name)) {
+                    self.default = Some(search.name.clone());
+                }
+                self.searches[index] = search;
+            }
+            None => self.searches.push(search),
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        if let Some(index) = self.position(name) {
+            let removed = self.searches.remove(index);
+            if self.default.as_deref() == Some(removed.name.as_str()) {
+                self.default = None;
+            }
+        }
+    }
+
+    /// Makes a saved search the default, or none with `None`.
+    pub fn set_default(&mut self, name: Option<&str>) {
+        self.default = name.and_then(|name| self.get(name)).map(|search| search.name.clone());
+    }
+
+    pub fn default_search(&self) -> Option<&SavedSearch> {
+        self.get(self.default.as_deref()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Priority;
+    use dicom::core::{DataElement, PrimitiveValue, VR};
+    use dicom::dictionary_std::tags;
+    use dicom::object::InMemDicomObject;
+
+    fn study(name: &str, modalities: &[&str], date: &str, time: &str) -> Study {
+        Study::from_dicom(&InMemDicomObject::from_element_iter([
+            DataElement::new(tags::PATIENT_NAME, VR::PN, name),
+            DataElement::new(tags::MODALITIES_IN_STUDY, VR::CS, PrimitiveValue::Strs(modalities.iter().map(|m| m.to_string()).collect())),
+            DataElement::new(tags::STUDY_DATE, VR::DA, date),
+            DataElement::new(tags::STUDY_TIME, VR::TM, time),
+        ]))
+    }
+
+    fn neuro() -> SavedSearch {
+        SavedSearch {
+            name: String::from("Neuro"),
+            dates: Dates::Preset(String::from("3d")),
+            modalities: vec![String::from("CT"), String::from("MR")],
+            filters: TextFilters {
+                description: String::from("brain"),
+                ..TextFilters::default()
+            },
+            sort: Sort::Newest,
+        }
+    }
+
+    #[test]
+    fn results_are_sorted() {
+        let mut studies = vec![
+            study("KHAN^AYESHA", &["CT"], "20230724", "090000"),
+            study("", &["CT"], "", ""),
+            study("ALI^SARA", &["MR"], "20230725", "080000"),
+        ];
+        Sort::Newest.apply(&mut studies);
+        assert_eq!(studies[0].patient.name.as_deref(), Some("ALI SARA"));
+        assert!(studies[2].date.is_none());
+        Sort::Name.apply(&mut studies);
+        assert_eq!(studies[0].patient.name.as_deref(), Some("ALI SARA"));
+        assert!(studies[2].patient.name.is_none());
+        studies[1].priority = Some(Priority::Stat);
+        studies[2].priority = Some(Priority::Low);
+        Sort::Priority.apply(&mut studies);
+        assert_eq!(studies.iter().map(|study| study.priority).collect::<Vec<_>>(), [Some(Priority::Stat), None, Some(Priority::Low)]);
+        assert_eq!(Sort::from_key(Sort::Oldest.key()), Some(Sort::Oldest));
+    }
+
+    #[test]
+    fn arrived_studies_are_told_apart_by_uid() {
+        let with_uid = |uid: &str| Study {
+            study_uid: Some(String::from(uid)),
+            ..study("KHAN^AYESHA", &["CT"], "20230724", "090000")
+        };
+        let loaded = [with_uid("1.2.1"), with_uid("1.2.2")];
+        let fetched = [with_uid("1.2.2"), with_uid("1.2.3"), study("ALI^SARA", &["MR"], "20230724", "100000")];
+        let arrived = arrived(&loaded, &fetched);
+        assert_eq!(arrived.len(), 1);
+        assert_eq!(arrived[0].study_uid.as_deref(), Some("1.2.3"));
+        assert!(super::arrived(&fetched, &loaded).iter().all(|study| study.study_uid.as_deref() == Some("1.2.1")));
+    }
+
+    #[test]
+    fn saved_searches_keep_relative_dates() {
+        let now = NaiveDateTime::parse_from_str("2023-07-26 09:30:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        let search = neuro();
+        assert_eq!(search.query(now), "StudyDate=20230723-20230726&ModalitiesInStudy=CT&ModalitiesInStudy=MR");
+        let mut found = [study("KHAN^AYESHA", &["CT"], "20230724", "090000"), study("ALI^SARA", &["MR"], "20230725", "080000")];
+        assert_eq!(search.count(&found, now), 0);
+        found[0] = Study {
+            description: Some(String::from("CT BRAIN")),
+            ..found[0].clone()
+        };
+        assert_eq!(search.count(&found, now), 1);
+
+        let fixed = Dates::of(&Period::days(now.date(), now.date()), None);
+        assert_eq!(fixed.period(now).query(), "StudyDate=20230726-20230726");
+        assert_eq!(Dates::of(&fixed.period(now), Some(Preset::Days(3))), search.dates);
+    }
+
+    #[test]
+    fn saving_replaces_by_name_and_keeps_the_default() {
+        let mut saved = SavedSearches::default();
+        saved.save(neuro());
+        saved.save(SavedSearch {
+            name: String::from("US only"),
+            dates: Dates::Preset(String::from("today")),
+            modalities: vec![String::from("US")],
+            filters: TextFilters::default(),
+            sort: Sort::Archive,
+        });
+        saved.set_default(Some("neuro"));
+        assert_eq!(saved.default.as_deref(), Some("Neuro"));
+        saved.save(SavedSearch {
+            name: String::from(" NEURO "),
+            ..neuro()
+        });
+        assert_eq!(saved.searches.len(), 2);
+        assert_eq!(saved.searches[0].name, "NEURO");
+        assert_eq!(saved.default_search().map(|search| search.name.as_str()), Some("NEURO"));
+        saved.set_default(Some("missing"));
+        assert!(saved.default.is_none());
+
+        saved.set_default(Some("US only"));
+        saved.remove("us ONLY");
+        assert!(saved.default.is_none() && saved.searches.len() == 1);
+
+        let json = serde_json::to_string(&saved).unwrap();
+        assert_eq!(serde_json::from_str::<SavedSearches>(&json).unwrap(), saved);
+        let old: SavedSearches = serde_json::from_str(r#"{"searches": [{"name": "Any", "dates": {"preset": "any"}}]}"#).unwrap();
+        assert_eq!(old.searches[0].sort, Sort::Priority);
+    }
+}
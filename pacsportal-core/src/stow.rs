@@ -0,0 +1,144 @@
+use dicom::object::{DefaultDicomObject, InMemDicomObject};
+
+/// Content type of the bodies built by [`stow_body`].
+pub const CONTENT_TYPE: &str = "multipart/related; type=\"application/dicom+json\"; boundary=myboundary";
+
+/// Wraps a dataset as a `multipart/related` STOW-RS body using the
+/// `myboundary` boundary.
+pub fn stow_body(object: InMemDicomObject) -> String {
+    let mut request_body = String::from("\r\n--myboundary");
+    request_body.push_str("\r\nContent-Type: application/dicom+json\r\n\r\n");
+    request_body.push('[');
+    request_body.push_str(&dicom_json::to_string(object).unwrap());
+    request_body.push(']');
+    request_body.push_str("\r\n--myboundary--");
+    request_body
+}
+
+/// A dataset received in a STOW-RS request.
+pub enum Part {
+    Json(InMemDicomObject),
+    File(Box<DefaultDicomObject>),
+}
+
+impl Part {
+    pub fn dataset(&self) -> &InMemDicomObject {
+        match self {
+            Part::Json(object) => object,
+            Part::File(file) => file,
+        }
+    }
+}
+
+/// The `boundary` parameter of a `multipart/related` content type.
+pub fn boundary(content_type: &str) -> Option<String> {
+    content_type.split(';').find_map(|parameter| {
+        let (name, value) = parameter.trim().split_once('=')?;
+        if name.trim().eq_ignore_ascii_case("boundary") {
+            Some(value.trim().trim_matches('"').to_owned())
+        } else {
+            None
+        }
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// Splits a multipart body into the content type and body of each part.
+pub fn split_parts<'a>(body: &'a [u8], boundary: &str) -> Vec<(String, &'a [u8])> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let mut parts = Vec::new();
+    let Some(first) = find(body, &delimiter) else {
+        return parts;
+    };
+    let mut rest = &body[first + delimiter.len()..];
+    while !rest.starts_with(b"--") {
+        let Some(end) = find(rest, &delimiter) else {
+            break;
+        };
+        let part = rest[..end].strip_prefix(b"\r\n").unwrap_or(&rest[..end]);
+        let part = part.strip_suffix(b"\r\n").unwrap_or(part);
+        let (headers, content) = match find(part, b"\r\n\r\n") {
+            Some(split) => (&part[..split], &part[split + 4..]),
+            None => (&part[..0], part),
+        };
+        let content_type = String::from_utf8_lossy(headers)
+            .lines()
+            .find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                name.trim().eq_ignore_ascii_case("content-type").then(|| value.trim().to_owned())
+            })
+            .unwrap_or_default();
+        parts.push((content_type, content));
+        rest = &rest[end + delimiter.len()..];
+    }
+    parts
+}
+
+fn parse_json(content: &[u8]) -> Result<Vec<Part>, String> {
+    let values: Vec<serde_json::Value> = serde_json::from_slice(content).map_err(|e| e.to_string())?;
+    values
+        .into_iter()
+        .map(|value| dicom_json::from_value(value).map(Part::Json).map_err(|e| e.to_string()))
+        .collect()
+}
+
+/// The datasets in a STOW-RS request body: DICOM JSON arrays or Part 10
+/// files, either alone or as the parts of a `multipart/related` body.
+pub fn parse(content_type: &str, body: &[u8]) -> Result<Vec<Part>, String> {
+    if content_type.starts_with("multipart/related") {
+        let boundary = boundary(content_type).ok_or_else(|| String::from("Missing multipart boundary"))?;
+        let mut datasets = Vec::new();
+        for (part_type, content) in split_parts(body, &boundary) {
+            datasets.extend(parse(&part_type, content)?);
+        }
+        Ok(datasets)
+    } else if content_type.starts_with("application/dicom+json") {
+        parse_json(body)
+    } else if content_type.starts_with("application/dicom") {
+        DefaultDicomObject::from_reader(body)
+            .map(|file| vec![Part::File(Box::new(file))])
+            .map_err(|e| e.to_string())
+    } else {
+        Err(format!("Unsupported content type {}", content_type))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom::core::{DataElement, VR};
+    use dicom::dictionary_std::tags;
+    use crate::attributes::text;
+
+    #[test]
+    fn parses_the_portal_stow_body() {
+        let sr = InMemDicomObject::from_element_iter([
+            DataElement::new(tags::SOP_INSTANCE_UID, VR::UI, "1.2.3.4"),
+            DataElement::new(tags::TEXT_VALUE, VR::UT, "No acute findings.\r\n--not a boundary"),
+        ]);
+        let parts = parse(CONTENT_TYPE, stow_body(sr).as_bytes()).unwrap();
+        assert_eq!(parts.len(), 1);
+        match &parts[0] {
+            Part::Json(object) => {
+                assert_eq!(text(object, tags::SOP_INSTANCE_UID).as_deref(), Some("1.2.3.4"));
+                assert_eq!(text(object, tags::TEXT_VALUE).as_deref(), Some("No acute findings.\r\n--not a boundary"));
+            }
+            Part::File(_) => panic!("expected a JSON part"),
+        }
+    }
+
+    #[test]
+    fn boundary_may_be_quoted() {
+        assert_eq!(boundary("multipart/related; type=\"application/dicom\"; boundary=\"abc\"").as_deref(), Some("abc"));
+        assert_eq!(boundary("multipart/related"), None);
+    }
+
+    #[test]
+    fn unsupported_parts_are_errors() {
+        let body = b"--b\r\nContent-Type: text/plain\r\n\r\nhello\r\n--b--";
+        assert!(parse("multipart/related; boundary=b", body).is_err());
+    }
+}
@@ -0,0 +1,270 @@
+//! Report turnaround: the time from acquiring a study to verifying its first
+//! report, summarised by modality, radiologist or day.
+
+use std::collections::BTreeMap;
+
+use chrono::NaiveDateTime;
+use dicom::dictionary_std::tags;
+use dicom::object::InMemDicomObject;
+
+use crate::attributes::{self, person_name, text};
+use crate::codes;
+use crate::critical::CRITICAL_ACKNOWLEDGEMENT;
+use crate::csv;
+use crate::model::Study;
+use crate::worklist::acquired_at;
+
+/// Modalities of documents rather than images, left out of a study's modality.
+const DOCUMENT_MODALITIES: [&str; 3] = ["SR", "KO", "PR"];
+
+/// One reported study.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Turnaround {
+    pub study_uid: String,
+    pub modality: String,
+    pub radiologist: String,
+    pub acquired_at: NaiveDateTime,
+    pub reported_at: NaiveDateTime,
+}
+
+impl Turnaround {
+    pub fn minutes(&self) -> i64 {
+        (self.reported_at - self.acquired_at).num_minutes()
+    }
+}
+
+/// When a report was signed: its Verification DateTime, or its Content Date
+/// and Time for reports stored without a verifying observer.
+pub fn reported_at(sr: &InMemDicomObject) -> Option<NaiveDateTime> {
+    let verified = verifying_observer(sr)
+        .and_then(|observer| text(observer, tags::VERIFICATION_DATE_TIME))
+        .and_then(|value| parse_date_time(&value));
+    verified.or_else(|| attributes::date_time(sr, tags::CONTENT_DATE, tags::CONTENT_TIME))
+}
+
+/// Who signed a report, falling back to its Content Creator.
+pub fn radiologist(sr: &InMemDicomObject) -> Option<String> {
+    verifying_observer(sr)
+        .and_then(|observer| person_name(observer, tags::VERIFYING_OBSERVER_NAME))
+        .or_else(|| person_name(sr, tags::CONTENT_CREATOR_NAME))
+}
+
+fn verifying_observer(sr: &InMemDicomObject) -> Option<&InMemDicomObject> {
+    sr.get(tags::VERIFYING_OBSERVER_SEQUENCE)?.items()?.first()
+}
+
+/// A DT value to the second; fractions and UTC offsets are ignored, as
+/// they are for the other local times of the archive.
+fn parse_date_time(value: &str) -> Option<NaiveDateTime> {
+    let digits: String = value.chars().take_while(|c| c.is_ascii_digit()).take(14).collect();
+    if digits.len() < 8 || !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    let padded = format!("{:0<14}", digits);
+    NaiveDateTime::parse_from_str(&padded, "%Y%m%d%H%M%S").ok()
+}
+
+/// Pairs every study with its first report. Acknowledgements of critical
+/// results are not reports, and studies reported before their acquisition
+/// time, i.e. with a wrong clock somewhere, are left out.
+pub fn turnarounds(studies: &[Study], reports: &[InMemDicomObject]) -> Vec<Turnaround> {
+    let mut first_reports: BTreeMap<String, (NaiveDateTime, String)> = BTreeMap::new();
+    for sr in reports {
+        if codes::has_title(sr, &CRITICAL_ACKNOWLEDGEMENT) {
+            continue;
+        }
+        let (Some(study_uid), Some(at)) = (text(sr, tags::STUDY_INSTANCE_UID), reported_at(sr)) else {
+            continue;
+        };
+        let radiologist = radiologist(sr).unwrap_or_else(|| String::from("Unknown"));
+        match first_reports.get(&study_uid) {
+            Some((earlier, _)) if *earlier <= at => {}
+            _ => {
+                first_reports.insert(study_uid, (at, radiologist));
+            }
+        }
+    }
+
+    studies
+        .iter()
+        .filter_map(|study| {
+            let study_uid = study.study_uid.clone()?;
+            let acquired_at = acquired_at(study)?;
+            let (reported_at, radiologist) = first_reports.get(&study_uid)?.clone();
+            if reported_at < acquired_at {
+                return None;
+            }
+            let modalities: Vec<&str> = study
+                .modalities
+                .iter()
+                .map(|modality| modality.as_str())
+                .filter(|modality| !DOCUMENT_MODALITIES.contains(modality))
+                .collect();
+            let modality = if modalities.is_empty() { String::from("Unknown") } else { modalities.join(", ") };
+            Some(Turnaround {
+                study_uid,
+                modality,
+                radiologist,
+                acquired_at,
+                reported_at,
+            })
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Grouping {
+    Modality,
+    Radiologist,
+    Day,
+}
+
+impl Grouping {
+    pub const ALL: [Grouping; 3] = [Grouping::Modality, Grouping::Radiologist, Grouping::Day];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Grouping::Modality => "Modality",
+            Grouping::Radiologist => "Radiologist",
+            Grouping::Day => "Day",
+        }
+    }
+
+    /// The group of a report; days are those the report was signed on.
+    pub fn key(&self, turnaround: &Turnaround) -> String {
+        match self {
+            Grouping::Modality => turnaround.modality.clone(),
+            Grouping::Radiologist => turnaround.radiologist.clone(),
+            Grouping::Day => turnaround.reported_at.format("%Y-%m-%d").to_string(),
+        }
+    }
+}
+
+/// Turnaround of one group, in minutes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub key: String,
+    pub count: usize,
+    pub median: i64,
+    pub p90: i64,
+}
+
+/// The nearest-rank percentile of sorted values, so always one of them.
+fn percentile(sorted: &[i64], percent: usize) -> i64 {
+    let rank = (sorted.len() * percent).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+/// The median and 90th percentile per group, ordered by group.
+pub fn summarize(turnarounds: &[Turnaround], grouping: Grouping) -> Vec<Summary> {
+    let mut groups: BTreeMap<String, Vec<i64>> = BTreeMap::new();
+    for turnaround in turnarounds {
+        groups.entry(grouping.key(turnaround)).or_default().push(turnaround.minutes());
+    }
+    groups
+        .into_iter()
+        .map(|(key, mut minutes)| {
+            minutes.sort_unstable();
+            Summary {
+                key,
+                count: minutes.len(),
+                median: percentile(&minutes, 50),
+                p90: percentile(&minutes, 90),
+            }
+        })
+        .collect()
+}
+
+/// Every grouping's summaries in one sheet.
+pub fn csv(turnarounds: &[Turnaround]) -> String {
+    let mut sheet = csv::row(["Grouping", "Group", "Reports", "Median (minutes)", "90th percentile (minutes)"]);
+    for grouping in Grouping::ALL {
+        for summary in summarize(turnarounds, grouping) {
+            sheet.push_str(&csv::row([
+                grouping.label().to_owned(),
+                summary.key,
+                summary.count.to_string(),
+                summary.median.to_string(),
+                summary.p90.to_string(),
+            ]));
+        }
+    }
+    sheet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom::core::smallvec::smallvec;
+    use dicom::core::value::DataSetSequence;
+    use dicom::core::{DataElement, DicomValue, Length, PrimitiveValue, VR};
+
+    fn study(uid: &str, modalities: &[&str], date: &str, time: &str) -> Study {
+        Study::from_dicom(&InMemDicomObject::from_element_iter([
+            DataElement::new(tags::STUDY_INSTANCE_UID, VR::UI, uid),
+            DataElement::new(tags::MODALITIES_IN_STUDY, VR::CS, PrimitiveValue::Strs(modalities.iter().map(|m| m.to_string()).collect())),
+            DataElement::new(tags::STUDY_DATE, VR::DA, date),
+            DataElement::new(tags::STUDY_TIME, VR::TM, time),
+        ]))
+    }
+
+    fn report(study_uid: &str, content: &str, verified: Option<(&str, &str)>) -> InMemDicomObject {
+        let mut sr = InMemDicomObject::from_element_iter([
+            DataElement::new(tags::STUDY_INSTANCE_UID, VR::UI, study_uid),
+            DataElement::new(tags::CONTENT_DATE, VR::DA, &content[..8]),
+            DataElement::new(tags::CONTENT_TIME, VR::TM, &content[8..]),
+            DataElement::new(tags::CONTENT_CREATOR_NAME, VR::PN, "CREATOR^ANNA"),
+        ]);
+        if let Some((at, name)) = verified {
+            let observer = InMemDicomObject::from_element_iter([
+                DataElement::new(tags::VERIFICATION_DATE_TIME, VR::DT, at),
+                DataElement::new(tags::VERIFYING_OBSERVER_NAME, VR::PN, name),
+            ]);
+            sr.put(DataElement::new(
+                tags::VERIFYING_OBSERVER_SEQUENCE,
+                VR::SQ,
+                DicomValue::Sequence(DataSetSequence::new(smallvec![observer], Length::UNDEFINED)),
+            ));
+        }
+        sr
+    }
+
+    #[test]
+    fn first_report_of_each_study() {
+        let studies = [
+            study("1", &["CT", "SR"], "20230724", "080000"),
+            study("2", &["MR"], "20230724", "090000"),
+            study("3", &["CR"], "20230724", "100000"),
+            study("4", &["US"], "20230724", "120000"),
+        ];
+        let reports = [
+            report("1", "20230724100000", Some(("20230724093000.000+0500", "JILANI^WASAY"))),
+            report("1", "20230725100000", Some(("20230725100000", "JILANI^WASAY"))),
+            report("2", "20230724110000", None),
+            report("4", "20230724110000", None),
+        ];
+        let found = turnarounds(&studies, &reports);
+        assert_eq!(found.len(), 2);
+        assert_eq!((found[0].modality.as_str(), found[0].radiologist.as_str(), found[0].minutes()), ("CT", "JILANI WASAY", 90));
+        assert_eq!((found[1].modality.as_str(), found[1].radiologist.as_str(), found[1].minutes()), ("MR", "CREATOR ANNA", 120));
+    }
+
+    #[test]
+    fn median_and_90th_percentile() {
+        let at = chrono::NaiveDate::from_ymd_opt(2023, 7, 24).unwrap().and_hms_opt(8, 0, 0).unwrap();
+        let turnarounds: Vec<Turnaround> = (1..=10)
+            .map(|hours| Turnaround {
+                study_uid: hours.to_string(),
+                modality: String::from(if hours % 2 == 0 { "CT" } else { "MR" }),
+                radiologist: String::from("JILANI WASAY"),
+                acquired_at: at,
+                reported_at: at + chrono::Duration::hours(hours),
+            })
+            .collect();
+        let all = summarize(&turnarounds, Grouping::Radiologist);
+        assert_eq!(all, [Summary { key: String::from("JILANI WASAY"), count: 10, median: 300, p90: 540 }]);
+        let by_modality = summarize(&turnarounds, Grouping::Modality);
+        assert_eq!((by_modality[0].key.as_str(), by_modality[0].median, by_modality[0].p90), ("CT", 360, 600));
+        assert!(csv(&turnarounds).contains("Day,2023-07-24,10,300,540\r\n"));
+    }
+}
@@ -0,0 +1,98 @@
+//! The reading worklist: studies without a report, the most urgent and the
+//! longest waiting first.
+
+use chrono::{NaiveDateTime, NaiveTime};
+
+use crate::model::Study;
+
+/// Whether the study has a report. Reports are stored into the study they
+/// report on, so its modalities then include SR.
+pub fn is_reported(study: &Study) -> bool {
+    study.modalities.iter().any(|modality| modality == "SR")
+}
+
+/// When the study was acquired; a missing time counts as midnight.
+pub fn acquired_at(study: &Study) -> Option<NaiveDateTime> {
+    Some(study.date?.and_time(study.time.unwrap_or(NaiveTime::MIN)))
+}
+
+/// The unreported studies, ordered by priority (unknown counting as routine)
+/// and then by age, oldest first. Studies without a date go last.
+pub fn worklist(studies: &[Study]) -> Vec<Study> {
+    let mut unreported: Vec<Study> = studies
+        .iter()
+        .filter(|study| !is_reported(study) && study.study_uid.is_some())
+        .cloned()
+        .collect();
+    unreported.sort_by_key(|study| {
+        (
+            study.urgency(),
+            acquired_at(study).is_none(),
+            acquired_at(study),
+        )
+    });
+    unreported
+}
+
+/// How long a study has waited, e.g. `2d 4h`, `3h 05m` or `12m`.
+pub fn waiting_label(acquired_at: NaiveDateTime, now: NaiveDateTime) -> String {
+    duration_label((now - acquired_at).num_minutes())
+}
+
+/// A number of minutes as days and hours, hours and minutes or minutes.
+pub fn duration_label(minutes: i64) -> String {
+    let minutes = minutes.max(0);
+    let (days, hours, minutes) = (minutes / (24 * 60), minutes / 60 % 24, minutes % 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {:02}m", hours, minutes)
+    } else {
+        format!("{}m", minutes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use dicom::core::{DataElement, PrimitiveValue, VR};
+    use dicom::dictionary_std::tags;
+    use dicom::object::InMemDicomObject;
+
+    fn study(uid: &str, modalities: &[&str], date: &str, time: &str, priority: Option<&str>) -> Study {
+        let mut object = InMemDicomObject::from_element_iter([
+            DataElement::new(tags::STUDY_INSTANCE_UID, VR::UI, uid),
+            DataElement::new(tags::MODALITIES_IN_STUDY, VR::CS, PrimitiveValue::Strs(modalities.iter().map(|m| m.to_string()).collect())),
+            DataElement::new(tags::STUDY_DATE, VR::DA, date),
+            DataElement::new(tags::STUDY_TIME, VR::TM, time),
+        ]);
+        if let Some(priority) = priority {
+            object.put(DataElement::new(tags::REQUESTED_PROCEDURE_PRIORITY, VR::CS, priority));
+        }
+        Study::from_dicom(&object)
+    }
+
+    #[test]
+    fn unreported_studies_by_priority_then_age() {
+        let studies = [
+            study("1", &["CT"], "20230725", "090000", None),
+            study("2", &["CT", "SR"], "20230724", "080000", Some("STAT")),
+            study("3", &["MR"], "20230725", "100000", Some("STAT")),
+            study("4", &["CR"], "20230724", "230000", None),
+            study("5", &["US"], "", "", Some("ROUTINE")),
+            study("6", &["CR"], "20230723", "070000", Some("LOW")),
+        ];
+        let order: Vec<String> = worklist(&studies).into_iter().filter_map(|study| study.study_uid).collect();
+        assert_eq!(order, ["3", "4", "1", "5", "6"]);
+    }
+
+    #[test]
+    fn waiting_labels() {
+        let at = NaiveDate::from_ymd_opt(2023, 7, 24).unwrap().and_hms_opt(9, 0, 0).unwrap();
+        assert_eq!(waiting_label(at, at + chrono::Duration::minutes(12)), "12m");
+        assert_eq!(waiting_label(at, at + chrono::Duration::minutes(185)), "3h 05m");
+        assert_eq!(waiting_label(at, at + chrono::Duration::hours(52)), "2d 4h");
+        assert_eq!(waiting_label(at, at - chrono::Duration::hours(1)), "0m");
+    }
+}
@@ -0,0 +1,293 @@
+//! A stand-in for the hospital archive during development. Serves the
+//! QIDO-RS, WADO-RS and STOW-RS requests the portal and its server make from an in-memory
+//! store, seeded either from a directory of DICOM files or with synthetic
+//! patients.
+//!
+//! ```sh
+//! cargo run -p pacsportal-mock
+//! PACSPORTAL_RS_BASE=/dicomweb trunk serve
+//! ```
+//!
+//! Trunk proxies `/dicomweb` to the mock, see `Trunk.toml`. Without the
+//! proxy, build with `PACSPORTAL_RS_BASE=http://127.0.0.1:8042/dicomweb`.
+//!
+//! Every DICOMweb request is recorded so that the browser tests can check
+//! what the portal sent: `GET {prefix}/mock/requests` lists them and
+//! `DELETE {prefix}/mock/requests` clears the list.
+
+mod qido;
+mod store;
+mod synthetic;
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+
+use axum::body::{Body, Bytes};
+use axum::extract::{self, Path, State};
+use axum::http::{header, HeaderMap, Request, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use chrono::Local;
+use clap::Parser;
+use dicom::core::{DataElement, DicomValue, VR};
+use dicom::dictionary_std::{tags, uids};
+use dicom::object::{FileMetaTableBuilder, InMemDicomObject};
+use pacsportal_core::attributes::text;
+use pacsportal_core::stow;
+use tower_http::cors::CorsLayer;
+
+use crate::qido::Query;
+use crate::store::Store;
+
+type SharedStore = Arc<RwLock<Store>>;
+type RequestLog = Arc<Mutex<Vec<serde_json::Value>>>;
+type Params = extract::Query<Vec<(String, String)>>;
+
+#[derive(Parser)]
+#[command(about = "Mock DICOMweb archive for developing and testing the portal")]
+struct Args {
+    /// Address to listen on.
+    #[arg(long, default_value = "127.0.0.1:8042")]
+    listen: SocketAddr,
+    /// Path the DICOMweb endpoints are served under; matches the
+    /// PACSPORTAL_RS_BASE the portal is built with.
+    #[arg(long, default_value = "/dicomweb")]
+    prefix: String,
+    /// Directory of DICOM files to serve instead of synthetic patients.
+    #[arg(long)]
+    dir: Option<PathBuf>,
+    /// Number of synthetic patients to generate.
+    #[arg(long, default_value_t = 20)]
+    patients: usize,
+}
+
+fn dicom_json(objects: Vec<InMemDicomObject>) -> Response {
+    if objects.is_empty() {
+        return StatusCode::NO_CONTENT.into_response();
+    }
+    match dicom_json::to_string(objects) {
+        Ok(body) => ([(header::CONTENT_TYPE, "application/dicom+json")], body).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+fn search(params: &[(String, String)], records: Vec<InMemDicomObject>) -> Response {
+    match Query::parse(params) {
+        Ok(query) => dicom_json(query.apply(records)),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn search_studies(State(store): State<SharedStore>, extract::Query(params): Params) -> Response {
+    let studies = store.read().unwrap().studies();
+    search(&params, studies)
+}
+
+async fn search_series(State(store): State<SharedStore>, Path(study): Path<String>, extract::Query(params): Params) -> Response {
+    let series = store.read().unwrap().series(&study);
+    search(&params, series)
+}
+
+async fn search_instances(State(store): State<SharedStore>, extract::Query(params): Params) -> Response {
+    let instances = store.read().unwrap().instances(None, None);
+    search(&params, instances)
+}
+
+async fn search_study_instances(
+    State(store): State<SharedStore>,
+    Path(study): Path<String>,
+    extract::Query(params): Params,
+) -> Response {
+    let instances = store.read().unwrap().instances(Some(&study), None);
+    search(&params, instances)
+}
+
+async fn search_series_instances(
+    State(store): State<SharedStore>,
+    Path((study, series)): Path<(String, String)>,
+    extract::Query(params): Params,
+) -> Response {
+    let instances = store.read().unwrap().instances(Some(&study), Some(&series));
+    search(&params, instances)
+}
+
+async fn metadata(State(store): State<SharedStore>, Path((study, series, instance)): Path<(String, String, String)>) -> Response {
+    match store.read().unwrap().instance(&study, &series, &instance) {
+        Some(instance) => dicom_json(vec![instance.clone()]),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// An instance as a Part 10 file in a `multipart/related` body. The store
+/// keeps no pixel data, so neither do the files.
+async fn retrieve(State(store): State<SharedStore>, Path((study, series, instance)): Path<(String, String, String)>) -> Response {
+    let Some(instance) = store.read().unwrap().instance(&study, &series, &instance).cloned() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let meta = FileMetaTableBuilder::new().transfer_syntax(uids::EXPLICIT_VR_LITTLE_ENDIAN);
+    let mut file = Vec::new();
+    if let Err(e) = instance.with_meta(meta).map_err(|e| e.to_string()).and_then(|object| object.write_all(&mut file).map_err(|e| e.to_string())) {
+        return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+    }
+    let mut body = b"--instance\r\nContent-Type: application/dicom\r\n\r\n".to_vec();
+    body.extend_from_slice(&file);
+    body.extend_from_slice(b"\r\n--instance--");
+    ([(header::CONTENT_TYPE, "multipart/related; type=\"application/dicom\"; boundary=instance")], body).into_response()
+}
+
+/// A placeholder in place of the rendered image; the store keeps no pixel data.
+async fn rendered(State(store): State<SharedStore>, Path((study, series, instance)): Path<(String, String, String)>) -> Response {
+    let store = store.read().unwrap();
+    let Some(instance) = store.instance(&study, &series, &instance) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let label = format!(
+        "{} {} #{}",
+        text(instance, tags::MODALITY).unwrap_or_default(),
+        text(instance, tags::SERIES_DESCRIPTION).unwrap_or_default(),
+        text(instance, tags::INSTANCE_NUMBER).unwrap_or_default(),
+    )
+    .replace('&', "&amp;")
+    .replace('<', "&lt;");
+    let svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"256\" height=\"256\">\
+         <rect width=\"256\" height=\"256\" fill=\"#000\"/>\
+         <circle cx=\"128\" cy=\"112\" r=\"64\" fill=\"#444\"/>\
+         <text x=\"128\" y=\"224\" fill=\"#ffd400\" font-family=\"sans-serif\" font-size=\"18\" text-anchor=\"middle\">{}</text>\
+         </svg>",
+        label
+    );
+    ([(header::CONTENT_TYPE, "image/svg+xml")], svg).into_response()
+}
+
+/// Stores the datasets of a STOW-RS request. Responds like an archive would:
+/// 200 when all were stored, 202 when some were and 409 when none were.
+async fn store_instances(State(store): State<SharedStore>, headers: HeaderMap, body: Bytes) -> Response {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let parts = match stow::parse(content_type, &body) {
+        Ok(parts) => parts,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    let mut store = store.write().unwrap();
+    let (mut stored, mut failed) = (Vec::new(), 0);
+    for part in parts {
+        let result = match part {
+            stow::Part::Json(object) => store.insert(object),
+            stow::Part::File(file) => store.insert_file(*file),
+        };
+        match result {
+            Ok(sop_uid) => stored.push(sop_uid),
+            Err(e) => {
+                eprintln!("Rejected instance: {}", e);
+                failed += 1;
+            }
+        }
+    }
+    let status = match (stored.is_empty(), failed) {
+        (true, _) => StatusCode::CONFLICT,
+        (false, 0) => StatusCode::OK,
+        (false, _) => StatusCode::ACCEPTED,
+    };
+    let referenced = stored.into_iter().map(|sop_uid| {
+        InMemDicomObject::from_element_iter([DataElement::new(tags::REFERENCED_SOP_INSTANCE_UID, VR::UI, sop_uid)])
+    });
+    let response = InMemDicomObject::from_element_iter([DataElement::new(
+        tags::REFERENCED_SOP_SEQUENCE,
+        VR::SQ,
+        DicomValue::new_sequence(referenced.collect::<Vec<_>>(), dicom::core::Length::UNDEFINED),
+    )]);
+    match dicom_json::to_string(response) {
+        Ok(body) => (status, [(header::CONTENT_TYPE, "application/dicom+json")], body).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// Records the method, path, query parameters and content type of a request.
+async fn record(State(log): State<RequestLog>, request: Request<Body>, next: Next<Body>) -> Response {
+    let params = extract::Query::<Vec<(String, String)>>::try_from_uri(request.uri())
+        .map(|extract::Query(params)| params)
+        .unwrap_or_default();
+    let content_type = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let entry = serde_json::json!({
+        "method": request.method().as_str(),
+        "path": request.uri().path(),
+        "params": params,
+        "contentType": content_type,
+    });
+    log.lock().unwrap().push(entry);
+    next.run(request).await
+}
+
+async fn recorded_requests(State(log): State<RequestLog>) -> Json<Vec<serde_json::Value>> {
+    Json(log.lock().unwrap().clone())
+}
+
+async fn clear_requests(State(log): State<RequestLog>) -> StatusCode {
+    log.lock().unwrap().clear();
+    StatusCode::NO_CONTENT
+}
+
+fn routes(store: SharedStore, log: RequestLog) -> Router {
+    let recorder = Router::new()
+        .route("/mock/requests", get(recorded_requests).delete(clear_requests))
+        .with_state(log.clone());
+    Router::new()
+        .route("/studies", get(search_studies).post(store_instances))
+        .route("/studies/:study/series", get(search_series))
+        .route("/studies/:study/instances", get(search_study_instances))
+        .route("/studies/:study/series/:series/instances", get(search_series_instances))
+        .route("/studies/:study/series/:series/instances/:instance", get(retrieve))
+        .route("/studies/:study/series/:series/instances/:instance/metadata", get(metadata))
+        .route("/studies/:study/series/:series/instances/:instance/rendered", get(rendered))
+        .route("/instances", get(search_instances))
+        .with_state(store)
+        .layer(middleware::from_fn_with_state(log, record))
+        .merge(recorder)
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+
+    let mut store = Store::default();
+    match &args.dir {
+        Some(dir) => {
+            let (loaded, skipped) = store.load_dir(dir);
+            println!("Loaded {} instances from {} ({} files skipped)", loaded, dir.display(), skipped);
+            if store.is_empty() {
+                eprintln!("No DICOM files were found; every search will come back empty.");
+            }
+        }
+        None => {
+            synthetic::seed(&mut store, args.patients, Local::now().date_naive());
+            println!("Generated {} synthetic patients ({} instances)", args.patients, store.len());
+        }
+    }
+
+    let store = Arc::new(RwLock::new(store));
+    let log = RequestLog::default();
+    let prefix = args.prefix.trim_end_matches('/');
+    let app = if prefix.is_empty() {
+        routes(store, log)
+    } else {
+        Router::new().nest(prefix, routes(store, log))
+    };
+    // the portal may be served from another origin when not going through the Trunk proxy
+    let app = app.layer(CorsLayer::permissive());
+
+    println!("Serving DICOMweb on http://{}{}", args.listen, prefix);
+    axum::Server::bind(&args.listen)
+        .serve(app.into_make_service())
+        .await
+        .expect("server error");
+}
@@ -0,0 +1,233 @@
+use dicom::core::dictionary::DataDictionary;
+use dicom::core::{Tag, VR};
+use dicom::dictionary_std::StandardDataDictionary;
+use dicom::object::InMemDicomObject;
+
+/// Query parameters which shape the response instead of matching attributes.
+const CONTROL_KEYS: [&str; 5] = ["includefield", "limit", "offset", "fuzzymatching", "orderby"];
+
+/// A single attribute filter, e.g. `StudyDate=20230701-20230731` or
+/// `ConceptNameCodeSequence.CodeValue=CRIT001`. A record matches when any of
+/// `values` matches.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    pub path: Vec<Tag>, // enclosing sequences, then the attribute itself
+    pub values: Vec<String>,
+}
+
+/// The subset of QIDO-RS query parameters the portal sends.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Query {
+    pub filters: Vec<Filter>,
+    pub limit: Option<usize>,
+    pub offset: usize,
+}
+
+/// A keyword such as `PatientID` or a tag in `ggggeeee` form.
+fn parse_tag(key: &str) -> Option<Tag> {
+    if key.len() == 8 && key.chars().all(|c| c.is_ascii_hexdigit()) {
+        let group = u16::from_str_radix(&key[..4], 16).ok()?;
+        let element = u16::from_str_radix(&key[4..], 16).ok()?;
+        return Some(Tag(group, element));
+    }
+    StandardDataDictionary.parse_tag(key)
+}
+
+impl Query {
+    pub fn parse(params: &[(String, String)]) -> Result<Query, String> {
+        let mut query = Query::default();
+        for (key, value) in params {
+            match key.as_str() {
+                "limit" => query.limit = Some(value.parse().map_err(|_| format!("Invalid limit {}", value))?),
+                "offset" => query.offset = value.parse().map_err(|_| format!("Invalid offset {}", value))?,
+                key if CONTROL_KEYS.contains(&key) => {}
+                key => {
+                    let path = key
+                        .split('.')
+                        .map(|part| parse_tag(part).ok_or_else(|| format!("Unknown attribute {}", part)))
+                        .collect::<Result<Vec<Tag>, String>>()?;
+                    // repeated keys, e.g. ModalitiesInStudy=CT&ModalitiesInStudy=MR, widen the match
+                    match query.filters.iter_mut().find(|filter| filter.path == path) {
+                        Some(filter) => filter.values.push(value.clone()),
+                        None => query.filters.push(Filter { path, values: vec![value.clone()] }),
+                    }
+                }
+            }
+        }
+        Ok(query)
+    }
+
+    pub fn matches(&self, record: &InMemDicomObject) -> bool {
+        self.filters.iter().all(|filter| matches_path(record, &filter.path, &filter.values))
+    }
+
+    /// The matching records, paged by `offset` and `limit`.
+    pub fn apply(&self, records: Vec<InMemDicomObject>) -> Vec<InMemDicomObject> {
+        records
+            .into_iter()
+            .filter(|record| self.matches(record))
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+}
+
+fn matches_path(record: &InMemDicomObject, path: &[Tag], values: &[String]) -> bool {
+    let Some(element) = record.get(path[0]) else {
+        return false;
+    };
+    if path.len() > 1 {
+        // sequence matching: any item may match the rest of the path
+        return element
+            .items()
+            .map(|items| items.iter().any(|item| matches_path(item, &path[1..], values)))
+            .unwrap_or(false);
+    }
+    if values.iter().all(|value| value.is_empty()) {
+        return true;
+    }
+    let vr = element.vr();
+    let actual: Vec<String> = element
+        .to_multi_str()
+        .map(|actual| actual.iter().map(|value| value.trim_end_matches('\0').trim().to_owned()).collect())
+        .unwrap_or_default();
+    values.iter().any(|value| {
+        // UID lists and the like are comma separated; names may contain commas
+        let alternatives: Vec<&str> = if vr == VR::PN { vec![value.as_str()] } else { value.split(',').collect() };
+        alternatives
+            .iter()
+            .any(|wanted| actual.iter().any(|actual| matches_value(vr, wanted, actual)))
+    })
+}
+
+fn matches_value(vr: VR, wanted: &str, actual: &str) -> bool {
+    if matches!(vr, VR::DA | VR::TM | VR::DT) {
+        if let Some((from, to)) = wanted.split_once('-') {
+            let actual = actual.replace(':', "");
+            return (from.is_empty() || prefix(&actual, from) >= from) && (to.is_empty() || prefix(&actual, to) <= to);
+        }
+        return actual.replace(':', "").starts_with(wanted);
+    }
+    if vr == VR::PN {
+        return glob(&wanted.to_uppercase(), &actual.to_uppercase());
+    }
+    glob(wanted, actual)
+}
+
+/// `value` cut to the length of `bound`, so that times with and without
+/// fractional seconds compare sensibly.
+fn prefix<'a>(value: &'a str, bound: &str) -> &'a str {
+    value.get(..bound.len()).unwrap_or(value)
+}
+
+/// Matching with the `*` and `?` wildcards.
+fn glob(pattern: &str, value: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let value: Vec<char> = value.chars().collect();
+    let (mut p, mut v) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while v < value.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == value[v]) {
+            p += 1;
+            v += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, v));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            v = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom::core::{dicom_value, smallvec::smallvec, value::DataSetSequence, DataElement, DicomValue, Length};
+    use dicom::dictionary_std::tags;
+
+    fn params(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    fn study() -> InMemDicomObject {
+        InMemDicomObject::from_element_iter([
+            DataElement::new(tags::PATIENT_NAME, VR::PN, "Khan^Ayesha"),
+            DataElement::new(tags::PATIENT_ID, VR::LO, "MOCK0001"),
+            DataElement::new(tags::STUDY_DATE, VR::DA, "20230724"),
+            DataElement::new(tags::STUDY_TIME, VR::TM, "090543.953"),
+            DataElement::new(tags::STUDY_INSTANCE_UID, VR::UI, "1.2.3"),
+            DataElement::new(tags::MODALITIES_IN_STUDY, VR::CS, dicom_value!(Strs, ["CT", "SR"])),
+            DataElement::new(
+                tags::CONCEPT_NAME_CODE_SEQUENCE,
+                VR::SQ,
+                DicomValue::Sequence(DataSetSequence::new(
+                    smallvec![InMemDicomObject::from_element_iter([
+                        DataElement::new(tags::CODE_VALUE, VR::SH, "CRIT001"),
+                        DataElement::new(tags::CODING_SCHEME_DESIGNATOR, VR::SH, "99SCH"),
+                    ])],
+                    Length::UNDEFINED,
+                )),
+            ),
+        ])
+    }
+
+    fn matches(pairs: &[(&str, &str)]) -> bool {
+        Query::parse(&params(pairs)).unwrap().matches(&study())
+    }
+
+    #[test]
+    fn date_and_time_ranges() {
+        assert!(matches(&[("StudyDate", "20230701-20230731")]));
+        assert!(matches(&[("StudyDate", "20230724")]));
+        assert!(matches(&[("StudyDate", "-20230724")]));
+        assert!(!matches(&[("StudyDate", "20230725-")]));
+        assert!(matches(&[("StudyTime", "080000-090543")]));
+        assert!(!matches(&[("StudyTime", "100000-235959")]));
+    }
+
+    #[test]
+    fn wildcards_and_case_insensitive_names() {
+        assert!(matches(&[("PatientName", "KHAN*")]));
+        assert!(matches(&[("PatientName", "*ayes?a")]));
+        assert!(!matches(&[("PatientName", "Jilani*")]));
+        assert!(matches(&[("PatientID", "MOCK*")]));
+        assert!(!matches(&[("PatientID", "mock0001")]));
+    }
+
+    #[test]
+    fn multiple_values_and_lists() {
+        assert!(matches(&[("ModalitiesInStudy", "MR"), ("ModalitiesInStudy", "CT")]));
+        assert!(!matches(&[("ModalitiesInStudy", "MR")]));
+        assert!(matches(&[("StudyInstanceUID", "1.2.4,1.2.3")]));
+        assert!(matches(&[("0020000D", "1.2.3")]));
+    }
+
+    #[test]
+    fn sequence_matching() {
+        assert!(matches(&[
+            ("ConceptNameCodeSequence.CodeValue", "CRIT001"),
+            ("ConceptNameCodeSequence.CodingSchemeDesignator", "99SCH"),
+        ]));
+        assert!(!matches(&[("ConceptNameCodeSequence.CodeValue", "CRIT002")]));
+    }
+
+    #[test]
+    fn missing_attributes_do_not_match_but_empty_values_do() {
+        assert!(!matches(&[("AccessionNumber", "A100")]));
+        assert!(matches(&[("PatientID", "")]));
+    }
+
+    #[test]
+    fn control_keys_are_not_filters() {
+        let query = Query::parse(&params(&[("includefield", "StudyDescription"), ("limit", "5"), ("offset", "10")])).unwrap();
+        assert!(query.filters.is_empty());
+        assert_eq!(query.limit, Some(5));
+        assert_eq!(query.offset, 10);
+        assert!(Query::parse(&params(&[("NotAnAttribute", "1")])).is_err());
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use dicom::core::{dicom_value, DataElement, PrimitiveValue, Tag, VR};
+use dicom::dictionary_std::tags;
+use dicom::object::{DefaultDicomObject, InMemDicomObject};
+use pacsportal_core::attributes::text;
+use walkdir::WalkDir;
+
+/// Attributes returned for a study, taken from any of its instances.
+const STUDY_ATTRIBUTES: [Tag; 15] = [
+    tags::SPECIFIC_CHARACTER_SET,
+    tags::SOURCE_APPLICATION_ENTITY_TITLE,
+    tags::STUDY_DATE,
+    tags::STUDY_TIME,
+    tags::ACCESSION_NUMBER,
+    tags::MANUFACTURER,
+    tags::REFERRING_PHYSICIAN_NAME,
+    tags::STUDY_DESCRIPTION,
+    tags::PATIENT_NAME,
+    tags::PATIENT_ID,
+    tags::PATIENT_BIRTH_DATE,
+    tags::PATIENT_SEX,
+    tags::STUDY_INSTANCE_UID,
+    tags::STUDY_ID,
+    tags::REQUESTED_PROCEDURE_PRIORITY,
+];
+
+/// Attributes returned for a series, taken from any of its instances.
+const SERIES_ATTRIBUTES: [Tag; 6] = [
+    tags::SPECIFIC_CHARACTER_SET,
+    tags::MODALITY,
+    tags::SERIES_DESCRIPTION,
+    tags::STUDY_INSTANCE_UID,
+    tags::SERIES_INSTANCE_UID,
+    tags::SERIES_NUMBER,
+];
+
+/// Bulk data is dropped on the way in; the portal only asks for metadata and
+/// rendered thumbnails, which are placeholders.
+const BULK_DATA: [Tag; 4] = [
+    tags::PIXEL_DATA,
+    tags::FLOAT_PIXEL_DATA,
+    tags::DOUBLE_FLOAT_PIXEL_DATA,
+    tags::ENCAPSULATED_DOCUMENT,
+];
+
+/// In-memory archive of instances, keyed by SOP Instance UID.
+#[derive(Debug, Default)]
+pub struct Store {
+    instances: BTreeMap<String, InMemDicomObject>,
+}
+
+/// Each of `attributes` from the first of `instances` that has it, since
+/// e.g. a report does not repeat the StudyDescription of the images.
+fn first_values(instances: &[&InMemDicomObject], attributes: &[Tag]) -> InMemDicomObject {
+    InMemDicomObject::from_element_iter(
+        attributes
+            .iter()
+            .filter_map(|tag| instances.iter().find_map(|instance| instance.get(*tag)))
+            .cloned(),
+    )
+}
+
+fn number(object: &InMemDicomObject, tag: Tag) -> i32 {
+    text(object, tag).and_then(|n| n.parse().ok()).unwrap_or_default()
+}
+
+impl Store {
+    /// Stores an instance, replacing any with the same SOP Instance UID.
+    /// Returns that UID.
+    pub fn insert(&mut self, mut object: InMemDicomObject) -> Result<String, String> {
+        for tag in [tags::STUDY_INSTANCE_UID, tags::SERIES_INSTANCE_UID, tags::SOP_CLASS_UID] {
+            if text(&object, tag).is_none() {
+                return Err(format!("Instance is missing {}", tag));
+            }
+        }
+        let sop_uid = text(&object, tags::SOP_INSTANCE_UID).ok_or_else(|| String::from("Instance is missing SOPInstanceUID"))?;
+        for tag in BULK_DATA {
+            object.remove_element(tag);
+        }
+        self.instances.insert(sop_uid.clone(), object);
+        Ok(sop_uid)
+    }
+
+    /// Stores a Part 10 file, keeping the sending AE title from its meta group.
+    pub fn insert_file(&mut self, file: DefaultDicomObject) -> Result<String, String> {
+        let source_ae = file.meta().source_application_entity_title.clone();
+        let mut object = file.into_inner();
+        if let Some(source_ae) = source_ae {
+            object.put(DataElement::new(
+                tags::SOURCE_APPLICATION_ENTITY_TITLE,
+                VR::AE,
+                dicom_value!(Str, source_ae.trim_end_matches('\0').trim()),
+            ));
+        }
+        self.insert(object)
+    }
+
+    /// Loads every DICOM file below `dir`. Returns how many were loaded and
+    /// how many files were skipped because they could not be read.
+    pub fn load_dir(&mut self, dir: &Path) -> (usize, usize) {
+        let (mut loaded, mut skipped) = (0, 0);
+        for entry in WalkDir::new(dir).into_iter().filter_map(|entry| entry.ok()) {
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            match DefaultDicomObject::open_file(entry.path()).map_err(|e| e.to_string()).and_then(|file| self.insert_file(file)) {
+                Ok(_) => loaded += 1,
+                Err(_) => skipped += 1,
+            }
+        }
+        (loaded, skipped)
+    }
+
+    pub fn len(&self) -> usize {
+        self.instances.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instances.is_empty()
+    }
+
+    /// Instances, optionally restricted to a study and series, in instance
+    /// number order.
+    pub fn instances(&self, study_uid: Option<&str>, series_uid: Option<&str>) -> Vec<InMemDicomObject> {
+        let mut instances: Vec<&InMemDicomObject> = self
+            .instances
+            .values()
+            .filter(|instance| study_uid.is_none() || text(instance, tags::STUDY_INSTANCE_UID).as_deref() == study_uid)
+            .filter(|instance| series_uid.is_none() || text(instance, tags::SERIES_INSTANCE_UID).as_deref() == series_uid)
+            .collect();
+        instances.sort_by_key(|instance| number(instance, tags::INSTANCE_NUMBER));
+        instances.into_iter().cloned().collect()
+    }
+
+    pub fn instance(&self, study_uid: &str, series_uid: &str, sop_uid: &str) -> Option<&InMemDicomObject> {
+        self.instances.get(sop_uid).filter(|instance| {
+            text(instance, tags::STUDY_INSTANCE_UID).as_deref() == Some(study_uid)
+                && text(instance, tags::SERIES_INSTANCE_UID).as_deref() == Some(series_uid)
+        })
+    }
+
+    /// One record per study with ModalitiesInStudy and the related series and
+    /// instance counts filled in, most recent first.
+    pub fn studies(&self) -> Vec<InMemDicomObject> {
+        let mut grouped: BTreeMap<String, Vec<&InMemDicomObject>> = BTreeMap::new();
+        for instance in self.instances.values() {
+            let study_uid = text(instance, tags::STUDY_INSTANCE_UID).unwrap_or_default();
+            grouped.entry(study_uid).or_default().push(instance);
+        }
+        let mut studies: Vec<InMemDicomObject> = grouped
+            .values()
+            .map(|instances| {
+                let mut study = first_values(instances, &STUDY_ATTRIBUTES);
+                let mut modalities: Vec<String> = Vec::new();
+                let mut series: Vec<String> = Vec::new();
+                for instance in instances {
+                    if let Some(modality) = text(instance, tags::MODALITY) {
+                        if !modalities.contains(&modality) {
+                            modalities.push(modality);
+                        }
+                    }
+                    if let Some(series_uid) = text(instance, tags::SERIES_INSTANCE_UID) {
+                        if !series.contains(&series_uid) {
+                            series.push(series_uid);
+                        }
+                    }
+                }
+                study.put(DataElement::new(tags::MODALITIES_IN_STUDY, VR::CS, PrimitiveValue::Strs(modalities.into_iter().collect())));
+                study.put(DataElement::new(tags::NUMBER_OF_STUDY_RELATED_SERIES, VR::IS, series.len().to_string()));
+                study.put(DataElement::new(tags::NUMBER_OF_STUDY_RELATED_INSTANCES, VR::IS, instances.len().to_string()));
+                study
+            })
+            .collect();
+        // DA and TM values sort correctly as strings
+        studies.sort_by_key(|study| {
+            std::cmp::Reverse((text(study, tags::STUDY_DATE), text(study, tags::STUDY_TIME)))
+        });
+        studies
+    }
+
+    /// One record per series of a study, in series number order.
+    pub fn series(&self, study_uid: &str) -> Vec<InMemDicomObject> {
+        let instances = self.instances(Some(study_uid), None);
+        let mut grouped: BTreeMap<String, Vec<&InMemDicomObject>> = BTreeMap::new();
+        for instance in instances.iter() {
+            let series_uid = text(instance, tags::SERIES_INSTANCE_UID).unwrap_or_default();
+            grouped.entry(series_uid).or_default().push(instance);
+        }
+        let mut series: Vec<InMemDicomObject> = grouped
+            .values()
+            .map(|instances| {
+                let mut series = first_values(instances, &SERIES_ATTRIBUTES);
+                series.put(DataElement::new(
+                    tags::NUMBER_OF_SERIES_RELATED_INSTANCES,
+                    VR::IS,
+                    instances.len().to_string(),
+                ));
+                series
+            })
+            .collect();
+        series.sort_by_key(|series| number(series, tags::SERIES_NUMBER));
+        series
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom::dictionary_std::uids;
+
+    fn instance(study: &str, series: &str, sop: &str, modality: &str, number: &str) -> InMemDicomObject {
+        InMemDicomObject::from_element_iter([
+            DataElement::new(tags::SOP_CLASS_UID, VR::UI, uids::CT_IMAGE_STORAGE),
+            DataElement::new(tags::SOP_INSTANCE_UID, VR::UI, sop),
+            DataElement::new(tags::STUDY_INSTANCE_UID, VR::UI, study),
+            DataElement::new(tags::SERIES_INSTANCE_UID, VR::UI, series),
+            DataElement::new(tags::MODALITY, VR::CS, modality),
+            DataElement::new(tags::INSTANCE_NUMBER, VR::IS, number),
+            DataElement::new(tags::PATIENT_ID, VR::LO, "MOCK0001"),
+            DataElement::new(tags::PIXEL_DATA, VR::OB, dicom_value!(U8, [0, 1, 2, 3])),
+        ])
+    }
+
+    fn store() -> Store {
+        let mut store = Store::default();
+        store.insert(instance("1.1", "1.1.1", "1.1.1.2", "CT", "2")).unwrap();
+        store.insert(instance("1.1", "1.1.1", "1.1.1.1", "CT", "1")).unwrap();
+        store.insert(instance("1.1", "1.1.2", "1.1.2.1", "SR", "1")).unwrap();
+        store.insert(instance("1.2", "1.2.1", "1.2.1.1", "MR", "1")).unwrap();
+        store
+    }
+
+    #[test]
+    fn studies_aggregate_their_instances() {
+        let studies = store().studies();
+        assert_eq!(studies.len(), 2);
+        let study = studies
+            .iter()
+            .find(|study| text(study, tags::STUDY_INSTANCE_UID).as_deref() == Some("1.1"))
+            .unwrap();
+        assert_eq!(study.get(tags::MODALITIES_IN_STUDY).unwrap().to_multi_str().unwrap().to_vec(), ["CT", "SR"]);
+        assert_eq!(text(study, tags::NUMBER_OF_STUDY_RELATED_SERIES).as_deref(), Some("2"));
+        assert_eq!(text(study, tags::NUMBER_OF_STUDY_RELATED_INSTANCES).as_deref(), Some("3"));
+        assert_eq!(text(study, tags::PATIENT_ID).as_deref(), Some("MOCK0001"));
+    }
+
+    #[test]
+    fn instances_are_ordered_and_stripped_of_pixel_data() {
+        let store = store();
+        let instances = store.instances(Some("1.1"), Some("1.1.1"));
+        let uids: Vec<String> = instances.iter().filter_map(|i| text(i, tags::SOP_INSTANCE_UID)).collect();
+        assert_eq!(uids, ["1.1.1.1", "1.1.1.2"]);
+        assert!(instances[0].get(tags::PIXEL_DATA).is_none());
+        assert!(store.instance("1.1", "1.1.1", "1.1.1.1").is_some());
+        assert!(store.instance("1.2", "1.1.1", "1.1.1.1").is_none());
+    }
+
+    #[test]
+    fn series_count_their_instances() {
+        let series = store().series("1.1");
+        assert_eq!(series.len(), 2);
+        assert_eq!(text(&series[0], tags::NUMBER_OF_SERIES_RELATED_INSTANCES).as_deref(), Some("2"));
+    }
+
+    #[test]
+    fn instances_without_uids_are_rejected() {
+        let mut store = Store::default();
+        assert!(store.insert(InMemDicomObject::new_empty()).is_err());
+        assert!(store.is_empty());
+    }
+}
@@ -0,0 +1,184 @@
+use chrono::{Days, NaiveDate, NaiveTime};
+use dicom::core::{DataElement, VR};
+use dicom::dictionary_std::{tags, uids};
+use dicom::object::InMemDicomObject;
+use pacsportal_core::report::ReportBuilder;
+
+use crate::store::Store;
+
+const FAMILY_NAMES: [&str; 12] = [
+    "KHAN", "AHMED", "HUSSAIN", "SIDDIQUI", "QURESHI", "MALIK", "SHAH", "BUTT", "CHAUDHRY", "MIRZA", "RAZA", "SHEIKH",
+];
+const MALE_NAMES: [&str; 6] = ["ALI", "HAMZA", "USMAN", "BILAL", "FAISAL", "IMRAN"];
+const FEMALE_NAMES: [&str; 6] = ["AYESHA", "FATIMA", "SANA", "HIRA", "MARIAM", "ZAINAB"];
+const REFERRING_PHYSICIANS: [&str; 4] = ["RAZA^SALMAN^DR", "NAQVI^SADIA^DR", "BAIG^ADNAN^DR", "LODHI^NIDA^DR"];
+
+/// Modality, SOP class of its images, and the studies it is used for.
+const PROTOCOLS: [(&str, &str, [&str; 3]); 7] = [
+    ("CR", uids::COMPUTED_RADIOGRAPHY_IMAGE_STORAGE, ["CHEST PA", "KNEE AP/LAT", "LUMBAR SPINE AP/LAT"]),
+    ("DR", uids::DIGITAL_X_RAY_IMAGE_STORAGE_FOR_PRESENTATION, ["CHEST PA", "PELVIS AP", "HAND PA/OBL"]),
+    ("CT", uids::CT_IMAGE_STORAGE, ["CT BRAIN PLAIN", "CT CHEST WITH CONTRAST", "CT ABDOMEN PELVIS"]),
+    ("MR", uids::MR_IMAGE_STORAGE, ["MRI BRAIN", "MRI LUMBAR SPINE", "MRI KNEE RIGHT"]),
+    ("US", uids::ULTRASOUND_IMAGE_STORAGE, ["US ABDOMEN", "US PELVIS", "US KUB"]),
+    ("XA", uids::X_RAY_ANGIOGRAPHIC_IMAGE_STORAGE, ["CORONARY ANGIOGRAPHY", "CEREBRAL ANGIOGRAPHY", "PERIPHERAL ANGIOGRAPHY"]),
+    ("NM", uids::NUCLEAR_MEDICINE_IMAGE_STORAGE, ["BONE SCAN", "THYROID SCAN", "RENAL SCAN"]),
+];
+
+const FINDINGS: [&str; 4] = [
+    "No acute abnormality is seen.",
+    "Mild degenerative changes. No acute bony injury.",
+    "Findings are stable compared with the previous examination.",
+    "Small nonspecific nodule, follow up is recommended in 6 months.",
+];
+
+/// Deterministic pseudo-random numbers, so that every run of the mock
+/// serves the same patients and studies.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        // xorshift64*
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn pick<'a, T>(&mut self, values: &'a [T]) -> &'a T {
+        &values[self.below(values.len())]
+    }
+
+    fn uid(&mut self) -> String {
+        format!("2.25.{}", ((self.next() as u128) << 64 | self.next() as u128) >> 1)
+    }
+}
+
+/// Fills the store with `patients` synthetic patients. Every patient has a
+/// study within the week before `today`, unreported, and may have older
+/// reported studies; one report in the set is flagged critical.
+pub fn seed(store: &mut Store, patients: usize, today: NaiveDate) {
+    let mut rng = Rng(0x5eed_cafe_f00d_d00d);
+    let mut accession = 1000;
+    let mut flagged_critical = false;
+    for p in 0..patients {
+        let sex = if rng.below(2) == 0 { "M" } else { "F" };
+        let given = if sex == "M" { rng.pick(&MALE_NAMES) } else { rng.pick(&FEMALE_NAMES) };
+        let name = format!("{}^{}", rng.pick(&FAMILY_NAMES), given);
+        let birth_date = today - Days::new(365 * (1 + rng.below(85) as u64) + rng.below(365) as u64);
+        let patient = [
+            DataElement::new(tags::PATIENT_NAME, VR::PN, name),
+            DataElement::new(tags::PATIENT_ID, VR::LO, format!("MOCK{:04}", p + 1)),
+            DataElement::new(tags::PATIENT_BIRTH_DATE, VR::DA, birth_date.format("%Y%m%d").to_string()),
+            DataElement::new(tags::PATIENT_SEX, VR::CS, sex),
+        ];
+
+        let mut days_ago = rng.below(7) as u64;
+        for s in 0..1 + rng.below(4) {
+            let (modality, sop_class, descriptions) = rng.pick(&PROTOCOLS);
+            let date = today - Days::new(days_ago);
+            let time = NaiveTime::from_hms_opt(7 + rng.below(14) as u32, rng.below(60) as u32, rng.below(60) as u32).unwrap();
+            accession += 1;
+            let study_uid = rng.uid();
+            let mut study = InMemDicomObject::from_element_iter(patient.iter().cloned());
+            for element in [
+                DataElement::new(tags::SPECIFIC_CHARACTER_SET, VR::CS, "ISO_IR 100"),
+                DataElement::new(tags::STUDY_INSTANCE_UID, VR::UI, study_uid),
+                DataElement::new(tags::STUDY_DATE, VR::DA, date.format("%Y%m%d").to_string()),
+                DataElement::new(tags::STUDY_TIME, VR::TM, time.format("%H%M%S").to_string()),
+                DataElement::new(tags::STUDY_ID, VR::SH, format!("{}", s + 1)),
+                DataElement::new(tags::ACCESSION_NUMBER, VR::SH, format!("ACC{}", accession)),
+                DataElement::new(tags::STUDY_DESCRIPTION, VR::LO, rng.pick(descriptions).to_string()),
+                DataElement::new(tags::REFERRING_PHYSICIAN_NAME, VR::PN, rng.pick(&REFERRING_PHYSICIANS).to_string()),
+                DataElement::new(tags::MANUFACTURER, VR::LO, "PACSPORTAL MOCK"),
+                DataElement::new(tags::SOURCE_APPLICATION_ENTITY_TITLE, VR::AE, format!("MOCK_{}", modality)),
+            ] {
+                study.put(element);
+            }
+            // a few urgent requests, without drawing on the generator
+            match accession % 9 {
+                0 => study.put(DataElement::new(tags::REQUESTED_PROCEDURE_PRIORITY, VR::CS, "STAT")),
+                4 => study.put(DataElement::new(tags::REQUESTED_PROCEDURE_PRIORITY, VR::CS, "HIGH")),
+                _ => None,
+            };
+
+            for series_number in 1..=1 + rng.below(3) {
+                let series_uid = rng.uid();
+                for instance_number in 1..=2 + rng.below(5) {
+                    let mut instance = study.clone();
+                    for element in [
+                        DataElement::new(tags::SOP_CLASS_UID, VR::UI, sop_class.to_string()),
+                        DataElement::new(tags::SOP_INSTANCE_UID, VR::UI, rng.uid()),
+                        DataElement::new(tags::SERIES_INSTANCE_UID, VR::UI, series_uid.clone()),
+                        DataElement::new(tags::MODALITY, VR::CS, modality.to_string()),
+                        DataElement::new(tags::SERIES_NUMBER, VR::IS, series_number.to_string()),
+                        DataElement::new(tags::SERIES_DESCRIPTION, VR::LO, format!("SERIES {}", series_number)),
+                        DataElement::new(tags::INSTANCE_NUMBER, VR::IS, instance_number.to_string()),
+                    ] {
+                        instance.put(element);
+                    }
+                    store.insert(instance).expect("synthetic instances have all UIDs");
+                }
+            }
+
+            // the most recent study of every patient is left for the radiologist
+            if s > 0 {
+                let critical = !flagged_critical && p % 3 == 0;
+                flagged_critical |= critical;
+                let text = if critical {
+                    "Large right sided pneumothorax with mediastinal shift."
+                } else {
+                    rng.pick(&FINDINGS)
+                };
+                let reported_at = (date + Days::new(1)).and_time(time);
+                let mut sr = ReportBuilder::new(&study, text, "MOCK^RADIOLOGIST^DR")
+                    .organization("PACS Portal Mock")
+                    .critical(critical)
+                    .at(reported_at)
+                    .build();
+                sr.put(DataElement::new(tags::SOP_INSTANCE_UID, VR::UI, rng.uid()));
+                sr.put(DataElement::new(tags::SERIES_INSTANCE_UID, VR::UI, rng.uid()));
+                store.insert(sr).expect("reports have all UIDs");
+            }
+            days_ago += 30 + rng.below(400) as u64;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pacsportal_core::attributes::text;
+
+    #[test]
+    fn seeding_is_deterministic() {
+        let today = NaiveDate::from_ymd_opt(2023, 7, 25).unwrap();
+        let (mut first, mut second) = (Store::default(), Store::default());
+        seed(&mut first, 5, today);
+        seed(&mut second, 5, today);
+        assert_eq!(first.len(), second.len());
+        assert_eq!(first.studies(), second.studies());
+    }
+
+    #[test]
+    fn every_patient_has_a_recent_unreported_study() {
+        let today = NaiveDate::from_ymd_opt(2023, 7, 25).unwrap();
+        let mut store = Store::default();
+        seed(&mut store, 8, today);
+        let studies = store.studies();
+        for p in 1..=8 {
+            let id = format!("MOCK{:04}", p);
+            let latest = studies
+                .iter()
+                .find(|study| text(study, tags::PATIENT_ID).as_deref() == Some(id.as_str()))
+                .unwrap();
+            assert!(text(latest, tags::STUDY_DATE).unwrap().as_str() >= "20230718");
+            let modalities = latest.get(tags::MODALITIES_IN_STUDY).unwrap().to_multi_str().unwrap().to_vec();
+            assert!(!modalities.contains(&String::from("SR")));
+        }
+        assert!(studies.iter().any(|study| text(study, tags::REQUESTED_PROCEDURE_PRIORITY).as_deref() == Some("STAT")));
+    }
+}
@@ -0,0 +1,419 @@
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Mutex;
+
+use axum::extract::{ConnectInfo, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{DateTime, FixedOffset, Local, SecondsFormat};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::SharedState;
+
+/// Study Root Query/Retrieve Information Model - FIND, the SOP class a
+/// QIDO-RS study search is audited as.
+const STUDY_ROOT_FIND: &str = "1.2.840.10008.5.1.4.1.2.2.1";
+
+/// Records kept in memory for the admin page; older ones are only in the log file.
+const KEPT_RECORDS: usize = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Event {
+    Login,
+    Logout,
+    Query,
+    StudyOpened,
+    ReportCreated,
+    ReportAmended,
+    /// A referring physician acknowledged a critical result.
+    CriticalAcknowledged,
+    InstancesStored,
+    Export,
+    ShareCreated,
+    ShareRevoked,
+    /// A study opened through a share link, by someone without a login.
+    ShareAccessed,
+}
+
+impl Event {
+    /// EventID, EventActionCode and, for logins, EventTypeCode of the DICOM
+    /// audit message (PS3.15 A.5.3).
+    fn codes(self) -> ((&'static str, &'static str), &'static str, Option<(&'static str, &'static str)>) {
+        const AUTHENTICATION: (&str, &str) = ("110114", "User Authentication");
+        const INSTANCES_ACCESSED: (&str, &str) = ("110103", "DICOM Instances Accessed");
+        match self {
+            Event::Login => (AUTHENTICATION, "E", Some(("110122", "Login"))),
+            Event::Logout => (AUTHENTICATION, "E", Some(("110123", "Logout"))),
+            Event::Query => (("110112", "Query"), "E", None),
+            Event::StudyOpened => (INSTANCES_ACCESSED, "R", None),
+            Event::ReportCreated | Event::CriticalAcknowledged | Event::InstancesStored => (INSTANCES_ACCESSED, "C", None),
+            Event::ReportAmended => (INSTANCES_ACCESSED, "U", None),
+            Event::Export => (("110106", "Export"), "R", None),
+            Event::ShareCreated | Event::ShareRevoked => (
+                ("110113", "Security Alert"),
+                "E",
+                Some(("110135", "Object Security Attributes Changed")),
+            ),
+            Event::ShareAccessed => (INSTANCES_ACCESSED, "R", None),
+        }
+    }
+}
+
+/// One audited action.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    pub time: DateTime<FixedOffset>,
+    pub event: Event,
+    pub success: bool,
+    pub username: String,
+    pub address: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub patient_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub patient_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub study_uid: Option<String>,
+    /// The query string of a search, the SOP Instance UID of a report, the
+    /// format of an export, or the share link.
+    #[serde(default)]
+    pub detail: String,
+}
+
+impl Record {
+    pub fn new(event: Event, success: bool, username: &str, address: SocketAddr) -> Self {
+        Record {
+            time: Local::now().fixed_offset(),
+            event,
+            success,
+            username: username.to_owned(),
+            address: address.ip().to_string(),
+            patient_id: None,
+            patient_name: None,
+            study_uid: None,
+            detail: String::new(),
+        }
+    }
+
+    pub fn patient(mut self, id: Option<String>, name: Option<String>) -> Self {
+        self.patient_id = id;
+        self.patient_name = name;
+        self
+    }
+
+    pub fn study(mut self, uid: Option<String>) -> Self {
+        self.study_uid = uid;
+        self
+    }
+
+    pub fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = detail.into();
+        self
+    }
+
+    /// The record as a DICOM Audit Message (PS3.15 A.5), which is also the
+    /// IHE ATNA format.
+    pub fn to_xml(&self, source_id: &str) -> String {
+        let ((event_code, event_name), action, event_type) = self.event.codes();
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<AuditMessage>\n");
+        xml.push_str(&format!(
+            "  <EventIdentification EventActionCode=\"{}\" EventDateTime=\"{}\" EventOutcomeIndicator=\"{}\">\n",
+            action,
+            self.time.to_rfc3339_opts(SecondsFormat::Millis, false),
+            if self.success { "0" } else { "4" }
+        ));
+        xml.push_str(&format!("    {}\n", code("EventID", event_code, "DCM", event_name)));
+        if let Some((type_code, type_name)) = event_type {
+            xml.push_str(&format!("    {}\n", code("EventTypeCode", type_code, "DCM", type_name)));
+        }
+        xml.push_str("  </EventIdentification>\n");
+        xml.push_str(&format!(
+            "  <ActiveParticipant UserID=\"{}\" UserIsRequestor=\"true\" NetworkAccessPointID=\"{}\" NetworkAccessPointTypeCode=\"2\"/>\n",
+            escape(&self.username),
+            escape(&self.address)
+        ));
+        if self.event == Event::Export {
+            xml.push_str(&format!(
+                "  <ActiveParticipant UserID=\"{}\" UserIsRequestor=\"false\">\n    {}\n  </ActiveParticipant>\n",
+                escape(&self.detail),
+                code("RoleIDCode", "110154", "DCM", "Destination Media")
+            ));
+        }
+        xml.push_str(&format!(
+            "  <AuditSourceIdentification AuditSourceID=\"{}\">\n    {}\n  </AuditSourceIdentification>\n",
+            escape(source_id),
+            code("AuditSourceTypeCode", "4", "DCM", "Application Server Process")
+        ));
+        if let Some(patient_id) = &self.patient_id {
+            xml.push_str(&format!(
+                "  <ParticipantObjectIdentification ParticipantObjectID=\"{}\" ParticipantObjectTypeCode=\"1\" ParticipantObjectTypeCodeRole=\"1\">\n    {}\n",
+                escape(patient_id),
+                code("ParticipantObjectIDTypeCode", "2", "RFC-3881", "Patient Number")
+            ));
+            if let Some(name) = &self.patient_name {
+                xml.push_str(&format!("    <ParticipantObjectName>{}</ParticipantObjectName>\n", escape(name)));
+            }
+            xml.push_str("  </ParticipantObjectIdentification>\n");
+        }
+        if let Some(study_uid) = &self.study_uid {
+            xml.push_str(&format!(
+                "  <ParticipantObjectIdentification ParticipantObjectID=\"{}\" ParticipantObjectTypeCode=\"2\" ParticipantObjectTypeCodeRole=\"3\">\n    {}\n  </ParticipantObjectIdentification>\n",
+                escape(study_uid),
+                code("ParticipantObjectIDTypeCode", "110180", "DCM", "Study Instance UID")
+            ));
+        }
+        if self.event == Event::Query {
+            xml.push_str(&format!(
+                "  <ParticipantObjectIdentification ParticipantObjectID=\"{}\" ParticipantObjectTypeCode=\"2\" ParticipantObjectTypeCodeRole=\"3\">\n    {}\n    <ParticipantObjectQuery>{}</ParticipantObjectQuery>\n  </ParticipantObjectIdentification>\n",
+                STUDY_ROOT_FIND,
+                code("ParticipantObjectIDTypeCode", "110181", "DCM", "SOP Class UID"),
+                STANDARD.encode(&self.detail)
+            ));
+        }
+        xml.push_str("</AuditMessage>\n");
+        xml
+    }
+}
+
+fn code(element: &str, value: &str, scheme: &str, meaning: &str) -> String {
+    format!(
+        "<{} csd-code=\"{}\" codeSystemName=\"{}\" originalText=\"{}\"/>",
+        element, value, scheme, meaning
+    )
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Filters of the admin page; all are optional.
+#[derive(Debug, Default, Deserialize)]
+pub struct Filter {
+    pub username: Option<String>,
+    pub patient_id: Option<String>,
+    pub study_uid: Option<String>,
+    pub event: Option<Event>,
+    pub from: Option<DateTime<FixedOffset>>,
+    pub to: Option<DateTime<FixedOffset>>,
+    pub limit: Option<usize>,
+}
+
+impl Filter {
+    pub fn matches(&self, record: &Record) -> bool {
+        fn same(wanted: &Option<String>, actual: &str) -> bool {
+            wanted.as_deref().is_none_or(|wanted| wanted.is_empty() || wanted == actual)
+        }
+        same(&self.username, &record.username)
+            && same(&self.patient_id, record.patient_id.as_deref().unwrap_or_default())
+            && same(&self.study_uid, record.study_uid.as_deref().unwrap_or_default())
+            && self.event.is_none_or(|event| event == record.event)
+            && self.from.is_none_or(|from| record.time >= from)
+            && self.to.is_none_or(|to| record.time <= to)
+    }
+}
+
+/// Keeps the audit trail: a JSON line per record in the log file, the most
+/// recent records in memory, and the audit message sent to the configured
+/// syslog or HTTP collector.
+pub struct Auditor {
+    source_id: String,
+    records: Mutex<VecDeque<Record>>,
+    file: Mutex<Option<File>>,
+    sink: Option<UnboundedSender<String>>,
+}
+
+impl Auditor {
+    pub fn new(source_id: &str, log: Option<&Path>, sink: Option<UnboundedSender<String>>) -> Result<Self, String> {
+        let mut records = VecDeque::new();
+        let file = match log {
+            Some(path) => {
+                if let Ok(existing) = File::open(path) {
+                    for line in BufReader::new(existing).lines().map_while(Result::ok) {
+                        match serde_json::from_str::<Record>(&line) {
+                            Ok(record) => {
+                                if records.len() >= KEPT_RECORDS {
+                                    records.pop_front();
+                                }
+                                records.push_back(record);
+                            }
+                            Err(e) => eprintln!("Skipping unreadable audit record in {}: {}", path.display(), e),
+                        }
+                    }
+                }
+                Some(
+                    OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(path)
+                        .map_err(|e| format!("{}: {}", path.display(), e))?,
+                )
+            }
+            None => None,
+        };
+        Ok(Auditor {
+            source_id: source_id.to_owned(),
+            records: Mutex::new(records),
+            file: Mutex::new(file),
+            sink,
+        })
+    }
+
+    pub fn record(&self, record: Record) {
+        if let Some(file) = self.file.lock().unwrap().as_mut() {
+            let line = serde_json::to_string(&record).expect("records serialize");
+            if let Err(e) = writeln!(file, "{}", line) {
+                eprintln!("Could not write the audit log: {}", e);
+            }
+        }
+        if let Some(sink) = &self.sink {
+            let _ = sink.send(record.to_xml(&self.source_id));
+        }
+        let mut records = self.records.lock().unwrap();
+        if records.len() >= KEPT_RECORDS {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+
+    /// Matching records, most recent first.
+    pub fn search(&self, filter: &Filter) -> Vec<Record> {
+        self.records
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .filter(|record| filter.matches(record))
+            .take(filter.limit.unwrap_or(500))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Events only the browser knows about.
+#[derive(Deserialize)]
+pub struct Reported {
+    event: Event,
+    patient_id: Option<String>,
+    patient_name: Option<String>,
+    study_uid: Option<String>,
+    #[serde(default)]
+    detail: String,
+}
+
+/// Records an event reported by the portal for the logged in user, e.g.
+/// opening a study in the viewer or exporting search results.
+pub async fn report(
+    State(state): State<SharedState>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(reported): Json<Reported>,
+) -> Response {
+    let Some(session) = state.sessions.for_request(&headers) else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+    // the server sees logins, searches and stores itself
+    if !matches!(reported.event, Event::StudyOpened | Event::Export) {
+        return (StatusCode::BAD_REQUEST, "This event is recorded by the server.").into_response();
+    }
+    let record = Record::new(reported.event, true, &session.username, address)
+        .patient(reported.patient_id, reported.patient_name)
+        .study(reported.study_uid)
+        .detail(reported.detail);
+    state.auditor.record(record);
+    StatusCode::NO_CONTENT.into_response()
+}
+
+/// The audit trail for the admin page.
+pub async fn browse(State(state): State<SharedState>, headers: HeaderMap, Query(filter): Query<Filter>) -> Response {
+    match state.sessions.for_request(&headers) {
+        Some(session) if session.admin => Json(state.auditor.search(&filter)).into_response(),
+        Some(_) => StatusCode::FORBIDDEN.into_response(),
+        None => StatusCode::UNAUTHORIZED.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address() -> SocketAddr {
+        SocketAddr::from(([10, 0, 0, 5], 51000))
+    }
+
+    #[test]
+    fn login_messages() {
+        let xml = Record::new(Event::Login, false, "root", address()).to_xml("PACSPORTAL");
+        assert!(xml.contains("EventActionCode=\"E\""));
+        assert!(xml.contains("EventOutcomeIndicator=\"4\""));
+        assert!(xml.contains("<EventID csd-code=\"110114\""));
+        assert!(xml.contains("<EventTypeCode csd-code=\"110122\""));
+        assert!(xml.contains("UserID=\"root\""));
+        assert!(xml.contains("NetworkAccessPointID=\"10.0.0.5\""));
+        assert!(xml.contains("AuditSourceID=\"PACSPORTAL\""));
+        assert!(!xml.contains("ParticipantObjectIdentification"));
+    }
+
+    #[test]
+    fn participants_are_escaped() {
+        let xml = Record::new(Event::StudyOpened, true, "radiologist", address())
+            .patient(Some(String::from("MOCK0001")), Some(String::from("O'NEIL^<SAM>")))
+            .study(Some(String::from("1.2.3")))
+            .to_xml("PACSPORTAL");
+        assert!(xml.contains("EventActionCode=\"R\""));
+        assert!(xml.contains("ParticipantObjectID=\"MOCK0001\" ParticipantObjectTypeCode=\"1\""));
+        assert!(xml.contains("<ParticipantObjectName>O&apos;NEIL^&lt;SAM&gt;</ParticipantObjectName>"));
+        assert!(xml.contains("ParticipantObjectID=\"1.2.3\" ParticipantObjectTypeCode=\"2\""));
+    }
+
+    #[test]
+    fn queries_carry_the_query_string() {
+        let xml = Record::new(Event::Query, true, "radiologist", address())
+            .detail("StudyDate=20230725-20230725")
+            .to_xml("PACSPORTAL");
+        assert!(xml.contains(STUDY_ROOT_FIND));
+        assert!(xml.contains(&format!(
+            "<ParticipantObjectQuery>{}</ParticipantObjectQuery>",
+            STANDARD.encode("StudyDate=20230725-20230725")
+        )));
+    }
+
+    #[test]
+    fn records_survive_a_restart_and_can_be_filtered() {
+        let path = std::env::temp_dir().join(format!("pacsportal-audit-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let auditor = Auditor::new("PACSPORTAL", Some(&path), None).unwrap();
+        auditor.record(Record::new(Event::Login, true, "root", address()));
+        auditor.record(
+            Record::new(Event::ReportCreated, true, "radiologist", address()).patient(Some(String::from("MOCK0001")), None),
+        );
+        drop(auditor);
+
+        let auditor = Auditor::new("PACSPORTAL", Some(&path), None).unwrap();
+        assert_eq!(auditor.search(&Filter::default()).len(), 2);
+        let by_patient = auditor.search(&Filter {
+            patient_id: Some(String::from("MOCK0001")),
+            ..Filter::default()
+        });
+        assert_eq!(by_patient.len(), 1);
+        assert_eq!(by_patient[0].event, Event::ReportCreated);
+        let logins = auditor.search(&Filter {
+            event: Some(Event::Login),
+            username: Some(String::from("radiologist")),
+            ..Filter::default()
+        });
+        assert!(logins.is_empty());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,288 @@
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+/// What a user may do with the archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Reads studies and stores reports, key images and acknowledgements.
+    Radiologist,
+    /// Reads studies and reports, and acknowledges critical results.
+    Referring,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct User {
+    pub username: String,
+    /// Argon2 PHC string, see `pacsportal-server hash-password`.
+    pub password_hash: String,
+    pub role: Role,
+    /// The name reports are verified with, e.g. `DR WASAY JILANI`; the
+    /// username when left out.
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default = "default_department")]
+    pub department: String,
+    /// May browse the audit trail.
+    #[serde(default)]
+    pub admin: bool,
+}
+
+fn default_department() -> String {
+    String::from("Radiology")
+}
+
+/// The DICOMweb archive requests are forwarded to, and the credentials the
+/// server presents to it. Clients never see these.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Archive {
+    /// DICOMweb root, e.g. `http://210.56.0.36:8080/dcm4chee-arc/aets/SCHPACS2/rs`.
+    pub url: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Sent as a bearer token instead of basic authentication.
+    pub token: Option<String>,
+}
+
+/// PostgREST serving the metadata schema of `pacsportal-store`. Like the
+/// archive, it should only be reachable from this server.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Store {
+    /// e.g. `http://127.0.0.1:3000`.
+    pub url: String,
+    /// JWT for the `pacsportal_web` role, when PostgREST requires one.
+    pub token: Option<String>,
+}
+
+/// De-identified exports for teaching and research, see `download.rs` and
+/// `teaching.rs`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Deidentification {
+    /// Remapped UIDs and pseudonyms are derived from this secret, so that
+    /// exports of one patient stay linked to each other. Without it, every
+    /// export is remapped on its own.
+    pub uid_secret: Option<String>,
+    /// DICOMweb archive of the teaching file collection, which de-identified
+    /// studies are stored to; there is no collection when absent.
+    pub teaching: Option<Archive>,
+}
+
+/// Signed, expiring links to a single study for consultants who cannot log
+/// in, see `share.rs`. There are no links when absent.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Sharing {
+    /// Key the links are signed with; changing it invalidates every link.
+    pub secret: String,
+    /// Longest time a link may be valid for.
+    #[serde(default = "default_max_days")]
+    pub max_days: u32,
+    /// Links issued and revoked, a JSON line each.
+    #[serde(default = "default_registry")]
+    pub registry: PathBuf,
+}
+
+fn default_max_days() -> u32 {
+    14
+}
+
+fn default_registry() -> PathBuf {
+    PathBuf::from("shares.jsonl")
+}
+
+/// The live feed of new studies and reports, see `feed.rs`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FeedConfig {
+    /// How often the archive is searched for new studies; 0 only passes on
+    /// the reports stored through the portal.
+    pub poll_seconds: u64,
+    /// Days before today whose studies are watched too, for studies of the
+    /// evening that are stored after midnight.
+    pub days: u64,
+}
+
+impl Default for FeedConfig {
+    fn default() -> Self {
+        FeedConfig { poll_seconds: 30, days: 1 }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Tls {
+    pub certificate: PathBuf,
+    pub key: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyslogTransport {
+    #[default]
+    Udp,
+    Tcp,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Syslog {
+    pub address: SocketAddr,
+    #[serde(default)]
+    pub transport: SyslogTransport,
+}
+
+/// Where the audit trail goes. Records are always kept in `log` for the
+/// admin page, and also sent to an ATNA audit record repository over syslog
+/// or to an HTTP collector when one is configured.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AuditConfig {
+    pub log: Option<PathBuf>,
+    /// AuditSourceID of the audit messages.
+    pub source_id: String,
+    pub syslog: Option<Syslog>,
+    /// Receives each audit message as an `application/xml` POST.
+    pub http: Option<String>,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        AuditConfig {
+            log: Some(PathBuf::from("audit.jsonl")),
+            source_id: String::from("PACSPORTAL"),
+            syslog: None,
+            http: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    #[serde(default = "default_listen")]
+    pub listen: SocketAddr,
+    /// Output of `trunk build`.
+    #[serde(default = "default_dist")]
+    pub dist: PathBuf,
+    /// Idle time after which a session has to log in again.
+    #[serde(default = "default_session_minutes")]
+    pub session_minutes: u64,
+    /// Serves plain HTTP when absent, e.g. behind a TLS terminating proxy.
+    pub tls: Option<Tls>,
+    pub archive: Archive,
+    /// Drafts, assignments and the like are not kept when absent.
+    pub store: Option<Store>,
+    #[serde(default)]
+    pub audit: AuditConfig,
+    #[serde(default)]
+    pub deidentification: Deidentification,
+    pub sharing: Option<Sharing>,
+    #[serde(default)]
+    pub feed: FeedConfig,
+    #[serde(default)]
+    pub users: Vec<User>,
+}
+
+fn default_listen() -> SocketAddr {
+    SocketAddr::from(([0, 0, 0, 0], 8443))
+}
+
+fn default_dist() -> PathBuf {
+    PathBuf::from("dist")
+}
+
+fn default_session_minutes() -> u64 {
+    12 * 60
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let config: Config = toml::from_str(text).map_err(|e| e.to_string())?;
+        if !config.archive.url.starts_with("http://") {
+            return Err(format!("archive.url must be an http:// URL, not {}", config.archive.url));
+        }
+        if let Some(store) = &config.store {
+            if !store.url.starts_with("http://") {
+                return Err(format!("store.url must be an http:// URL, not {}", store.url));
+            }
+        }
+        if let Some(teaching) = &config.deidentification.teaching {
+            if !teaching.url.starts_with("http://") {
+                return Err(format!("deidentification.teaching.url must be an http:// URL, not {}", teaching.url));
+            }
+        }
+        if let Some(sharing) = &config.sharing {
+            if sharing.secret.len() < 32 {
+                return Err(String::from("sharing.secret must be at least 32 characters"));
+            }
+            if sharing.max_days == 0 {
+                return Err(String::from("sharing.max_days must be at least 1"));
+            }
+        }
+        if config.audit.syslog.is_some() && config.audit.http.is_some() {
+            return Err(String::from("Configure either audit.syslog or audit.http, not both"));
+        }
+        if let Some(url) = &config.audit.http {
+            if !url.starts_with("http://") || url.parse::<hyper::Uri>().is_err() {
+                return Err(format!("audit.http must be an http:// URL, not {}", url));
+            }
+        }
+        for (i, user) in config.users.iter().enumerate() {
+            if config.users[..i].iter().any(|other| other.username == user.username) {
+                return Err(format!("User {} is configured twice", user.username));
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn load(path: &Path) -> Result<Config, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Config::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn user(&self, username: &str) -> Option<&User> {
+        self.users.iter().find(|user| user.username == username)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = include_str!("../pacsportal.example.toml");
+
+    #[test]
+    fn the_example_configuration_is_valid() {
+        let config = Config::parse(EXAMPLE).unwrap();
+        assert!(config.tls.is_some());
+        assert_eq!(config.store.as_ref().unwrap().url, "http://127.0.0.1:3000");
+        assert_eq!(config.user("radiologist").unwrap().role, Role::Radiologist);
+        assert!(config.user("radiologist").unwrap().admin);
+        assert_eq!(config.audit.syslog.as_ref().unwrap().transport, SyslogTransport::Tcp);
+        assert!(config.deidentification.uid_secret.is_some());
+        assert_eq!(config.sharing.as_ref().unwrap().max_days, 14);
+        assert_eq!(config.deidentification.teaching.as_ref().unwrap().username.as_deref(), Some("pacsportal"));
+        assert_eq!(config.feed.poll_seconds, 30);
+        assert_eq!(config.user("root").unwrap().role, Role::Referring);
+        assert!(config.user("nobody").is_none());
+    }
+
+    #[test]
+    fn defaults_and_validation() {
+        let config = Config::parse("[archive]\nurl = \"http://127.0.0.1:8042/dicomweb\"\n").unwrap();
+        assert_eq!(config.listen.port(), 8443);
+        assert_eq!(config.dist, PathBuf::from("dist"));
+        assert!(config.tls.is_none() && config.store.is_none() && config.users.is_empty());
+        assert_eq!(config.audit.log, Some(PathBuf::from("audit.jsonl")));
+        assert!(config.audit.syslog.is_none() && config.audit.http.is_none());
+        assert!(config.deidentification.uid_secret.is_none() && config.deidentification.teaching.is_none());
+        assert!(config.sharing.is_none());
+        assert_eq!((config.feed.poll_seconds, config.feed.days), (30, 1));
+
+        assert!(Config::parse("[archive]\nurl = \"https://archive\"\n").is_err());
+        let twice = "[archive]\nurl = \"http://archive\"\n\
+                     [[users]]\nusername = \"a\"\npassword_hash = \"x\"\nrole = \"referring\"\n\
+                     [[users]]\nusername = \"a\"\npassword_hash = \"y\"\nrole = \"radiologist\"\n";
+        assert!(Config::parse(twice).is_err());
+        assert!(Config::parse("[archive]\nurl = \"http://archive\"\n[sharing]\nsecret = \"short\"\n").is_err());
+    }
+}
@@ -0,0 +1,418 @@
+//! Study downloads for handing images to patients on CD or USB, or
+//! de-identified for teaching and research. Every instance is retrieved
+//! with WADO-RS and written, with a DICOMDIR, into a ZIP spooled to a
+//! temporary file, so that only one instance is held in memory at a time;
+//! the file is then streamed to the browser.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::Path as FilePath;
+
+use axum::body::{Body, Bytes, StreamBody};
+use axum::extract::{ConnectInfo, Path, Query, State};
+use axum::http::{header, HeaderMap, Request, StatusCode};
+use axum::response::{IntoResponse, Response};
+use dicom::dictionary_std::tags;
+use dicom::object::OpenFileOptions;
+use pacsportal_core::attributes::{new_uid, text};
+use pacsportal_core::deidentify::{is_pseudonym, Deidentifier, Options, UidMap, Withheld};
+use pacsportal_core::dicomdir::{self, Entry};
+use pacsportal_core::stow;
+use serde_json::Value;
+use tokio_util::io::ReaderStream;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::audit::{Event, Record};
+use crate::proxy::credentials;
+use crate::SharedState;
+
+/// Instances are asked for as stored, whatever their transfer syntax.
+const INSTANCE_ACCEPT: &str = "multipart/related; type=\"application/dicom\"; transfer-syntax=*";
+
+/// Whether `uid` is a UID, the only thing put into archive paths.
+pub fn is_uid(uid: &str) -> bool {
+    !uid.is_empty() && uid.len() <= 64 && uid.chars().all(|c| c.is_ascii_digit() || c == '.')
+}
+
+/// The first value of an attribute of a DICOM JSON object, as text.
+pub fn json_text(object: &Value, tag: &str) -> Option<String> {
+    match object.get(tag)?.get("Value")?.get(0)? {
+        Value::String(value) => Some(value.clone()),
+        Value::Number(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// Series and SOP Instance UIDs of a QIDO-RS instance search, series in the
+/// order the archive lists them and instances by Instance Number.
+pub fn instances(listing: &[Value]) -> Vec<(String, String)> {
+    let mut series_order: Vec<String> = Vec::new();
+    let mut found = Vec::new();
+    for instance in listing {
+        let (Some(series_uid), Some(sop_uid)) = (json_text(instance, "0020000E"), json_text(instance, "00080018")) else {
+            continue;
+        };
+        if !is_uid(&series_uid) || !is_uid(&sop_uid) {
+            continue;
+        }
+        let series = match series_order.iter().position(|uid| *uid == series_uid) {
+            Some(series) => series,
+            None => {
+                series_order.push(series_uid.clone());
+                series_order.len() - 1
+            }
+        };
+        let number = json_text(instance, "00200013").and_then(|number| number.trim().parse::<i64>().ok());
+        found.push((series, number, series_uid, sop_uid));
+    }
+    found.sort_by_key(|(series, number, _, _)| (*series, *number));
+    found.into_iter().map(|(_, _, series_uid, sop_uid)| (series_uid, sop_uid)).collect()
+}
+
+/// The Part 10 file in a WADO-RS response, with the 128 byte preamble that
+/// some archives leave out.
+pub fn part10(content_type: &str, body: &[u8]) -> Result<Vec<u8>, String> {
+    let file = if content_type.starts_with("multipart/related") {
+        let boundary = stow::boundary(content_type).ok_or_else(|| String::from("Missing multipart boundary"))?;
+        stow::split_parts(body, &boundary)
+            .into_iter()
+            .find(|(part_type, _)| part_type.starts_with("application/dicom") && !part_type.starts_with("application/dicom+"))
+            .map(|(_, content)| content)
+            .ok_or_else(|| String::from("The response holds no DICOM file"))?
+    } else {
+        body
+    };
+    if file.get(128..132) == Some(b"DICM") {
+        Ok(file.to_vec())
+    } else if file.starts_with(b"DICM") {
+        let mut with_preamble = vec![0; 128];
+        with_preamble.extend_from_slice(file);
+        Ok(with_preamble)
+    } else {
+        Err(String::from("The response is not a DICOM file"))
+    }
+}
+
+/// A file name of patient ID and study date, e.g. `SCH-001_20230724.zip`.
+pub fn file_name(patient_id: Option<&str>, study_date: Option<&str>) -> String {
+    let name = [patient_id, study_date]
+        .into_iter()
+        .flatten()
+        .map(|part| part.chars().filter(|c| c.is_ascii_alphanumeric() || *c == '-').collect::<String>())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("_");
+    if name.is_empty() {
+        String::from("study.zip")
+    } else {
+        format!("{}.zip", name)
+    }
+}
+
+/// A GET request to the archive for `path`, relative to the DICOMweb root,
+/// answered with its content type and body.
+pub async fn fetch(state: &SharedState, path: &str, accept: &str) -> Result<(String, Bytes), String> {
+    let mut request = Request::get(format!("{}/{}", state.config.archive.url.trim_end_matches('/'), path)).header(header::ACCEPT, accept);
+    if let Some(credentials) = credentials(&state.config.archive) {
+        request = request.header(header::AUTHORIZATION, credentials);
+    }
+    let request = request.body(Body::empty()).map_err(|e| e.to_string())?;
+    let response = state.client.request(request).await.map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("the archive answered {} for {}", response.status(), path));
+    }
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_owned();
+    let body = hyper::body::to_bytes(response.into_body()).await.map_err(|e| e.to_string())?;
+    Ok((content_type, body))
+}
+
+/// Series and SOP Instance UIDs of the instances of a study, in the order of
+/// [`instances`].
+pub async fn study_instances(state: &SharedState, study_uid: &str) -> Result<Vec<(String, String)>, String> {
+    let (_, body) = fetch(state, &format!("studies/{}/instances?includefield=InstanceNumber", study_uid), "application/dicom+json").await?;
+    if body.is_empty() {
+        return Ok(Vec::new());
+    }
+    let listing = serde_json::from_slice::<Vec<Value>>(&body).map_err(|e| e.to_string())?;
+    Ok(instances(&listing))
+}
+
+/// An instance as a Part 10 file, retrieved with WADO-RS.
+pub async fn retrieve(state: &SharedState, study_uid: &str, series_uid: &str, sop_uid: &str) -> Result<Vec<u8>, String> {
+    let path = format!("studies/{}/series/{}/instances/{}", study_uid, series_uid, sop_uid);
+    let (content_type, body) = fetch(state, &path, INSTANCE_ACCEPT).await?;
+    part10(&content_type, &body).map_err(|e| format!("instance {}: {}", sop_uid, e))
+}
+
+/// The de-identification of an export with `options`, remapping with the
+/// configured secret. The patient is named `pseudonym` when one is given.
+pub fn deidentifier(secret: Option<&str>, options: Options, pseudonym: Option<&str>) -> Result<Deidentifier, &'static str> {
+    let pseudonym = pseudonym.map(str::trim).filter(|pseudonym| !pseudonym.is_empty());
+    if pseudonym.is_some_and(|pseudonym| !is_pseudonym(pseudonym)) {
+        return Err("A pseudonym is at most 64 characters, without backslashes.");
+    }
+    Ok(Deidentifier {
+        options,
+        uids: secret.map(UidMap::new).unwrap_or_else(UidMap::random),
+        pseudonym: pseudonym.map(str::to_owned),
+    })
+}
+
+/// The options of a de-identified download, given as query parameters that
+/// are `true` when chosen, e.g. `?retain_dates=true`.
+pub fn options(params: &HashMap<String, String>) -> Options {
+    let chosen = |name: &str| params.get(name).is_some_and(|value| value == "true");
+    Options {
+        retain_dates: chosen("retain_dates"),
+        retain_uids: chosen("retain_uids"),
+        retain_patient_characteristics: chosen("retain_patient_characteristics"),
+        retain_device_identity: chosen("retain_device_identity"),
+        retain_institution_identity: chosen("retain_institution_identity"),
+    }
+}
+
+/// What the audit record and the file name need to know of a written study.
+struct Written {
+    instances: usize,
+    /// Instances left out by the de-identification.
+    withheld: usize,
+    /// The patient as archived, for the audit record.
+    patient_id: Option<String>,
+    patient_name: Option<String>,
+    /// Made of the patient ID and study date as written, which are a
+    /// pseudonym and no date when de-identified.
+    file_name: String,
+}
+
+/// Retrieves `instances` of the study one by one into a ZIP at `path`,
+/// followed by their DICOMDIR, de-identifying each when asked to.
+async fn write_zip(
+    state: &SharedState,
+    study_uid: &str,
+    instances: &[(String, String)],
+    deidentifier: Option<&Deidentifier>,
+    path: &FilePath,
+) -> Result<Written, String> {
+    let mut zip = ZipWriter::new(File::create(path).map_err(|e| e.to_string())?);
+    // images hardly compress, and stored files are quicker to burn and copy
+    let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+    let mut entries = Vec::with_capacity(instances.len());
+    let mut series_numbers: HashMap<&str, (usize, usize)> = HashMap::new();
+    let mut written = Written {
+        instances: 0,
+        withheld: 0,
+        patient_id: None,
+        patient_name: None,
+        file_name: file_name(None, None),
+    };
+
+    for (series_uid, sop_uid) in instances {
+        let file = retrieve(state, study_uid, series_uid, sop_uid).await?;
+        let read = |file: &[u8]| {
+            OpenFileOptions::new()
+                .read_until(tags::PIXEL_DATA)
+                .from_reader(file)
+                .map_err(|e| format!("instance {}: {}", sop_uid, e))
+        };
+        let mut dataset = read(&file)?;
+        if written.instances + written.withheld == 0 {
+            written.patient_id = text(&dataset, tags::PATIENT_ID);
+            written.patient_name = text(&dataset, tags::PATIENT_NAME);
+        }
+        let file = match deidentifier.map(|deidentifier| deidentifier.file(&file)) {
+            None => file,
+            Some(Ok(deidentified)) => {
+                dataset = read(&deidentified)?;
+                deidentified
+            }
+            Some(Err(Withheld::BurnedInAnnotation)) => {
+                written.withheld += 1;
+                continue;
+            }
+            Some(Err(e)) => return Err(format!("instance {}: {}", sop_uid, e)),
+        };
+
+        let next_series = series_numbers.len() + 1;
+        let (series, count) = series_numbers.entry(series_uid.as_str()).or_insert((next_series, 0));
+        *count += 1;
+        let entry = Entry::new(dicomdir::file_id(*series, *count), &dataset);
+        tokio::task::block_in_place(|| {
+            zip.start_file(entry.path(), options)?;
+            zip.write_all(&file)?;
+            Ok::<_, zip::result::ZipError>(())
+        })
+        .map_err(|e| e.to_string())?;
+
+        if written.instances == 0 {
+            written.file_name = file_name(text(&dataset, tags::PATIENT_ID).as_deref(), text(&dataset, tags::STUDY_DATE).as_deref());
+        }
+        written.instances += 1;
+        entries.push(entry);
+    }
+    if entries.is_empty() {
+        return Ok(written);
+    }
+
+    let dicomdir = dicomdir::dicomdir(&entries)?;
+    tokio::task::block_in_place(|| {
+        zip.start_file("DICOMDIR", options)?;
+        zip.write_all(&dicomdir)?;
+        zip.finish()?.sync_all()?;
+        Ok::<_, zip::result::ZipError>(())
+    })
+    .map_err(|e| e.to_string())?;
+    Ok(written)
+}
+
+/// Downloads a study as a ZIP with a DICOMDIR. Downloads are audited as
+/// exports.
+pub async fn study(
+    State(state): State<SharedState>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    Path(study_uid): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    export(&state, address, &headers, &study_uid, None).await
+}
+
+/// Downloads a study de-identified with the options of the query, see
+/// [`options`], and an optional `pseudonym`.
+pub async fn deidentified(
+    State(state): State<SharedState>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    Path(study_uid): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Response {
+    let secret = state.config.deidentification.uid_secret.as_deref();
+    match deidentifier(secret, options(&params), params.get("pseudonym").map(String::as_str)) {
+        Ok(deidentifier) => export(&state, address, &headers, &study_uid, Some(&deidentifier)).await,
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn export(state: &SharedState, address: SocketAddr, headers: &HeaderMap, study_uid: &str, deidentifier: Option<&Deidentifier>) -> Response {
+    let Some(session) = state.sessions.for_request(headers) else {
+        return (StatusCode::UNAUTHORIZED, "Please log in again.").into_response();
+    };
+    if !is_uid(study_uid) {
+        return (StatusCode::BAD_REQUEST, "This is not a Study Instance UID.").into_response();
+    }
+
+    let instances = match study_instances(state, study_uid).await {
+        Ok(instances) => instances,
+        Err(e) => {
+            eprintln!("Download of {} for {} failed: {}", study_uid, session.username, e);
+            return (StatusCode::BAD_GATEWAY, "The archive could not be searched.").into_response();
+        }
+    };
+    if instances.is_empty() {
+        return (StatusCode::NOT_FOUND, "The study has no instances.").into_response();
+    }
+
+    let kind = if deidentifier.is_some() { "De-identified ZIP download" } else { "ZIP download" };
+    let path = std::env::temp_dir().join(format!("pacsportal-{}.zip", new_uid()));
+    let result = write_zip(state, study_uid, &instances, deidentifier, &path).await;
+    let exported = result.as_ref().is_ok_and(|written| written.instances > 0);
+    let record = Record::new(Event::Export, exported, &session.username, address).study(Some(study_uid.to_owned()));
+    let written = match result {
+        Ok(written) if written.instances > 0 => written,
+        Ok(written) => {
+            let _ = std::fs::remove_file(&path);
+            state.auditor.record(record.patient(written.patient_id, written.patient_name).detail(kind));
+            return (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "Every image of the study may show burned-in patient details, so none can be exported de-identified.",
+            )
+                .into_response();
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&path);
+            state.auditor.record(record.detail(kind));
+            eprintln!("Download of {} for {} failed: {}", study_uid, session.username, e);
+            return (StatusCode::BAD_GATEWAY, "The study could not be retrieved from the archive.").into_response();
+        }
+    };
+    let detail = match written.withheld {
+        0 => format!("{}, {} instances", kind, written.instances),
+        withheld => format!("{}, {} instances, {} withheld for burned-in annotation", kind, written.instances, withheld),
+    };
+    state.auditor.record(record.patient(written.patient_id.clone(), written.patient_name.clone()).detail(detail));
+
+    let file = match tokio::fs::File::open(&path).await {
+        Ok(file) => file,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    let length = file.metadata().await.map(|metadata| metadata.len()).unwrap_or_default();
+    // the open file stays readable once unlinked, and is gone when the download ends
+    let _ = std::fs::remove_file(&path);
+    (
+        [
+            (header::CONTENT_TYPE, String::from("application/zip")),
+            (header::CONTENT_LENGTH, length.to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", written.file_name)),
+        ],
+        StreamBody::new(ReaderStream::new(file)),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instances_by_series_and_number() {
+        let listing: Vec<Value> = serde_json::from_str(
+            r#"[
+                {"0020000E": {"vr": "UI", "Value": ["1.2.3.1"]}, "00080018": {"vr": "UI", "Value": ["1.2.3.1.2"]}, "00200013": {"vr": "IS", "Value": [2]}},
+                {"0020000E": {"vr": "UI", "Value": ["1.2.3.2"]}, "00080018": {"vr": "UI", "Value": ["1.2.3.2.1"]}},
+                {"0020000E": {"vr": "UI", "Value": ["1.2.3.1"]}, "00080018": {"vr": "UI", "Value": ["1.2.3.1.1"]}, "00200013": {"vr": "IS", "Value": ["1"]}},
+                {"0020000E": {"vr": "UI", "Value": ["../etc"]}, "00080018": {"vr": "UI", "Value": ["1.2"]}}
+            ]"#,
+        )
+        .unwrap();
+        let sop_uids: Vec<String> = instances(&listing).into_iter().map(|(_, sop_uid)| sop_uid).collect();
+        assert_eq!(sop_uids, ["1.2.3.1.1", "1.2.3.1.2", "1.2.3.2.1"]);
+    }
+
+    #[test]
+    fn files_get_a_preamble() {
+        let body = b"\r\n--b\r\nContent-Type: application/dicom\r\n\r\nDICM....\r\n--b--";
+        let file = part10("multipart/related; type=\"application/dicom\"; boundary=b", body).unwrap();
+        assert_eq!(file.len(), 128 + 8);
+        assert_eq!(&file[128..132], b"DICM");
+        assert!(part10("application/dicom", b"not dicom").is_err());
+    }
+
+    #[test]
+    fn file_names_are_safe() {
+        assert_eq!(file_name(Some("SCH-001"), Some("20230724")), "SCH-001_20230724.zip");
+        assert_eq!(file_name(Some("../\"x\""), None), "x.zip");
+        assert_eq!(file_name(None, None), "study.zip");
+    }
+
+    #[test]
+    fn deidentified_download_options() {
+        let params: HashMap<String, String> = [("retain_dates", "true"), ("retain_uids", "false"), ("pseudonym", "CASE 12")]
+            .into_iter()
+            .map(|(key, value)| (key.to_owned(), value.to_owned()))
+            .collect();
+        let options = options(&params);
+        assert!(options.retain_dates && !options.retain_uids && !options.retain_device_identity);
+
+        let named = deidentifier(Some("secret"), options, Some(" CASE 12 ")).unwrap();
+        assert_eq!(named.pseudonym.as_deref(), Some("CASE 12"));
+        assert_eq!(named.uids.uid("1.2.3"), UidMap::new("secret").uid("1.2.3"));
+        assert!(deidentifier(None, options, Some("")).unwrap().pseudonym.is_none());
+        assert!(deidentifier(None, options, Some("A\\B")).is_err());
+    }
+}
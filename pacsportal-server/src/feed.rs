@@ -0,0 +1,172 @@
+//! Live updates for the search and worklist pages, so that every open
+//! terminal need not poll the archive itself: the server searches the archive
+//! for new studies once for everyone, and sees the reports stored through it.
+//! Pages subscribe to `/api/events` as server-sent events and search again
+//! when told of a change. The events name the study only.
+
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::time::Duration;
+
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::sse::{Event as SseEvent, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use chrono::{Days, Local};
+use futures_util::stream;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::config::FeedConfig;
+use crate::download::{fetch, json_text};
+use crate::session;
+use crate::SharedState;
+
+/// Changes kept for a subscriber that falls behind; it misses older ones,
+/// which does no harm as any change makes a page search again.
+const CAPACITY: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Change {
+    /// A study the archive did not have at the last search.
+    StudyArrived { study_uid: String },
+    /// A report was created or amended through the portal.
+    ReportStored { study_uid: String },
+}
+
+pub struct Feed {
+    sender: broadcast::Sender<Change>,
+}
+
+impl Feed {
+    pub fn new() -> Self {
+        Feed {
+            sender: broadcast::channel(CAPACITY).0,
+        }
+    }
+
+    /// Tells every subscribed page of `change`; nobody may be listening.
+    pub fn publish(&self, change: Change) {
+        let _ = self.sender.send(change);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Change> {
+        self.sender.subscribe()
+    }
+}
+
+/// The Study Instance UIDs of a QIDO-RS study search.
+fn study_uids(listing: &[Value]) -> Vec<String> {
+    listing
+        .iter()
+        .filter_map(|study| json_text(study, "0020000D"))
+        .collect()
+}
+
+/// The studies of `found` that are not `known`, in the archive's order.
+pub fn arrived(known: &HashSet<String>, found: &[String]) -> Vec<String> {
+    found.iter().filter(|study_uid| !known.contains(*study_uid)).cloned().collect()
+}
+
+/// The studies of the days the feed watches, up to today.
+async fn watched_studies(state: &SharedState, feed: &FeedConfig) -> Result<Vec<String>, String> {
+    let today = Local::now().date_naive();
+    let from = today.checked_sub_days(Days::new(feed.days)).unwrap_or(today);
+    let path = format!("studies?StudyDate={}-{}&includefield=StudyInstanceUID", from.format("%Y%m%d"), today.format("%Y%m%d"));
+    let (_, body) = fetch(state, &path, "application/dicom+json").await?;
+    if body.is_empty() {
+        return Ok(Vec::new());
+    }
+    let listing = serde_json::from_slice::<Vec<Value>>(&body).map_err(|e| e.to_string())?;
+    Ok(study_uids(&listing))
+}
+
+/// Searches the archive every `poll_seconds` and publishes the studies that
+/// were not there the time before. The first search only learns what is
+/// there already; a failed one is skipped.
+pub fn spawn(state: SharedState) {
+    let feed = state.config.feed.clone();
+    if feed.poll_seconds == 0 {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut known: Option<HashSet<String>> = None;
+        let mut interval = tokio::time::interval(Duration::from_secs(feed.poll_seconds));
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let found = match watched_studies(&state, &feed).await {
+                Ok(found) => found,
+                Err(e) => {
+                    eprintln!("Could not search the archive for new studies: {}", e);
+                    continue;
+                }
+            };
+            if let Some(known) = &known {
+                for study_uid in arrived(known, &found) {
+                    state.feed.publish(Change::StudyArrived { study_uid });
+                }
+            }
+            // studies fall out of the watched days, so only the last search is remembered
+            known = Some(found.into_iter().collect());
+        }
+    });
+}
+
+/// The changes as server-sent events, for as long as the session lasts.
+pub async fn events(State(state): State<SharedState>, headers: HeaderMap) -> Response {
+    let Some(token) = session::token(&headers).filter(|token| state.sessions.get(token).is_some()) else {
+        return (StatusCode::UNAUTHORIZED, "Please log in again.").into_response();
+    };
+    let receiver = state.feed.subscribe();
+    let changes = stream::unfold((receiver, state, token), |(mut receiver, state, token)| async move {
+        loop {
+            let change = match receiver.recv().await {
+                Ok(change) => change,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            };
+            // an open page does not keep its session alive, but ends with it
+            if !state.sessions.is_active(&token) {
+                return None;
+            }
+            let event = SseEvent::default().json_data(&change).unwrap_or_default();
+            return Some((Ok::<_, Infallible>(event), (receiver, state, token)));
+        }
+    });
+    Sse::new(changes).keep_alive(KeepAlive::default()).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn new_studies_are_found_by_uid() {
+        let listing = [
+            json!({"0020000D": {"vr": "UI", "Value": ["1.2.1"]}}),
+            json!({"00100020": {"vr": "LO", "Value": ["MOCK0001"]}}),
+            json!({"0020000D": {"vr": "UI", "Value": ["1.2.3"]}}),
+        ];
+        let found = study_uids(&listing);
+        assert_eq!(found, ["1.2.1", "1.2.3"]);
+        let known: HashSet<String> = [String::from("1.2.1"), String::from("1.2.2")].into_iter().collect();
+        assert_eq!(arrived(&known, &found), ["1.2.3"]);
+    }
+
+    #[test]
+    fn changes_reach_every_subscriber() {
+        let feed = Feed::new();
+        feed.publish(Change::ReportStored { study_uid: String::from("1.2.0") });
+        let mut first = feed.subscribe();
+        let mut second = feed.subscribe();
+        feed.publish(Change::StudyArrived { study_uid: String::from("1.2.3") });
+        let change = first.try_recv().unwrap();
+        assert_eq!(second.try_recv().unwrap(), change);
+        assert!(first.try_recv().is_err());
+        assert_eq!(serde_json::to_value(&change).unwrap(), json!({"type": "study-arrived", "study_uid": "1.2.3"}));
+    }
+}
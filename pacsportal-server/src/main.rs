@@ -0,0 +1,167 @@
+//! Serves the portal and stands between it and the archive. Browsers log in
+//! here and only ever talk to this server: it serves the Trunk build,
+//! terminates TLS, and forwards DICOMweb requests of logged in users to the
+//! archive with its own credentials, so the archive needs neither CORS nor
+//! exposure to the clinical network. Requests to the metadata store of
+//! `pacsportal-store` are forwarded the same way. Studies are downloaded
+//! as ZIPs with a DICOMDIR from here too, as they are or de-identified, see
+//! `download.rs`, and added to the teaching files, see `teaching.rs`.
+//! Consultants without a login open single studies through share links,
+//! see `share.rs`. Open search and worklist pages are told of new studies
+//! and reports as server-sent events, see `feed.rs`.
+//!
+//! ```sh
+//! PACSPORTAL_RS_BASE=/dicomweb PACSPORTAL_API_BASE=/api PACSPORTAL_STORE_BASE=/store trunk build --release
+//! cargo run --release -p pacsportal-server -- --config pacsportal.toml
+//! ```
+//!
+//! See `pacsportal.example.toml` for the configuration, and
+//! `pacsportal-server hash-password` to add users.
+//!
+//! Logins, searches and stored reports are audited here; the portal reports
+//! the study opens and exports only it sees. See `audit.rs`.
+
+mod audit;
+mod config;
+mod download;
+mod feed;
+mod proxy;
+mod session;
+mod share;
+mod sink;
+mod store;
+mod teaching;
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::routing::{any, delete, get, post};
+use axum::Router;
+use axum_server::tls_rustls::RustlsConfig;
+use clap::{Parser, Subcommand};
+use hyper::client::HttpConnector;
+use hyper::Client;
+use tower_http::services::{ServeDir, ServeFile};
+
+use crate::audit::Auditor;
+use crate::config::Config;
+use crate::feed::Feed;
+use crate::session::Sessions;
+use crate::share::Shares;
+
+pub struct AppState {
+    pub config: Config,
+    pub sessions: Sessions,
+    pub auditor: Auditor,
+    /// Share links, when sharing is configured.
+    pub shares: Option<Shares>,
+    pub feed: Feed,
+    pub client: Client<HttpConnector>,
+}
+
+pub type SharedState = Arc<AppState>;
+
+#[derive(Parser)]
+#[command(about = "Serves the portal and proxies DICOMweb requests to the archive")]
+struct Args {
+    /// Configuration file.
+    #[arg(long, default_value = "pacsportal.toml")]
+    config: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Prints the password_hash of a user for the configuration file.
+    HashPassword { password: String },
+}
+
+fn routes(state: SharedState) -> Router {
+    let dist = state.config.dist.clone();
+    // unknown paths are routes of the app, e.g. a reload of /search
+    let app = ServeDir::new(&dist).fallback(ServeFile::new(dist.join("index.html")));
+    Router::new()
+        .route("/api/login", post(session::login))
+        .route("/api/logout", post(session::logout))
+        .route("/api/session", get(session::current))
+        .route("/api/events", get(feed::events))
+        .route("/api/audit", get(audit::browse).post(audit::report))
+        .route("/api/studies/:study/download", get(download::study))
+        .route("/api/studies/:study/deidentified", get(download::deidentified))
+        .route("/api/studies/:study/teaching", post(teaching::add))
+        .route("/api/shares", get(share::list).post(share::create))
+        .route("/api/shares/:id", delete(share::revoke))
+        .route("/api/shared/:token", get(share::summary))
+        .route("/api/shared/:token/dicomweb/*path", get(share::forward))
+        .route("/dicomweb/*path", any(proxy::forward))
+        .route("/store/*path", any(store::forward))
+        .fallback_service(app)
+        .with_state(state)
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+    if let Some(Command::HashPassword { password }) = args.command {
+        println!("{}", session::hash_password(&password));
+        return;
+    }
+
+    let config = match Config::load(&args.config) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    if config.users.is_empty() {
+        eprintln!("No users are configured; nobody will be able to log in.");
+    }
+    let listen = config.listen;
+    let tls = config.tls.clone();
+    let client = Client::new();
+    let sink = sink::spawn(&config.audit, client.clone());
+    let auditor = match Auditor::new(&config.audit.source_id, config.audit.log.as_deref(), sink) {
+        Ok(auditor) => auditor,
+        Err(e) => {
+            eprintln!("Could not open the audit log {}", e);
+            std::process::exit(1);
+        }
+    };
+    let shares = match config.sharing.as_ref().map(Shares::from_config).transpose() {
+        Ok(shares) => shares,
+        Err(e) => {
+            eprintln!("Could not open the share registry {}", e);
+            std::process::exit(1);
+        }
+    };
+    let state = Arc::new(AppState {
+        sessions: Sessions::new(Duration::from_secs(config.session_minutes * 60)),
+        auditor,
+        shares,
+        feed: Feed::new(),
+        client,
+        config,
+    });
+    feed::spawn(state.clone());
+    // client addresses go into the audit trail
+    let app = routes(state).into_make_service_with_connect_info::<SocketAddr>();
+
+    match tls {
+        Some(tls) => {
+            let rustls = RustlsConfig::from_pem_file(&tls.certificate, &tls.key)
+                .await
+                .expect("could not load the TLS certificate and key");
+            println!("Serving the portal on https://{}", listen);
+            axum_server::bind_rustls(listen, rustls).serve(app).await.expect("server error");
+        }
+        None => {
+            eprintln!("No [tls] section is configured; serving plain HTTP.");
+            println!("Serving the portal on http://{}", listen);
+            axum::Server::bind(&listen).serve(app).await.expect("server error");
+        }
+    }
+}
@@ -0,0 +1,365 @@
+use std::net::SocketAddr;
+
+use axum::body::Body;
+use axum::extract::{self, ConnectInfo, Path, State};
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, Method, Request, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use dicom::dictionary_std::tags;
+use pacsportal_core::attributes::{person_name, text};
+use pacsportal_core::codes;
+use pacsportal_core::critical::CRITICAL_ACKNOWLEDGEMENT;
+use pacsportal_core::stow::{self, Part};
+
+use crate::audit::{Event, Record};
+use crate::config::{Archive, Role};
+use crate::feed::Change;
+use crate::SharedState;
+
+/// Largest STOW-RS body the portal sends. Every store is read to check and
+/// audit what is stored; reports and key image selections are a few kilobytes.
+const INSPECTED_BODY_LIMIT: usize = 4 * 1024 * 1024;
+
+/// Headers that concern a single connection, plus the client's own
+/// credentials, none of which are passed on.
+const DROPPED_HEADERS: [HeaderName; 9] = [
+    header::HOST,
+    header::COOKIE,
+    header::AUTHORIZATION,
+    header::CONNECTION,
+    header::PROXY_AUTHORIZATION,
+    header::TE,
+    header::TRAILER,
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+];
+
+/// Whether `path`, relative to the DICOMweb root, is a STOW-RS endpoint:
+/// `studies` or `studies/{StudyInstanceUID}`.
+fn is_stow(path: &str) -> bool {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    segments[0] == "studies" && segments.len() <= 2
+}
+
+/// Whether every segment of `path` names something, so that the path stays
+/// below the root it is appended to: no `.`, `..` or empty segments, which
+/// the archive would resolve outside its DICOMweb root.
+pub fn is_plain(path: &str) -> bool {
+    path.trim_start_matches('/')
+        .split('/')
+        .all(|segment| !segment.is_empty() && segment != "." && segment != "..")
+}
+
+/// Whether a `method` request to `path` may be forwarded at all. Everyone
+/// may read; the only writes are STOW-RS stores, which [`may_store`] checks
+/// for referring physicians.
+pub fn may_request(method: &Method, path: &str) -> Result<(), &'static str> {
+    if !is_plain(path) {
+        return Err("This request is not allowed through the portal.");
+    }
+    match *method {
+        Method::GET | Method::HEAD => Ok(()),
+        Method::POST if is_stow(path) => Ok(()),
+        _ => Err("This request is not allowed through the portal."),
+    }
+}
+
+/// Whether the user may store the datasets of a STOW-RS body. Referring
+/// physicians may only store critical result acknowledgements, and nobody
+/// may sign or create a document under another name than their `username`
+/// or the `name` they sign reports with.
+pub fn may_store(role: Role, username: &str, name: &str, parts: &[Part]) -> Result<(), String> {
+    if parts.is_empty() {
+        return Err(String::from("The request contains no instances."));
+    }
+    let is_user = |person: &String| [username, name].iter().any(|own| spoken_name(own).eq_ignore_ascii_case(person));
+    for part in parts {
+        let dataset = part.dataset();
+        let observers = dataset
+            .get(tags::VERIFYING_OBSERVER_SEQUENCE)
+            .and_then(|observers| observers.items())
+            .unwrap_or_default();
+        let mut people = observers
+            .iter()
+            .filter_map(|observer| person_name(observer, tags::VERIFYING_OBSERVER_NAME))
+            .chain(person_name(dataset, tags::CONTENT_CREATOR_NAME));
+        if let Some(other) = people.find(|person| !is_user(person)) {
+            return Err(format!("Documents may not be signed or created as {}.", other));
+        }
+    }
+    if role == Role::Radiologist {
+        return Ok(());
+    }
+    if parts
+        .iter()
+        .all(|part| codes::has_title(part.dataset(), &CRITICAL_ACKNOWLEDGEMENT))
+    {
+        Ok(())
+    } else {
+        Err(String::from("Only radiologists may store reports and key images."))
+    }
+}
+
+/// A person name as [`person_name`] gives it, with `^` separators as spaces.
+fn spoken_name(name: &str) -> String {
+    name.split('^')
+        .map(|component| component.trim())
+        .filter(|component| !component.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Audit records of storing `parts`: reports are created, or amended when
+/// they name the report they replace. Acknowledgements of critical results
+/// are SRs too, but not reports.
+fn stored_records(parts: &[Part], username: &str, address: SocketAddr) -> Vec<Record> {
+    parts
+        .iter()
+        .map(|part| {
+            let dataset = part.dataset();
+            let event = if text(dataset, tags::MODALITY).as_deref() != Some("SR") {
+                Event::InstancesStored
+            } else if codes::has_title(dataset, &CRITICAL_ACKNOWLEDGEMENT) {
+                Event::CriticalAcknowledged
+            } else if dataset.get(tags::PREDECESSOR_DOCUMENTS_SEQUENCE).is_some() {
+                Event::ReportAmended
+            } else {
+                Event::ReportCreated
+            };
+            Record::new(event, false, username, address)
+                .patient(text(dataset, tags::PATIENT_ID), text(dataset, tags::PATIENT_NAME))
+                .study(text(dataset, tags::STUDY_INSTANCE_UID))
+                .detail(text(dataset, tags::SOP_INSTANCE_UID).unwrap_or_default())
+        })
+        .collect()
+}
+
+/// The audit record of a QIDO-RS study search, naming the patient or study
+/// when the search is for one.
+fn query_record(uri: &Uri, username: &str, address: SocketAddr) -> Record {
+    let params = extract::Query::<Vec<(String, String)>>::try_from_uri(uri)
+        .map(|extract::Query(params)| params)
+        .unwrap_or_default();
+    let param = |key: &str| params.iter().find(|(k, value)| k == key && !value.is_empty()).map(|(_, value)| value.clone());
+    Record::new(Event::Query, false, username, address)
+        .patient(param("PatientID"), param("PatientName"))
+        .study(param("StudyInstanceUID"))
+        .detail(uri.query().unwrap_or_default())
+}
+
+/// The Authorization header the archive expects from the portal.
+pub fn credentials(archive: &Archive) -> Option<HeaderValue> {
+    let value = match (&archive.token, &archive.username) {
+        (Some(token), _) => format!("Bearer {}", token),
+        (None, Some(username)) => format!(
+            "Basic {}",
+            STANDARD.encode(format!("{}:{}", username, archive.password.as_deref().unwrap_or_default()))
+        ),
+        (None, None) => return None,
+    };
+    HeaderValue::from_str(&value).ok()
+}
+
+pub fn strip_headers(headers: &mut HeaderMap) {
+    for name in DROPPED_HEADERS {
+        headers.remove(name);
+    }
+}
+
+/// Forwards a DICOMweb request to the archive on behalf of the logged in
+/// user, with the archive credentials of the server. Searches and stores are
+/// audited once the archive has answered.
+pub async fn forward(
+    State(state): State<SharedState>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    Path(path): Path<String>,
+    request: Request<Body>,
+) -> Response {
+    let Some(session) = state.sessions.for_request(request.headers()) else {
+        return (StatusCode::UNAUTHORIZED, "Please log in again.").into_response();
+    };
+    if let Err(e) = may_request(request.method(), &path) {
+        return (StatusCode::FORBIDDEN, e).into_response();
+    }
+
+    let (mut parts, body) = request.into_parts();
+    let mut audited = Vec::new();
+    let body = if parts.method == Method::POST {
+        let too_large = parts
+            .headers
+            .get(header::CONTENT_LENGTH)
+            .and_then(|length| length.to_str().ok())
+            .and_then(|length| length.parse::<usize>().ok())
+            .is_some_and(|length| length > INSPECTED_BODY_LIMIT);
+        if too_large {
+            return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+        }
+        let bytes = match hyper::body::to_bytes(body).await {
+            Ok(bytes) if bytes.len() <= INSPECTED_BODY_LIMIT => bytes,
+            Ok(_) => return StatusCode::PAYLOAD_TOO_LARGE.into_response(),
+            Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+        };
+        let content_type = parts
+            .headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        let datasets = match stow::parse(content_type, &bytes) {
+            Ok(datasets) => datasets,
+            Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+        };
+        if let Err(e) = may_store(session.role, &session.username, &session.name, &datasets) {
+            return (StatusCode::FORBIDDEN, e).into_response();
+        }
+        audited = stored_records(&datasets, &session.username, address);
+        Body::from(bytes)
+    } else {
+        if path.trim_matches('/') == "studies" {
+            audited.push(query_record(&parts.uri, &session.username, address));
+        }
+        body
+    };
+
+    let query = parts.uri.query().map(|query| format!("?{}", query)).unwrap_or_default();
+    let upstream = format!("{}/{}{}", state.config.archive.url.trim_end_matches('/'), path.trim_start_matches('/'), query);
+    parts.uri = match upstream.parse::<Uri>() {
+        Ok(uri) => uri,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    strip_headers(&mut parts.headers);
+    if let Some(credentials) = credentials(&state.config.archive) {
+        parts.headers.insert(header::AUTHORIZATION, credentials);
+    }
+
+    let response = state.client.request(Request::from_parts(parts, body)).await;
+    let success = response.as_ref().is_ok_and(|response| response.status().is_success());
+    for mut record in audited {
+        record.success = success;
+        if let (true, Event::ReportCreated | Event::ReportAmended, Some(study_uid)) = (success, record.event, &record.study_uid) {
+            state.feed.publish(Change::ReportStored { study_uid: study_uid.clone() });
+        }
+        state.auditor.record(record);
+    }
+    match response {
+        Ok(response) => {
+            let (mut parts, body) = response.into_parts();
+            strip_headers(&mut parts.headers);
+            // the archive's own session cookies stay between it and the server
+            parts.headers.remove(header::SET_COOKIE);
+            Response::from_parts(parts, axum::body::boxed(body))
+        }
+        Err(e) => {
+            eprintln!("Archive request for {} failed: {}", session.username, e);
+            (StatusCode::BAD_GATEWAY, "The archive could not be reached.").into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use dicom::core::{DataElement, DicomValue, Length, VR};
+    use dicom::object::InMemDicomObject;
+    use pacsportal_core::critical::acknowledgement;
+    use pacsportal_core::report::ReportBuilder;
+    use pacsportal_core::stow::{stow_body, CONTENT_TYPE};
+
+    fn study() -> InMemDicomObject {
+        InMemDicomObject::from_element_iter([
+            DataElement::new(tags::STUDY_INSTANCE_UID, VR::UI, "1.2.3"),
+            DataElement::new(tags::PATIENT_ID, VR::LO, "MOCK0001"),
+        ])
+    }
+
+    #[test]
+    fn everyone_reads_but_only_stow_writes() {
+        assert!(may_request(&Method::GET, "studies/1.2.3/series").is_ok());
+        assert!(may_request(&Method::POST, "studies").is_ok());
+        assert!(may_request(&Method::POST, "studies/1.2.3").is_ok());
+        assert!(may_request(&Method::POST, "studies/1.2.3/reject/113001^DCM").is_err());
+        assert!(may_request(&Method::DELETE, "studies/1.2.3").is_err());
+    }
+
+    #[test]
+    fn requests_stay_below_the_dicomweb_root() {
+        assert!(may_request(&Method::GET, "/studies/1.2.3/series/4/instances/5/rendered").is_ok());
+        assert!(may_request(&Method::GET, "../../admin/config").is_err());
+        assert!(may_request(&Method::GET, "studies/../../patients").is_err());
+        assert!(may_request(&Method::HEAD, "studies/./1.2.3").is_err());
+        assert!(may_request(&Method::GET, "studies//1.2.3").is_err());
+        assert!(may_request(&Method::POST, "studies/..").is_err());
+    }
+
+    #[test]
+    fn referring_physicians_only_store_acknowledgements() {
+        let now = NaiveDate::from_ymd_opt(2023, 7, 25).unwrap().and_hms_opt(9, 30, 0).unwrap();
+        let report = ReportBuilder::new(&study(), "Pneumothorax.", "DR WASAY JILANI").critical(true).at(now).build();
+        let ack = acknowledgement(&report, "root", now);
+
+        let report = stow::parse(CONTENT_TYPE, stow_body(report).as_bytes()).unwrap();
+        let ack = stow::parse(CONTENT_TYPE, stow_body(ack).as_bytes()).unwrap();
+        assert!(may_store(Role::Radiologist, "radiologist", "DR WASAY JILANI", &report).is_ok());
+        assert!(may_store(Role::Referring, "root", "Root", &ack).is_ok());
+        assert!(may_store(Role::Referring, "root", "Root", &report).is_err());
+        assert!(may_store(Role::Radiologist, "radiologist", "DR WASAY JILANI", &[]).is_err());
+    }
+
+    #[test]
+    fn nobody_signs_for_someone_else() {
+        let now = NaiveDate::from_ymd_opt(2023, 7, 25).unwrap().and_hms_opt(9, 30, 0).unwrap();
+        let report = ReportBuilder::new(&study(), "Pneumothorax.", "JILANI^WASAY").critical(true).at(now).build();
+        let ack = acknowledgement(&report, "root", now);
+
+        let report = stow::parse(CONTENT_TYPE, stow_body(report).as_bytes()).unwrap();
+        let ack = stow::parse(CONTENT_TYPE, stow_body(ack).as_bytes()).unwrap();
+        assert!(may_store(Role::Radiologist, "wasay", "Jilani Wasay", &report).is_ok());
+        assert!(may_store(Role::Radiologist, "ayesha", "DR AYESHA KHAN", &report).is_err());
+        assert!(may_store(Role::Radiologist, "root", "Root", &ack).is_ok());
+        assert!(may_store(Role::Referring, "referrer", "Referrer", &ack).is_err());
+    }
+
+    #[test]
+    fn stores_and_searches_are_audited() {
+        let address = SocketAddr::from(([10, 0, 0, 5], 51000));
+        let mut report = ReportBuilder::new(&study(), "No acute findings.", "DR WASAY JILANI").build();
+        let created = stored_records(&[Part::Json(report.clone())], "radiologist", address);
+        assert_eq!(created[0].event, Event::ReportCreated);
+        assert_eq!(created[0].patient_id.as_deref(), Some("MOCK0001"));
+        assert_eq!(created[0].study_uid.as_deref(), Some("1.2.3"));
+        assert_eq!(created[0].detail, text(&report, tags::SOP_INSTANCE_UID).unwrap());
+
+        report.put(DataElement::new(tags::PREDECESSOR_DOCUMENTS_SEQUENCE, VR::SQ, DicomValue::new_sequence(Vec::<InMemDicomObject>::new(), Length::UNDEFINED)));
+        let amended = stored_records(&[Part::Json(report)], "radiologist", address);
+        assert_eq!(amended[0].event, Event::ReportAmended);
+
+        let critical = ReportBuilder::new(&study(), "Pneumothorax.", "DR WASAY JILANI").critical(true).build();
+        let ack = acknowledgement(&critical, "root", NaiveDate::from_ymd_opt(2023, 7, 25).unwrap().and_hms_opt(10, 0, 0).unwrap());
+        let acknowledged = stored_records(&[Part::Json(ack)], "root", address);
+        assert_eq!(acknowledged[0].event, Event::CriticalAcknowledged);
+
+        let uri: Uri = "/dicomweb/studies?PatientID=MOCK0001&includefield=StudyDescription".parse().unwrap();
+        let query = query_record(&uri, "root", address);
+        assert_eq!(query.event, Event::Query);
+        assert_eq!(query.patient_id.as_deref(), Some("MOCK0001"));
+        assert_eq!(query.study_uid, None);
+        assert_eq!(query.detail, "PatientID=MOCK0001&includefield=StudyDescription");
+    }
+
+    #[test]
+    fn archive_credentials() {
+        let mut archive = Archive {
+            url: String::from("http://archive/rs"),
+            username: Some(String::from("portal")),
+            password: Some(String::from("secret")),
+            token: None,
+        };
+        assert_eq!(credentials(&archive).unwrap(), "Basic cG9ydGFsOnNlY3JldA==");
+        archive.token = Some(String::from("abc"));
+        assert_eq!(credentials(&archive).unwrap(), "Bearer abc");
+        archive.token = None;
+        archive.username = None;
+        assert!(credentials(&archive).is_none());
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use axum::extract::{ConnectInfo, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::Deserialize;
+
+use crate::audit::{Event, Record};
+use crate::config::{Role, User};
+use crate::SharedState;
+
+pub const COOKIE: &str = "pacsportal_session";
+
+/// A logged in user.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    pub username: String,
+    /// The name the user signs reports with.
+    pub name: String,
+    pub role: Role,
+    pub department: String,
+    pub admin: bool,
+    last_seen: Instant,
+}
+
+/// Sessions by token. They live in memory only, so a restart logs everyone out.
+pub struct Sessions {
+    idle: Duration,
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+impl Sessions {
+    pub fn new(idle: Duration) -> Self {
+        Sessions {
+            idle,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Starts a session for `user` and returns its token.
+    pub fn start(&self, user: &User) -> String {
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        let token = URL_SAFE_NO_PAD.encode(bytes);
+        let session = Session {
+            username: user.username.clone(),
+            name: user.name.clone().unwrap_or_else(|| user.username.clone()),
+            role: user.role,
+            department: user.department.clone(),
+            admin: user.admin,
+            last_seen: Instant::now(),
+        };
+        let mut sessions = self.sessions.lock().unwrap();
+        let idle = self.idle;
+        sessions.retain(|_, session| session.last_seen.elapsed() < idle);
+        sessions.insert(token.clone(), session);
+        token
+    }
+
+    /// The session of `token` unless it has been idle for too long. Using a
+    /// session keeps it alive.
+    pub fn get(&self, token: &str) -> Option<Session> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions.get_mut(token)?;
+        if session.last_seen.elapsed() >= self.idle {
+            sessions.remove(token);
+            return None;
+        }
+        session.last_seen = Instant::now();
+        Some(session.clone())
+    }
+
+    /// Whether the session of `token` is still alive, without keeping it so.
+    pub fn is_active(&self, token: &str) -> bool {
+        self.sessions
+            .lock()
+            .unwrap()
+            .get(token)
+            .is_some_and(|session| session.last_seen.elapsed() < self.idle)
+    }
+
+    pub fn end(&self, token: &str) {
+        self.sessions.lock().unwrap().remove(token);
+    }
+
+    /// The session a request belongs to, going by its cookie.
+    pub fn for_request(&self, headers: &HeaderMap) -> Option<Session> {
+        self.get(&token(headers)?)
+    }
+}
+
+/// The session token in the Cookie header of a request.
+pub fn token(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .find_map(|cookie| {
+            let (name, value) = cookie.trim().split_once('=')?;
+            (name == COOKIE && !value.is_empty()).then(|| value.to_owned())
+        })
+}
+
+/// A Set-Cookie value for the session token. An empty token removes the cookie.
+pub fn cookie(token: &str, max_age: Duration, secure: bool) -> String {
+    let max_age = if token.is_empty() { 0 } else { max_age.as_secs() };
+    format!(
+        "{}={}; Path=/; Max-Age={}; HttpOnly; SameSite=Strict{}",
+        COOKIE,
+        token,
+        max_age,
+        if secure { "; Secure" } else { "" }
+    )
+}
+
+pub fn hash_password(password: &str) -> String {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .expect("hashing with the default parameters")
+        .to_string()
+}
+
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash)
+        .map(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok())
+        .unwrap_or(false)
+}
+
+/// What the portal is told about the logged in user.
+fn session_json(session: &Session) -> serde_json::Value {
+    serde_json::json!({
+        "username": session.username,
+        "name": session.name,
+        "radiologist": session.role == Role::Radiologist,
+        "department": session.department,
+        "admin": session.admin,
+    })
+}
+
+#[derive(Deserialize)]
+pub struct Credentials {
+    username: String,
+    password: String,
+}
+
+pub async fn login(
+    State(state): State<SharedState>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    Json(credentials): Json<Credentials>,
+) -> Response {
+    let user = state
+        .config
+        .user(&credentials.username)
+        .filter(|user| verify_password(&credentials.password, &user.password_hash));
+    state
+        .auditor
+        .record(Record::new(Event::Login, user.is_some(), &credentials.username, address));
+    let Some(user) = user else {
+        return (StatusCode::UNAUTHORIZED, "Incorrect username and password.").into_response();
+    };
+    let token = state.sessions.start(user);
+    let session = state.sessions.get(&token).expect("the session was just started");
+    let set_cookie = cookie(&token, state.sessions.idle, state.config.tls.is_some());
+    ([(header::SET_COOKIE, set_cookie)], Json(session_json(&session))).into_response()
+}
+
+pub async fn logout(State(state): State<SharedState>, ConnectInfo(address): ConnectInfo<SocketAddr>, headers: HeaderMap) -> Response {
+    if let Some(session) = state.sessions.for_request(&headers) {
+        state.auditor.record(Record::new(Event::Logout, true, &session.username, address));
+    }
+    if let Some(token) = token(&headers) {
+        state.sessions.end(&token);
+    }
+    let set_cookie = cookie("", Duration::ZERO, state.config.tls.is_some());
+    (StatusCode::NO_CONTENT, [(header::SET_COOKIE, set_cookie)]).into_response()
+}
+
+pub async fn current(State(state): State<SharedState>, headers: HeaderMap) -> Response {
+    match state.sessions.for_request(&headers) {
+        Some(session) => Json(session_json(&session)).into_response(),
+        None => StatusCode::UNAUTHORIZED.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn user() -> User {
+        User {
+            username: String::from("radiologist"),
+            password_hash: hash_password("secret"),
+            role: Role::Radiologist,
+            name: Some(String::from("DR WASAY JILANI")),
+            department: String::from("Radiology"),
+            admin: false,
+        }
+    }
+
+    #[test]
+    fn passwords_are_checked_against_their_hash() {
+        let hash = hash_password("secret");
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify_password("secret", &hash));
+        assert!(!verify_password("Secret", &hash));
+        assert!(!verify_password("secret", "not a hash"));
+    }
+
+    #[test]
+    fn sessions_expire_when_idle() {
+        let sessions = Sessions::new(Duration::from_secs(60));
+        let token = sessions.start(&user());
+        assert_eq!(sessions.get(&token).unwrap().role, Role::Radiologist);
+        assert!(sessions.get("forged").is_none());
+        sessions.end(&token);
+        assert!(sessions.get(&token).is_none());
+
+        let sessions = Sessions::new(Duration::ZERO);
+        let token = sessions.start(&user());
+        assert!(sessions.get(&token).is_none());
+    }
+
+    #[test]
+    fn the_token_is_read_from_the_cookie_header() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(token(&headers), None);
+        headers.insert(header::COOKIE, HeaderValue::from_static("theme=dark; pacsportal_session=abc; other=1"));
+        assert_eq!(token(&headers).as_deref(), Some("abc"));
+        assert_eq!(
+            cookie("abc", Duration::from_secs(600), true),
+            "pacsportal_session=abc; Path=/; Max-Age=600; HttpOnly; SameSite=Strict; Secure"
+        );
+        assert!(cookie("", Duration::from_secs(600), false).contains("Max-Age=0"));
+    }
+}
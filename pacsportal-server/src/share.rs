@@ -0,0 +1,470 @@
+//! Share links: signed, expiring and revocable links that give consultants
+//! outside the hospital read-only access to the report and images of one
+//! study, without a login. A link carries the study and its expiry, signed
+//! with HMAC-SHA256; the registry, kept like the audit log as a JSON line
+//! per change, knows whether it was revoked. Every request through a link is
+//! audited.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::net::SocketAddr;
+use std::path::Path as FilePath;
+use std::sync::Mutex;
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use axum::body::Body;
+use axum::extract::{ConnectInfo, Path, Query, State};
+use axum::http::{header, HeaderMap, Method, Request, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, FixedOffset, Local};
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::audit::{Event, Record};
+use crate::config::{Role, Sharing};
+use crate::download::{fetch, is_uid, json_text};
+use crate::proxy::{credentials, is_plain, strip_headers};
+use crate::SharedState;
+
+/// A link as registered.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Share {
+    pub id: String,
+    pub study_uid: String,
+    #[serde(default)]
+    pub patient_id: Option<String>,
+    #[serde(default)]
+    pub patient_name: Option<String>,
+    pub created_by: String,
+    pub created: DateTime<FixedOffset>,
+    pub expires: DateTime<FixedOffset>,
+    #[serde(default)]
+    pub revoked: Option<DateTime<FixedOffset>>,
+    #[serde(default)]
+    pub revoked_by: Option<String>,
+}
+
+impl Share {
+    pub fn is_active(&self, now: DateTime<FixedOffset>) -> bool {
+        self.revoked.is_none() && now < self.expires
+    }
+
+    /// What the link signs: its ID, study and expiry.
+    fn payload(&self) -> String {
+        format!("{}.{}.{}", self.id, self.study_uid, self.expires.timestamp())
+    }
+}
+
+/// The links issued, and the key they are signed with.
+pub struct Shares {
+    key: hmac::Key,
+    shares: Mutex<Vec<Share>>,
+    file: Mutex<Option<File>>,
+}
+
+impl Shares {
+    pub fn new(secret: &str, registry: Option<&FilePath>) -> Result<Self, String> {
+        let mut shares: Vec<Share> = Vec::new();
+        let file = match registry {
+            Some(path) => {
+                if let Ok(existing) = File::open(path) {
+                    for line in BufReader::new(existing).lines().map_while(Result::ok) {
+                        match serde_json::from_str::<Share>(&line) {
+                            // a revocation is written as the share again
+                            Ok(share) => match shares.iter_mut().find(|known| known.id == share.id) {
+                                Some(known) => *known = share,
+                                None => shares.push(share),
+                            },
+                            Err(e) => eprintln!("Skipping unreadable share in {}: {}", path.display(), e),
+                        }
+                    }
+                }
+                Some(
+                    OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(path)
+                        .map_err(|e| format!("{}: {}", path.display(), e))?,
+                )
+            }
+            None => None,
+        };
+        Ok(Shares {
+            key: hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes()),
+            shares: Mutex::new(shares),
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn from_config(sharing: &Sharing) -> Result<Self, String> {
+        Shares::new(&sharing.secret, Some(&sharing.registry))
+    }
+
+    fn write(&self, share: &Share) {
+        if let Some(file) = self.file.lock().unwrap().as_mut() {
+            let line = serde_json::to_string(share).expect("shares serialize");
+            if let Err(e) = writeln!(file, "{}", line) {
+                eprintln!("Could not write the share registry: {}", e);
+            }
+        }
+    }
+
+    /// The token of a link: its payload and signature, both base64url.
+    pub fn token(&self, share: &Share) -> String {
+        let payload = share.payload();
+        let signature = hmac::sign(&self.key, payload.as_bytes());
+        format!("{}.{}", URL_SAFE_NO_PAD.encode(payload), URL_SAFE_NO_PAD.encode(signature.as_ref()))
+    }
+
+    /// Registers a new link to a study, valid for `days`.
+    pub fn issue(&self, study_uid: &str, patient: (Option<String>, Option<String>), username: &str, days: u32) -> Share {
+        let mut id = [0u8; 16];
+        OsRng.fill_bytes(&mut id);
+        let created = Local::now().fixed_offset();
+        let share = Share {
+            id: URL_SAFE_NO_PAD.encode(id),
+            study_uid: study_uid.to_owned(),
+            patient_id: patient.0,
+            patient_name: patient.1,
+            created_by: username.to_owned(),
+            created,
+            expires: created + chrono::Duration::days(days.into()),
+            revoked: None,
+            revoked_by: None,
+        };
+        self.write(&share);
+        self.shares.lock().unwrap().push(share.clone());
+        share
+    }
+
+    pub fn revoke(&self, id: &str, username: &str) -> Option<Share> {
+        let mut shares = self.shares.lock().unwrap();
+        let share = shares.iter_mut().find(|share| share.id == id)?;
+        if share.revoked.is_none() {
+            share.revoked = Some(Local::now().fixed_offset());
+            share.revoked_by = Some(username.to_owned());
+            self.write(share);
+        }
+        Some(share.clone())
+    }
+
+    /// The links to a study, newest first.
+    pub fn for_study(&self, study_uid: &str) -> Vec<Share> {
+        let mut shares: Vec<Share> = self.shares.lock().unwrap().iter().filter(|share| share.study_uid == study_uid).cloned().collect();
+        shares.sort_by_key(|share| std::cmp::Reverse(share.created));
+        shares
+    }
+
+    /// The link a token stands for, if it is genuine, registered, unexpired
+    /// and not revoked.
+    pub fn verify(&self, token: &str, now: DateTime<FixedOffset>) -> Result<Share, &'static str> {
+        const INVALID: &str = "This link is not valid.";
+        let (payload, signature) = token.split_once('.').ok_or(INVALID)?;
+        let payload = URL_SAFE_NO_PAD.decode(payload).map_err(|_| INVALID)?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| INVALID)?;
+        hmac::verify(&self.key, &payload, &signature).map_err(|_| INVALID)?;
+        let payload = String::from_utf8(payload).map_err(|_| INVALID)?;
+        let shares = self.shares.lock().unwrap();
+        let share = shares.iter().find(|share| share.payload() == payload).ok_or(INVALID)?;
+        if share.revoked.is_some() {
+            return Err("This link has been revoked.");
+        }
+        if now >= share.expires {
+            return Err("This link has expired.");
+        }
+        Ok(share.clone())
+    }
+}
+
+/// A link as the portal lists it.
+#[derive(Debug, Serialize)]
+pub struct Link {
+    pub id: String,
+    /// Only for active links.
+    pub token: Option<String>,
+    pub created_by: String,
+    pub created: DateTime<FixedOffset>,
+    pub expires: DateTime<FixedOffset>,
+    pub revoked: Option<DateTime<FixedOffset>>,
+}
+
+impl Link {
+    fn new(shares: &Shares, share: &Share) -> Link {
+        let now = Local::now().fixed_offset();
+        Link {
+            id: share.id.clone(),
+            token: share.is_active(now).then(|| shares.token(share)),
+            created_by: share.created_by.clone(),
+            created: share.created,
+            expires: share.expires,
+            revoked: share.revoked,
+        }
+    }
+}
+
+/// The shares of the server, or why the logged in user may not handle them.
+fn shares_for<'a>(state: &'a SharedState, headers: &HeaderMap) -> Result<(&'a Shares, String), (StatusCode, &'static str)> {
+    let Some(session) = state.sessions.for_request(headers) else {
+        return Err((StatusCode::UNAUTHORIZED, "Please log in again."));
+    };
+    if session.role != Role::Radiologist {
+        return Err((StatusCode::FORBIDDEN, "Only radiologists may share studies."));
+    }
+    match &state.shares {
+        Some(shares) => Ok((shares, session.username)),
+        None => Err((StatusCode::NOT_FOUND, "Sharing is not configured.")),
+    }
+}
+
+/// The study a QIDO-RS search of the archive finds for a UID.
+async fn find_study(state: &SharedState, study_uid: &str) -> Result<Option<Value>, String> {
+    let path = format!("studies?StudyInstanceUID={}&includefield=StudyDescription", study_uid);
+    let (_, body) = fetch(state, &path, "application/dicom+json").await?;
+    if body.is_empty() {
+        return Ok(None);
+    }
+    let found = serde_json::from_slice::<Vec<Value>>(&body).map_err(|e| e.to_string())?;
+    Ok(found.into_iter().next())
+}
+
+#[derive(Deserialize)]
+pub struct NewShare {
+    pub study_uid: String,
+    pub days: u32,
+}
+
+/// Creates a link to a study. Only radiologists may.
+pub async fn create(
+    State(state): State<SharedState>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(new): Json<NewShare>,
+) -> Response {
+    let (shares, username) = match shares_for(&state, &headers) {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
+    };
+    let max_days = state.config.sharing.as_ref().map(|sharing| sharing.max_days).unwrap_or_default();
+    if new.days == 0 || new.days > max_days {
+        return (StatusCode::BAD_REQUEST, format!("Links are valid for 1 to {} days.", max_days)).into_response();
+    }
+    if !is_uid(&new.study_uid) {
+        return (StatusCode::BAD_REQUEST, "This is not a Study Instance UID.").into_response();
+    }
+    let study = match find_study(&state, &new.study_uid).await {
+        Ok(Some(study)) => study,
+        Ok(None) => return (StatusCode::NOT_FOUND, "The study is not in the archive.").into_response(),
+        Err(e) => {
+            eprintln!("Sharing {} for {} failed: {}", new.study_uid, username, e);
+            return (StatusCode::BAD_GATEWAY, "The archive could not be searched.").into_response();
+        }
+    };
+
+    let patient = (json_text(&study, "00100020"), json_text(&study, "00100010"));
+    let share = shares.issue(&new.study_uid, patient, &username, new.days);
+    state.auditor.record(
+        Record::new(Event::ShareCreated, true, &username, address)
+            .patient(share.patient_id.clone(), share.patient_name.clone())
+            .study(Some(share.study_uid.clone()))
+            .detail(format!("link {}, until {}", share.id, share.expires.to_rfc3339())),
+    );
+    Json(Link::new(shares, &share)).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct ListQuery {
+    pub study: String,
+}
+
+/// The links to a study, newest first.
+pub async fn list(State(state): State<SharedState>, headers: HeaderMap, Query(query): Query<ListQuery>) -> Response {
+    match shares_for(&state, &headers) {
+        Ok((shares, _)) => Json(shares.for_study(&query.study).iter().map(|share| Link::new(shares, share)).collect::<Vec<_>>()).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Revokes a link; the links of a study may be revoked by any radiologist.
+pub async fn revoke(
+    State(state): State<SharedState>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let (shares, username) = match shares_for(&state, &headers) {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
+    };
+    let Some(share) = shares.revoke(&id, &username) else {
+        return (StatusCode::NOT_FOUND, "There is no such link.").into_response();
+    };
+    state.auditor.record(
+        Record::new(Event::ShareRevoked, true, &username, address)
+            .patient(share.patient_id.clone(), share.patient_name.clone())
+            .study(Some(share.study_uid.clone()))
+            .detail(format!("link {} of {}", share.id, share.created_by)),
+    );
+    Json(Link::new(shares, &share)).into_response()
+}
+
+/// The share a token stands for, or why it is no good.
+fn verified(state: &SharedState, token: &str) -> Result<Share, (StatusCode, &'static str)> {
+    let Some(shares) = &state.shares else {
+        return Err((StatusCode::NOT_FOUND, "Sharing is not configured."));
+    };
+    shares
+        .verify(token, Local::now().fixed_offset())
+        .map_err(|e| (StatusCode::FORBIDDEN, e))
+}
+
+fn access_record(share: &Share, success: bool, address: SocketAddr, detail: &str) -> Record {
+    Record::new(Event::ShareAccessed, success, &format!("link:{}", share.id), address)
+        .patient(share.patient_id.clone(), share.patient_name.clone())
+        .study(Some(share.study_uid.clone()))
+        .detail(format!("{}, link of {}", detail, share.created_by))
+}
+
+/// What the page of a link shows before the images: the study and until
+/// when the link is valid. Every call is audited as an opening of the link.
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    pub study_uid: String,
+    pub patient_name: Option<String>,
+    pub study_date: Option<String>,
+    pub description: Option<String>,
+    pub expires: DateTime<FixedOffset>,
+}
+
+pub async fn summary(State(state): State<SharedState>, ConnectInfo(address): ConnectInfo<SocketAddr>, Path(token): Path<String>) -> Response {
+    let share = match verified(&state, &token) {
+        Ok(share) => share,
+        Err(e) => return e.into_response(),
+    };
+    let study = find_study(&state, &share.study_uid).await;
+    state.auditor.record(access_record(&share, study.is_ok(), address, "opened"));
+    match study {
+        Ok(study) => Json(Summary {
+            study_uid: share.study_uid.clone(),
+            patient_name: study.as_ref().and_then(|study| json_text(study, "00100010")),
+            study_date: study.as_ref().and_then(|study| json_text(study, "00080020")),
+            description: study.as_ref().and_then(|study| json_text(study, "00081030")),
+            expires: share.expires,
+        })
+        .into_response(),
+        Err(e) => {
+            eprintln!("Share link {} failed: {}", share.id, e);
+            (StatusCode::BAD_GATEWAY, "The archive could not be searched.").into_response()
+        }
+    }
+}
+
+/// Whether `path`, relative to the DICOMweb root, stays within the study.
+pub fn within_study(path: &str, study_uid: &str) -> bool {
+    let mut segments = path.trim_start_matches('/').split('/');
+    is_plain(path) && segments.next() == Some("studies") && segments.next() == Some(study_uid)
+}
+
+/// Forwards a read-only DICOMweb request within the shared study to the
+/// archive. Every request is audited; the audit page collapses the requests
+/// of a viewing into one entry.
+pub async fn forward(
+    State(state): State<SharedState>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    Path((token, path)): Path<(String, String)>,
+    request: Request<Body>,
+) -> Response {
+    let share = match verified(&state, &token) {
+        Ok(share) => share,
+        Err(e) => return e.into_response(),
+    };
+    if !matches!(*request.method(), Method::GET | Method::HEAD) || !within_study(&path, &share.study_uid) {
+        return (StatusCode::FORBIDDEN, "This request is not allowed through a share link.").into_response();
+    }
+    let (mut parts, body) = request.into_parts();
+    let query = parts.uri.query().map(|query| format!("?{}", query)).unwrap_or_default();
+    let upstream = format!("{}/{}{}", state.config.archive.url.trim_end_matches('/'), path.trim_start_matches('/'), query);
+    parts.uri = match upstream.parse::<Uri>() {
+        Ok(uri) => uri,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    strip_headers(&mut parts.headers);
+    if let Some(credentials) = credentials(&state.config.archive) {
+        parts.headers.insert(header::AUTHORIZATION, credentials);
+    }
+
+    let response = state.client.request(Request::from_parts(parts, body)).await;
+    let success = response.as_ref().is_ok_and(|response| response.status().is_success());
+    state.auditor.record(access_record(&share, success, address, &path));
+    match response {
+        Ok(response) => {
+            let (mut parts, body) = response.into_parts();
+            strip_headers(&mut parts.headers);
+            parts.headers.remove(header::SET_COOKIE);
+            Response::from_parts(parts, axum::body::boxed(body))
+        }
+        Err(e) => {
+            eprintln!("Share link {} request failed: {}", share.id, e);
+            (StatusCode::BAD_GATEWAY, "The archive could not be reached.").into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "a secret of at least thirty-two characters";
+
+    #[test]
+    fn links_are_signed_expire_and_can_be_revoked() {
+        let shares = Shares::new(SECRET, None).unwrap();
+        let share = shares.issue("1.2.3", (Some(String::from("SCH-001")), None), "radiologist", 7);
+        let token = shares.token(&share);
+        let now = Local::now().fixed_offset();
+        assert_eq!(shares.verify(&token, now).unwrap().study_uid, "1.2.3");
+        assert_eq!(shares.verify(&token, now + chrono::Duration::days(8)), Err("This link has expired."));
+
+        // another study under the same signature, or another key, is no good
+        let (_, signature) = token.split_once('.').unwrap();
+        let forged = format!("{}.{}", URL_SAFE_NO_PAD.encode(format!("{}.1.2.4.{}", share.id, share.expires.timestamp())), signature);
+        assert_eq!(shares.verify(&forged, now), Err("This link is not valid."));
+        let other = Shares::new("another secret of at least thirty-two characters", None).unwrap();
+        assert!(other.verify(&token, now).is_err());
+
+        assert!(shares.revoke(&share.id, "admin").unwrap().revoked.is_some());
+        assert_eq!(shares.verify(&token, now), Err("This link has been revoked."));
+        assert!(shares.revoke("unknown", "admin").is_none());
+        assert_eq!(shares.for_study("1.2.3").len(), 1);
+    }
+
+    #[test]
+    fn the_registry_keeps_revocations() {
+        let path = std::env::temp_dir().join(format!("pacsportal-shares-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let shares = Shares::new(SECRET, Some(&path)).unwrap();
+        let kept = shares.issue("1.2.3", (None, None), "radiologist", 1);
+        let revoked = shares.issue("1.2.3", (None, None), "radiologist", 1);
+        shares.revoke(&revoked.id, "radiologist");
+        drop(shares);
+
+        let reloaded = Shares::new(SECRET, Some(&path)).unwrap();
+        let now = Local::now().fixed_offset();
+        assert!(reloaded.verify(&reloaded.token(&kept), now).is_ok());
+        assert!(reloaded.verify(&reloaded.token(&revoked), now).is_err());
+        assert_eq!(reloaded.for_study("1.2.3").len(), 2);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn requests_stay_within_the_study() {
+        assert!(within_study("studies/1.2.3/series", "1.2.3"));
+        assert!(within_study("/studies/1.2.3/series/4/instances/5/rendered", "1.2.3"));
+        assert!(!within_study("studies", "1.2.3"));
+        assert!(!within_study("studies/1.2.4/series", "1.2.3"));
+        assert!(!within_study("studies/1.2.3/../1.2.4", "1.2.3"));
+    }
+}
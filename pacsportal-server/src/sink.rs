@@ -0,0 +1,93 @@
+use std::net::SocketAddr;
+
+use chrono::{Local, SecondsFormat};
+use hyper::client::HttpConnector;
+use hyper::{Body, Client, Method, Request};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use crate::config::{AuditConfig, SyslogTransport};
+
+/// authpriv.notice, the facility and severity ATNA collectors expect.
+const PRIORITY: u8 = 10 * 8 + 5;
+
+/// An RFC 5424 syslog message carrying an audit message, as in IHE ITI-20.
+fn syslog_message(xml: &str) -> String {
+    format!(
+        "<{}>1 {} - pacsportal {} IHE+RFC-3881 - {}",
+        PRIORITY,
+        Local::now().to_rfc3339_opts(SecondsFormat::Millis, false),
+        std::process::id(),
+        xml
+    )
+}
+
+async fn send_syslog(mut messages: UnboundedReceiver<String>, address: SocketAddr, transport: SyslogTransport) {
+    let mut tcp: Option<TcpStream> = None;
+    while let Some(xml) = messages.recv().await {
+        let message = syslog_message(&xml);
+        let result = match transport {
+            SyslogTransport::Udp => match UdpSocket::bind("0.0.0.0:0").await {
+                Ok(socket) => socket.send_to(message.as_bytes(), address).await.map(|_| ()),
+                Err(e) => Err(e),
+            },
+            SyslogTransport::Tcp => {
+                if tcp.is_none() {
+                    tcp = TcpStream::connect(address).await.ok();
+                }
+                match tcp.as_mut() {
+                    // octet counting framing of RFC 6587
+                    Some(stream) => stream.write_all(format!("{} {}", message.len(), message).as_bytes()).await,
+                    None => Err(std::io::Error::new(std::io::ErrorKind::NotConnected, "could not connect")),
+                }
+            }
+        };
+        if let Err(e) = result {
+            eprintln!("Could not send an audit message to {}: {}", address, e);
+            tcp = None;
+        }
+    }
+}
+
+async fn send_http(mut messages: UnboundedReceiver<String>, url: String, client: Client<HttpConnector>) {
+    while let Some(xml) = messages.recv().await {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(&url)
+            .header("Content-Type", "application/xml")
+            .body(Body::from(xml))
+            .expect("the URL was checked when loading the configuration");
+        match client.request(request).await {
+            Ok(response) if response.status().is_success() => {}
+            Ok(response) => eprintln!("The audit collector at {} answered {}", url, response.status()),
+            Err(e) => eprintln!("Could not send an audit message to {}: {}", url, e),
+        }
+    }
+}
+
+/// Starts delivering audit messages to the configured collector, if any.
+/// Messages are sent in order, in the background.
+pub fn spawn(config: &AuditConfig, client: Client<HttpConnector>) -> Option<UnboundedSender<String>> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    if let Some(syslog) = &config.syslog {
+        tokio::spawn(send_syslog(receiver, syslog.address, syslog.transport));
+    } else if let Some(url) = &config.http {
+        tokio::spawn(send_http(receiver, url.clone(), client));
+    } else {
+        return None;
+    }
+    Some(sender)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn syslog_header() {
+        let message = syslog_message("<AuditMessage/>");
+        assert!(message.starts_with("<85>1 "));
+        assert!(message.ends_with(" IHE+RFC-3881 - <AuditMessage/>"));
+    }
+}
@@ -0,0 +1,105 @@
+use axum::body::Body;
+use axum::extract::{Path, State};
+use axum::http::{header, HeaderName, HeaderValue, Method, Request, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+
+use crate::config::Role;
+use crate::proxy::{is_plain, strip_headers};
+use crate::SharedState;
+
+/// Names the user rows belong to, see `pacsportal-store/migrations`.
+const USER_HEADER: HeaderName = HeaderName::from_static("x-pacsportal-user");
+/// Names the department whose macros the user shares.
+const DEPARTMENT_HEADER: HeaderName = HeaderName::from_static("x-pacsportal-department");
+
+/// Tables referring physicians may change; drafts, assignments, study locks
+/// and report macros are for radiologists.
+const REFERRING_TABLES: [&str; 3] = ["read_status", "critical_acknowledgements", "preferences"];
+
+/// Whether a `method` request to `path`, relative to the PostgREST root, may
+/// be forwarded. Only tables are served, not functions or the OpenAPI root.
+pub fn may_request(role: Role, method: &Method, path: &str) -> Result<(), &'static str> {
+    let table = path.trim_matches('/');
+    if !is_plain(table) || table.contains('/') {
+        return Err("This request is not allowed through the portal.");
+    }
+    match *method {
+        Method::GET | Method::HEAD => Ok(()),
+        Method::POST | Method::PATCH | Method::DELETE => {
+            if role == Role::Radiologist || REFERRING_TABLES.contains(&table) {
+                Ok(())
+            } else {
+                Err("Only radiologists may change drafts and assignments.")
+            }
+        }
+        _ => Err("This request is not allowed through the portal."),
+    }
+}
+
+/// Forwards a PostgREST request of the logged in user to the metadata store,
+/// naming the user of the session whatever the client claimed.
+pub async fn forward(State(state): State<SharedState>, Path(path): Path<String>, request: Request<Body>) -> Response {
+    let Some(session) = state.sessions.for_request(request.headers()) else {
+        return (StatusCode::UNAUTHORIZED, "Please log in again.").into_response();
+    };
+    let Some(store) = &state.config.store else {
+        return (StatusCode::NOT_FOUND, "No metadata store is configured.").into_response();
+    };
+    if let Err(e) = may_request(session.role, request.method(), &path) {
+        return (StatusCode::FORBIDDEN, e).into_response();
+    }
+
+    let (mut parts, body) = request.into_parts();
+    let query = parts.uri.query().map(|query| format!("?{}", query)).unwrap_or_default();
+    let upstream = format!("{}/{}{}", store.url.trim_end_matches('/'), path.trim_start_matches('/'), query);
+    parts.uri = match upstream.parse::<Uri>() {
+        Ok(uri) => uri,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    strip_headers(&mut parts.headers);
+    match HeaderValue::from_str(&session.username) {
+        Ok(username) => parts.headers.insert(USER_HEADER, username),
+        Err(_) => return (StatusCode::BAD_REQUEST, "The username cannot be sent to the store.").into_response(),
+    };
+    match HeaderValue::from_str(&session.department) {
+        Ok(department) => parts.headers.insert(DEPARTMENT_HEADER, department),
+        Err(_) => return (StatusCode::BAD_REQUEST, "The department cannot be sent to the store.").into_response(),
+    };
+    if let Some(token) = store.token.as_ref().and_then(|token| HeaderValue::from_str(&format!("Bearer {}", token)).ok()) {
+        parts.headers.insert(header::AUTHORIZATION, token);
+    }
+
+    match state.client.request(Request::from_parts(parts, body)).await {
+        Ok(response) => {
+            let (mut parts, body) = response.into_parts();
+            strip_headers(&mut parts.headers);
+            Response::from_parts(parts, axum::body::boxed(body))
+        }
+        Err(e) => {
+            eprintln!("Store request for {} failed: {}", session.username, e);
+            (StatusCode::BAD_GATEWAY, "The metadata store could not be reached.").into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tables_by_role() {
+        assert!(may_request(Role::Referring, &Method::GET, "assignments").is_ok());
+        assert!(may_request(Role::Referring, &Method::POST, "preferences").is_ok());
+        assert!(may_request(Role::Referring, &Method::POST, "report_drafts").is_err());
+        assert!(may_request(Role::Radiologist, &Method::PATCH, "report_drafts").is_ok());
+        assert!(may_request(Role::Referring, &Method::GET, "active_locks").is_ok());
+        assert!(may_request(Role::Referring, &Method::POST, "study_locks").is_err());
+        assert!(may_request(Role::Referring, &Method::POST, "report_macros").is_err());
+        assert!(may_request(Role::Radiologist, &Method::DELETE, "report_macros").is_ok());
+        assert!(may_request(Role::Radiologist, &Method::POST, "rpc/current_username").is_err());
+        assert!(may_request(Role::Radiologist, &Method::GET, "/").is_err());
+        assert!(may_request(Role::Radiologist, &Method::GET, "..").is_err());
+        assert!(may_request(Role::Radiologist, &Method::GET, ".").is_err());
+        assert!(may_request(Role::Radiologist, &Method::PUT, "preferences").is_err());
+    }
+}
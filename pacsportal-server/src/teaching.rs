@@ -0,0 +1,174 @@
+//! The teaching file collection: studies de-identified like the downloads of
+//! `download.rs` and stored with STOW-RS, one instance per request, to an
+//! archive of their own, so that cases can be shown without the patient.
+
+use std::net::SocketAddr;
+
+use axum::body::Body;
+use axum::extract::{ConnectInfo, Path, State};
+use axum::http::{header, HeaderMap, Request, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use dicom::dictionary_std::tags;
+use dicom::object::OpenFileOptions;
+use pacsportal_core::attributes::text;
+use pacsportal_core::deidentify::{Options, Withheld};
+use serde::Deserialize;
+
+use crate::audit::{Event, Record};
+use crate::config::{Archive, Role};
+use crate::download::{deidentifier, is_uid, retrieve, study_instances};
+use crate::proxy::credentials;
+use crate::SharedState;
+
+const BOUNDARY: &str = "pacsportal-teaching";
+
+#[derive(Deserialize)]
+pub struct Addition {
+    #[serde(default)]
+    pub options: Options,
+    #[serde(default)]
+    pub pseudonym: Option<String>,
+}
+
+/// A STOW-RS body of one Part 10 file.
+pub fn stow_body(file: &[u8]) -> Vec<u8> {
+    let mut body = format!("--{}\r\nContent-Type: application/dicom\r\n\r\n", BOUNDARY).into_bytes();
+    body.extend_from_slice(file);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", BOUNDARY).as_bytes());
+    body
+}
+
+async fn store(state: &SharedState, archive: &Archive, file: &[u8]) -> Result<(), String> {
+    let mut request = Request::post(format!("{}/studies", archive.url.trim_end_matches('/')))
+        .header(
+            header::CONTENT_TYPE,
+            format!("multipart/related; type=\"application/dicom\"; boundary={}", BOUNDARY),
+        )
+        .header(header::ACCEPT, "application/dicom+json");
+    if let Some(credentials) = credentials(archive) {
+        request = request.header(header::AUTHORIZATION, credentials);
+    }
+    let request = request.body(Body::from(stow_body(file))).map_err(|e| e.to_string())?;
+    let response = state.client.request(request).await.map_err(|e| e.to_string())?;
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("the teaching file archive answered {}", response.status()))
+    }
+}
+
+/// Adds a study to the teaching files, de-identified with the options and
+/// pseudonym of the request. Only radiologists may; additions are audited
+/// as exports.
+pub async fn add(
+    State(state): State<SharedState>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    Path(study_uid): Path<String>,
+    headers: HeaderMap,
+    Json(addition): Json<Addition>,
+) -> Response {
+    let Some(session) = state.sessions.for_request(&headers) else {
+        return (StatusCode::UNAUTHORIZED, "Please log in again.").into_response();
+    };
+    if session.role != Role::Radiologist {
+        return (StatusCode::FORBIDDEN, "Only radiologists may add studies to the teaching files.").into_response();
+    }
+    let Some(archive) = &state.config.deidentification.teaching else {
+        return (StatusCode::NOT_FOUND, "No teaching file collection is configured.").into_response();
+    };
+    if !is_uid(&study_uid) {
+        return (StatusCode::BAD_REQUEST, "This is not a Study Instance UID.").into_response();
+    }
+    let secret = state.config.deidentification.uid_secret.as_deref();
+    let deidentifier = match deidentifier(secret, addition.options, addition.pseudonym.as_deref()) {
+        Ok(deidentifier) => deidentifier,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+
+    let instances = match study_instances(&state, &study_uid).await {
+        Ok(instances) if instances.is_empty() => return (StatusCode::NOT_FOUND, "The study has no instances.").into_response(),
+        Ok(instances) => instances,
+        Err(e) => {
+            eprintln!("Teaching file addition of {} for {} failed: {}", study_uid, session.username, e);
+            return (StatusCode::BAD_GATEWAY, "The archive could not be searched.").into_response();
+        }
+    };
+
+    let (mut stored, mut withheld) = (0, 0);
+    let mut patient = (None, None);
+    let mut result = Ok(());
+    for (series_uid, sop_uid) in &instances {
+        let file = match retrieve(&state, &study_uid, series_uid, sop_uid).await {
+            Ok(file) => file,
+            Err(e) => {
+                result = Err(e);
+                break;
+            }
+        };
+        if stored + withheld == 0 {
+            if let Ok(dataset) = OpenFileOptions::new().read_until(tags::PIXEL_DATA).from_reader(&file[..]) {
+                patient = (text(&dataset, tags::PATIENT_ID), text(&dataset, tags::PATIENT_NAME));
+            }
+        }
+        match deidentifier.file(&file) {
+            Ok(deidentified) => {
+                if let Err(e) = store(&state, archive, &deidentified).await {
+                    result = Err(e);
+                    break;
+                }
+                stored += 1;
+            }
+            Err(Withheld::BurnedInAnnotation) => withheld += 1,
+            Err(e) => {
+                result = Err(format!("instance {}: {}", sop_uid, e));
+                break;
+            }
+        }
+    }
+
+    let detail = format!("Teaching files, {} instances, {} withheld for burned-in annotation", stored, withheld);
+    state.auditor.record(
+        Record::new(Event::Export, result.is_ok() && stored > 0, &session.username, address)
+            .patient(patient.0, patient.1)
+            .study(Some(study_uid.clone()))
+            .detail(detail),
+    );
+    match result {
+        Err(e) => {
+            eprintln!("Teaching file addition of {} for {} failed: {}", study_uid, session.username, e);
+            let message = format!("The study could not be added to the teaching files; {} images were added before the failure.", stored);
+            (StatusCode::BAD_GATEWAY, message).into_response()
+        }
+        Ok(()) if stored == 0 => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Every image of the study may show burned-in patient details, so none was added to the teaching files.",
+        )
+            .into_response(),
+        Ok(()) if withheld > 0 => format!(
+            "Added {} images to the teaching files. {} images were left out as they may show burned-in patient details.",
+            stored, withheld
+        )
+        .into_response(),
+        Ok(()) => format!("Added {} images to the teaching files.", stored).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pacsportal_core::stow;
+
+    #[test]
+    fn stores_one_file_per_request() {
+        let body = stow_body(b"DICM....");
+        let content_type = format!("multipart/related; type=\"application/dicom\"; boundary={}", BOUNDARY);
+        let boundary = stow::boundary(&content_type).unwrap();
+        let parts = stow::split_parts(&body, &boundary);
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0], (String::from("application/dicom"), &b"DICM...."[..]));
+
+        let addition: Addition = serde_json::from_str(r#"{"options": {"retain_dates": true}}"#).unwrap();
+        assert!(addition.options.retain_dates && addition.pseudonym.is_none());
+    }
+}
@@ -0,0 +1,117 @@
+-- Metadata the archive does not hold: report drafts, study assignments,
+-- read status, critical result acknowledgements and user preferences.
+--
+-- PostgREST serves the `pacsportal` schema as the `pacsportal_web` role.
+-- Requests come through pacsportal-server, which sets X-Pacsportal-User to
+-- the logged in user; rows owned by a user are only visible to them.
+
+begin;
+
+create schema if not exists pacsportal;
+
+create table if not exists pacsportal.migrations (
+    version integer primary key,
+    applied_at timestamptz not null default now()
+);
+
+do $$
+begin
+    if not exists (select from pg_roles where rolname = 'pacsportal_web') then
+        create role pacsportal_web nologin;
+    end if;
+end
+$$;
+
+-- The user a request was made for, as set by pacsportal-server.
+create or replace function pacsportal.current_username() returns text
+language sql stable as $$
+    select nullif(current_setting('request.headers', true)::json ->> 'x-pacsportal-user', '')
+$$;
+
+create table pacsportal.report_drafts (
+    study_uid text not null,
+    username text not null default pacsportal.current_username(),
+    text text not null default '',
+    critical boolean not null default false,
+    updated_at timestamptz not null default now(),
+    primary key (study_uid, username)
+);
+
+create table pacsportal.assignments (
+    study_uid text primary key,
+    radiologist text not null,
+    assigned_by text not null default pacsportal.current_username(),
+    assigned_at timestamptz not null default now()
+);
+create index assignments_radiologist on pacsportal.assignments (radiologist);
+
+create table pacsportal.read_status (
+    study_uid text not null,
+    username text not null default pacsportal.current_username(),
+    read_at timestamptz not null default now(),
+    primary key (study_uid, username)
+);
+
+-- Mirrors the acknowledgement SRs in the archive so that they can be listed
+-- without a QIDO per study.
+create table pacsportal.critical_acknowledgements (
+    report_uid text primary key,
+    study_uid text not null,
+    acknowledged_by text not null default pacsportal.current_username(),
+    acknowledged_at timestamptz not null default now()
+);
+create index critical_acknowledgements_study on pacsportal.critical_acknowledgements (study_uid);
+
+create table pacsportal.preferences (
+    username text not null default pacsportal.current_username(),
+    key text not null,
+    value jsonb not null,
+    updated_at timestamptz not null default now(),
+    primary key (username, key)
+);
+
+alter table pacsportal.report_drafts enable row level security;
+create policy own_drafts on pacsportal.report_drafts
+    using (username = pacsportal.current_username());
+
+alter table pacsportal.read_status enable row level security;
+create policy own_read_status on pacsportal.read_status
+    using (username = pacsportal.current_username());
+
+alter table pacsportal.preferences enable row level security;
+create policy own_preferences on pacsportal.preferences
+    using (username = pacsportal.current_username());
+
+-- everyone sees assignments and acknowledgements, but only makes them in
+-- their own name; an assignment is changed or withdrawn only by whoever
+-- made it or the radiologist it is for
+alter table pacsportal.assignments enable row level security;
+create policy see_assignments on pacsportal.assignments for select
+    using (true);
+create policy assign_as_self on pacsportal.assignments for insert
+    with check (assigned_by = pacsportal.current_username());
+create policy reassign_own on pacsportal.assignments for update
+    using (assigned_by = pacsportal.current_username() or radiologist = pacsportal.current_username())
+    with check (assigned_by = pacsportal.current_username());
+create policy withdraw_own on pacsportal.assignments for delete
+    using (assigned_by = pacsportal.current_username() or radiologist = pacsportal.current_username());
+
+alter table pacsportal.critical_acknowledgements enable row level security;
+create policy acknowledged_as_self on pacsportal.critical_acknowledgements
+    using (true)
+    with check (acknowledged_by = pacsportal.current_username());
+
+grant usage on schema pacsportal to pacsportal_web;
+grant execute on function pacsportal.current_username() to pacsportal_web;
+grant select, insert, update, delete on
+    pacsportal.report_drafts,
+    pacsportal.assignments,
+    pacsportal.read_status,
+    pacsportal.preferences
+    to pacsportal_web;
+-- acknowledgements are a record, never changed afterwards
+grant select, insert on pacsportal.critical_acknowledgements to pacsportal_web;
+
+insert into pacsportal.migrations (version) values (1);
+
+commit;
@@ -0,0 +1,40 @@
+-- Locks of studies being reported, so that two radiologists do not report
+-- the same study. A lock lapses once its holder has been inactive for 15
+-- minutes (LOCK_TIMEOUT_MINUTES in src/lib.rs): anyone may then delete it.
+
+begin;
+
+create table pacsportal.study_locks (
+    study_uid text primary key,
+    username text not null default pacsportal.current_username(),
+    claimed_at timestamptz not null default now(),
+    active_at timestamptz not null default now()
+);
+
+create or replace function pacsportal.lock_has_lapsed(active_at timestamptz) returns boolean
+language sql stable as $$
+    select active_at < now() - interval '15 minutes'
+$$;
+
+-- the locks still held, which is what the portal shows
+create view pacsportal.active_locks with (security_invoker = true) as
+    select * from pacsportal.study_locks
+    where not pacsportal.lock_has_lapsed(active_at);
+
+alter table pacsportal.study_locks enable row level security;
+create policy see_locks on pacsportal.study_locks for select
+    using (true);
+create policy claim_as_self on pacsportal.study_locks for insert
+    with check (username = pacsportal.current_username());
+create policy keep_own_lock on pacsportal.study_locks for update
+    using (username = pacsportal.current_username());
+create policy release_own_or_lapsed on pacsportal.study_locks for delete
+    using (username = pacsportal.current_username() or pacsportal.lock_has_lapsed(active_at));
+
+grant execute on function pacsportal.lock_has_lapsed(timestamptz) to pacsportal_web;
+grant select, insert, update, delete on pacsportal.study_locks to pacsportal_web;
+grant select on pacsportal.active_locks to pacsportal_web;
+
+insert into pacsportal.migrations (version) values (2);
+
+commit;
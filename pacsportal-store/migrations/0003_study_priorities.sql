@@ -0,0 +1,30 @@
+-- Priorities set in the portal for studies whose request carries none, or
+-- the wrong one. They win over the priority in the archive; everyone sees
+-- them, but sets them in their own name, and only changes or clears those
+-- they set.
+
+begin;
+
+create table pacsportal.study_priorities (
+    study_uid text primary key,
+    priority text not null check (priority in ('STAT', 'HIGH', 'ROUTINE', 'LOW')),
+    set_by text not null default pacsportal.current_username(),
+    set_at timestamptz not null default now()
+);
+
+alter table pacsportal.study_priorities enable row level security;
+create policy see_priorities on pacsportal.study_priorities for select
+    using (true);
+create policy prioritize_as_self on pacsportal.study_priorities for insert
+    with check (set_by = pacsportal.current_username());
+create policy change_own_priority on pacsportal.study_priorities for update
+    using (set_by = pacsportal.current_username())
+    with check (set_by = pacsportal.current_username());
+create policy clear_own_priority on pacsportal.study_priorities for delete
+    using (set_by = pacsportal.current_username());
+
+grant select, insert, update, delete on pacsportal.study_priorities to pacsportal_web;
+
+insert into pacsportal.migrations (version) values (3);
+
+commit;
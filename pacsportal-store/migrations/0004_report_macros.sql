@@ -0,0 +1,41 @@
+-- Report macros, personal or shared with a department. Users see and change
+-- their own macros and those of their department, which pacsportal-server
+-- sets as X-Pacsportal-Department from the user's configuration.
+
+begin;
+
+-- The department a request was made for, as set by pacsportal-server.
+create or replace function pacsportal.current_department() returns text
+language sql stable as $$
+    select nullif(current_setting('request.headers', true)::json ->> 'x-pacsportal-department', '')
+$$;
+
+create table pacsportal.report_macros (
+    scope text not null check (scope in ('user', 'department')),
+    -- the username or department the macro belongs to
+    owner text not null,
+    trigger text not null,
+    phrase text not null,
+    updated_by text not null default pacsportal.current_username(),
+    updated_at timestamptz not null default now(),
+    primary key (scope, owner, trigger)
+);
+
+create or replace function pacsportal.is_own_macro(scope text, owner text) returns boolean
+language sql stable as $$
+    select (scope = 'user' and owner = pacsportal.current_username())
+        or (scope = 'department' and owner = pacsportal.current_department())
+$$;
+
+alter table pacsportal.report_macros enable row level security;
+create policy own_or_department_macros on pacsportal.report_macros
+    using (pacsportal.is_own_macro(scope, owner))
+    with check (pacsportal.is_own_macro(scope, owner) and updated_by = pacsportal.current_username());
+
+grant execute on function pacsportal.current_department() to pacsportal_web;
+grant execute on function pacsportal.is_own_macro(text, text) to pacsportal_web;
+grant select, insert, update, delete on pacsportal.report_macros to pacsportal_web;
+
+insert into pacsportal.migrations (version) values (4);
+
+commit;
@@ -0,0 +1,500 @@
+//! Portal metadata the archive does not hold, kept in PostgreSQL and reached
+//! through PostgREST: report drafts, study assignments, read status, critical
+//! result acknowledgements, study priorities, report macros and user preferences. The
+//! schema is created by `migrations/`; see `docker-compose.yml` for a local database.
+//!
+//! Each [`Store`] acts for one user of a department, named in the
+//! `X-Pacsportal-User` and `X-Pacsportal-Department` headers.
+//! `pacsportal-server` overwrites those headers with the user of the session,
+//! so they only decide anything when talking to PostgREST directly during
+//! development.
+
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use postgrest::{Builder, Postgrest};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+pub const SCHEMA: &str = "pacsportal";
+pub const USER_HEADER: &str = "X-Pacsportal-User";
+pub const DEPARTMENT_HEADER: &str = "X-Pacsportal-Department";
+
+/// Minutes of inactivity after which a study lock lapses, as set in
+/// `migrations/0002_study_locks.sql`.
+pub const LOCK_TIMEOUT_MINUTES: i64 = 15;
+
+/// UIDs per `in.()` filter, keeping request URLs to a few kilobytes.
+const UIDS_PER_REQUEST: usize = 50;
+
+#[derive(Debug)]
+pub enum Error {
+    /// PostgREST could not be reached.
+    Http(reqwest::Error),
+    /// PostgREST answered with an error status and message.
+    Status(u16, String),
+    Parse(serde_json::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Http(e) => write!(f, "the metadata store could not be reached: {}", e),
+            Error::Status(status, message) => write!(f, "the metadata store answered {}: {}", status, message),
+            Error::Parse(e) => write!(f, "unexpected answer from the metadata store: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A report being written, saved so that it survives a closed tab.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Draft {
+    pub study_uid: String,
+    pub username: String,
+    pub text: String,
+    pub critical: bool,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A study given to a radiologist to report.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Assignment {
+    pub study_uid: String,
+    pub radiologist: String,
+    pub assigned_by: String,
+    pub assigned_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Acknowledgement {
+    /// SOP Instance UID of the acknowledged critical report.
+    pub report_uid: String,
+    pub study_uid: String,
+    pub acknowledged_by: String,
+    pub acknowledged_at: DateTime<Utc>,
+}
+
+/// A priority set in the portal, which wins over the one of the request.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StudyPriority {
+    pub study_uid: String,
+    /// `STAT`, `HIGH`, `ROUTINE` or `LOW`.
+    pub priority: String,
+    pub set_by: String,
+    pub set_at: DateTime<Utc>,
+}
+
+/// A report macro of the user or of their department.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredMacro {
+    /// `user` or `department`.
+    pub scope: String,
+    /// The username or department the macro belongs to.
+    pub owner: String,
+    pub trigger: String,
+    pub phrase: String,
+    pub updated_by: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A study being reported by someone.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Lock {
+    pub study_uid: String,
+    pub username: String,
+    pub claimed_at: DateTime<Utc>,
+    /// Last sign of activity of the holder.
+    pub active_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Claim {
+    Claimed,
+    /// The user held the study already, e.g. claimed from the worklist.
+    Kept,
+    HeldBy(Lock),
+}
+
+#[derive(Deserialize)]
+struct Preference {
+    value: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct StudyRow {
+    study_uid: String,
+}
+
+async fn send(builder: Builder) -> Result<String, Error> {
+    let response = builder.execute().await.map_err(Error::Http)?;
+    let status = response.status();
+    let text = response.text().await.map_err(Error::Http)?;
+    if status.is_success() {
+        Ok(text)
+    } else {
+        Err(Error::Status(status.as_u16(), text))
+    }
+}
+
+async fn rows<T: DeserializeOwned>(builder: Builder) -> Result<Vec<T>, Error> {
+    let text = send(builder).await?;
+    serde_json::from_str(&text).map_err(Error::Parse)
+}
+
+#[derive(Clone)]
+pub struct Store {
+    client: Postgrest,
+    username: String,
+}
+
+impl Store {
+    /// `url` is the PostgREST root, e.g. `http://127.0.0.1:3000` or the
+    /// `/store` of pacsportal-server made absolute.
+    pub fn new(url: &str, username: &str, department: &str) -> Self {
+        Store {
+            client: Postgrest::new(url.trim_end_matches('/'))
+                .schema(SCHEMA)
+                .insert_header(USER_HEADER, username)
+                .insert_header(DEPARTMENT_HEADER, department),
+            username: username.to_owned(),
+        }
+    }
+
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    fn drafts(&self, study_uid: &str) -> Builder {
+        self.client.from("report_drafts").eq("study_uid", study_uid)
+    }
+
+    /// The user's draft for a study.
+    pub async fn draft(&self, study_uid: &str) -> Result<Option<Draft>, Error> {
+        Ok(rows(self.drafts(study_uid).select("*")).await?.into_iter().next())
+    }
+
+    pub async fn save_draft(&self, study_uid: &str, text: &str, critical: bool) -> Result<(), Error> {
+        let row = json!({
+            "study_uid": study_uid,
+            "username": self.username,
+            "text": text,
+            "critical": critical,
+            "updated_at": Utc::now(),
+        });
+        send(self.client.from("report_drafts").upsert(row.to_string()).on_conflict("study_uid,username")).await?;
+        Ok(())
+    }
+
+    /// Drops the draft once the report is stored in the archive.
+    pub async fn delete_draft(&self, study_uid: &str) -> Result<(), Error> {
+        send(self.drafts(study_uid).delete()).await?;
+        Ok(())
+    }
+
+    /// All current assignments, oldest first.
+    pub async fn assignments(&self) -> Result<Vec<Assignment>, Error> {
+        rows(self.client.from("assignments").select("*").order("assigned_at.asc")).await
+    }
+
+    /// Gives a study to a radiologist, replacing any earlier assignment.
+    pub async fn assign(&self, study_uid: &str, radiologist: &str) -> Result<(), Error> {
+        let row = json!({
+            "study_uid": study_uid,
+            "radiologist": radiologist,
+            "assigned_by": self.username,
+            "assigned_at": Utc::now(),
+        });
+        send(self.client.from("assignments").upsert(row.to_string()).on_conflict("study_uid")).await?;
+        Ok(())
+    }
+
+    pub async fn unassign(&self, study_uid: &str) -> Result<(), Error> {
+        send(self.client.from("assignments").eq("study_uid", study_uid).delete()).await?;
+        Ok(())
+    }
+
+    fn read_studies_among<'a>(&self, study_uids: &'a [String]) -> Builder {
+        self.client
+            .from("read_status")
+            .select("study_uid")
+            .in_("study_uid", study_uids.iter().map(String::as_str).collect::<Vec<&'a str>>())
+    }
+
+    /// Which of `study_uids` the user has read.
+    pub async fn read_studies(&self, study_uids: &[String]) -> Result<Vec<String>, Error> {
+        let mut read = Vec::new();
+        for chunk in study_uids.chunks(UIDS_PER_REQUEST) {
+            let found: Vec<StudyRow> = rows(self.read_studies_among(chunk)).await?;
+            read.extend(found.into_iter().map(|row| row.study_uid));
+        }
+        Ok(read)
+    }
+
+    pub async fn mark_read(&self, study_uid: &str) -> Result<(), Error> {
+        let row = json!({ "study_uid": study_uid, "username": self.username, "read_at": Utc::now() });
+        send(self.client.from("read_status").upsert(row.to_string()).on_conflict("study_uid,username")).await?;
+        Ok(())
+    }
+
+    pub async fn mark_unread(&self, study_uid: &str) -> Result<(), Error> {
+        send(self.client.from("read_status").eq("study_uid", study_uid).delete()).await?;
+        Ok(())
+    }
+
+    /// Records that the user acknowledged a critical report.
+    pub async fn acknowledge(&self, report_uid: &str, study_uid: &str) -> Result<(), Error> {
+        let row = json!({
+            "report_uid": report_uid,
+            "study_uid": study_uid,
+            "acknowledged_by": self.username,
+            "acknowledged_at": Utc::now(),
+        });
+        send(self.client.from("critical_acknowledgements").insert(row.to_string())).await?;
+        Ok(())
+    }
+
+    /// Acknowledgements of critical reports in the given studies.
+    pub async fn acknowledgements(&self, study_uids: &[String]) -> Result<Vec<Acknowledgement>, Error> {
+        let mut acknowledgements = Vec::new();
+        for chunk in study_uids.chunks(UIDS_PER_REQUEST) {
+            let builder = self
+                .client
+                .from("critical_acknowledgements")
+                .select("*")
+                .in_("study_uid", chunk.iter().map(String::as_str).collect::<Vec<&str>>());
+            acknowledgements.extend(rows::<Acknowledgement>(builder).await?);
+        }
+        Ok(acknowledgements)
+    }
+
+    /// The priorities set for any of `study_uids`.
+    pub async fn priorities(&self, study_uids: &[String]) -> Result<Vec<StudyPriority>, Error> {
+        let mut priorities = Vec::new();
+        for chunk in study_uids.chunks(UIDS_PER_REQUEST) {
+            let builder = self
+                .client
+                .from("study_priorities")
+                .select("*")
+                .in_("study_uid", chunk.iter().map(String::as_str).collect::<Vec<&str>>());
+            priorities.extend(rows::<StudyPriority>(builder).await?);
+        }
+        Ok(priorities)
+    }
+
+    /// Sets the priority of a study, replacing any set before.
+    pub async fn set_priority(&self, study_uid: &str, priority: &str) -> Result<(), Error> {
+        let row = json!({ "study_uid": study_uid, "priority": priority, "set_by": self.username, "set_at": Utc::now() });
+        send(self.client.from("study_priorities").upsert(row.to_string()).on_conflict("study_uid")).await?;
+        Ok(())
+    }
+
+    /// Goes back to the priority of the request.
+    pub async fn clear_priority(&self, study_uid: &str) -> Result<(), Error> {
+        send(self.client.from("study_priorities").eq("study_uid", study_uid).delete()).await?;
+        Ok(())
+    }
+
+    /// The macros of the user and of their department.
+    pub async fn macros(&self) -> Result<Vec<StoredMacro>, Error> {
+        rows(self.client.from("report_macros").select("*").order("trigger.asc")).await
+    }
+
+    /// Saves a macro of the user or the department, replacing the one with
+    /// the same trigger.
+    pub async fn save_macro(&self, scope: &str, owner: &str, trigger: &str, phrase: &str) -> Result<(), Error> {
+        let row = json!({
+            "scope": scope,
+            "owner": owner,
+            "trigger": trigger,
+            "phrase": phrase,
+            "updated_by": self.username,
+            "updated_at": Utc::now(),
+        });
+        send(self.client.from("report_macros").upsert(row.to_string()).on_conflict("scope,owner,trigger")).await?;
+        Ok(())
+    }
+
+    pub async fn delete_macro(&self, scope: &str, owner: &str, trigger: &str) -> Result<(), Error> {
+        let builder = self
+            .client
+            .from("report_macros")
+            .eq("scope", scope)
+            .eq("owner", owner)
+            .eq("trigger", trigger);
+        send(builder.delete()).await?;
+        Ok(())
+    }
+
+    /// The locks which have not lapsed.
+    pub async fn locks(&self) -> Result<Vec<Lock>, Error> {
+        rows(self.client.from("active_locks").select("*")).await
+    }
+
+    pub async fn lock(&self, study_uid: &str) -> Result<Option<Lock>, Error> {
+        Ok(rows(self.client.from("active_locks").select("*").eq("study_uid", study_uid)).await?.into_iter().next())
+    }
+
+    /// Locks a study for the user, unless someone else holds it. Claiming a
+    /// study the user holds already counts as activity.
+    pub async fn claim(&self, study_uid: &str) -> Result<Claim, Error> {
+        match self.lock(study_uid).await? {
+            Some(lock) if lock.username == self.username => {
+                self.touch(study_uid).await?;
+                return Ok(Claim::Kept);
+            }
+            Some(lock) => return Ok(Claim::HeldBy(lock)),
+            None => {}
+        }
+        // a lapsed lock is still in the table; only lapsed or own locks can be deleted
+        send(self.client.from("study_locks").eq("study_uid", study_uid).delete()).await?;
+        let row = json!({ "study_uid": study_uid, "username": self.username });
+        match send(self.client.from("study_locks").insert(row.to_string())).await {
+            Ok(_) => Ok(Claim::Claimed),
+            // someone else was quicker
+            Err(Error::Status(409, message)) => match self.lock(study_uid).await? {
+                Some(lock) => Ok(Claim::HeldBy(lock)),
+                None => Err(Error::Status(409, message)),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    fn own_lock(&self, study_uid: &str) -> Builder {
+        self.client.from("study_locks").eq("study_uid", study_uid).eq("username", &self.username)
+    }
+
+    /// Keeps the user's lock from lapsing.
+    pub async fn touch(&self, study_uid: &str) -> Result<(), Error> {
+        send(self.own_lock(study_uid).update(json!({ "active_at": Utc::now() }).to_string())).await?;
+        Ok(())
+    }
+
+    pub async fn release(&self, study_uid: &str) -> Result<(), Error> {
+        send(self.own_lock(study_uid).delete()).await?;
+        Ok(())
+    }
+
+    fn preferences(&self, key: &str) -> Builder {
+        self.client.from("preferences").eq("key", key)
+    }
+
+    /// A preference of the user, `None` when never set.
+    pub async fn preference<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, Error> {
+        match rows::<Preference>(self.preferences(key).select("value")).await?.into_iter().next() {
+            Some(preference) => serde_json::from_value(preference.value).map(Some).map_err(Error::Parse),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn set_preference<T: Serialize>(&self, key: &str, value: &T) -> Result<(), Error> {
+        let value = serde_json::to_value(value).map_err(Error::Parse)?;
+        let row = json!({ "username": self.username, "key": key, "value": value, "updated_at": Utc::now() });
+        send(self.client.from("preferences").upsert(row.to_string()).on_conflict("username,key")).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(builder: Builder) -> reqwest::Request {
+        builder.build().build().unwrap()
+    }
+
+    #[test]
+    fn requests_name_the_schema_and_the_user() {
+        let store = Store::new("http://127.0.0.1:3000/", "radiologist", "Radiology");
+        let draft = request(store.drafts("1.2.3").select("*"));
+        assert_eq!(draft.url().as_str(), "http://127.0.0.1:3000/report_drafts?study_uid=eq.1.2.3&select=*");
+        assert_eq!(draft.headers()["Accept-Profile"], SCHEMA);
+        assert_eq!(draft.headers()[USER_HEADER], "radiologist");
+        assert_eq!(draft.headers()[DEPARTMENT_HEADER], "Radiology");
+
+        let read = request(store.read_studies_among(&[String::from("1.2.3"), String::from("1.2.4")]));
+        assert_eq!(read.url().query(), Some("select=study_uid&study_uid=in.%281.2.3%2C1.2.4%29"));
+    }
+
+    /// Runs against the database of `docker-compose.yml`:
+    /// `PACSPORTAL_STORE_URL=http://127.0.0.1:3000 cargo test -p pacsportal-store -- --ignored`
+    #[tokio::test]
+    #[ignore]
+    async fn round_trip_with_postgrest() {
+        let url = std::env::var("PACSPORTAL_STORE_URL").unwrap_or_else(|_| String::from("http://127.0.0.1:3000"));
+        let study = format!("2.25.{}", Utc::now().timestamp_millis());
+        let store = Store::new(&url, "test-radiologist", "test-radiology");
+        let other = Store::new(&url, "test-other", "test-radiology");
+        let outsider = Store::new(&url, "test-outsider", "test-cardiology");
+
+        store.save_draft(&study, "First impression.", false).await.unwrap();
+        store.save_draft(&study, "Second impression.", true).await.unwrap();
+        let draft = store.draft(&study).await.unwrap().unwrap();
+        assert_eq!((draft.text.as_str(), draft.critical), ("Second impression.", true));
+        assert!(other.draft(&study).await.unwrap().is_none(), "drafts are private");
+        store.delete_draft(&study).await.unwrap();
+        assert!(store.draft(&study).await.unwrap().is_none());
+
+        store.assign(&study, "test-other").await.unwrap();
+        let assignment = other.assignments().await.unwrap().into_iter().find(|a| a.study_uid == study).unwrap();
+        assert_eq!((assignment.radiologist.as_str(), assignment.assigned_by.as_str()), ("test-other", "test-radiologist"));
+        assert!(outsider.assign(&study, "test-outsider").await.is_err(), "only its maker or assignee changes an assignment");
+        outsider.unassign(&study).await.unwrap();
+        assert_eq!(store.assignments().await.unwrap().iter().filter(|a| a.study_uid == study).count(), 1);
+        other.unassign(&study).await.unwrap();
+        assert!(!store.assignments().await.unwrap().iter().any(|a| a.study_uid == study));
+
+        store.mark_read(&study).await.unwrap();
+        store.mark_read(&study).await.unwrap();
+        assert_eq!(store.read_studies(std::slice::from_ref(&study)).await.unwrap(), vec![study.clone()]);
+        assert!(other.read_studies(std::slice::from_ref(&study)).await.unwrap().is_empty());
+        store.mark_unread(&study).await.unwrap();
+        assert!(store.read_studies(std::slice::from_ref(&study)).await.unwrap().is_empty());
+
+        let report = format!("{}.1", study);
+        other.acknowledge(&report, &study).await.unwrap();
+        assert!(other.acknowledge(&report, &study).await.is_err(), "a report is acknowledged once");
+        let acknowledgements = store.acknowledgements(std::slice::from_ref(&study)).await.unwrap();
+        assert_eq!(acknowledgements[0].acknowledged_by, "test-other");
+
+        assert_eq!(store.claim(&study).await.unwrap(), Claim::Claimed);
+        assert_eq!(store.claim(&study).await.unwrap(), Claim::Kept);
+        match other.claim(&study).await.unwrap() {
+            Claim::HeldBy(lock) => assert_eq!(lock.username, "test-radiologist"),
+            Claim::Claimed | Claim::Kept => panic!("the study is locked"),
+        }
+        other.release(&study).await.unwrap();
+        assert!(store.locks().await.unwrap().iter().any(|lock| lock.study_uid == study));
+        store.release(&study).await.unwrap();
+        assert_eq!(other.claim(&study).await.unwrap(), Claim::Claimed);
+        other.release(&study).await.unwrap();
+
+        store.set_priority(&study, "STAT").await.unwrap();
+        store.set_priority(&study, "HIGH").await.unwrap();
+        assert!(other.set_priority(&study, "LOW").await.is_err(), "only whoever set a priority changes it");
+        other.clear_priority(&study).await.unwrap();
+        let priorities = other.priorities(std::slice::from_ref(&study)).await.unwrap();
+        assert_eq!((priorities[0].priority.as_str(), priorities[0].set_by.as_str()), ("HIGH", "test-radiologist"));
+        assert!(store.set_priority(&study, "SOON").await.is_err());
+        store.clear_priority(&study).await.unwrap();
+        assert!(other.priorities(std::slice::from_ref(&study)).await.unwrap().is_empty());
+
+        let trigger = format!("t{}", Utc::now().timestamp_millis());
+        store.save_macro("user", "test-radiologist", &trigger, "Personal.").await.unwrap();
+        store.save_macro("department", "test-radiology", &trigger, "Shared.").await.unwrap();
+        assert!(store.save_macro("department", "test-cardiology", &trigger, "Elsewhere.").await.is_err());
+        let own = |macros: Vec<StoredMacro>| macros.into_iter().filter(|m| m.trigger == trigger).map(|m| m.phrase).collect::<Vec<_>>();
+        assert_eq!(own(store.macros().await.unwrap()).len(), 2);
+        assert_eq!(own(other.macros().await.unwrap()), ["Shared."]);
+        assert!(own(outsider.macros().await.unwrap()).is_empty(), "macros stay in the department");
+        other.delete_macro("department", "test-radiology", &trigger).await.unwrap();
+        store.delete_macro("user", "test-radiologist", &trigger).await.unwrap();
+        assert!(own(store.macros().await.unwrap()).is_empty());
+
+        store.set_preference("test", &vec!["CT", "MR"]).await.unwrap();
+        assert_eq!(store.preference::<Vec<String>>("test").await.unwrap(), Some(vec![String::from("CT"), String::from("MR")]));
+        assert_eq!(other.preference::<Vec<String>>("test").await.unwrap(), None);
+    }
+}
@@ -0,0 +1,224 @@
+use gloo::net::http::Request;
+use pacsportal_core::deidentify::Options;
+use serde::Deserialize;
+
+use crate::Authorized;
+
+/// Session API of `pacsportal-server`, e.g. `/api`. Set `PACSPORTAL_API_BASE`
+/// at build time when the portal is served by it; other builds, such as
+/// `trunk serve` against the mock archive, log in with the built-in
+/// development accounts.
+pub const API_BASE: Option<&str> = option_env!("PACSPORTAL_API_BASE");
+
+#[derive(Deserialize)]
+struct SessionInfo {
+    username: String,
+    name: String,
+    radiologist: bool,
+    department: String,
+    admin: bool,
+}
+
+/// Logs in with the server, which sets the session cookie. `Ok(None)` means
+/// the credentials were wrong.
+pub async fn log_in(base: &str, username: &str, password: &str) -> Result<Option<Authorized>, String> {
+    let res = Request::post(&format!("{}/login", base))
+        .json(&serde_json::json!({ "username": username, "password": password }))
+        .map_err(|e| e.to_string())?
+        .send()
+        .await
+        .map_err(|_| String::from("Unable to reach the server. Please try again later or contact your system administrator."))?;
+    match res.status() {
+        200 => {
+            let session = res
+                .json::<SessionInfo>()
+                .await
+                .map_err(|_| String::from("Unable to parse data from server. Please report this to your system administrator."))?;
+            Ok(Some(Authorized {
+                inner: session.radiologist,
+                username: session.username,
+                name: session.name,
+                department: session.department,
+                admin: session.admin,
+            }))
+        }
+        401 => Ok(None),
+        status => Err(format!("The server sent back an error: {}. Please report this to your system administrator.", status)),
+    }
+}
+
+/// Ends the session on the server. Failing is harmless: it expires anyway.
+pub async fn log_out(base: &str) {
+    let _ = Request::post(&format!("{}/logout", base)).send().await;
+}
+
+/// Reports something only the portal sees to the audit trail of the server,
+/// e.g. `study-opened` when a study is opened in the viewer. Does nothing
+/// without a server; a failure must not stop the user, so it is ignored.
+pub fn audit(event: &'static str, patient_id: Option<String>, patient_name: Option<String>, study_uid: Option<String>, detail: String) {
+    let Some(base) = API_BASE else {
+        return;
+    };
+    let body = serde_json::json!({
+        "event": event,
+        "patient_id": patient_id,
+        "patient_name": patient_name,
+        "study_uid": study_uid,
+        "detail": detail,
+    });
+    wasm_bindgen_futures::spawn_local(async move {
+        if let Ok(request) = Request::post(&format!("{}/audit", base)).json(&body) {
+            let _ = request.send().await;
+        }
+    });
+}
+
+/// An entry of the audit trail, as the server lists it.
+#[derive(Clone, PartialEq, Deserialize)]
+pub struct AuditRecord {
+    pub time: String,
+    pub event: String,
+    pub success: bool,
+    pub username: String,
+    pub address: String,
+    #[serde(default)]
+    pub patient_id: Option<String>,
+    #[serde(default)]
+    pub patient_name: Option<String>,
+    #[serde(default)]
+    pub study_uid: Option<String>,
+    #[serde(default)]
+    pub detail: String,
+}
+
+/// Searches the audit trail, newest first. Only admins may.
+pub async fn audit_records(base: &str, query: &[(&str, String)]) -> Result<Vec<AuditRecord>, String> {
+    let res = Request::get(&format!("{}/audit", base))
+        .query(query.iter().map(|(key, value)| (*key, value.as_str())))
+        .send()
+        .await
+        .map_err(|_| String::from("Unable to reach the server. Please try again later or contact your system administrator."))?;
+    match res.status() {
+        200 => res
+            .json::<Vec<AuditRecord>>()
+            .await
+            .map_err(|_| String::from("Unable to parse data from server. Please report this to your system administrator.")),
+        401 => Err(String::from("Your session has expired. Please log in again.")),
+        403 => Err(String::from("Only administrators may browse the audit trail.")),
+        status => Err(format!("The server sent back an error: {}. Please report this to your system administrator.", status)),
+    }
+}
+
+/// Adds a study, de-identified with `options`, to the teaching files. The
+/// server answers with what was added, which is shown as it is.
+pub async fn add_to_teaching_files(base: &str, study_uid: &str, options: Options, pseudonym: &str) -> Result<String, String> {
+    let res = Request::post(&format!("{}/studies/{}/teaching", base, study_uid))
+        .json(&serde_json::json!({ "options": options, "pseudonym": pseudonym }))
+        .map_err(|e| e.to_string())?
+        .send()
+        .await
+        .map_err(|_| String::from("Unable to reach the server. Please try again later or contact your system administrator."))?;
+    let message = res.text().await.unwrap_or_default();
+    match res.status() {
+        200 => Ok(message),
+        401 => Err(String::from("Your session has expired. Please log in again.")),
+        400 | 403 | 404 | 422 | 502 => Err(message),
+        status => Err(format!("The server sent back an error: {}. Please report this to your system administrator.", status)),
+    }
+}
+
+/// A share link of a study, as the server lists it. Only active links come
+/// with their token.
+#[derive(Clone, PartialEq, Deserialize)]
+pub struct ShareLink {
+    pub id: String,
+    #[serde(default)]
+    pub token: Option<String>,
+    pub created_by: String,
+    pub created: String,
+    pub expires: String,
+    #[serde(default)]
+    pub revoked: Option<String>,
+}
+
+fn share_error(status: u16) -> String {
+    match status {
+        401 => String::from("Your session has expired. Please log in again."),
+        403 => String::from("Only radiologists may share studies."),
+        404 => String::from("Sharing is not configured on the server."),
+        status => format!("The server sent back an error: {}. Please report this to your system administrator.", status),
+    }
+}
+
+pub async fn share_links(base: &str, study_uid: &str) -> Result<Vec<ShareLink>, String> {
+    let res = Request::get(&format!("{}/shares", base))
+        .query([("study", study_uid)])
+        .send()
+        .await
+        .map_err(|_| String::from("Unable to reach the server. Please try again later or contact your system administrator."))?;
+    match res.status() {
+        200 => res
+            .json::<Vec<ShareLink>>()
+            .await
+            .map_err(|_| String::from("Unable to parse data from server. Please report this to your system administrator.")),
+        status => Err(share_error(status)),
+    }
+}
+
+/// Creates a link to a study, valid for `days`.
+pub async fn create_share_link(base: &str, study_uid: &str, days: u32) -> Result<ShareLink, String> {
+    let res = Request::post(&format!("{}/shares", base))
+        .json(&serde_json::json!({ "study_uid": study_uid, "days": days }))
+        .map_err(|e| e.to_string())?
+        .send()
+        .await
+        .map_err(|_| String::from("Unable to reach the server. Please try again later or contact your system administrator."))?;
+    match res.status() {
+        200 => res
+            .json::<ShareLink>()
+            .await
+            .map_err(|_| String::from("Unable to parse data from server. Please report this to your system administrator.")),
+        400 => Err(res.text().await.unwrap_or_default()),
+        status => Err(share_error(status)),
+    }
+}
+
+pub async fn revoke_share_link(base: &str, id: &str) -> Result<(), String> {
+    let res = Request::delete(&format!("{}/shares/{}", base, id))
+        .send()
+        .await
+        .map_err(|_| String::from("Unable to reach the server. Please try again later or contact your system administrator."))?;
+    match res.status() {
+        200 => Ok(()),
+        status => Err(share_error(status)),
+    }
+}
+
+/// The study a share link opens.
+#[derive(Clone, PartialEq, Deserialize)]
+pub struct SharedStudy {
+    pub study_uid: String,
+    #[serde(default)]
+    pub patient_name: Option<String>,
+    #[serde(default)]
+    pub study_date: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub expires: String,
+}
+
+/// Opens a share link. Needs no login; the server audits every opening.
+pub async fn shared_study(base: &str, token: &str) -> Result<SharedStudy, String> {
+    let res = Request::get(&format!("{}/shared/{}", base, token))
+        .send()
+        .await
+        .map_err(|_| String::from("Unable to reach the server. Please try again later."))?;
+    match res.status() {
+        200 => res
+            .json::<SharedStudy>()
+            .await
+            .map_err(|_| String::from("Unable to parse data from server.")),
+        403 | 404 => Err(res.text().await.unwrap_or_default()),
+        status => Err(format!("The server sent back an error: {}.", status)),
+    }
+}
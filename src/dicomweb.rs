@@ -0,0 +1,115 @@
+use dicom::dictionary_std::tags;
+use dicom::object::InMemDicomObject;
+use gloo::net::http::Request;
+use pacsportal_core::attributes::text;
+use pacsportal_core::codes;
+use pacsportal_core::critical::CRITICAL_ACKNOWLEDGEMENT;
+use pacsportal_core::kos::{key_images, KeyImage};
+use pacsportal_core::report::report_text;
+
+pub use pacsportal_core::stow::{stow_body, CONTENT_TYPE as STOW_CONTENT_TYPE};
+
+/// DICOMweb root of the archive. Set `PACSPORTAL_RS_BASE` at build time to use
+/// another one, e.g. `/dicomweb` to go through the Trunk proxy to the mock
+/// server in `pacsportal-mock`.
+pub const RS_BASE: &str = match option_env!("PACSPORTAL_RS_BASE") {
+    Some(base) => base,
+    None => "http://210.56.0.36:8080/dcm4chee-arc/aets/SCHPACS2/rs",
+};
+
+/// Root of the viewer that studies are opened in. Set
+/// `PACSPORTAL_VIEWER_BASE` at build time to use another one.
+pub const VIEWER_BASE: &str = match option_env!("PACSPORTAL_VIEWER_BASE") {
+    Some(base) => base,
+    None => "http://210.56.0.36:3000/Viewer",
+};
+
+/// Where the viewer shows a study.
+pub fn viewer_url(study_uid: &str) -> String {
+    format!("{}/{}", VIEWER_BASE, study_uid)
+}
+
+/// GETs a DICOM JSON array. Any failure, including an empty 204 response,
+/// yields `None`.
+pub async fn fetch_json(url: &str) -> Option<Vec<InMemDicomObject>> {
+    let res = Request::get(url).send().await.ok()?;
+    if res.status() != 200 {
+        return None;
+    }
+    let data = res.json::<Vec<serde_json::Value>>().await.ok()?;
+    Some(
+        data.into_iter()
+            .filter_map(|object| dicom_json::from_value(object).ok())
+            .collect(),
+    )
+}
+
+/// Retrieves the text of every report stored in a study via WADO-RS metadata.
+/// Acknowledgements of critical results are SRs too, but not reports.
+pub async fn fetch_reports(study_uid: &str) -> Vec<String> {
+    fetch_reports_from(RS_BASE, study_uid).await
+}
+
+/// [`fetch_reports`] through another DICOMweb root, such as that of a share link.
+pub async fn fetch_reports_from(base: &str, study_uid: &str) -> Vec<String> {
+    let srs = fetch_json(&format!("{}/studies/{}/instances?Modality=SR", base, study_uid))
+        .await
+        .unwrap_or_default();
+    let mut reports = Vec::new();
+    for sr in srs {
+        let series_uid = text(&sr, tags::SERIES_INSTANCE_UID);
+        let sop_uid = text(&sr, tags::SOP_INSTANCE_UID);
+        if let (Some(series_uid), Some(sop_uid)) = (series_uid, sop_uid) {
+            let metadata = fetch_json(&format!(
+                "{}/studies/{}/series/{}/instances/{}/metadata",
+                base, study_uid, series_uid, sop_uid
+            ))
+            .await
+            .unwrap_or_default();
+            reports.extend(
+                metadata
+                    .iter()
+                    .filter(|sr| !codes::has_title(sr, &CRITICAL_ACKNOWLEDGEMENT))
+                    .filter_map(report_text),
+            );
+        }
+    }
+    reports
+}
+
+/// WADO-RS rendered (JPEG) version of a key image, for thumbnails.
+pub fn rendered_url(image: &KeyImage) -> String {
+    format!(
+        "{}/studies/{}/series/{}/instances/{}/rendered",
+        RS_BASE, image.study_uid, image.series_uid, image.sop_uid
+    )
+}
+
+/// Retrieves every KOS document stored in a study and collects the key
+/// images they reference.
+pub async fn fetch_key_images(study_uid: &str) -> Vec<KeyImage> {
+    let documents = fetch_json(&format!("{}/studies/{}/instances?Modality=KO", RS_BASE, study_uid))
+        .await
+        .unwrap_or_default();
+    let mut images = Vec::new();
+    for document in documents {
+        let series_uid = text(&document, tags::SERIES_INSTANCE_UID);
+        let sop_uid = text(&document, tags::SOP_INSTANCE_UID);
+        if let (Some(series_uid), Some(sop_uid)) = (series_uid, sop_uid) {
+            let metadata = fetch_json(&format!(
+                "{}/studies/{}/series/{}/instances/{}/metadata",
+                RS_BASE, study_uid, series_uid, sop_uid
+            ))
+            .await
+            .unwrap_or_default();
+            for kos in metadata.iter() {
+                for image in key_images(kos) {
+                    if !images.contains(&image) {
+                        images.push(image);
+                    }
+                }
+            }
+        }
+    }
+    images
+}
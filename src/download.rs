@@ -0,0 +1,20 @@
+//! Saving files made in the browser, such as exports, without a server round trip.
+
+use data_encoding::BASE64;
+use wasm_bindgen::JsCast;
+use web_sys::HtmlElement;
+
+/// Offers `bytes` to the user as a download named `file_name`.
+pub fn save(file_name: &str, content_type: &str, bytes: &[u8]) -> Result<(), String> {
+    let link = gloo::utils::document()
+        .create_element("a")
+        .map_err(|_| String::from("Unable to create the download link."))?;
+    let href = format!("data:{};base64,{}", content_type, BASE64.encode(bytes));
+    link.set_attribute("href", &href)
+        .and_then(|_| link.set_attribute("download", file_name))
+        .map_err(|_| String::from("Unable to create the download link."))?;
+    link.dyn_into::<HtmlElement>()
+        .map_err(|_| String::from("Unable to create the download link."))?
+        .click();
+    Ok(())
+}
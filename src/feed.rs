@@ -0,0 +1,58 @@
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::JsCast;
+use web_sys::{Event, EventSource};
+use yew::Callback;
+
+use crate::api::API_BASE;
+use crate::log;
+
+/// An open subscription to the live feed of `pacsportal-server`; dropping it
+/// closes the connection.
+pub struct Subscription {
+    source: EventSource,
+    _on_open: Closure<dyn FnMut(Event)>,
+    _on_message: Closure<dyn FnMut(Event)>,
+    _on_error: Closure<dyn FnMut(Event)>,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.source.close();
+    }
+}
+
+/// Calls `on_change` whenever a study arrives or a report is stored, so that
+/// the page can search again. `on_live` is told whether the feed is connected;
+/// the browser reconnects by itself after an interruption, but not once the
+/// server refused, e.g. because the session ended. `None` when the portal is
+/// not served by the server.
+pub fn subscribe(on_change: Callback<()>, on_live: Callback<bool>) -> Option<Subscription> {
+    let source = match EventSource::new(&format!("{}/events", API_BASE?)) {
+        Ok(source) => source,
+        Err(e) => {
+            log::warn("feed", format!("could not subscribe: {:?}", e));
+            return None;
+        }
+    };
+    let on_open = {
+        let on_live = on_live.clone();
+        Closure::<dyn FnMut(Event)>::new(move |_: Event| on_live.emit(true))
+    };
+    let on_message = Closure::<dyn FnMut(Event)>::new(move |_: Event| on_change.emit(()));
+    let on_error = {
+        let source = source.clone();
+        Closure::<dyn FnMut(Event)>::new(move |_: Event| {
+            log::debug("feed", "the feed was interrupted");
+            on_live.emit(source.ready_state() == EventSource::OPEN);
+        })
+    };
+    source.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+    source.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+    source.set_onerror(Some(on_error.as_ref().unchecked_ref()));
+    Some(Subscription {
+        source,
+        _on_open: on_open,
+        _on_message: on_message,
+        _on_error: on_error,
+    })
+}
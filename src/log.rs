@@ -0,0 +1,68 @@
+//! Console logging. Every line carries a level and the part of the portal it
+//! comes from, e.g. `12:04:31.207 DEBUG search: 14 studies`. Debug lines are
+//! only written once debugging is switched on at runtime with Ctrl+Shift+D,
+//! which is remembered by the browser.
+//!
+//! Reading room terminals are shared, so messages must never contain patient
+//! data: describe DICOM objects and queries with [`redact`] instead of
+//! logging their values.
+
+use std::fmt::Display;
+
+use chrono::Local;
+use gloo::storage::{LocalStorage, Storage};
+
+pub use pacsportal_core::redact;
+
+const DEBUG_KEY: &str = "pacsportal.debug";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+pub fn debug_enabled() -> bool {
+    LocalStorage::get(DEBUG_KEY).unwrap_or(false)
+}
+
+/// Switches debug logging on or off and says which it is now.
+pub fn toggle_debug() -> bool {
+    let enabled = !debug_enabled();
+    if LocalStorage::set(DEBUG_KEY, enabled).is_err() {
+        gloo::console::error!("Unable to save the debug setting to local storage.");
+    }
+    info("log", if enabled { "debug logging on" } else { "debug logging off" });
+    enabled
+}
+
+pub fn log(level: Level, target: &str, message: impl Display) {
+    if level == Level::Debug && !debug_enabled() {
+        return;
+    }
+    let time = Local::now().format("%H:%M:%S%.3f");
+    match level {
+        Level::Error => gloo::console::error!(format!("{} ERROR {}: {}", time, target, message)),
+        Level::Warn => gloo::console::warn!(format!("{} WARN {}: {}", time, target, message)),
+        Level::Info => gloo::console::info!(format!("{} INFO {}: {}", time, target, message)),
+        Level::Debug => gloo::console::debug!(format!("{} DEBUG {}: {}", time, target, message)),
+    }
+}
+
+pub fn error(target: &str, message: impl Display) {
+    log(Level::Error, target, message);
+}
+
+pub fn warn(target: &str, message: impl Display) {
+    log(Level::Warn, target, message);
+}
+
+pub fn info(target: &str, message: impl Display) {
+    log(Level::Info, target, message);
+}
+
+pub fn debug(target: &str, message: impl Display) {
+    log(Level::Debug, target, message);
+}
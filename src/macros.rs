@@ -0,0 +1,98 @@
+use gloo::storage::{LocalStorage, Storage};
+
+pub use pacsportal_core::macros::{char_index, expand, expand_at_caret, utf16_offset, Macro, MacroScope, PatientContext};
+
+use crate::store::{Store, StoredMacro};
+
+/// Where macros are kept in builds without a metadata store, which cannot
+/// share them with the department.
+const MACROS_KEY: &str = "pacsportal.macros";
+
+fn load_local() -> Vec<Macro> {
+    LocalStorage::get(MACROS_KEY).unwrap_or_default()
+}
+
+fn save_local(macros: &[Macro]) {
+    if LocalStorage::set(MACROS_KEY, macros).is_err() {
+        crate::log::error("macros", "unable to save report macros to local storage");
+    }
+}
+
+fn from_stored(stored: StoredMacro) -> Option<Macro> {
+    let scope = match stored.scope.as_str() {
+        "user" => MacroScope::User(stored.owner),
+        "department" => MacroScope::Department(stored.owner),
+        _ => return None,
+    };
+    Some(Macro {
+        trigger: stored.trigger,
+        phrase: stored.phrase,
+        scope,
+    })
+}
+
+fn scope_and_owner(scope: &MacroScope) -> (&'static str, &str) {
+    match scope {
+        MacroScope::User(username) => ("user", username),
+        MacroScope::Department(department) => ("department", department),
+    }
+}
+
+/// The macros of the user and of their department, from the metadata store
+/// if there is one.
+pub async fn load_all(store: Option<&Store>) -> Result<Vec<Macro>, String> {
+    match store {
+        Some(store) => Ok(store
+            .macros()
+            .await
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .filter_map(from_stored)
+            .collect()),
+        None => Ok(load_local()),
+    }
+}
+
+/// Saves `saved`, replacing the macro with the same trigger in its scope.
+pub async fn save(store: Option<&Store>, saved: &Macro) -> Result<(), String> {
+    match store {
+        Some(store) => {
+            let (scope, owner) = scope_and_owner(&saved.scope);
+            store.save_macro(scope, owner, &saved.trigger, &saved.phrase).await.map_err(|e| e.to_string())
+        }
+        None => {
+            let mut macros: Vec<Macro> = load_local()
+                .into_iter()
+                .filter(|m| !(m.trigger == saved.trigger && m.scope == saved.scope))
+                .collect();
+            macros.push(saved.clone());
+            save_local(&macros);
+            Ok(())
+        }
+    }
+}
+
+pub async fn delete(store: Option<&Store>, deleted: &Macro) -> Result<(), String> {
+    match store {
+        Some(store) => {
+            let (scope, owner) = scope_and_owner(&deleted.scope);
+            store.delete_macro(scope, owner, &deleted.trigger).await.map_err(|e| e.to_string())
+        }
+        None => {
+            let macros: Vec<Macro> = load_local().into_iter().filter(|m| m != deleted).collect();
+            save_local(&macros);
+            Ok(())
+        }
+    }
+}
+
+/// Stored macros usable by `username`; see [`pacsportal_core::macros::available_from`].
+pub async fn available_for(store: Option<&Store>, username: &str, department: &str) -> Vec<Macro> {
+    match load_all(store).await {
+        Ok(all) => pacsportal_core::macros::available_from(&all, username, department),
+        Err(e) => {
+            crate::log::warn("macros", format!("could not load the macros: {}", e));
+            Vec::new()
+        }
+    }
+}
@@ -1,22 +1,58 @@
+mod api;
+mod dicomweb;
+mod download;
+mod feed;
+mod log;
+mod macros;
 mod pages;
+mod saved_searches;
+mod store;
+#[cfg(all(test, target_arch = "wasm32"))]
+mod tests;
+use pages::audit::Audit;
+use pages::critical::CriticalResults;
 use pages::login::Login;
+use pages::macros::Macros;
+use pages::patient::Patient;
 use pages::reporting::Reporting;
 use pages::search::Search;
+use pages::shared::Shared;
+use pages::turnaround::Turnaround;
+use pages::worklist::Worklist;
 
 use std::rc::Rc;
+use wasm_bindgen::JsCast;
 use yew::prelude::*;
 use yew_router::prelude::*;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Authorized {
     pub inner: bool,
+    pub username: String,
+    /// The name reports are verified with.
+    pub name: String,
+    pub department: String,
+    /// May browse the audit trail.
+    pub admin: bool,
+}
+
+impl Authorized {
+    pub fn anonymous() -> Self {
+        Authorized {
+            inner: false,
+            username: String::new(),
+            name: String::new(),
+            department: String::new(),
+            admin: false,
+        }
+    }
 }
 
 impl Reducible for Authorized {
-    type Action = bool;
+    type Action = Authorized;
 
     fn reduce(self: Rc<Self>, action: Self::Action) -> Rc<Self> {
-        Authorized { inner: action }.into()
+        action.into()
     }
 }
 
@@ -30,6 +66,20 @@ pub enum Route {
     Search,
     #[at("/reporting/:uid")]
     Reporting {uid: String},
+    #[at("/worklist")]
+    Worklist,
+    #[at("/macros")]
+    Macros,
+    #[at("/critical")]
+    CriticalResults,
+    #[at("/audit")]
+    Audit,
+    #[at("/turnaround")]
+    Turnaround,
+    #[at("/patient/:id")]
+    Patient {id: String},
+    #[at("/share/:token")]
+    Shared {token: String},
     #[at("/404")]
     NotFound,
 }
@@ -39,13 +89,35 @@ fn switch(routes: Route) -> Html {
         Route::Search => html! { <Search /> },
         Route::Login => html! { <Login /> },
         Route::Reporting {uid} => html! { <Reporting study_uid={uid} /> },
+        Route::Worklist => html! { <Worklist /> },
+        Route::Macros => html! { <Macros /> },
+        Route::CriticalResults => html! { <CriticalResults /> },
+        Route::Audit => html! { <Audit /> },
+        Route::Turnaround => html! { <Turnaround /> },
+        Route::Patient {id} => html! { <Patient patient_id={id} /> },
+        Route::Shared {token} => html! { <Shared token={token} /> },
         Route::NotFound => html! { <h1>{"404: Not Found"}</h1> },
     }
 }
 
 #[function_component(App)]
 fn app() -> Html {
-    let ctx = use_reducer(|| Authorized { inner: false });
+    let ctx = use_reducer(Authorized::anonymous);
+
+    use_effect_with_deps(
+        |_| {
+            let listener = gloo::events::EventListener::new(&gloo::utils::document(), "keydown", |event| {
+                if let Some(event) = event.dyn_ref::<web_sys::KeyboardEvent>() {
+                    if event.ctrl_key() && event.shift_key() && event.key().eq_ignore_ascii_case("d") {
+                        event.prevent_default();
+                        log::toggle_debug();
+                    }
+                }
+            });
+            move || drop(listener)
+        },
+        (),
+    );
 
     html! {
         <ContextProvider<AuthorizedContext> context={ctx}>
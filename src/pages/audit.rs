@@ -0,0 +1,248 @@
+use chrono::{Local, NaiveDate, TimeZone};
+use web_sys::{HtmlInputElement, HtmlSelectElement};
+use yew::prelude::*;
+use yew_router::prelude::use_navigator;
+
+use crate::api::{self, AuditRecord};
+use crate::Route;
+
+const EVENTS: [(&str, &str); 12] = [
+    ("login", "Login"),
+    ("logout", "Logout"),
+    ("query", "Search"),
+    ("study-opened", "Study opened"),
+    ("report-created", "Report created"),
+    ("report-amended", "Report amended"),
+    ("critical-acknowledged", "Critical result acknowledged"),
+    ("instances-stored", "Instances stored"),
+    ("export", "Export"),
+    ("share-created", "Share link created"),
+    ("share-revoked", "Share link revoked"),
+    ("share-accessed", "Opened through share link"),
+];
+
+fn event_label(event: &str) -> &str {
+    EVENTS
+        .iter()
+        .find(|(value, _)| *value == event)
+        .map(|(_, label)| *label)
+        .unwrap_or(event)
+}
+
+/// Start of the given day in local time, as the server expects it.
+fn day_start(date: &str, days_after: u64) -> Option<String> {
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?;
+    let date = date.checked_add_days(chrono::Days::new(days_after))?;
+    let start = Local.from_local_datetime(&date.and_hms_opt(0, 0, 0)?).earliest()?;
+    Some(start.to_rfc3339())
+}
+
+/// A row of the trail: a record, or the run of requests of one viewing
+/// through a share link, which are audited one by one.
+struct Row<'a> {
+    record: &'a AuditRecord,
+    count: usize,
+    /// Time of the earliest request of the run.
+    since: &'a str,
+}
+
+/// Collapses consecutive share link requests from the same address with the
+/// same outcome into one row.
+fn rows(records: &[AuditRecord]) -> Vec<Row<'_>> {
+    let mut rows: Vec<Row> = Vec::new();
+    for record in records {
+        if let Some(last) = rows.last_mut() {
+            if record.event == "share-accessed"
+                && last.record.event == record.event
+                && last.record.username == record.username
+                && last.record.address == record.address
+                && last.record.success == record.success
+            {
+                last.count += 1;
+                last.since = &record.time;
+                continue;
+            }
+        }
+        rows.push(Row { record, count: 1, since: &record.time });
+    }
+    rows
+}
+
+fn local_time(time: &str) -> String {
+    time.get(..19).unwrap_or(time).replace('T', " ")
+}
+
+#[derive(Clone, Default, PartialEq)]
+struct Filters {
+    username: String,
+    patient_id: String,
+    event: String,
+    from: String,
+    to: String,
+}
+
+impl Filters {
+    fn query(&self) -> Vec<(&'static str, String)> {
+        let mut query = Vec::new();
+        if !self.username.is_empty() {
+            query.push(("username", self.username.clone()));
+        }
+        if !self.patient_id.is_empty() {
+            query.push(("patient_id", self.patient_id.clone()));
+        }
+        if !self.event.is_empty() {
+            query.push(("event", self.event.clone()));
+        }
+        if let Some(from) = day_start(&self.from, 0) {
+            query.push(("from", from));
+        }
+        // up to the end of the chosen day
+        if let Some(to) = day_start(&self.to, 1) {
+            query.push(("to", to));
+        }
+        query
+    }
+}
+
+#[function_component(Audit)]
+pub fn audit() -> Html {
+    let records = use_state(Vec::<AuditRecord>::new);
+    let loaded_status = use_state(|| String::from("Loading..."));
+    let filters = use_state(Filters::default);
+    let navigator = use_navigator().unwrap();
+
+    use_effect_with_deps(
+        {
+            let records = records.clone();
+            let loaded_status = loaded_status.clone();
+            move |filters: &Filters| {
+                let query = filters.query();
+                match api::API_BASE {
+                    None => loaded_status.set(String::from("The audit trail is kept by pacsportal-server; this build does not use it.")),
+                    Some(base) => {
+                        loaded_status.set(String::from("Loading..."));
+                        wasm_bindgen_futures::spawn_local(async move {
+                            match api::audit_records(base, &query).await {
+                                Ok(fetched) => {
+                                    records.set(fetched);
+                                    loaded_status.set(String::from(""));
+                                }
+                                Err(error) => loaded_status.set(error),
+                            }
+                        });
+                    }
+                }
+            }
+        },
+        (*filters).clone(),
+    );
+
+    let on_input = |update: fn(&mut Filters, String)| {
+        let filters = filters.clone();
+        move |e: Event| {
+            let value = e
+                .target_dyn_into::<HtmlInputElement>()
+                .map(|input| input.value())
+                .or_else(|| e.target_dyn_into::<HtmlSelectElement>().map(|select| select.value()))
+                .unwrap_or_default();
+            let mut changed = (*filters).clone();
+            update(&mut changed, value.trim().to_owned());
+            filters.set(changed);
+        }
+    };
+
+    let rows = rows(&records)
+        .into_iter()
+        .map(|Row { record, count, since }| {
+            let time = if count > 1 {
+                format!("{} to {}", local_time(since), local_time(&record.time))
+            } else {
+                local_time(&record.time)
+            };
+            let detail = if count > 1 {
+                format!("{} requests, the last {}", count, record.detail)
+            } else {
+                record.detail.clone()
+            };
+            let patient = match (&record.patient_id, &record.patient_name) {
+                (Some(id), Some(name)) => format!("{} {}", id, name.replace('^', " ").trim()),
+                (Some(id), None) => id.clone(),
+                (None, Some(name)) => name.replace('^', " ").trim().to_owned(),
+                (None, None) => String::new(),
+            };
+            html! {
+                <tr class="border-b dark:border-neutral-500">
+                    <td class="px-2 py-1 text-white whitespace-nowrap">{time}</td>
+                    <td class="px-2 py-1 text-white">{record.username.clone()}</td>
+                    <td class="px-2 py-1 text-white">{event_label(&record.event).to_owned()}</td>
+                    if record.success {
+                        <td class="px-2 py-1 text-grey">{"Success"}</td>
+                    } else {
+                        <td class="px-2 py-1 text-red font-semibold">{"Failure"}</td>
+                    }
+                    <td class="px-2 py-1 text-grey">{record.address.clone()}</td>
+                    <td class="px-2 py-1 text-white">{patient}</td>
+                    <td class="px-2 py-1 text-grey break-all">{record.study_uid.clone().unwrap_or_default()}</td>
+                    <td class="px-2 py-1 text-grey break-all">{detail}</td>
+                </tr>
+            }
+        })
+        .collect::<Html>();
+
+    html! {
+        <div class="min-h-screen bg-black px-6 md:px-12 py-6">
+            <div class="flex items-center justify-between border-b border-white/10 pb-6">
+                <div>
+                    <h1 class="text-white text-base font-semibold leading-7">{"Audit Trail"}</h1>
+                    <p class="mt-1 text-sm leading-6 text-gray-500">{"Logins, searches, opened studies, reports, exports and share links, newest first."}</p>
+                </div>
+                <button onclick={
+                    move |_: MouseEvent| {
+                        navigator.push(&Route::Search);
+                    }
+                } type="button" class="text-sm font-semibold leading-6 text-gray-500">{"Back"}</button>
+            </div>
+            <div class="mt-6 flex flex-wrap items-center gap-x-4 gap-y-2 text-sm">
+                <input onchange={on_input(|filters, value| filters.username = value)} type="text" placeholder="User" class="bg-black border px-2 py-1 text-white" />
+                <input onchange={on_input(|filters, value| filters.patient_id = value)} type="text" placeholder="Patient ID" class="bg-black border px-2 py-1 text-white" />
+                <select onchange={on_input(|filters, value| filters.event = value)} class="bg-black border px-2 py-1 text-white">
+                    <option value="" selected=true>{"All events"}</option>
+                    {
+                        EVENTS.iter().map(|(value, label)| html! {
+                            <option value={*value}>{*label}</option>
+                        }).collect::<Html>()
+                    }
+                </select>
+                <label class="text-grey">{"From "}
+                    <input onchange={on_input(|filters, value| filters.from = value)} type="date" class="bg-black border px-2 py-1 text-white" />
+                </label>
+                <label class="text-grey">{"To "}
+                    <input onchange={on_input(|filters, value| filters.to = value)} type="date" class="bg-black border px-2 py-1 text-white" />
+                </label>
+            </div>
+            if !loaded_status.is_empty() {
+                <p class="mt-6 text-white">{(*loaded_status).clone()}</p>
+            } else if records.is_empty() {
+                <p class="mt-6 text-white">{"No matching records."}</p>
+            } else {
+                <table class="mt-6 w-full text-left text-sm font-light">
+                    <thead class="border-b font-medium dark:border-neutral-500">
+                        <tr>
+                            <th scope="col" class="px-2 py-1 text-grey">{"Time"}</th>
+                            <th scope="col" class="px-2 py-1 text-grey">{"User"}</th>
+                            <th scope="col" class="px-2 py-1 text-grey">{"Event"}</th>
+                            <th scope="col" class="px-2 py-1 text-grey">{"Outcome"}</th>
+                            <th scope="col" class="px-2 py-1 text-grey">{"Address"}</th>
+                            <th scope="col" class="px-2 py-1 text-grey">{"Patient"}</th>
+                            <th scope="col" class="px-2 py-1 text-grey">{"Study"}</th>
+                            <th scope="col" class="px-2 py-1 text-grey">{"Detail"}</th>
+                        </tr>
+                    </thead>
+                    <tbody>
+                        {rows}
+                    </tbody>
+                </table>
+            }
+        </div>
+    }
+}
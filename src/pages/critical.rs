@@ -0,0 +1,295 @@
+use chrono::{Local, NaiveDateTime};
+use dicom::{dictionary_std::tags, object::InMemDicomObject};
+use gloo::net::http::Request;
+use pacsportal_core::attributes;
+use pacsportal_core::codes::Code;
+use pacsportal_core::critical::{self, CRITICAL_ACKNOWLEDGEMENT, CRITICAL_REPORT};
+use yew::prelude::*;
+use yew_router::prelude::use_navigator;
+
+use crate::dicomweb;
+use crate::log;
+use crate::store::{self, Acknowledgement};
+use crate::{AuthorizedContext, Route};
+
+/// Who acknowledged a critical report, and when.
+#[derive(Clone, PartialEq)]
+struct Acknowledged {
+    by: String,
+    at: Option<NaiveDateTime>,
+}
+
+impl Acknowledged {
+    fn from_sr(ack: &InMemDicomObject) -> Self {
+        Acknowledged {
+            by: attributes::person_name(ack, tags::CONTENT_CREATOR_NAME).unwrap_or_default(),
+            at: content_date_time(ack),
+        }
+    }
+
+    fn from_store(ack: &Acknowledgement) -> Self {
+        Acknowledged {
+            by: ack.acknowledged_by.clone(),
+            at: Some(ack.acknowledged_at.with_timezone(&Local).naive_local()),
+        }
+    }
+}
+
+/// A critical report together with the acknowledgement that followed it, if any.
+#[derive(Clone, PartialEq)]
+struct CriticalResult {
+    report: InMemDicomObject,
+    acknowledgement: Option<Acknowledged>,
+}
+
+fn content_date_time(object: &InMemDicomObject) -> Option<NaiveDateTime> {
+    attributes::date_time(object, tags::CONTENT_DATE, tags::CONTENT_TIME)
+}
+
+async fn query_instances(code: &Code) -> Result<Vec<InMemDicomObject>, String> {
+    let include_fields = "&includefield=ContentDate&includefield=ContentTime&includefield=ContentCreatorName&includefield=ReferringPhysicianName";
+    let fetched = Request::get(&format!(
+        "{}/instances?Modality=SR&{}{}",
+        dicomweb::RS_BASE,
+        critical::title_query(code),
+        include_fields
+    ))
+    .send()
+    .await;
+    match fetched {
+        Ok(res) => {
+            if res.status() == 204 {
+                Ok(Vec::new())
+            } else if res.status() != 200 {
+                Err(format!("The server sent back an error: {}. Please report this to your system administrator.", res.status()))
+            } else {
+                match res.json::<Vec<serde_json::Value>>().await {
+                    Ok(data) => Ok(data
+                        .into_iter()
+                        .filter_map(|instance| dicom_json::from_value(instance).ok())
+                        .collect()),
+                    Err(_) => Err(String::from("Unable to parse data from server. Please report this to your system administrator.")),
+                }
+            }
+        }
+        Err(_) => Err(String::from("Unable to reach the server. Please try again later or contact your system administrator.")),
+    }
+}
+
+/// Pairs every critical report with the first acknowledgement stored in the
+/// same study after it.
+fn pair_acknowledgements(
+    reports: Vec<InMemDicomObject>,
+    acknowledgements: Vec<InMemDicomObject>,
+) -> Vec<CriticalResult> {
+    let mut results: Vec<CriticalResult> = reports
+        .into_iter()
+        .map(|report| {
+            let study_uid = attributes::text(&report, tags::STUDY_INSTANCE_UID).unwrap_or_default();
+            let reported_at = content_date_time(&report);
+            let acknowledgement = acknowledgements
+                .iter()
+                .filter(|ack| attributes::text(ack, tags::STUDY_INSTANCE_UID).unwrap_or_default() == study_uid)
+                .filter(|ack| content_date_time(ack) >= reported_at)
+                .min_by_key(|ack| content_date_time(ack))
+                .map(Acknowledged::from_sr);
+            CriticalResult {
+                report,
+                acknowledgement,
+            }
+        })
+        .collect();
+    results.sort_by_key(|result| std::cmp::Reverse(content_date_time(&result.report)));
+    results
+}
+
+/// Fills in the acknowledgements the metadata store recorded for reports
+/// whose acknowledgement the archive search did not return, e.g. one stored
+/// moments ago.
+fn add_stored_acknowledgements(results: &mut [CriticalResult], stored: &[Acknowledgement]) {
+    for result in results.iter_mut().filter(|result| result.acknowledgement.is_none()) {
+        let report_uid = attributes::text(&result.report, tags::SOP_INSTANCE_UID).unwrap_or_default();
+        result.acknowledgement = stored
+            .iter()
+            .find(|ack| ack.report_uid == report_uid)
+            .map(Acknowledged::from_store);
+    }
+}
+
+#[function_component(CriticalResults)]
+pub fn critical_results() -> Html {
+    let results = use_state(Vec::<CriticalResult>::new);
+    let loaded_status = use_state(|| String::from("Loading..."));
+    let show_acknowledged = use_state(|| false);
+    let refresh = use_state(|| 0u32);
+    let auth_ctx = use_context::<AuthorizedContext>().unwrap();
+    let navigator = use_navigator().unwrap();
+    let store = use_memo(store::for_user, (*auth_ctx).clone());
+
+    use_effect_with_deps(
+        {
+            let results = results.clone();
+            let loaded_status = loaded_status.clone();
+            let store = store.clone();
+            move |_| {
+                loaded_status.set(String::from("Loading..."));
+                wasm_bindgen_futures::spawn_local(async move {
+                    let reports = query_instances(&CRITICAL_REPORT).await;
+                    let acknowledgements = query_instances(&CRITICAL_ACKNOWLEDGEMENT).await;
+                    match (reports, acknowledgements) {
+                        (Ok(reports), Ok(acknowledgements)) => {
+                            let mut paired = pair_acknowledgements(reports, acknowledgements);
+                            let unacknowledged: Vec<String> = paired
+                                .iter()
+                                .filter(|result| result.acknowledgement.is_none())
+                                .map(|result| attributes::text(&result.report, tags::STUDY_INSTANCE_UID).unwrap_or_default())
+                                .collect();
+                            if let (Some(store), false) = ((*store).clone(), unacknowledged.is_empty()) {
+                                match store.acknowledgements(&unacknowledged).await {
+                                    Ok(stored) => add_stored_acknowledgements(&mut paired, &stored),
+                                    Err(e) => log::warn("critical", format!("could not load the acknowledgements from the store: {}", e)),
+                                }
+                            }
+                            results.set(paired);
+                            loaded_status.set(String::from(""));
+                        }
+                        (Err(error), _) | (_, Err(error)) => loaded_status.set(error),
+                    }
+                });
+            }
+        },
+        *refresh,
+    );
+
+    let acknowledge = {
+        let auth_ctx = auth_ctx.clone();
+        let store = store.clone();
+        let refresh = refresh.clone();
+        let loaded_status = loaded_status.clone();
+        move |report: &InMemDicomObject| -> Callback<MouseEvent> {
+            let report = report.clone();
+            let auth_ctx = auth_ctx.clone();
+            let store = store.clone();
+            let refresh = refresh.clone();
+            let loaded_status = loaded_status.clone();
+            Callback::from(move |_: MouseEvent| {
+                let ack = critical::acknowledgement(&report, &auth_ctx.username, Local::now().naive_local());
+                let request_body = dicomweb::stow_body(ack);
+                let refresh = refresh.clone();
+                let loaded_status = loaded_status.clone();
+                let store = (*store).clone();
+                let report_uid = attributes::text(&report, tags::SOP_INSTANCE_UID).unwrap_or_default();
+                let study_uid = attributes::text(&report, tags::STUDY_INSTANCE_UID).unwrap_or_default();
+                wasm_bindgen_futures::spawn_local(async move {
+                    let result = match Request::post(&format!("{}/studies", dicomweb::RS_BASE))
+                        .header("Content-Type", dicomweb::STOW_CONTENT_TYPE)
+                        .body(request_body)
+                    {
+                        Ok(request) => request.send().await.map_err(|e| e.to_string()),
+                        Err(e) => Err(e.to_string()),
+                    };
+                    match result {
+                        Ok(res) if res.ok() => {
+                            // the SR in the archive is the record; the store only mirrors it
+                            if let Some(store) = store {
+                                if let Err(e) = store.acknowledge(&report_uid, &study_uid).await {
+                                    log::warn("critical", format!("could not record the acknowledgement in the store: {}", e));
+                                }
+                            }
+                            refresh.set(*refresh + 1)
+                        }
+                        Ok(res) => loaded_status.set(format!("The server sent back an error: {}. The acknowledgement was not recorded; please report this to your system administrator.", res.status())),
+                        Err(e) => loaded_status.set(format!("Unable to record the acknowledgement: {}. Please try again or contact your system administrator.", e)),
+                    }
+                });
+            })
+        }
+    };
+
+    let rows = {
+        let results = results.clone();
+        let show_acknowledged = show_acknowledged.clone();
+        let auth_ctx = auth_ctx.clone();
+        move || -> Html {
+            results
+                .iter()
+                .filter(|result| *show_acknowledged || result.acknowledgement.is_none())
+                .map(|result| {
+                    let report = &result.report;
+                    let reported_at = content_date_time(report)
+                        .map(|at| at.format("%Y-%m-%d %H:%M").to_string())
+                        .unwrap_or_default();
+                    let status = match &result.acknowledgement {
+                        Some(ack) => html! {
+                            <span class="text-grey">
+                                {format!(
+                                    "Acknowledged by {} at {}",
+                                    ack.by,
+                                    ack.at.map(|at| at.format("%Y-%m-%d %H:%M").to_string()).unwrap_or_default()
+                                )}
+                            </span>
+                        },
+                        None if !auth_ctx.inner => html! {
+                            <button onclick={acknowledge(report)} type="button" class="inline-block px-2 py-1 bg-[#ffd400] shadow-lg text-xs font-medium">{"Acknowledge"}</button>
+                        },
+                        None => html! { <span class="text-red font-semibold">{"Awaiting acknowledgement"}</span> },
+                    };
+                    html! {
+                        <tr key={attributes::text(report, tags::SOP_INSTANCE_UID).unwrap_or_default()} class="border-b dark:border-neutral-500">
+                            <td class="px-2 py-1 text-white font-medium">{attributes::text(report, tags::PATIENT_ID).unwrap_or_default()}</td>
+                            <td class="px-2 py-1 text-white">{attributes::person_name(report, tags::PATIENT_NAME).unwrap_or_default()}</td>
+                            <td class="px-2 py-1 text-white">{attributes::text(report, tags::ACCESSION_NUMBER).unwrap_or_default()}</td>
+                            <td class="px-2 py-1 text-white">{attributes::person_name(report, tags::REFERRING_PHYSICIAN_NAME).unwrap_or_default()}</td>
+                            <td class="px-2 py-1 text-white">{reported_at}</td>
+                            <td class="px-2 py-1">{status}</td>
+                        </tr>
+                    }
+                })
+                .collect::<Html>()
+        }
+    };
+
+    html! {
+        <div class="min-h-screen bg-black px-6 md:px-12 py-6">
+            <div class="flex items-center justify-between border-b border-white/10 pb-6">
+                <div>
+                    <h1 class="text-white text-base font-semibold leading-7">{"Critical Results"}</h1>
+                    <p class="mt-1 text-sm leading-6 text-gray-500">{"Reports flagged as critical which the referring physician has not yet acknowledged."}</p>
+                </div>
+                <div class="flex items-center gap-x-6">
+                    <label class="text-sm text-white">
+                        <input type="checkbox" class="mr-2" checked={*show_acknowledged} onclick={
+                            let show_acknowledged = show_acknowledged.clone();
+                            move |_: MouseEvent| show_acknowledged.set(!*show_acknowledged)
+                        } />
+                        {"Show acknowledged"}
+                    </label>
+                    <button onclick={
+                        move |_: MouseEvent| {
+                            navigator.push(&Route::Search);
+                        }
+                    } type="button" class="text-sm font-semibold leading-6 text-gray-500">{"Back"}</button>
+                </div>
+            </div>
+            if !loaded_status.is_empty() {
+                <p class="mt-6 text-white">{(*loaded_status).clone()}</p>
+            } else {
+                <table class="mt-6 w-full text-left text-sm font-light">
+                    <thead class="border-b font-medium dark:border-neutral-500">
+                        <tr>
+                            <th scope="col" class="px-2 py-1 text-grey">{"Patient ID"}</th>
+                            <th scope="col" class="px-2 py-1 text-grey">{"Name"}</th>
+                            <th scope="col" class="px-2 py-1 text-grey">{"Accession"}</th>
+                            <th scope="col" class="px-2 py-1 text-grey">{"Referring Physician"}</th>
+                            <th scope="col" class="px-2 py-1 text-grey">{"Reported"}</th>
+                            <th scope="col" class="px-2 py-1 text-grey">{"Status"}</th>
+                        </tr>
+                    </thead>
+                    <tbody>
+                        {rows()}
+                    </tbody>
+                </table>
+            }
+        </div>
+    }
+}
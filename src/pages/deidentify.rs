@@ -0,0 +1,115 @@
+use pacsportal_core::deidentify::{self, Options};
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+
+use crate::api;
+use crate::AuthorizedContext;
+
+/// Gives the flag of one option of the profile.
+type OptionFlag = fn(&mut Options) -> &mut bool;
+
+/// The options of the profile, as they are offered.
+const OPTIONS: [(&str, OptionFlag); 5] = [
+    ("Keep dates", |options| &mut options.retain_dates),
+    ("Keep UIDs", |options| &mut options.retain_uids),
+    ("Keep age, sex, size and weight", |options| &mut options.retain_patient_characteristics),
+    ("Keep device identity", |options| &mut options.retain_device_identity),
+    ("Keep institution", |options| &mut options.retain_institution_identity),
+];
+
+#[derive(Properties, PartialEq)]
+pub struct DeidentifyProps {
+    pub study_uid: String,
+}
+
+/// De-identified export of a study for teaching and research: a ZIP
+/// download, or an addition to the teaching files for radiologists. Only
+/// shown when the portal is served by `pacsportal-server`, which does both.
+#[function_component(Deidentify)]
+pub fn deidentify(props: &DeidentifyProps) -> Html {
+    let open = use_state(|| false);
+    let options = use_state(Options::default);
+    let pseudonym = use_state(String::new);
+    let status = use_state(|| String::from(""));
+    let auth_ctx = use_context::<AuthorizedContext>().unwrap();
+
+    let Some(base) = api::API_BASE else {
+        return html! {};
+    };
+    if !*open {
+        return html! {
+            <button onclick={move |_: MouseEvent| open.set(true)} type="button" class="mt-1 text-xs text-grey hover:text-white">{"De-identify..."}</button>
+        };
+    }
+
+    let on_pseudonym = {
+        let pseudonym = pseudonym.clone();
+        move |e: InputEvent| {
+            if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                pseudonym.set(input.value());
+            }
+        }
+    };
+    let on_teaching = {
+        let options = options.clone();
+        let pseudonym = pseudonym.clone();
+        let status = status.clone();
+        let study_uid = props.study_uid.clone();
+        move |_: MouseEvent| {
+            let options = *options;
+            let pseudonym = (*pseudonym).clone();
+            let status = status.clone();
+            let study_uid = study_uid.clone();
+            status.set(String::from("Adding to the teaching files..."));
+            wasm_bindgen_futures::spawn_local(async move {
+                match api::add_to_teaching_files(base, &study_uid, options, &pseudonym).await {
+                    Ok(message) | Err(message) => status.set(message),
+                }
+            });
+        }
+    };
+    let href = format!(
+        "{}/studies/{}/deidentified?{}",
+        base,
+        props.study_uid,
+        deidentify::query(&options, &pseudonym)
+    );
+
+    html! {
+        <div class="mt-2 p-3 max-w-xl border border-white/20 text-sm text-white">
+            <p class="text-xs text-grey">{"Names, IDs, descriptions and private data are removed as in the DICOM Basic Confidentiality Profile. Images that may show burned-in patient details are left out."}</p>
+            <div class="mt-2 grid grid-cols-2 gap-1">
+                {
+                    OPTIONS.iter().map(|(label, option)| {
+                        let options = options.clone();
+                        let option = *option;
+                        let mut chosen = *options;
+                        let checked = *option(&mut chosen);
+                        html! {
+                            <label class="text-xs">
+                                <input type="checkbox" class="mr-2" checked={checked} onclick={move |_: MouseEvent| {
+                                    let mut changed = *options;
+                                    let value = option(&mut changed);
+                                    *value = !*value;
+                                    options.set(changed);
+                                }} />
+                                {*label}
+                            </label>
+                        }
+                    }).collect::<Html>()
+                }
+            </div>
+            <input type="text" value={(*pseudonym).clone()} oninput={on_pseudonym} maxlength="64" placeholder="Pseudonym, e.g. TEACHING^CASE 12; made from the patient ID when empty" class="mt-2 w-full bg-black border border-white/20 px-2 py-1 text-xs" />
+            <div class="mt-2 flex items-center gap-x-4">
+                <a href={href} download="" class="inline-block px-2 py-1 border text-xs font-medium hover:bg-yellow hover:text-black">{"Download de-identified"}</a>
+                if auth_ctx.inner {
+                    <button onclick={on_teaching} type="button" class="inline-block px-2 py-1 bg-[#ffd400] text-black text-xs font-medium">{"Add to teaching files"}</button>
+                }
+                <button onclick={move |_: MouseEvent| open.set(false)} type="button" class="text-xs text-grey">{"Close"}</button>
+            </div>
+            if !status.is_empty() {
+                <p class="mt-2 text-xs">{(*status).clone()}</p>
+            }
+        </div>
+    }
+}
@@ -0,0 +1,220 @@
+use chrono::Local;
+use dicom::dictionary_std::tags;
+use dicom::object::InMemDicomObject;
+use gloo::net::http::Request;
+use pacsportal_core::attributes::text;
+use pacsportal_core::kos::{self, KeyImage};
+use pacsportal_core::model::{Instance, Series};
+use yew::prelude::*;
+
+use crate::dicomweb;
+
+#[derive(Properties, PartialEq)]
+pub struct KeyImagesProps {
+    pub study_uid: String,
+    #[prop_or_default]
+    pub version: u32, // bump to re-fetch after a new KOS was stored
+}
+
+#[function_component(KeyImages)]
+pub fn key_images(props: &KeyImagesProps) -> Html {
+    let images = use_state(Vec::<KeyImage>::new);
+
+    use_effect_with_deps(
+        {
+            let images = images.clone();
+            move |(study_uid, _): &(String, u32)| {
+                let study_uid = study_uid.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    images.set(dicomweb::fetch_key_images(&study_uid).await);
+                });
+            }
+        },
+        (props.study_uid.clone(), props.version),
+    );
+
+    html! {
+        if !images.is_empty() {
+            <div class="mt-2 flex flex-wrap gap-2">
+                {
+                    images.iter().map(|image| html! {
+                        <a key={image.sop_uid.clone()} href={dicomweb::viewer_url(&image.study_uid)} target="_blank" rel="noopener noreferrer">
+                            <img src={dicomweb::rendered_url(image)} alt="Key image" class="h-32 w-32 object-contain bg-black border border-[#ffd400]" />
+                        </a>
+                    }).collect::<Html>()
+                }
+            </div>
+        }
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct KeyImageSelectorProps {
+    pub study_details: InMemDicomObject,
+    pub on_saved: Callback<()>,
+}
+
+/// Drill-down from the series of a study to its instances, where images can
+/// be picked and stored as a Key Object Selection document.
+#[function_component(KeyImageSelector)]
+pub fn key_image_selector(props: &KeyImageSelectorProps) -> Html {
+    let series = use_state(Vec::<Series>::new);
+    let open_series = use_state(|| Option::<String>::None);
+    let instances = use_state(Vec::<KeyImage>::new);
+    let selected = use_state(Vec::<KeyImage>::new);
+    let status = use_state(|| String::from(""));
+    let study_uid = text(&props.study_details, tags::STUDY_INSTANCE_UID).unwrap_or_default();
+
+    use_effect_with_deps(
+        {
+            let series = series.clone();
+            move |study_uid: &String| {
+                let study_uid = study_uid.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    let fetched = dicomweb::fetch_json(&format!(
+                        "{}/studies/{}/series?includefield=SeriesDescription",
+                        dicomweb::RS_BASE,
+                        study_uid
+                    ))
+                    .await
+                    .unwrap_or_default();
+                    // key images only make sense for image series
+                    series.set(
+                        fetched
+                            .iter()
+                            .map(Series::from_dicom)
+                            .filter(|s| s.is_image_series())
+                            .collect(),
+                    );
+                });
+            }
+        },
+        study_uid.clone(),
+    );
+
+    use_effect_with_deps(
+        {
+            let instances = instances.clone();
+            let study_uid = study_uid.clone();
+            move |open_series: &Option<String>| {
+                instances.set(Vec::new());
+                if let Some(series_uid) = open_series.clone() {
+                    wasm_bindgen_futures::spawn_local(async move {
+                        let fetched = dicomweb::fetch_json(&format!(
+                            "{}/studies/{}/series/{}/instances",
+                            dicomweb::RS_BASE,
+                            study_uid,
+                            series_uid
+                        ))
+                        .await
+                        .unwrap_or_default();
+                        let mut fetched: Vec<Instance> = fetched.iter().map(Instance::from_dicom).collect();
+                        fetched.sort_by_key(|instance| instance.number.unwrap_or_default());
+                        instances.set(
+                            fetched
+                                .into_iter()
+                                .filter_map(|instance| {
+                                    Some(KeyImage {
+                                        study_uid: study_uid.clone(),
+                                        series_uid: series_uid.clone(),
+                                        sop_class_uid: instance.sop_class_uid?,
+                                        sop_uid: instance.sop_uid?,
+                                    })
+                                })
+                                .collect(),
+                        );
+                    });
+                }
+            }
+        },
+        (*open_series).clone(),
+    );
+
+    let onsave = {
+        let selected = selected.clone();
+        let status = status.clone();
+        let study_details = props.study_details.clone();
+        let on_saved = props.on_saved.clone();
+        Callback::from(move |_: MouseEvent| {
+            let request_body = dicomweb::stow_body(kos::key_object_selection(&study_details, &selected, Local::now().naive_local()));
+            let selected = selected.clone();
+            let status = status.clone();
+            let on_saved = on_saved.clone();
+            status.set(String::from("Saving key images..."));
+            wasm_bindgen_futures::spawn_local(async move {
+                let result = match Request::post(&format!("{}/studies", dicomweb::RS_BASE))
+                    .header("Content-Type", dicomweb::STOW_CONTENT_TYPE)
+                    .body(request_body)
+                {
+                    Ok(request) => request.send().await.map_err(|e| e.to_string()),
+                    Err(e) => Err(e.to_string()),
+                };
+                match result {
+                    Ok(res) if res.ok() => {
+                        selected.set(Vec::new());
+                        status.set(String::from(""));
+                        on_saved.emit(());
+                    }
+                    Ok(res) => status.set(format!("The server sent back an error: {}. The key images were not saved; please report this to your system administrator.", res.status())),
+                    Err(e) => status.set(format!("Unable to save the key images: {}. Please try again or contact your system administrator.", e)),
+                }
+            });
+        })
+    };
+
+    html! {
+        <div class="mt-4">
+            <div class="flex flex-wrap gap-2">
+                {
+                    series.iter().map(|s| {
+                        let series_uid = s.series_uid.clone().unwrap_or_default();
+                        let label = s.label();
+                        let is_open = open_series.as_deref() == Some(series_uid.as_str());
+                        let onclick = {
+                            let open_series = open_series.clone();
+                            move |_: MouseEvent| {
+                                if is_open {
+                                    open_series.set(None);
+                                } else {
+                                    open_series.set(Some(series_uid.clone()));
+                                }
+                            }
+                        };
+                        html! {
+                            <button {onclick} type="button" class={classes!("px-2", "py-1", "border", "text-xs", if is_open { "bg-[#ffd400] text-black" } else { "text-white" })}>{label}</button>
+                        }
+                    }).collect::<Html>()
+                }
+            </div>
+            <div class="mt-2 flex flex-wrap gap-1 max-h-96 overflow-y-auto">
+                {
+                    instances.iter().map(|image| {
+                        let is_selected = selected.contains(image);
+                        let onclick = {
+                            let selected = selected.clone();
+                            let image = image.clone();
+                            move |_: MouseEvent| {
+                                let mut new_selected = (*selected).clone();
+                                if is_selected {
+                                    new_selected.retain(|s| *s != image);
+                                } else {
+                                    new_selected.push(image.clone());
+                                }
+                                selected.set(new_selected);
+                            }
+                        };
+                        html! {
+                            <img key={image.sop_uid.clone()} {onclick} src={dicomweb::rendered_url(image)} alt="Instance" loading="lazy" class={classes!("h-24", "w-24", "object-contain", "cursor-pointer", "border-2", if is_selected { "border-[#ffd400]" } else { "border-transparent" })} />
+                        }
+                    }).collect::<Html>()
+                }
+            </div>
+            <div class="mt-2 flex items-center gap-x-4">
+                <button onclick={onsave} disabled={selected.is_empty()} type="button" class="inline-block px-2 py-1 bg-[#ffd400] shadow-lg text-xs font-medium disabled:opacity-50">
+                    {format!("Save {} key image(s)", selected.len())}
+                </button>
+                <span class="text-xs text-gray-500">{(*status).clone()}</span>
+            </div>
+        </div>
+    }
+}
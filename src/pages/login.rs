@@ -2,7 +2,8 @@ use web_sys::HtmlInputElement;
 use yew::prelude::*;
 use yew_router::prelude::*;
 
-use crate::{AuthorizedContext, Route};
+use crate::api;
+use crate::{Authorized, AuthorizedContext, Route};
 
 #[derive(Debug, Clone, PartialEq, Eq /*Serialize, Deserialize*/)]
 pub struct Credentials {
@@ -14,14 +15,14 @@ pub struct Credentials {
 pub fn login() -> Html {
     let username_node_ref = use_node_ref();
     let password_node_ref = use_node_ref();
-    let is_error = use_state(|| false);
+    let error = use_state(|| Option::<String>::None);
     let navigator = use_navigator().unwrap();
     let auth_ctx = use_context::<AuthorizedContext>().unwrap();
 
     let onsubmit = {
         let username_node_ref = username_node_ref.clone();
         let password_node_ref = password_node_ref.clone();
-        let is_error = is_error.clone();
+        let error = error.clone();
 
         Callback::from(move |event: SubmitEvent| {
             event.prevent_default();
@@ -33,6 +34,25 @@ pub fn login() -> Html {
                 .cast::<HtmlInputElement>()
                 .unwrap()
                 .value();
+            error.set(None);
+
+            if let Some(base) = api::API_BASE {
+                let error = error.clone();
+                let navigator = navigator.clone();
+                let auth_ctx = auth_ctx.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    match api::log_in(base, &username, &password).await {
+                        Ok(Some(authorized)) => {
+                            auth_ctx.dispatch(authorized);
+                            navigator.replace(&Route::Search);
+                        }
+                        Ok(None) => error.set(Some(String::from("Incorrect username and password."))),
+                        Err(e) => error.set(Some(e)),
+                    }
+                });
+                return;
+            }
+
             let entered_credentials = Credentials { username, password };
             let doctor_credentials = Credentials {
                 username: "root".into(),
@@ -42,17 +62,25 @@ pub fn login() -> Html {
                 username: "radiologist".into(),
                 password: "ultimate_radiologist".into(),
             };
-            is_error.set(false);
 
             if entered_credentials == doctor_credentials
                 || entered_credentials == radiologist_credentials
             {
-                if entered_credentials == radiologist_credentials {
-                    auth_ctx.dispatch(true);
-                }
+                auth_ctx.dispatch(Authorized {
+                    inner: entered_credentials == radiologist_credentials,
+                    username: entered_credentials.username.clone(),
+                    name: if entered_credentials == radiologist_credentials {
+                        String::from("DR WASAY JILANI")
+                    } else {
+                        entered_credentials.username.clone()
+                    },
+                    // the department the server gives accounts without one
+                    department: String::from("Radiology"),
+                    admin: false,
+                });
                 navigator.replace(&Route::Search);
             } else {
-                is_error.set(true);
+                error.set(Some(String::from("Incorrect username and password.")));
             }
         })
     };
@@ -83,8 +111,8 @@ pub fn login() -> Html {
                     <div>
                         <button type="submit" class="flex w-full justify-center rounded-sm bg-indigo-600 px-3 py-1.5 text-sm font-semibold leading-6 text-white shadow-sm hover:bg-indigo-500 focus-visible:outline focus-visible:outline-2 focus-visible:outline-offset-2 focus-visible:outline-indigo-600">{"Login"}</button>
                     </div>
-                    if *is_error {
-                        <p class="text-red">{"Incorrect username and password."}</p>
+                    if let Some(error) = &*error {
+                        <p class="text-red">{error}</p>
                     }
                 </form>
             </div>
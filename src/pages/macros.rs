@@ -0,0 +1,208 @@
+use web_sys::{HtmlInputElement, HtmlSelectElement, HtmlTextAreaElement};
+use yew::prelude::*;
+use yew_router::prelude::use_navigator;
+
+use crate::macros::{self, Macro, MacroScope};
+use crate::store;
+use crate::AuthorizedContext;
+
+#[function_component(Macros)]
+pub fn macros_page() -> Html {
+    let auth_ctx = use_context::<AuthorizedContext>().unwrap();
+    let navigator = use_navigator().unwrap();
+    let store = use_memo(store::for_user, (*auth_ctx).clone());
+    let all_macros = use_state(Vec::<Macro>::new);
+    let is_error = use_state(|| false);
+    let status = use_state(String::new);
+    let trigger_node_ref = use_node_ref();
+    let phrase_node_ref = use_node_ref();
+    let scope_node_ref = use_node_ref();
+
+    use_effect_with_deps(
+        {
+            let all_macros = all_macros.clone();
+            let status = status.clone();
+            let store = store.clone();
+            move |_| {
+                wasm_bindgen_futures::spawn_local(async move {
+                    match macros::load_all((*store).as_ref()).await {
+                        Ok(loaded) => all_macros.set(loaded),
+                        Err(e) => status.set(format!("The macros could not be loaded: {}", e)),
+                    }
+                });
+            }
+        },
+        (*auth_ctx).clone(),
+    );
+
+    let onsubmit = {
+        let auth_ctx = auth_ctx.clone();
+        let store = store.clone();
+        let all_macros = all_macros.clone();
+        let is_error = is_error.clone();
+        let status = status.clone();
+        let trigger_node_ref = trigger_node_ref.clone();
+        let phrase_node_ref = phrase_node_ref.clone();
+        let scope_node_ref = scope_node_ref.clone();
+        Callback::from(move |event: SubmitEvent| {
+            event.prevent_default();
+            let (Some(trigger_input), Some(phrase_input), Some(scope_select)) = (
+                trigger_node_ref.cast::<HtmlInputElement>(),
+                phrase_node_ref.cast::<HtmlTextAreaElement>(),
+                scope_node_ref.cast::<HtmlSelectElement>(),
+            ) else {
+                return;
+            };
+            let trigger = trigger_input.value().trim().trim_start_matches('.').to_owned();
+            let phrase = phrase_input.value();
+            let scope = match scope_select.value().as_str() {
+                "department" => MacroScope::Department(auth_ctx.department.clone()),
+                _ => MacroScope::User(auth_ctx.username.clone()),
+            };
+            if trigger.is_empty() || trigger.contains(char::is_whitespace) || phrase.trim().is_empty() {
+                is_error.set(true);
+                return;
+            }
+            is_error.set(false);
+
+            let saved = Macro { trigger, phrase, scope };
+            let store = store.clone();
+            let all_macros = all_macros.clone();
+            let status = status.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match macros::save((*store).as_ref(), &saved).await {
+                    Ok(()) => {
+                        // saving a trigger that already exists in the same scope replaces it
+                        let mut new_macros: Vec<Macro> = (*all_macros)
+                            .iter()
+                            .filter(|m| !(m.trigger == saved.trigger && m.scope == saved.scope))
+                            .cloned()
+                            .collect();
+                        new_macros.push(saved);
+                        all_macros.set(new_macros);
+                        status.set(String::new());
+                        trigger_input.set_value("");
+                        phrase_input.set_value("");
+                    }
+                    Err(e) => status.set(format!("The macro could not be saved: {}", e)),
+                }
+            });
+        })
+    };
+
+    let rows = {
+        let all_macros = all_macros.clone();
+        let auth_ctx = auth_ctx.clone();
+        let store = store.clone();
+        let status = status.clone();
+        move || -> Html {
+            let own = MacroScope::User(auth_ctx.username.clone());
+            let department = MacroScope::Department(auth_ctx.department.clone());
+            all_macros
+                .iter()
+                .filter(|m| m.scope == own || m.scope == department)
+                .map(|m| {
+                    let scope_label = match &m.scope {
+                        MacroScope::User(_) => String::from("Personal"),
+                        MacroScope::Department(name) => name.clone(),
+                    };
+                    let ondelete = {
+                        let all_macros = all_macros.clone();
+                        let store = store.clone();
+                        let status = status.clone();
+                        let to_delete = m.clone();
+                        move |_: MouseEvent| {
+                            let all_macros = all_macros.clone();
+                            let store = store.clone();
+                            let status = status.clone();
+                            let to_delete = to_delete.clone();
+                            wasm_bindgen_futures::spawn_local(async move {
+                                match macros::delete((*store).as_ref(), &to_delete).await {
+                                    Ok(()) => {
+                                        let new_macros: Vec<Macro> = (*all_macros)
+                                            .iter()
+                                            .filter(|m| **m != to_delete)
+                                            .cloned()
+                                            .collect();
+                                        all_macros.set(new_macros);
+                                        status.set(String::new());
+                                    }
+                                    Err(e) => status.set(format!("The macro could not be deleted: {}", e)),
+                                }
+                            });
+                        }
+                    };
+                    html! {
+                        <tr key={format!("{:?}{}", m.scope, m.trigger)} class="border-b dark:border-neutral-500">
+                            <td class="px-2 py-1 text-white font-medium">{format!(".{}", m.trigger)}</td>
+                            <td class="px-2 py-1 text-white whitespace-pre-wrap">{m.phrase.clone()}</td>
+                            <td class="px-2 py-1 text-grey">{scope_label}</td>
+                            <td class="px-2 py-1">
+                                <button onclick={ondelete} type="button" class="inline-block px-2 py-1 bg-red shadow-lg text-xs font-medium text-white">{"Delete"}</button>
+                            </td>
+                        </tr>
+                    }
+                })
+                .collect::<Html>()
+        }
+    };
+
+    html! {
+        <div class="min-h-screen bg-black px-6 md:px-12 py-6">
+            <div class="border-b border-white/10 pb-12">
+                <h1 class="text-white text-base font-semibold leading-7">{"Report Macros"}</h1>
+                <p class="mt-1 text-sm leading-6 text-gray-500">{"Type a trigger such as .nchest followed by a space in the report to expand its phrase. Use {sex} and {age} in a phrase to insert the patient's sex and age at the time of the study."}</p>
+
+                <table class="mt-10 w-full text-left text-sm font-light">
+                    <thead class="border-b font-medium dark:border-neutral-500">
+                        <tr>
+                            <th scope="col" class="px-2 py-1 text-grey">{"Trigger"}</th>
+                            <th scope="col" class="px-2 py-1 text-grey">{"Phrase"}</th>
+                            <th scope="col" class="px-2 py-1 text-grey">{"Shared with"}</th>
+                            <th scope="col" class="px-2 py-1"></th>
+                        </tr>
+                    </thead>
+                    <tbody>
+                        {rows()}
+                    </tbody>
+                </table>
+
+                <form class="mt-10 space-y-3" {onsubmit}>
+                    <div class="flex gap-x-6">
+                        <div>
+                            <label for="trigger" class="block text-sm font-medium leading-6 text-white">{"Trigger"}</label>
+                            <input id="trigger" ref={&trigger_node_ref} placeholder=".nchest" class="mt-1 block rounded-sm border-0 py-1 text-gray-900 shadow-sm ring-1 ring-inset ring-gray-300 sm:text-sm sm:leading-6" />
+                        </div>
+                        <div>
+                            <label for="scope" class="block text-sm font-medium leading-6 text-white">{"Shared with"}</label>
+                            <select id="scope" ref={&scope_node_ref} class="mt-1 block rounded-sm border-0 py-1 text-gray-900 shadow-sm ring-1 ring-inset ring-gray-300 sm:text-sm sm:leading-6">
+                                <option value="user" selected={true}>{"Only me"}</option>
+                                if !auth_ctx.department.is_empty() {
+                                    <option value="department">{format!("{} department", auth_ctx.department)}</option>
+                                }
+                            </select>
+                        </div>
+                    </div>
+                    <div>
+                        <label for="phrase" class="block text-sm font-medium leading-6 text-white">{"Phrase"}</label>
+                        <textarea id="phrase" ref={&phrase_node_ref} rows="6" class="mt-1 block w-full bg-transparent text-white border-0 py-1.5 shadow-sm ring-1 ring-inset ring-gray-300 focus:ring-2 focus:ring-inset focus:ring-indigo-600 sm:text-sm sm:leading-6"></textarea>
+                    </div>
+                    if *is_error {
+                        <p class="text-red">{"A macro needs a trigger without spaces and a phrase."}</p>
+                    }
+                    if !status.is_empty() {
+                        <p class="text-red">{(*status).clone()}</p>
+                    }
+                    <div class="flex items-center justify-end gap-x-6">
+                        <button onclick={
+                            move |_: MouseEvent| {
+                                navigator.back();
+                            }
+                        } type="button" class="text-sm font-semibold leading-6 text-gray-500">{"Back"}</button>
+                        <button type="submit" class="bg-indigo-600 px-3 py-2 rounded-sm text-sm font-semibold text-white shadow-sm hover:bg-indigo-500">{"Save Macro"}</button>
+                    </div>
+                </form>
+            </div>
+        </div>
+    }
+}
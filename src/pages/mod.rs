@@ -1,3 +1,15 @@
+pub mod audit;
+pub mod critical;
+pub mod deidentify;
+pub mod key_images;
 pub mod login;
+pub mod macros;
+pub mod patient;
+pub mod priors;
 pub mod reporting;
+pub mod saved_searches;
 pub mod search;
+pub mod share;
+pub mod shared;
+pub mod turnaround;
+pub mod worklist;
@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+
+use dicom::object::InMemDicomObject;
+use gloo::net::http::Request;
+use pacsportal_core::model::Study;
+use yew::prelude::*;
+use yew_router::prelude::use_navigator;
+
+use crate::api;
+use crate::dicomweb;
+use crate::pages::deidentify::Deidentify;
+use crate::pages::key_images::KeyImages;
+use crate::pages::share::ShareLinks;
+use crate::{AuthorizedContext, Route};
+
+#[derive(Properties, PartialEq)]
+pub struct PatientProps {
+    pub patient_id: String,
+}
+
+#[function_component(Patient)]
+pub fn patient(props: &PatientProps) -> Html {
+    let studies = use_state(Vec::<Study>::new);
+    let reports = use_state(HashMap::<String, Vec<String>>::new);
+    let loaded_status = use_state(|| String::from("Loading..."));
+    let auth_ctx = use_context::<AuthorizedContext>().unwrap();
+    let navigator = use_navigator().unwrap();
+
+    use_effect_with_deps(
+        {
+            let studies = studies.clone();
+            let reports = reports.clone();
+            let loaded_status = loaded_status.clone();
+            move |patient_id: &String| {
+                let patient_id = patient_id.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    let include_fields = "?includefield=StudyDescription&includefield=PatientBirthDate&includefield=PatientSex&includefield=NumberOfStudyRelatedSeries&includefield=NumberOfStudyRelatedInstances";
+                    let fetched_details = Request::get(&format!("{}/studies{}", dicomweb::RS_BASE, include_fields))
+                        .query([("PatientID", patient_id.as_str())])
+                        .send()
+                        .await;
+                    match fetched_details {
+                        Ok(res) => {
+                            if res.status() != 200 {
+                                if res.status() == 204 {
+                                    loaded_status.set(format!("There are no studies for patient {}.", patient_id));
+                                } else {
+                                    loaded_status.set(format!("The server sent back an error: {}. Please report this to your system administrator.", res.status()));
+                                }
+                            } else {
+                                match res.json::<Vec<serde_json::Value>>().await {
+                                    Ok(data) => {
+                                        let mut fetched_data: Vec<Study> = data
+                                            .into_iter()
+                                            .filter_map(|study| dicom_json::from_value::<InMemDicomObject>(study).ok())
+                                            .map(|study| Study::from_dicom(&study))
+                                            .collect();
+                                        // most recent first
+                                        fetched_data.sort_by_key(|study| std::cmp::Reverse((study.date, study.time)));
+                                        studies.set(fetched_data.clone());
+                                        loaded_status.set(String::from(""));
+
+                                        let mut fetched_reports = HashMap::new();
+                                        for study in fetched_data.iter() {
+                                            let uid = study.study_uid.clone().unwrap_or_default();
+                                            let study_reports = dicomweb::fetch_reports(&uid).await;
+                                            fetched_reports.insert(uid, study_reports);
+                                        }
+                                        reports.set(fetched_reports);
+                                    }
+                                    Err(_) => loaded_status.set(String::from("Unable to parse data from server. Please report this to your system administrator.")),
+                                }
+                            }
+                        }
+                        Err(_) => {
+                            loaded_status.set(String::from("Unable to reach the server. Please try again later or contact your system administrator."));
+                        }
+                    };
+                });
+            }
+        },
+        props.patient_id.clone(),
+    );
+
+    let demographics = {
+        let studies = studies.clone();
+        let patient_id = props.patient_id.clone();
+        move || -> Html {
+            // demographics are repeated on every study; take the latest
+            let (name, birth_date, sex) = match studies.first() {
+                Some(study) => (
+                    study.patient.name.clone().unwrap_or_default(),
+                    study.patient.birth_date.map(|date| date.format("%Y-%m-%d").to_string()).unwrap_or_default(),
+                    study.patient.sex.clone().unwrap_or_default(),
+                ),
+                None => (String::new(), String::new(), String::new()),
+            };
+            html! {
+                <div>
+                    <h1 class="text-white text-2xl font-semibold">{name}</h1>
+                    <p class="mt-1 text-sm text-grey">
+                        {"ID "}<span class="text-white">{patient_id}</span>
+                        {" · Born "}<span class="text-white">{birth_date}</span>
+                        {" · Sex "}<span class="text-white">{sex}</span>
+                        {" · Studies "}<span class="text-white">{studies.len()}</span>
+                    </p>
+                </div>
+            }
+        }
+    };
+
+    let timeline = {
+        let studies = studies.clone();
+        let reports = reports.clone();
+        let navigator = navigator.clone();
+        move || -> Html {
+            html! {
+                <ol class="relative mt-6 border-l border-white/20">
+                    {
+                        studies.iter().map(|study| {
+                            let uid = study.study_uid.clone().unwrap_or_default();
+                            let date = study.date_label();
+                            let time = study.time.map(|time| time.format("%H:%M").to_string()).unwrap_or_default();
+                            let modalities = study.modalities_label();
+                            let series_count = study.series_count.map(|count| count.to_string()).unwrap_or_default();
+                            let instance_count = study.instance_count.map(|count| count.to_string()).unwrap_or_default();
+                            let report_button = if auth_ctx.inner && !modalities.contains("SR") {
+                                let navigator = navigator.clone();
+                                let uid = uid.clone();
+                                html! {
+                                    <button onclick={move |_: MouseEvent| navigator.push(&Route::Reporting { uid: uid.clone() })} type="button" class="ml-4 inline-block px-2 py-1 bg-[#ffd400] shadow-lg text-xs font-medium">{"Report"}</button>
+                                }
+                            } else {
+                                html! {}
+                            };
+                            let on_open = {
+                                let patient_id = study.patient.id.clone().unwrap_or_default();
+                                let patient_name = study.patient.name.clone().unwrap_or_default();
+                                let uid = uid.clone();
+                                move |_: MouseEvent| api::audit("study-opened", Some(patient_id.clone()), Some(patient_name.clone()), Some(uid.clone()), String::from("viewer"))
+                            };
+                            html! {
+                                <li key={uid.clone()} class="mb-8 ml-4">
+                                    <div class="absolute w-3 h-3 bg-[#ffd400] rounded-full -left-1.5 mt-1.5"></div>
+                                    <time class="text-sm text-grey">{date}{" "}{time}</time>
+                                    <div class="flex items-center">
+                                        <a onclick={on_open} href={dicomweb::viewer_url(&uid)} target="_blank" rel="noopener noreferrer" class="text-lg font-semibold text-white">
+                                            {modalities.clone()}{" "}{study.description.clone().unwrap_or_default()}
+                                        </a>
+                                        {report_button}
+                                        if let Some(base) = api::API_BASE {
+                                            <a href={format!("{}/studies/{}/download", base, uid)} download="" title="ZIP with a DICOMDIR, for CD or USB" class="ml-4 inline-block px-2 py-1 border text-xs font-medium text-white hover:bg-yellow hover:text-black">{"Download"}</a>
+                                        }
+                                    </div>
+                                    <p class="text-xs text-grey">
+                                        {"Accession "}{study.accession.clone().unwrap_or_default()}
+                                        {" · "}{series_count}{" series, "}{instance_count}{" images"}
+                                    </p>
+                                    <Deidentify study_uid={uid.clone()} />
+                                    <ShareLinks study_uid={uid.clone()} />
+                                    {
+                                        match reports.get(&uid) {
+                                            Some(texts) if !texts.is_empty() => texts.iter().map(|text| html! {
+                                                <p class="mt-2 text-sm text-gray-300 whitespace-pre-wrap">{text.clone()}</p>
+                                            }).collect::<Html>(),
+                                            Some(_) => html! { <p class="mt-2 text-sm text-gray-500">{"Not reported."}</p> },
+                                            None => html! { <p class="mt-2 text-sm text-gray-500">{"Loading report..."}</p> },
+                                        }
+                                    }
+                                    <KeyImages study_uid={uid.clone()} />
+                                </li>
+                            }
+                        }).collect::<Html>()
+                    }
+                </ol>
+            }
+        }
+    };
+
+    html! {
+        <div class="min-h-screen bg-black px-6 md:px-12 py-6">
+            <div class="flex items-center justify-between border-b border-white/10 pb-6">
+                {demographics()}
+                <button onclick={
+                    move |_: MouseEvent| {
+                        navigator.back();
+                    }
+                } type="button" class="text-sm font-semibold leading-6 text-gray-500">{"Back"}</button>
+            </div>
+            if !loaded_status.is_empty() {
+                <p class="mt-6 text-white">{(*loaded_status).clone()}</p>
+            } else {
+                {timeline()}
+            }
+        </div>
+    }
+}
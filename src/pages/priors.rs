@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+
+use dicom::dictionary_std::tags;
+use dicom::object::InMemDicomObject;
+use gloo::net::http::Request;
+use yew::prelude::*;
+
+use crate::api;
+use crate::dicomweb;
+
+#[derive(Properties, PartialEq)]
+pub struct PriorStudiesProps {
+    pub patient_id: String,
+    pub study_uid: String, // the study being reported, left out of the list
+    pub on_cite: Callback<String>,
+}
+
+#[function_component(PriorStudies)]
+pub fn prior_studies(props: &PriorStudiesProps) -> Html {
+    let priors = use_state(Vec::<InMemDicomObject>::new);
+    let reports = use_state(HashMap::<String, Vec<String>>::new);
+    let loaded_status = use_state(|| String::from("Loading prior studies..."));
+
+    use_effect_with_deps(
+        {
+            let priors = priors.clone();
+            let reports = reports.clone();
+            let loaded_status = loaded_status.clone();
+            move |(patient_id, study_uid): &(String, String)| {
+                let patient_id = patient_id.clone();
+                let study_uid = study_uid.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    let fetched = Request::get(&format!("{}/studies?includefield=StudyDescription", dicomweb::RS_BASE))
+                        .query([("PatientID", patient_id.as_str())])
+                        .send()
+                        .await;
+                    let mut fetched_priors: Vec<InMemDicomObject> = match fetched {
+                        Ok(res) if res.status() == 200 => match res.json::<Vec<serde_json::Value>>().await {
+                            Ok(data) => data
+                                .into_iter()
+                                .filter_map(|study| dicom_json::from_value(study).ok())
+                                .collect(),
+                            Err(_) => {
+                                loaded_status.set(String::from("Unable to parse prior studies from server."));
+                                return;
+                            }
+                        },
+                        Ok(res) if res.status() == 204 => Vec::new(),
+                        Ok(res) => {
+                            loaded_status.set(format!("The server sent back an error while fetching prior studies: {}.", res.status()));
+                            return;
+                        }
+                        Err(_) => {
+                            loaded_status.set(String::from("Unable to reach the server for prior studies."));
+                            return;
+                        }
+                    };
+                    fetched_priors.retain(|study| {
+                        study
+                            .get(tags::STUDY_INSTANCE_UID)
+                            .and_then(|uid| uid.to_str().ok())
+                            .map(|uid| uid != study_uid.as_str())
+                            .unwrap_or(false)
+                    });
+                    // most recent first; DA values sort correctly as strings
+                    fetched_priors.sort_by_key(|study| {
+                        std::cmp::Reverse(
+                            study
+                                .get(tags::STUDY_DATE)
+                                .and_then(|date| date.to_str().ok())
+                                .map(|date| date.into_owned())
+                                .unwrap_or_default(),
+                        )
+                    });
+                    if fetched_priors.is_empty() {
+                        loaded_status.set(String::from("No prior studies for this patient."));
+                    } else {
+                        loaded_status.set(String::from(""));
+                    }
+                    priors.set(fetched_priors.clone());
+
+                    let mut fetched_reports = HashMap::new();
+                    for study in fetched_priors.iter() {
+                        if let Some(uid) = study.get(tags::STUDY_INSTANCE_UID).and_then(|uid| uid.to_str().ok()) {
+                            fetched_reports.insert(uid.to_string(), dicomweb::fetch_reports(&uid).await);
+                        }
+                    }
+                    reports.set(fetched_reports);
+                });
+            }
+        },
+        (props.patient_id.clone(), props.study_uid.clone()),
+    );
+
+    html! {
+        <div class="mt-10">
+            <h3 class="text-white text-sm font-semibold leading-6">{"Prior Studies"}</h3>
+            if !loaded_status.is_empty() {
+                <p class="mt-1 text-sm text-gray-500">{(*loaded_status).clone()}</p>
+            } else {
+                <ul class="mt-2 divide-y divide-white/10 max-h-96 overflow-y-auto">
+                    {
+                        priors.iter().map(|study| {
+                            let uid = study.get(tags::STUDY_INSTANCE_UID).and_then(|uid| uid.to_str().ok()).map(|uid| uid.into_owned()).unwrap_or_default();
+                            let date = study
+                                .get(tags::STUDY_DATE)
+                                .and_then(|date| date.to_date().ok())
+                                .and_then(|date| date.to_naive_date().ok())
+                                .map(|date| date.format("%Y-%m-%d").to_string())
+                                .unwrap_or_default();
+                            let modalities = study.get(tags::MODALITIES_IN_STUDY).and_then(|m| m.strings().ok()).map(|m| m.join(", ")).unwrap_or_default();
+                            let description = study.get(tags::STUDY_DESCRIPTION).and_then(|d| d.to_str().ok()).map(|d| d.into_owned()).unwrap_or_default();
+                            let citation = format!("Comparison is made with the previous {} {} dated {}.", modalities, description, date).replace("  ", " ");
+                            let on_cite = props.on_cite.clone();
+                            let on_open = {
+                                let patient_id = props.patient_id.clone();
+                                let uid = uid.clone();
+                                move |_: MouseEvent| api::audit("study-opened", Some(patient_id.clone()), None, Some(uid.clone()), String::from("viewer"))
+                            };
+                            html! {
+                                <li key={uid.clone()} class="py-2">
+                                    <div class="flex items-center justify-between">
+                                        <p class="text-sm text-white">
+                                            <a onclick={on_open} href={dicomweb::viewer_url(&uid)} target="_blank" rel="noopener noreferrer" class="font-medium">{date.clone()}{" "}{modalities.clone()}</a>
+                                            <span class="ml-2 text-grey">{description.clone()}</span>
+                                        </p>
+                                        <button onclick={move |_: MouseEvent| on_cite.emit(citation.clone())} type="button" class="inline-block px-2 py-1 border text-xs font-medium text-white hover:bg-yellow hover:text-black">{"Cite"}</button>
+                                    </div>
+                                    {
+                                        match reports.get(&uid) {
+                                            Some(texts) if !texts.is_empty() => texts.iter().map(|text| html! {
+                                                <p class="mt-1 text-xs text-gray-400 whitespace-pre-wrap">{text.clone()}</p>
+                                            }).collect::<Html>(),
+                                            Some(_) => html! { <p class="mt-1 text-xs text-gray-500">{"Not reported."}</p> },
+                                            None => html! { <p class="mt-1 text-xs text-gray-500">{"Loading report..."}</p> },
+                                        }
+                                    }
+                                </li>
+                            }
+                        }).collect::<Html>()
+                    }
+                </ul>
+            }
+        </div>
+    }
+}
@@ -1,26 +1,88 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
 use chrono::Local;
-use dicom::{
-    core::{smallvec::smallvec, value::DataSetSequence, DataElement, DicomValue, Length, VR},
-    dictionary_std::{tags, uids},
-    object::InMemDicomObject,
-};
+use dicom::{dictionary_std::tags, object::InMemDicomObject};
 use gloo::net::http::Request;
-use uuid::Uuid;
-use web_sys::HtmlTextAreaElement;
+use gloo::timers::callback::Interval;
+use pacsportal_core::model::Study;
+use pacsportal_core::report::ReportBuilder;
+use web_sys::{HtmlInputElement, HtmlTextAreaElement};
 use yew::prelude::*;
 use yew_router::prelude::use_navigator;
 
+use crate::api;
+use crate::dicomweb;
+use crate::log;
+use crate::macros::{self, PatientContext};
+use crate::pages::key_images::{KeyImageSelector, KeyImages};
+use crate::pages::priors::PriorStudies;
+use crate::store::{self, Claim, Draft, Lock, Store, LOCK_TIMEOUT_MINUTES};
+use crate::{AuthorizedContext, Route};
+
 #[derive(Properties, PartialEq)]
 pub struct ReportProps {
     pub study_uid: String,
 }
 
+/// How often the lock of an open study is renewed after input on the form,
+/// well within [`LOCK_TIMEOUT_MINUTES`], and a study someone else holds is
+/// tried again.
+const LOCK_CHECK_MILLIS: u32 = 60_000;
+
+/// Releases a lock taken by the page.
+fn release(store: Store, study_uid: String) {
+    wasm_bindgen_futures::spawn_local(async move {
+        if let Err(e) = store.release(&study_uid).await {
+            log::warn("reporting", format!("could not release the study: {}", e));
+        }
+    });
+}
+
+/// Replaces the current selection in the report, or inserts at the caret.
+fn insert_at_caret(report_textarea: &HtmlTextAreaElement, text: &str) {
+    let end = report_textarea.value().encode_utf16().count() as u32;
+    let start = report_textarea.selection_start().ok().flatten().unwrap_or(end);
+    let stop = report_textarea.selection_end().ok().flatten().unwrap_or(start);
+    let _ = report_textarea.set_range_text_with_start_and_end(text, start, stop);
+    let caret = start + text.encode_utf16().count() as u32;
+    let _ = report_textarea.set_selection_range(caret, caret);
+    let _ = report_textarea.focus();
+}
+
 #[function_component(Reporting)]
 pub fn reporting(props: &ReportProps) -> Html {
     let retrieving_status = use_state(|| String::from("Loading..."));
-    let study_details = use_state(|| InMemDicomObject::new_empty());
+    let save_status = use_state(String::new);
+    let study_details = use_state(InMemDicomObject::new_empty);
     let report_node_ref = use_node_ref();
+    let critical_node_ref = use_node_ref();
+    let key_images_version = use_state(|| 0u32);
+    let draft = use_state(|| Option::<Draft>::None);
+    // someone else reporting the study, who keeps this user from saving
+    let held_by = use_state(|| Option::<Lock>::None);
+    // whether held_by is set, for the lock's interval to read
+    let blocked = use_mut_ref(|| false);
+    // input on the form since the lock was last renewed
+    let active = use_mut_ref(|| false);
     let navigator = use_navigator().unwrap();
+    let auth_ctx = use_context::<AuthorizedContext>().unwrap();
+    let store = use_memo(store::for_user, (*auth_ctx).clone());
+    let available_macros = use_state(Vec::<macros::Macro>::new);
+
+    use_effect_with_deps(
+        {
+            let available_macros = available_macros.clone();
+            let store = store.clone();
+            move |auth_ctx: &crate::Authorized| {
+                let auth_ctx = auth_ctx.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    available_macros.set(macros::available_for((*store).as_ref(), &auth_ctx.username, &auth_ctx.department).await);
+                });
+            }
+        },
+        (*auth_ctx).clone(),
+    );
 
     use_effect_with_deps(
         {
@@ -31,8 +93,8 @@ pub fn reporting(props: &ReportProps) -> Html {
                 wasm_bindgen_futures::spawn_local(async move {
                     let include_fields = "&includefield=StudyID&includefield=PatientBirthDate&includefield=PatientSex&includefield=Manufacturer";
                     let fetched_details = Request::get(&format!(
-                    "http://210.56.0.36:8080/dcm4chee-arc/aets/SCHPACS2/rs/studies?StudyInstanceUID={}{}",
-                    study_uid, include_fields
+                    "{}/studies?StudyInstanceUID={}{}",
+                    dicomweb::RS_BASE, study_uid, include_fields
                 ))
                 .send()
                 .await;
@@ -40,7 +102,7 @@ pub fn reporting(props: &ReportProps) -> Html {
                         Ok(res) => {
                             if res.status() != 200 {
                                 if res.status() == 204 {
-                                    retrieving_status.set(format!("There are no search results for these search parameters. Please change your parameters and try again."));
+                                    retrieving_status.set(String::from("There are no search results for these search parameters. Please change your parameters and try again."));
                                 } else {
                                     retrieving_status.set(format!("The server sent back an error: {}. Please report this to your system administrator.", res.status()));
                                 }
@@ -48,12 +110,18 @@ pub fn reporting(props: &ReportProps) -> Html {
                                 let res_json = res.json::<Vec<serde_json::Value>>().await;
                                 match res_json {
                                 Ok(data) => {
-                                    let fetched_data: Vec<InMemDicomObject> = data.iter().map(|series| dicom_json::from_value(series.clone()).unwrap()).collect();
-                                    study_details.set(fetched_data[0].clone()); // because we QIDO'd a single StudyInstanceUID, we will get only one result
-                                    // is_loaded.set(true);
-                                    retrieving_status.set(format!(""));
+                                    // because we QIDO'd a single StudyInstanceUID, we will get only one result
+                                    match data.first().and_then(|study| dicom_json::from_value::<InMemDicomObject>(study.clone()).ok()) {
+                                        Some(fetched_data) => {
+                                            let text = |tag| fetched_data.get(tag).and_then(|e| e.to_str().ok()).map(|v| v.trim().to_owned());
+                                            api::audit("study-opened", text(tags::PATIENT_ID), text(tags::PATIENT_NAME), Some(study_uid.clone()), String::from("reporting"));
+                                            study_details.set(fetched_data);
+                                            retrieving_status.set(String::new());
+                                        }
+                                        None => retrieving_status.set(String::from("Unable to parse data from server. Please report this to your system administrator.")),
+                                    }
                                 },
-                                Err(_) => retrieving_status.set(format!("Unable to parse data from server. Please report this to your system administrator.")),
+                                Err(_) => retrieving_status.set(String::from("Unable to parse data from server. Please report this to your system administrator.")),
                             }
                             }
                         }
@@ -67,11 +135,152 @@ pub fn reporting(props: &ReportProps) -> Html {
         (),
     );
 
-    let onclick = {
+    use_effect_with_deps(
+        {
+            let draft = draft.clone();
+            let store = store.clone();
+            move |study_uid: &String| {
+                let study_uid = study_uid.clone();
+                if let Some(store) = (*store).clone() {
+                    wasm_bindgen_futures::spawn_local(async move {
+                        match store.draft(&study_uid).await {
+                            Ok(found) => draft.set(found),
+                            Err(e) => log::warn("reporting", format!("could not load the draft: {}", e)),
+                        }
+                    });
+                }
+            }
+        },
+        props.study_uid.clone(),
+    );
+
+    // the study is locked while it is open here, and renewed while there is
+    // input on the form, so that it lapses on a page left unattended; a study
+    // someone else holds is tried again until they release it or it lapses.
+    // On leaving, only a lock taken here is released, not one claimed
+    // beforehand, e.g. from the worklist
+    use_effect_with_deps(
+        {
+            let held_by = held_by.clone();
+            let blocked = blocked.clone();
+            let active = active.clone();
+            let store = store.clone();
+            move |study_uid: &String| {
+                let claimed = Rc::new(Cell::new(false));
+                let left = Rc::new(Cell::new(false));
+                let mut keep_locked = None;
+                if let Some(store) = (*store).clone() {
+                    let claim = {
+                        let claimed = claimed.clone();
+                        let left = left.clone();
+                        let blocked = blocked.clone();
+                        let study_uid = study_uid.clone();
+                        move || {
+                            let claimed = claimed.clone();
+                            let left = left.clone();
+                            let blocked = blocked.clone();
+                            let held_by = held_by.clone();
+                            let study_uid = study_uid.clone();
+                            let store = store.clone();
+                            wasm_bindgen_futures::spawn_local(async move {
+                                match store.claim(&study_uid).await {
+                                    // the page was left before the claim came back
+                                    Ok(Claim::Claimed) if left.get() => release(store, study_uid),
+                                    Ok(claim @ (Claim::Claimed | Claim::Kept)) => {
+                                        if claim == Claim::Claimed {
+                                            claimed.set(true);
+                                        }
+                                        if blocked.replace(false) {
+                                            held_by.set(None);
+                                        }
+                                    }
+                                    Ok(Claim::HeldBy(lock)) => {
+                                        *blocked.borrow_mut() = true;
+                                        held_by.set(Some(lock));
+                                    }
+                                    Err(e) => log::warn("reporting", format!("could not lock the study: {}", e)),
+                                }
+                            });
+                        }
+                    };
+                    claim();
+                    keep_locked = Some(Interval::new(LOCK_CHECK_MILLIS, move || {
+                        if active.replace(false) || *blocked.borrow() {
+                            claim();
+                        }
+                    }));
+                }
+                let store = (*store).clone();
+                let study_uid = study_uid.clone();
+                move || {
+                    left.set(true);
+                    drop(keep_locked);
+                    if let (Some(store), true) = (store, claimed.get()) {
+                        release(store, study_uid);
+                    }
+                }
+            }
+        },
+        props.study_uid.clone(),
+    );
+
+    // the form only exists once the study has loaded
+    use_effect_with_deps(
+        {
+            let report_node_ref = report_node_ref.clone();
+            let critical_node_ref = critical_node_ref.clone();
+            move |(draft, form_shown): &(Option<Draft>, bool)| {
+                if let (Some(draft), true) = (draft, form_shown) {
+                    if let Some(report_textarea) = report_node_ref.cast::<HtmlTextAreaElement>() {
+                        if report_textarea.value().is_empty() {
+                            report_textarea.set_value(&draft.text);
+                        }
+                    }
+                    if let Some(checkbox) = critical_node_ref.cast::<HtmlInputElement>() {
+                        checkbox.set_checked(checkbox.checked() || draft.critical);
+                    }
+                }
+            }
+        },
+        ((*draft).clone(), retrieving_status.is_empty()),
+    );
+
+    // drafts are saved whenever the report or the critical flag loses focus
+    let save_draft = {
+        let report_node_ref = report_node_ref.clone();
+        let critical_node_ref = critical_node_ref.clone();
+        let save_status = save_status.clone();
+        let store = store.clone();
         let study_uid = props.study_uid.clone();
+        Callback::from(move |_: Event| {
+            let Some(store) = (*store).clone() else {
+                return;
+            };
+            let text = report_node_ref.cast::<HtmlTextAreaElement>().map(|textarea| textarea.value()).unwrap_or_default();
+            let critical = critical_node_ref.cast::<HtmlInputElement>().map(|checkbox| checkbox.checked()).unwrap_or(false);
+            let save_status = save_status.clone();
+            let study_uid = study_uid.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match store.save_draft(&study_uid, &text, critical).await {
+                    Ok(()) => save_status.set(String::new()),
+                    Err(e) => {
+                        log::warn("reporting", format!("could not save the draft: {}", e));
+                        save_status.set(String::from("The draft could not be saved."));
+                    }
+                }
+            });
+        })
+    };
+
+    let onclick = {
         let study_details = study_details.clone();
         let report_node_ref = report_node_ref.clone();
+        let critical_node_ref = critical_node_ref.clone();
         let navigator = navigator.clone();
+        let save_status = save_status.clone();
+        let held_by = held_by.clone();
+        let store = store.clone();
+        let study_uid = props.study_uid.clone();
         Callback::from(move |event: MouseEvent| {
             event.prevent_default();
             let mut report = String::from("");
@@ -111,182 +320,233 @@ pub fn reporting(props: &ReportProps) -> Html {
             //     DataElement::new(tags::TEXT_VALUE, VR::UT, report.clone()),
             // ]);
 
-            let mut sr = InMemDicomObject::from_element_iter([
-                DataElement::new(tags::SOP_CLASS_UID, VR::UI, uids::BASIC_TEXT_SR_STORAGE),
-                DataElement::new(
-                    tags::SOP_INSTANCE_UID,
-                    VR::UI,
-                    format!("2.25.{}", Uuid::new_v4()),
-                ),
-                study_details.get(tags::STUDY_DATE).unwrap().to_owned(),
-                study_details.get(tags::STUDY_TIME).unwrap().to_owned(),
-                // TODO: Need to modify this form so that the report can be back dated
-                DataElement::new(
-                    tags::CONTENT_DATE,
-                    VR::DA,
-                    Local::now().date_naive().format("%Y%m%d").to_string(),
-                ),
-                DataElement::new(
-                    tags::CONTENT_TIME,
-                    VR::TM,
-                    Local::now().naive_local().format("%H%M%S").to_string(),
-                ),
-                study_details
-                    .get(tags::ACCESSION_NUMBER)
-                    .unwrap()
-                    .to_owned(),
-                DataElement::new(tags::MODALITY, VR::CS, "SR"),
-                match study_details.get(tags::MANUFACTURER) {
-                    Some(_) => study_details.get(tags::MANUFACTURER).unwrap().to_owned(),
-                    None => DataElement::empty(tags::MANUFACTURER, VR::LO),
-                },
-                study_details
-                    .get(tags::REFERRING_PHYSICIAN_NAME)
-                    .unwrap()
-                    .to_owned(), // handle unwrap() errors with if let
-                study_details.get(tags::PATIENT_NAME).unwrap().to_owned(),
-                study_details.get(tags::PATIENT_ID).unwrap().to_owned(),
-                match study_details.get(tags::PATIENT_BIRTH_DATE) {
-                    Some(_) => study_details.get(tags::PATIENT_BIRTH_DATE).unwrap().to_owned(),
-                    None => DataElement::empty(tags::PATIENT_BIRTH_DATE, VR::DA),
-                },
-                study_details
-                    .get(tags::PATIENT_BIRTH_DATE)
-                    .unwrap()
-                    .to_owned(), // handle unwrap() errors with if let
-                study_details.get(tags::PATIENT_SEX).unwrap().to_owned(),
-                DataElement::new(
-                    tags::STUDY_INSTANCE_UID,
-                    VR::UI,
-                    study_uid.clone(), // "1.2.392.200036.9116.6.18.10562196.1467.20230724090543953.1.74",
-                ),
-                DataElement::new(
-                    tags::SERIES_INSTANCE_UID,
-                    VR::UI,
-                    format!("2.25.{}", Uuid::new_v4()),
-                ), // .to_string()?
-                study_details.get(tags::STUDY_ID).unwrap().to_owned(),
-                DataElement::new(tags::SERIES_NUMBER, VR::IS, "1"),
-                DataElement::new(tags::INSTANCE_NUMBER, VR::IS, "1"),
-                DataElement::new(
-                    tags::VERIFYING_OBSERVER_SEQUENCE,
-                    VR::SQ,
-                    DicomValue::Sequence(DataSetSequence::new(
-                        smallvec![InMemDicomObject::from_element_iter([
-                            DataElement::new(
-                                tags::VERIFYING_ORGANIZATION,
-                                VR::LO,
-                                "South City Hospital"
-                            ),
-                            DataElement::new(
-                                tags::VERIFICATION_DATE_TIME,
-                                VR::DT,
-                                Local::now()
-                                    .naive_local()
-                                    .format("%Y%m%d%H%M%S")
-                                    .to_string()
-                            ),
-                            DataElement::new(
-                                tags::VERIFYING_OBSERVER_NAME,
-                                VR::PN,
-                                "DR WASAY JILANI"
-                            ),
-                            DataElement::new(
-                                tags::VERIFYING_OBSERVER_IDENTIFICATION_CODE_SEQUENCE,
-                                VR::SQ,
-                                DicomValue::Sequence(DataSetSequence::empty())
-                            )
-                        ])],
-                        Length::UNDEFINED,
-                    )),
-                ),
-                DataElement::new(tags::COMPLETION_FLAG, VR::CS, "COMPLETE"),
-                DataElement::new(tags::VERIFICATION_FLAG, VR::CS, "VERIFIED"),
-                DataElement::new(tags::VALUE_TYPE, VR::CS, "TEXT"),
-                DataElement::new(tags::TEXT_VALUE, VR::UT, report.clone()),
-            ]);
-
-            let report_text = InMemDicomObject::from_element_iter([
-                DataElement::new(tags::RELATIONSHIP_TYPE, VR::CS, "CONTAINS"),
-                DataElement::new(tags::VALUE_TYPE, VR::CS, "TEXT"),
-                DataElement::new(tags::TEXT_VALUE, VR::UT, report.clone()),
-                // DataElement::new(tags::CONCEPT_NAME_CODE_SEQUENCE, VR::SQ, "SOMETHING_ELSE"), // TODO
-                DataElement::new(tags::CONTINUITY_OF_CONTENT, VR::CS, "SEPARATE"),
-            ]);
-
-            let contents = DataElement::new(
-                tags::CONTENT_SEQUENCE,
-                VR::SQ,
-                DicomValue::Sequence(DataSetSequence::new(
-                    smallvec![report_text,],
-                    Length::UNDEFINED,
-                )),
-            );
-
-            sr.put(contents);
-            let rereport = sr.element(tags::SOP_INSTANCE_UID).unwrap().to_str().unwrap();
-            gloo::console::log!(wasm_bindgen::JsValue::from(rereport.into_owned()));
-
-            let mut request_body = String::from("\r\n--myboundary");
-            request_body.push_str("\r\nContent-Type: application/dicom+json\r\n\r\n");
-            request_body.push_str("[");
-            request_body.push_str(&dicom_json::to_string(sr).unwrap());
-            request_body.push_str("]");
-            request_body.push_str("\r\n--myboundary--");
+            // the flag is the document title so that the dashboard can QIDO for it
+            let is_critical = critical_node_ref
+                .cast::<HtmlInputElement>()
+                .map(|checkbox| checkbox.checked())
+                .unwrap_or(false);
+            // TODO: Need to modify this form so that the report can be back dated
+            let sr = ReportBuilder::new(&study_details, report.clone(), &auth_ctx.name)
+                .critical(is_critical)
+                .build();
+            log::debug("reporting", format!("storing report {}", log::redact::summary(&sr)));
 
-            wasm_bindgen_futures::spawn_local(async move {
-                let result = Request::post(
-                    "http://210.56.0.36:8080/dcm4chee-arc/aets/SCHPACS2/rs/studies",
-                )
-                .header(
-                    "Content-Type",
-                    "multipart/related; type=\"application/dicom+json\"; boundary=myboundary",
-                )
-                .body(request_body)
-                .unwrap()
-                .send()
-                .await
-                .unwrap();
+            let request_body = dicomweb::stow_body(sr);
 
-                gloo::console::log!(result.ok());
+            let navigator = navigator.clone();
+            let save_status = save_status.clone();
+            let held_by = held_by.clone();
+            let blocked = blocked.clone();
+            let store = (*store).clone();
+            let study_uid = study_uid.clone();
+            save_status.set(String::from("Saving..."));
+            wasm_bindgen_futures::spawn_local(async move {
+                // the lock may have lapsed and been taken while this user was away
+                if let Some(store) = &store {
+                    if let Ok(Claim::HeldBy(lock)) = store.claim(&study_uid).await {
+                        save_status.set(String::new());
+                        *blocked.borrow_mut() = true;
+                        held_by.set(Some(lock));
+                        return;
+                    }
+                }
+                let result = match Request::post(&format!("{}/studies", dicomweb::RS_BASE))
+                    .header("Content-Type", dicomweb::STOW_CONTENT_TYPE)
+                    .body(request_body)
+                {
+                    Ok(request) => request.send().await.map_err(|e| e.to_string()),
+                    Err(e) => Err(e.to_string()),
+                };
+                match result {
+                    Ok(res) if res.ok() => {
+                        log::info("reporting", "report stored");
+                        if let Some(store) = store {
+                            if let Err(e) = store.delete_draft(&study_uid).await {
+                                log::warn("reporting", format!("could not delete the draft: {}", e));
+                            }
+                        }
+                        navigator.back();
+                    }
+                    Ok(res) => {
+                        log::error("reporting", format!("STOW failed with {}", res.status()));
+                        save_status.set(format!("The server sent back an error: {}. The report was not saved; please report this to your system administrator.", res.status()));
+                    }
+                    Err(e) => {
+                        log::error("reporting", format!("STOW failed: {}", e));
+                        save_status.set(String::from("Unable to reach the server. The report was not saved; please try again."));
+                    }
+                }
             });
-            navigator.back();
         })
     };
 
+    let patient_context = {
+        let study_details = study_details.clone();
+        move || -> PatientContext {
+            let sex = study_details
+                .get(tags::PATIENT_SEX)
+                .and_then(|sex| sex.to_str().ok())
+                .map(|sex| sex.into_owned());
+            let birth_date = study_details
+                .get(tags::PATIENT_BIRTH_DATE)
+                .and_then(|date| date.to_date().ok())
+                .and_then(|date| date.to_naive_date().ok());
+            let study_date = study_details
+                .get(tags::STUDY_DATE)
+                .and_then(|date| date.to_date().ok())
+                .and_then(|date| date.to_naive_date().ok());
+            PatientContext::new(sex.as_deref(), birth_date, study_date)
+        }
+    };
+
+    // expand a ".trigger" as soon as it is followed by a space or newline
+    let oninput = {
+        let report_node_ref = report_node_ref.clone();
+        let available_macros = available_macros.clone();
+        let patient_context = patient_context.clone();
+        Callback::from(move |_: InputEvent| {
+            if let Some(report_textarea) = report_node_ref.cast::<HtmlTextAreaElement>() {
+                let text = report_textarea.value();
+                let caret = match report_textarea.selection_start() {
+                    Ok(Some(caret)) => macros::char_index(&text, caret),
+                    _ => return,
+                };
+                if let Some((expanded, new_caret)) =
+                    macros::expand_at_caret(&text, caret, &available_macros, &patient_context())
+                {
+                    let new_caret = macros::utf16_offset(&expanded, new_caret);
+                    report_textarea.set_value(&expanded);
+                    let _ = report_textarea.set_selection_range(new_caret, new_caret);
+                }
+            }
+        })
+    };
+
+    let macro_palette = {
+        let report_node_ref = report_node_ref.clone();
+        let available_macros = available_macros.clone();
+        let patient_context = patient_context.clone();
+        let navigator = navigator.clone();
+        move || -> Html {
+            html! {
+                <div class="mt-2 flex flex-wrap items-center gap-2">
+                    {
+                        available_macros.iter().map(|m| {
+                            let onclick = {
+                                let report_node_ref = report_node_ref.clone();
+                                let phrase = macros::expand(&m.phrase, &patient_context());
+                                move |_: MouseEvent| {
+                                    if let Some(report_textarea) = report_node_ref.cast::<HtmlTextAreaElement>() {
+                                        insert_at_caret(&report_textarea, &phrase);
+                                    }
+                                }
+                            };
+                            html! {
+                                <button {onclick} type="button" title={m.phrase.clone()} class="inline-block px-2 py-1 border text-xs font-medium text-white hover:bg-yellow hover:text-black">{format!(".{}", m.trigger)}</button>
+                            }
+                        }).collect::<Html>()
+                    }
+                    <button onclick={
+                        move |_: MouseEvent| {
+                            navigator.push(&Route::Macros);
+                        }
+                    } type="button" class="text-xs font-medium text-gray-500 hover:text-white">{"Manage macros"}</button>
+                </div>
+            }
+        }
+    };
+
+    let on_cite = {
+        let report_node_ref = report_node_ref.clone();
+        Callback::from(move |citation: String| {
+            if let Some(report_textarea) = report_node_ref.cast::<HtmlTextAreaElement>() {
+                insert_at_caret(&report_textarea, &citation);
+            }
+        })
+    };
+
+    let on_key_images_saved = {
+        let key_images_version = key_images_version.clone();
+        Callback::from(move |_| key_images_version.set(*key_images_version + 1))
+    };
+
     let body = {
         let study_details = study_details.clone();
         let navigator = navigator.clone();
+        let save_status = save_status.clone();
+        let held_by = held_by.clone();
+        let study_uid = props.study_uid.clone();
+        let active = active.clone();
         move || -> Html {
-            let patient_name = study_details.get(tags::PATIENT_NAME).unwrap().to_str().unwrap().replace("^", " ").trim().to_owned();
-            let modalities = study_details.get(tags::MODALITIES_IN_STUDY).unwrap().strings().unwrap().join(", ");
-            let date = study_details.get(tags::STUDY_DATE).unwrap().to_date().unwrap().to_naive_date().unwrap().format("%Y-%m-%d").to_string();
-            let time = study_details.get(tags::STUDY_TIME).unwrap().to_time().unwrap().to_naive_time().unwrap().format("%H:%M:%S").to_string();
+            // typing, ticking and clicking on the form all keep the lock
+            let on_form_input = {
+                let active = active.clone();
+                move |_: InputEvent| *active.borrow_mut() = true
+            };
+            let on_form_click = {
+                let active = active.clone();
+                move |_: MouseEvent| *active.borrow_mut() = true
+            };
+            let summary = Study::from_dicom(&study_details);
+            let patient_id = summary.patient.id.clone().unwrap_or_default();
+            let patient_name = summary.patient.name.clone().unwrap_or_else(|| String::from("unknown patient"));
+            let modalities = summary.modalities_label();
+            let date = summary.date_label();
+            let time = summary.time_label();
             html! {
-                <form class="h-screen bg-black px-6 md:px-12 py-6">
+                <form oninput={on_form_input} onclick={on_form_click} class="h-screen bg-black px-6 md:px-12 py-6">
                     <div class="border-b border-white/10 pb-12">
                         <h1 class="text-white text-base font-semibold leading-7">{"Reporting"}</h1>
                         <p class="mt-1 text-sm leading-6 text-gray-500">{"Please make sure you are entering the report for the correct patient and type your report below."}</p>
+                        if let Some(lock) = &*held_by {
+                            <p class="mt-2 text-sm font-semibold text-red">
+                                {format!(
+                                    "{} has been reporting this study since {}. Saving is disabled until they release it or are inactive for {} minutes.",
+                                    lock.username,
+                                    lock.claimed_at.with_timezone(&Local).format("%H:%M"),
+                                    LOCK_TIMEOUT_MINUTES
+                                )}
+                            </p>
+                        }
 
                         <div class="mt-10">
                             <h3 class="text-white">{"Report for "}{modalities}{" of "}{patient_name}{" done on "}{date}{" at "}{time}</h3>
+                            if summary.is_malformed() {
+                                <p class="mt-1 text-sm text-red">{format!("This study is missing {}; the report will be stored with these left empty.", summary.missing.join(", "))}</p>
+                            }
+                            <KeyImages study_uid={study_uid.clone()} version={*key_images_version} />
                         </div>
 
                         <div class="mt-10">
                             <label for="about" class="block text-sm font-medium leading-6 text-white">{"Report"}</label>
                             <div class="mt-2">
-                                <textarea ref={report_node_ref} id="report" name="about" rows="15" class="block w-full bg-transparent text-white border-0 py-1.5 shadow-sm ring-1 ring-inset ring-gray-300 focus:ring-2 focus:ring-inset focus:ring-indigo-600 sm:text-sm sm:leading-6"></textarea>
+                                <textarea ref={report_node_ref} {oninput} onchange={save_draft.clone()} id="report" name="about" rows="15" class="block w-full bg-transparent text-white border-0 py-1.5 shadow-sm ring-1 ring-inset ring-gray-300 focus:ring-2 focus:ring-inset focus:ring-indigo-600 sm:text-sm sm:leading-6"></textarea>
                             </div>
+                            {macro_palette()}
                         </div>
+
+                        <div class="mt-6 flex items-center gap-x-3">
+                            <input ref={critical_node_ref} onchange={save_draft.clone()} id="critical" name="critical" type="checkbox" class="h-4 w-4 rounded border-gray-300 text-red focus:ring-red" />
+                            <label for="critical" class="text-sm font-medium leading-6 text-white">{"Critical finding: the referring physician must be informed and acknowledge this result"}</label>
+                        </div>
+
+                        <div class="mt-10">
+                            <h3 class="text-white text-sm font-semibold leading-6">{"Key Images"}</h3>
+                            <p class="mt-1 text-sm text-gray-500">{"Pick a series, select the representative images and save them for the referring physician."}</p>
+                            <KeyImageSelector study_details={(*study_details).clone()} on_saved={on_key_images_saved} />
+                        </div>
+
+                        <PriorStudies {patient_id} {study_uid} {on_cite} />
                     </div>
 
                     <div class="mt-6 flex items-center justify-end gap-x-6">
+                        <p class="text-sm text-red">{(*save_status).clone()}</p>
                         <button onclick={
                             move |_: MouseEvent| {
                                 navigator.back();
                             }
                         } type="button" class="text-sm font-semibold leading-6 text-gray-900">{"Cancel"}</button>
-                        <button {onclick} type="button" class="bg-indigo-600 px-3 py-2 rounded-sm text-sm font-semibold text-white shadow-sm hover:bg-indigo-500 focus-visible:outline focus-visible:outline-2 focus-visible:outline-offset-2 focus-visible:outline-indigo-600">{"Save"}</button>
+                        <button {onclick} disabled={held_by.is_some()} type="button" class="disabled:opacity-50 bg-indigo-600 px-3 py-2 rounded-sm text-sm font-semibold text-white shadow-sm hover:bg-indigo-500 focus-visible:outline focus-visible:outline-2 focus-visible:outline-offset-2 focus-visible:outline-indigo-600">{"Save"}</button>
                     </div>
                 </form>
             }
@@ -294,7 +554,7 @@ pub fn reporting(props: &ReportProps) -> Html {
     };
 
     html!(
-        if (*retrieving_status).clone() != String::from("") {
+        if !retrieving_status.is_empty() {
             <p>{(*retrieving_status).clone()}</p>
         } else {
             {body()}
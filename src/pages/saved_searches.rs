@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+
+use chrono::Local;
+use dicom::object::InMemDicomObject;
+use gloo::net::http::Request;
+use pacsportal_core::model::Study;
+use pacsportal_core::search::INCLUDE_FIELDS;
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+
+use crate::dicomweb;
+use crate::log;
+use crate::saved_searches::{self, SavedSearch, SavedSearches};
+use crate::store;
+use crate::AuthorizedContext;
+
+/// The number of studies a saved search shows right now; `None` when the
+/// archive could not be asked.
+async fn count(search: &SavedSearch) -> Option<usize> {
+    let now = Local::now().naive_local();
+    let res = Request::get(&format!("{}/studies?{}&{}", dicomweb::RS_BASE, search.query(now), INCLUDE_FIELDS))
+    .send()
+    .await
+    .ok()?;
+    match res.status() {
+        204 => Some(0),
+        200 => {
+            let data = res.json::<Vec<serde_json::Value>>().await.ok()?;
+            let studies: Vec<Study> = data
+                .into_iter()
+                .map(|study| Study::from_dicom(&dicom_json::from_value::<InMemDicomObject>(study).unwrap_or_else(|_| InMemDicomObject::new_empty())))
+                .collect();
+            Some(search.count(&studies, now))
+        }
+        _ => None,
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct SavedSearchListProps {
+    /// The search as it is on the page, without a name.
+    pub current: SavedSearch,
+    pub on_apply: Callback<SavedSearch>,
+}
+
+/// The sidebar of the search page: the user's saved searches with how many
+/// studies each shows, and the one applied when they log in.
+#[function_component(SavedSearchList)]
+pub fn saved_search_list(props: &SavedSearchListProps) -> Html {
+    let saved = use_state(|| Option::<SavedSearches>::None);
+    let counts = use_state(HashMap::<String, usize>::new);
+    let name = use_state(String::new);
+    let status = use_state(|| String::from(""));
+    let auth_ctx = use_context::<AuthorizedContext>().unwrap();
+    let store = use_memo(store::for_user, (*auth_ctx).clone());
+
+    use_effect_with_deps(
+        {
+            let saved = saved.clone();
+            let status = status.clone();
+            let store = store.clone();
+            let on_apply = props.on_apply.clone();
+            move |username: &String| {
+                let username = username.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    match saved_searches::load((*store).as_ref(), &username).await {
+                        Ok(loaded) => {
+                            if let Some(default) = loaded.default_search() {
+                                on_apply.emit(default.clone());
+                            }
+                            saved.set(Some(loaded));
+                        }
+                        Err(e) => {
+                            log::warn("search", format!("could not load the saved searches: {}", e));
+                            status.set(String::from("Unable to load your saved searches."));
+                        }
+                    }
+                });
+            }
+        },
+        auth_ctx.username.clone(),
+    );
+
+    use_effect_with_deps(
+        {
+            let counts = counts.clone();
+            move |saved: &Option<SavedSearches>| {
+                if let Some(saved) = saved.clone() {
+                    wasm_bindgen_futures::spawn_local(async move {
+                        let mut found = HashMap::new();
+                        for search in &saved.searches {
+                            if let Some(count) = count(search).await {
+                                found.insert(search.name.clone(), count);
+                            }
+                        }
+                        counts.set(found);
+                    });
+                }
+            }
+        },
+        (*saved).clone(),
+    );
+
+    let persist = {
+        let saved = saved.clone();
+        let status = status.clone();
+        let store = store.clone();
+        let username = auth_ctx.username.clone();
+        move |changed: SavedSearches| {
+            saved.set(Some(changed.clone()));
+            let status = status.clone();
+            let store = store.clone();
+            let username = username.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match saved_searches::save((*store).as_ref(), &username, &changed).await {
+                    Ok(()) => status.set(String::from("")),
+                    Err(e) => {
+                        log::error("search", format!("could not save the saved searches: {}", e));
+                        status.set(String::from("Unable to save your searches. Please try again."));
+                    }
+                }
+            });
+        }
+    };
+
+    let Some(current_saved) = (*saved).clone() else {
+        return html! {
+            <aside class="w-56 shrink-0 p-2 border-r border-white/20 text-xs text-grey">{(*status).clone()}</aside>
+        };
+    };
+
+    let on_save = {
+        let name = name.clone();
+        let current = props.current.clone();
+        let current_saved = current_saved.clone();
+        let persist = persist.clone();
+        move |_: MouseEvent| {
+            if name.trim().is_empty() {
+                return;
+            }
+            let mut changed = current_saved.clone();
+            changed.save(SavedSearch {
+                name: (*name).clone(),
+                ..current.clone()
+            });
+            name.set(String::new());
+            persist(changed);
+        }
+    };
+
+    html! {
+        <aside class="w-56 shrink-0 overflow-y-auto p-2 border-r border-white/20 text-sm text-white">
+            <h2 class="text-xs text-grey uppercase">{"Saved searches"}</h2>
+            <ul class="mt-1">
+                {
+                    current_saved.searches.iter().map(|search| {
+                        let is_default = current_saved.default.as_deref() == Some(search.name.as_str());
+                        let on_apply = {
+                            let on_apply = props.on_apply.clone();
+                            let search = search.clone();
+                            move |_: MouseEvent| on_apply.emit(search.clone())
+                        };
+                        let on_default = {
+                            let mut changed = current_saved.clone();
+                            changed.set_default((!is_default).then_some(search.name.as_str()));
+                            let persist = persist.clone();
+                            move |_: MouseEvent| persist(changed.clone())
+                        };
+                        let on_remove = {
+                            let mut changed = current_saved.clone();
+                            changed.remove(&search.name);
+                            let persist = persist.clone();
+                            move |_: MouseEvent| persist(changed.clone())
+                        };
+                        html! {
+                            <li key={search.name.clone()} class="flex items-center gap-x-1 mt-1">
+                                <button onclick={on_apply} type="button" class="flex-1 text-left truncate hover:text-[#ffd400]">{search.name.clone()}</button>
+                                <span class="text-xs text-grey">{counts.get(&search.name).map(|count| count.to_string()).unwrap_or_default()}</span>
+                                <button onclick={on_default} type="button" title={if is_default { "Opened when you log in" } else { "Open when you log in" }} class={classes!("text-xs", if is_default { "text-[#ffd400]" } else { "text-grey" })}>{if is_default { "★" } else { "☆" }}</button>
+                                <button onclick={on_remove} type="button" title="Delete" class="text-xs text-grey hover:text-red">{"✕"}</button>
+                            </li>
+                        }
+                    }).collect::<Html>()
+                }
+            </ul>
+            <input type="text" value={(*name).clone()} oninput={
+                let name = name.clone();
+                move |e: InputEvent| {
+                    if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                        name.set(input.value());
+                    }
+                }
+            } maxlength="40" placeholder="Name, e.g. Neuro" class="mt-3 w-full bg-black border border-white/20 px-2 py-1 text-xs" />
+            <button onclick={on_save} type="button" class="mt-1 w-full px-2 py-1 border text-xs hover:bg-yellow hover:text-black">{"Save current search"}</button>
+            if !status.is_empty() {
+                <p class="mt-2 text-xs">{(*status).clone()}</p>
+            }
+        </aside>
+    }
+}
@@ -1,46 +1,168 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use chrono::{prelude::*, Days, Months};
+use chrono::{prelude::*, Days};
 use dicom::dictionary_std::tags;
 use dicom::object::InMemDicomObject;
 use gloo::net::http::Request;
+use gloo::storage::{LocalStorage, Storage};
+use gloo::timers::callback::Interval;
+use pacsportal_core::attributes::strings;
+use pacsportal_core::export::{self, TextFilters};
+use pacsportal_core::modality;
+use pacsportal_core::model::{set_priorities, Priority, Study};
+use pacsportal_core::period::{self, Period, Preset};
+use pacsportal_core::search::{self, study_query, Dates, SavedSearch, Sort, INCLUDE_FIELDS};
 use wasm_bindgen::JsCast;
-use web_sys::{HtmlButtonElement, HtmlInputElement};
+use web_sys::{HtmlButtonElement, HtmlInputElement, HtmlSelectElement, Notification, NotificationPermission};
 use yew::prelude::*;
-use yew_router::prelude::use_navigator;
+use yew_router::prelude::{use_navigator, Link};
 
+use crate::api;
+use crate::dicomweb;
+use crate::download;
+use crate::feed;
+use crate::log;
+use crate::pages::saved_searches::SavedSearchList;
+use crate::pages::worklist::priority_classes;
+use crate::store;
 use crate::{AuthorizedContext, Route};
 
+/// The modality buttons, e.g. `CT,MR,US,MG`, in the order given. Without
+/// it, the buttons are those of the studies of the last
+/// [`DISCOVERY_DAYS`] in the archive.
+const MODALITIES: Option<&str> = option_env!("PACSPORTAL_MODALITIES");
+const DISCOVERY_DAYS: u64 = 90;
+
+/// The date preset buttons, e.g. `today,shift=20-08,24h,3d,any`; see
+/// [`Preset::parse`]. Without it, [`period::DEFAULT_PRESETS`].
+const DATE_PRESETS: Option<&str> = option_env!("PACSPORTAL_DATE_PRESETS");
+
+fn date_presets() -> Vec<Preset> {
+    let configured = DATE_PRESETS.map(Preset::parse_list).unwrap_or_default();
+    if configured.is_empty() {
+        Preset::parse_list(period::DEFAULT_PRESETS)
+    } else {
+        configured
+    }
+}
+
+/// How often the search is repeated in the background to find new studies,
+/// when the live feed of the server is not connected.
+const REFRESH_MILLIS: u32 = 60_000;
+/// Whether the user wants a browser notification for each new STAT study.
+const NOTIFY_STAT_KEY: &str = "pacsportal.notify-stat";
+
+/// The choices of the priority filter; unknown priorities count as routine.
+const PRIORITY_FILTERS: [(Option<Priority>, &str); 4] = [
+    (None, "Any priority"),
+    (Some(Priority::Stat), "STAT only"),
+    (Some(Priority::High), "STAT and HIGH"),
+    (Some(Priority::Routine), "Hide LOW"),
+];
+
+/// Rows of urgent studies stand out; the hover and new-study highlights
+/// still show on top.
+fn priority_row_classes(priority: Option<Priority>) -> &'static str {
+    match priority {
+        Some(Priority::Stat) => "border-l-4 border-l-red bg-red/30",
+        Some(Priority::High) => "border-l-4 border-l-[#ffd400]",
+        _ => "",
+    }
+}
+
+/// A browser notification of a new STAT study. It is seen outside the
+/// portal, so it names the study but not the patient.
+fn notify(study: &Study) {
+    if Notification::permission() != NotificationPermission::Granted {
+        return;
+    }
+    let title = format!(
+        "New STAT study: {} {} {}",
+        study.modalities_label(),
+        study.description.clone().unwrap_or_default(),
+        study.accession.clone().unwrap_or_default()
+    );
+    if let Err(e) = Notification::new(title.trim()) {
+        log::warn("search", format!("could not notify: {:?}", e));
+    }
+}
+
 #[derive(Clone, PartialEq)]
 struct FetchFilters {
-    start_date: NaiveDate,
-    end_date: NaiveDate,
-    modalities: HashMap<String, bool>,
+    period: Period,
+    /// The button the period came from, if it did.
+    preset: Option<Preset>,
+    /// The modalities of the pressed buttons; none means any.
+    modalities: Vec<String>,
 }
 
 impl FetchFilters {
     fn new() -> Self {
         FetchFilters {
-            start_date: Local::now().date_naive(),
-            end_date: Local::now().date_naive(),
-            modalities: HashMap::from([
-                (String::from("CR"), false),
-                (String::from("DR"), false),
-                (String::from("CT"), false),
-                (String::from("PT"), false),
-                (String::from("MR"), false),
-                (String::from("US"), false),
-                (String::from("XA"), false),
-                (String::from("NM"), false),
-                (String::from("OT"), false),
-            ]),
+            period: Preset::Today.period(Local::now().naive_local()),
+            preset: Some(Preset::Today),
+            modalities: Vec::new(),
+        }
+    }
+}
+
+/// The modalities of recent studies in the archive, in the stable order of
+/// [`modality::ordered`].
+async fn discover_modalities() -> Option<Vec<String>> {
+    let today = Local::now().date_naive();
+    let since = today.checked_sub_days(Days::new(DISCOVERY_DAYS))?;
+    let studies = dicomweb::fetch_json(&format!(
+        "{}/studies?StudyDate={}-{}&includefield=ModalitiesInStudy",
+        dicomweb::RS_BASE,
+        since.format("%Y%m%d"),
+        today.format("%Y%m%d")
+    ))
+    .await?;
+    let found = modality::ordered(studies.iter().flat_map(|study| strings(study, tags::MODALITIES_IN_STUDY)));
+    (!found.is_empty()).then_some(found)
+}
+
+const NO_RESULTS: &str = "There are no search results for these search parameters. Please change your parameters and try again.";
+
+/// The studies of a search, narrowed to the times of `period`; the error is
+/// the message for the user.
+async fn fetch_studies(matching: &str, period: &Period) -> Result<Vec<Study>, String> {
+    let query = format!("{}&{}", matching, INCLUDE_FIELDS);
+    log::debug("search", format!("QIDO studies?{}", log::redact::query(&query)));
+    let res = Request::get(&format!("{}/studies?{}", dicomweb::RS_BASE, query))
+        .send()
+        .await
+        .map_err(|_| String::from("Unable to reach the server. Please try again later or contact your system administrator."))?;
+    match res.status() {
+        204 => Ok(Vec::new()),
+        200 => {
+            let data = res
+                .json::<Vec<serde_json::Value>>()
+                .await
+                .map_err(|_| String::from("Unable to parse data from server. Please report this to your system administrator."))?;
+            // a study that is not even valid DICOM JSON is kept as an empty, malformed row
+            let fetched: Vec<Study> = data
+                .iter()
+                .map(|study| {
+                    let object = dicom_json::from_value::<InMemDicomObject>(study.clone()).unwrap_or_else(|_| InMemDicomObject::new_empty());
+                    Study::from_dicom(&object)
+                })
+                // QIDO can only match times within a day
+                .filter(|study| period.contains(study))
+                .collect();
+            log::debug("search", format!("{} studies", fetched.len()));
+            Ok(fetched)
+        }
+        status => {
+            log::error("search", format!("QIDO failed with {}", status));
+            Err(format!("The server sent back an error: {}. Please report this to your system administrator.", status))
         }
     }
 }
 
 #[function_component(Search)]
 pub fn search() -> Html {
-    let studies = use_state(|| Vec::<InMemDicomObject>::new());
+    let studies = use_state(Vec::<Study>::new);
     let is_loaded = use_state(|| false);
     let loaded_status = use_state(|| String::from("Loading..."));
     let id_filter = use_state(|| String::from(""));
@@ -49,73 +171,213 @@ pub fn search() -> Html {
     let modality_filter = use_state(|| String::from(""));
     let description_filter = use_state(|| String::from(""));
     let source_ae_filter = use_state(|| String::from(""));
-    let fetch_filters = use_state(|| FetchFilters::new());
+    let fetch_filters = use_state(FetchFilters::new);
+    let available_modalities = use_state(|| match MODALITIES {
+        Some(configured) => modality::parse(configured),
+        None => modality::DEFAULT.iter().map(|modality| modality.to_string()).collect(),
+    });
+    let images_only = use_state(|| false);
+    let priority_filter = use_state(|| Option::<Priority>::None);
+    let date_error = use_state(|| Option::<String>::None);
+    let sort = use_state(Sort::default);
+    // a search the user started is under way, which a refresh must not race
+    let loading = use_state(|| true);
+    // bumped by every search the user starts, so that the results of an
+    // earlier search or refresh still under way are dropped
+    let generation = use_mut_ref(|| 0u32);
+    let new_studies = use_state(HashSet::<String>::new);
+    let auto_refresh = use_state(|| true);
+    let refresh_tick = use_state(|| 0u32);
+    let ticks = use_mut_ref(|| 0u32);
+    let live = use_state(|| false);
+    let notify_stat = use_state(|| LocalStorage::get::<bool>(NOTIFY_STAT_KEY).unwrap_or(false));
     let auth_ctx = use_context::<AuthorizedContext>().unwrap();
     let navigator = use_navigator().unwrap();
+    let store = use_memo(store::for_user, (*auth_ctx).clone());
+    // studies the user has read, when there is a store to ask
+    let read = use_state(|| Option::<HashSet<String>>::None);
+    // priorities set in the store, which win over those of the requests
+    let priorities = use_state(HashMap::<String, Priority>::new);
 
     let fetch_callback = {
         let studies = studies.clone();
         let loaded_status = loaded_status.clone();
         let is_loaded = is_loaded.clone();
+        let loading = loading.clone();
+        let new_studies = new_studies.clone();
         let fetch_filters = fetch_filters.clone();
+        let generation = generation.clone();
         move |_: &_| {
-            let start_date = fetch_filters.start_date.format("%Y%m%d");
-            let end_date = fetch_filters.end_date.format("%Y%m%d");
-            let mut modalities = String::from("");
-            fetch_filters
-                .modalities
-                .iter()
-                .for_each(|(modality, is_selected)| {
-                    if *is_selected {
-                        modalities = format!("{}&ModalitiesInStudy={}", modalities, modality);
-                    }
-                });
-            gloo::console::log!(wasm_bindgen::JsValue::from(modalities.clone()));
+            let period = fetch_filters.period;
+            let matching = study_query(&period, &fetch_filters.modalities);
+            *generation.borrow_mut() += 1;
+            let started = *generation.borrow();
             is_loaded.set(false);
+            loading.set(true);
+            new_studies.set(HashSet::new());
             loaded_status.set(String::from("Loading..."));
             wasm_bindgen_futures::spawn_local(async move {
-                let fetched_details = Request::get(&format!(
-                    "http://210.56.0.36:8080/dcm4chee-arc/aets/SCHPACS2/rs/studies?StudyDate={}-{}{}&includefield=StudyDescription&includefield=SourceApplicationEntityTitle",
-                    start_date, end_date, modalities,
-                ))
-                .send()
-                .await;
-                match fetched_details {
-                    Ok(res) => {
-                        if res.status() != 200 {
-                            if res.status() == 204 {
-                                loaded_status.set(format!("There are no search results for these search parameters. Please change your parameters and try again."));
-                            } else {
-                                loaded_status.set(format!("The server sent back an error: {}. Please report this to your system administrator.", res.status()));
-                            }
-                        } else {
-                            let res_json = res.json::<Vec<serde_json::Value>>().await;
-                            match res_json {
-                                Ok(data) => {
-                                    let fetched_data: Vec<InMemDicomObject> = data.iter().map(|series| dicom_json::from_value(series.clone()).unwrap()).collect();
-                                    studies.set(fetched_data.clone());
-                                    is_loaded.set(true);
-                                },
-                                Err(_) => loaded_status.set(format!("Unable to parse data from server. Please report this to your system administrator.")),
-                            }
-                        }
+                let fetched = fetch_studies(&matching, &period).await;
+                if *generation.borrow() != started {
+                    return;
+                }
+                match fetched {
+                    Ok(fetched_data) if fetched_data.is_empty() => {
+                        studies.set(Vec::new());
+                        loaded_status.set(String::from(NO_RESULTS));
                     }
-                    Err(_) => {
-                        loaded_status.set(String::from("Unable to reach the server. Please try again later or contact your system administrator."));
+                    Ok(fetched_data) => {
+                        fetched_data.iter().filter(|study| study.is_malformed()).for_each(|study| {
+                            log::warn("search", format!("malformed study, missing {}", study.missing.join(", ")));
+                        });
+                        studies.set(fetched_data);
+                        is_loaded.set(true);
                     }
-                };
-
-                studies.clone().iter().for_each(|study| {
-                    let patient_id = study.element(tags::PATIENT_NAME).unwrap().to_str().unwrap();
-                    let object = wasm_bindgen::JsValue::from(patient_id.into_owned());
-                    gloo::console::log!(object);
-                });
+                    Err(e) => loaded_status.set(e),
+                }
+                loading.set(false);
             });
         }
     };
 
     use_effect_with_deps(fetch_callback, [fetch_filters.clone()]);
 
+    // the live feed of the server tells of new studies; without it, or while
+    // it is interrupted, the page searches again every so often
+    let bump_refresh = {
+        let ticks = ticks.clone();
+        let refresh_tick = refresh_tick.clone();
+        Callback::from(move |()| {
+            *ticks.borrow_mut() += 1;
+            refresh_tick.set(*ticks.borrow());
+        })
+    };
+
+    use_effect_with_deps(
+        {
+            let bump_refresh = bump_refresh.clone();
+            let live = live.clone();
+            move |auto_refresh: &bool| {
+                let subscription = if *auto_refresh {
+                    let live = live.clone();
+                    feed::subscribe(bump_refresh, Callback::from(move |connected| live.set(connected)))
+                } else {
+                    None
+                };
+                move || {
+                    drop(subscription);
+                    live.set(false);
+                }
+            }
+        },
+        *auto_refresh,
+    );
+
+    use_effect_with_deps(
+        move |(auto_refresh, live): &(bool, bool)| {
+            let interval = (*auto_refresh && !*live).then(|| Interval::new(REFRESH_MILLIS, move || bump_refresh.emit(())));
+            move || drop(interval)
+        },
+        (*auto_refresh, *live),
+    );
+
+    use_effect_with_deps(
+        {
+            let studies = studies.clone();
+            let loaded_status = loaded_status.clone();
+            let is_loaded = is_loaded.clone();
+            let new_studies = new_studies.clone();
+            let fetch_filters = fetch_filters.clone();
+            let generation = generation.clone();
+            let notify_stat = *notify_stat;
+            let loading = *loading;
+            move |tick: &u32| {
+                if *tick == 0 || loading {
+                    return;
+                }
+                let period = fetch_filters.period;
+                let matching = study_query(&period, &fetch_filters.modalities);
+                let started = *generation.borrow();
+                wasm_bindgen_futures::spawn_local(async move {
+                    let fetched = match fetch_studies(&matching, &period).await {
+                        // the user searched for something else meanwhile
+                        _ if *generation.borrow() != started => return,
+                        Ok(fetched) => fetched,
+                        Err(e) => {
+                            log::warn("search", format!("refresh failed: {}", e));
+                            return;
+                        }
+                    };
+                    if fetched == *studies {
+                        return;
+                    }
+                    let arrived = search::arrived(&studies, &fetched);
+                    if !arrived.is_empty() {
+                        log::info("search", format!("{} new studies", arrived.len()));
+                        if notify_stat {
+                            arrived
+                                .iter()
+                                .filter(|study| study.priority == Some(Priority::Stat))
+                                .for_each(|study| notify(study));
+                        }
+                        let mut now_new = (*new_studies).clone();
+                        now_new.extend(arrived.iter().filter_map(|study| study.study_uid.clone()));
+                        new_studies.set(now_new);
+                    }
+                    if fetched.is_empty() {
+                        is_loaded.set(false);
+                        loaded_status.set(String::from(NO_RESULTS));
+                    } else {
+                        is_loaded.set(true);
+                    }
+                    studies.set(fetched);
+                });
+            }
+        },
+        *refresh_tick,
+    );
+
+    use_effect_with_deps(
+        {
+            let available_modalities = available_modalities.clone();
+            move |_| {
+                if MODALITIES.is_none() {
+                    wasm_bindgen_futures::spawn_local(async move {
+                        match discover_modalities().await {
+                            Some(found) => available_modalities.set(found),
+                            None => log::warn("search", "could not discover the modalities of the archive"),
+                        }
+                    });
+                }
+            }
+        },
+        (),
+    );
+
+    use_effect_with_deps(
+        {
+            let read = read.clone();
+            let priorities = priorities.clone();
+            let store = store.clone();
+            move |studies: &Vec<Study>| {
+                let study_uids: Vec<String> = studies.iter().filter_map(|study| study.study_uid.clone()).collect();
+                if let (Some(store), false) = ((*store).clone(), study_uids.is_empty()) {
+                    wasm_bindgen_futures::spawn_local(async move {
+                        match store.read_studies(&study_uids).await {
+                            Ok(found) => read.set(Some(found.into_iter().collect())),
+                            Err(e) => log::warn("search", format!("could not load the read status: {}", e)),
+                        }
+                        match store.priorities(&study_uids).await {
+                            Ok(found) => priorities.set(store::priorities(found)),
+                            Err(e) => log::warn("search", format!("could not load the priorities: {}", e)),
+                        }
+                    });
+                }
+            }
+        },
+        (*studies).clone(),
+    );
+
     // let entries_to_show = use_memo(
     //     |_| {
     //         (*studies)
@@ -206,6 +468,7 @@ pub fn search() -> Html {
         let modality_filter = modality_filter.clone();
         let description_filter = description_filter.clone();
         let source_ae_filter = source_ae_filter.clone();
+        let date_error = date_error.clone();
         Callback::from(move |_: Event| {
             let id = filter_node_refs[0]
                 .cast::<HtmlInputElement>();
@@ -240,37 +503,126 @@ pub fn search() -> Html {
             if let Some(source_ae) = source_ae {
                 source_ae_filter.set(source_ae.value());
             }
-            if let Some(start_date) = start_date {
-                let date =
-                    NaiveDate::parse_from_str(start_date.value().as_ref(), "%Y-%m-%d").unwrap();
-                let mut new_fetch_filters = (*fetch_filters).clone();
-                new_fetch_filters.start_date = date;
-                fetch_filters.set(new_fetch_filters);
-            }
-            if let Some(end_date) = end_date {
-                let date =
-                    NaiveDate::parse_from_str(end_date.value().as_ref(), "%Y-%m-%d").unwrap();
-                let mut new_fetch_filters = (*fetch_filters).clone();
-                new_fetch_filters.end_date = date;
-                fetch_filters.set(new_fetch_filters);
+            // a cleared or mistyped date, or a start after the end, is
+            // pointed out and leaves the search as it is
+            if let (Some(start_date), Some(end_date)) = (start_date, end_date) {
+                let (start_date, end_date) = (start_date.value(), end_date.value());
+                let typed = if start_date.trim().is_empty() || end_date.trim().is_empty() {
+                    Err(String::from("Please enter both a start and an end date."))
+                } else {
+                    match (period::parse_date(&start_date), period::parse_date(&end_date)) {
+                        (Ok(start), Ok(end)) if start > end => Err(String::from("The start date is after the end date.")),
+                        (Ok(start), Ok(end)) => Ok(Period::days(start, end)),
+                        (Err(e), _) | (_, Err(e)) => Err(e.to_string()),
+                    }
+                };
+                match typed {
+                    Ok(typed) => {
+                        date_error.set(None);
+                        let unchanged = typed.start_date() == fetch_filters.period.start_date() && typed.end_date() == fetch_filters.period.end_date();
+                        if !unchanged {
+                            fetch_filters.set(FetchFilters {
+                                period: typed,
+                                preset: None,
+                                ..(*fetch_filters).clone()
+                            });
+                        }
+                    }
+                    Err(e) => date_error.set(Some(e)),
+                }
             }
         })
     };
 
+    let sorted_studies = {
+        let mut sorted = (*studies).clone();
+        set_priorities(&mut sorted, &priorities);
+        sort.apply(&mut sorted);
+        sorted
+    };
+    let current_search = SavedSearch {
+        name: String::new(),
+        dates: Dates::of(&fetch_filters.period, fetch_filters.preset),
+        modalities: fetch_filters.modalities.clone(),
+        filters: TextFilters {
+            id: (*id_filter).clone(),
+            name: (*name_filter).clone(),
+            accession: (*accession_filter).clone(),
+            modality: (*modality_filter).clone(),
+            description: (*description_filter).clone(),
+            source_ae: (*source_ae_filter).clone(),
+            images_only: *images_only,
+            priority: *priority_filter,
+        },
+        sort: *sort,
+    };
+    let apply_search = {
+        let fetch_filters = fetch_filters.clone();
+        let id_filter = id_filter.clone();
+        let name_filter = name_filter.clone();
+        let accession_filter = accession_filter.clone();
+        let modality_filter = modality_filter.clone();
+        let description_filter = description_filter.clone();
+        let source_ae_filter = source_ae_filter.clone();
+        let images_only = images_only.clone();
+        let priority_filter = priority_filter.clone();
+        let sort = sort.clone();
+        let date_error = date_error.clone();
+        Callback::from(move |search: SavedSearch| {
+            fetch_filters.set(FetchFilters {
+                period: search.dates.period(Local::now().naive_local()),
+                preset: search.dates.preset(),
+                modalities: search.modalities,
+            });
+            id_filter.set(search.filters.id);
+            name_filter.set(search.filters.name);
+            accession_filter.set(search.filters.accession);
+            modality_filter.set(search.filters.modality);
+            description_filter.set(search.filters.description);
+            source_ae_filter.set(search.filters.source_ae);
+            images_only.set(search.filters.images_only);
+            priority_filter.set(search.filters.priority);
+            sort.set(search.sort);
+            date_error.set(None);
+        })
+    };
+    let on_priority_filter = {
+        let priority_filter = priority_filter.clone();
+        move |e: Event| {
+            if let Some(select) = e.target_dyn_into::<HtmlSelectElement>() {
+                priority_filter.set(Priority::parse(&select.value()));
+            }
+        }
+    };
+    let on_sort = {
+        let sort = sort.clone();
+        move |e: Event| {
+            if let Some(chosen) = e.target_dyn_into::<HtmlSelectElement>().and_then(|select| Sort::from_key(&select.value())) {
+                sort.set(chosen);
+            }
+        }
+    };
+
     let header = {
         let auth_ctx = auth_ctx.clone();
+        let id_filter = id_filter.clone();
+        let name_filter = name_filter.clone();
+        let accession_filter = accession_filter.clone();
+        let modality_filter = modality_filter.clone();
+        let description_filter = description_filter.clone();
+        let source_ae_filter = source_ae_filter.clone();
         let filter_callback = filter_callback.clone();
         let filter_node_refs = filter_node_refs.clone();
         move || -> Html {
             html! {
                 <thead class="border-b font-medium dark:border-neutral-500 bg-black w-full sticky top-0">
                     <tr>
-                        <th scope="col" class="px-2"><input type="text" class="peer block min-h-[auto] w-full border-0 border-b-2 bg-transparent outline-none focus:outline-none p-1 text-white" onchange={&filter_callback} ref={&filter_node_refs[0]} placeholder="Patient ID" /></th>
-                        <th scope="col" class="px-2"><input type="text" class="peer block min-h-[auto] w-full border-0 border-b-2 bg-transparent outline-none focus:outline-none p-1 text-white" onchange={&filter_callback} ref={&filter_node_refs[1]} placeholder="Name" /></th>
-                        <th scope="col" class="px-2"><input type="text" class="peer block min-h-[auto] w-full border-0 border-b-2 bg-transparent outline-none focus:outline-none p-1 text-white" onchange={&filter_callback} ref={&filter_node_refs[2]} placeholder="Accession" /></th>
-                        <th scope="col" class="px-2"><input type="text" class="peer block min-h-[auto] w-full border-0 border-b-2 bg-transparent outline-none focus:outline-none p-1 text-white" onchange={&filter_callback} ref={&filter_node_refs[3]} placeholder="Modality" /></th>
-                        <th scope="col" class="px-2"><input type="text" class="peer block min-h-[auto] w-full border-0 border-b-2 bg-transparent outline-none focus:outline-none p-1 text-white" onchange={&filter_callback} ref={&filter_node_refs[4]} placeholder="Description" /></th>
-                        <th scope="col" class="px-2"><input type="text" class="peer block min-h-[auto] w-full border-0 border-b-2 bg-transparent outline-none focus:outline-none p-1 text-white" onchange={&filter_callback} ref={&filter_node_refs[5]} placeholder="Source AE" /></th>
+                        <th scope="col" class="px-2"><input type="text" class="peer block min-h-[auto] w-full border-0 border-b-2 bg-transparent outline-none focus:outline-none p-1 text-white" value={(*id_filter).clone()} onchange={&filter_callback} ref={&filter_node_refs[0]} placeholder="Patient ID" /></th>
+                        <th scope="col" class="px-2"><input type="text" class="peer block min-h-[auto] w-full border-0 border-b-2 bg-transparent outline-none focus:outline-none p-1 text-white" value={(*name_filter).clone()} onchange={&filter_callback} ref={&filter_node_refs[1]} placeholder="Name" /></th>
+                        <th scope="col" class="px-2"><input type="text" class="peer block min-h-[auto] w-full border-0 border-b-2 bg-transparent outline-none focus:outline-none p-1 text-white" value={(*accession_filter).clone()} onchange={&filter_callback} ref={&filter_node_refs[2]} placeholder="Accession" /></th>
+                        <th scope="col" class="px-2"><input type="text" class="peer block min-h-[auto] w-full border-0 border-b-2 bg-transparent outline-none focus:outline-none p-1 text-white" value={(*modality_filter).clone()} onchange={&filter_callback} ref={&filter_node_refs[3]} placeholder="Modality" /></th>
+                        <th scope="col" class="px-2"><input type="text" class="peer block min-h-[auto] w-full border-0 border-b-2 bg-transparent outline-none focus:outline-none p-1 text-white" value={(*description_filter).clone()} onchange={&filter_callback} ref={&filter_node_refs[4]} placeholder="Description" /></th>
+                        <th scope="col" class="px-2"><input type="text" class="peer block min-h-[auto] w-full border-0 border-b-2 bg-transparent outline-none focus:outline-none p-1 text-white" value={(*source_ae_filter).clone()} onchange={&filter_callback} ref={&filter_node_refs[5]} placeholder="Source AE" /></th>
                         <th scope="col" class="px-2 text-grey">{"Date & Time"}</th>
                         {
                             if auth_ctx.inner {
@@ -310,66 +662,137 @@ pub fn search() -> Html {
         }
     };
     let body = {
+        let is_loaded = is_loaded.clone();
         let loaded_status = loaded_status.clone();
-        let studies = studies.clone();
+        let studies = sorted_studies.clone();
         let id_filter = id_filter.clone();
         let name_filter = name_filter.clone();
         let accession_filter = accession_filter.clone();
         let modality_filter = modality_filter.clone();
         let description_filter = description_filter.clone();
         let source_ae_filter = source_ae_filter.clone();
+        let images_only = images_only.clone();
+        let priority_filter = priority_filter.clone();
         let navigator = navigator.clone();
+        let auth_ctx = auth_ctx.clone();
+        let read = read.clone();
+        let store = store.clone();
+        let new_studies = new_studies.clone();
         move || -> Html {
+            let text_filters = TextFilters {
+                id: (*id_filter).clone(),
+                name: (*name_filter).clone(),
+                accession: (*accession_filter).clone(),
+                modality: (*modality_filter).clone(),
+                description: (*description_filter).clone(),
+                source_ae: (*source_ae_filter).clone(),
+                images_only: *images_only,
+                priority: *priority_filter,
+            };
             if *is_loaded {
                 html! {
                     <tbody class="h-full overflow-y-auto">
                         {
                             studies.iter().map(move |entry| {
-                                let id = entry.get(tags::PATIENT_ID).unwrap().to_str().unwrap();
-                                let name = entry.get(tags::PATIENT_NAME).unwrap().to_str().unwrap().replace("^", " ").trim().to_owned();
-                                let accession = entry.get(tags::ACCESSION_NUMBER).unwrap().to_str().unwrap();
-                                let modalities = entry.get(tags::MODALITIES_IN_STUDY).unwrap().strings().unwrap().join(", ");
-                                let description = if let Some(description) = entry.get(tags::STUDY_DESCRIPTION) {
-                                    description.to_str().unwrap()
-                                } else {"".into()};
-                                let source_ae = if let Some(source_ae) = entry.get(tags::SOURCE_APPLICATION_ENTITY_TITLE) {
-                                    source_ae.to_str().unwrap()
-                                } else {"".into()};
-                                let date = entry.get(tags::STUDY_DATE).unwrap().to_date().unwrap().to_naive_date().unwrap().format("%Y-%m-%d").to_string();
-                                let time = entry.get(tags::STUDY_TIME).unwrap().to_time().unwrap().to_naive_time().unwrap().format("%H:%M:%S").to_string();
-                                let study_uid = entry.get(tags::STUDY_INSTANCE_UID).unwrap().to_str().unwrap();
-                                let to_show = id.contains(id_filter.as_str()) && name.to_lowercase().contains(name_filter.as_str()) && accession.contains(accession_filter.as_str()) && modalities.contains(&modality_filter.as_str().to_uppercase()) && description.to_lowercase().contains(description_filter.as_str()) && source_ae.contains(source_ae_filter.as_str());
+                                let id = entry.patient.id.clone().unwrap_or_default();
+                                let name = entry.patient.name.clone().unwrap_or_default();
+                                let accession = entry.accession.clone().unwrap_or_default();
+                                let modalities = entry.modalities_label();
+                                let description = entry.description.clone().unwrap_or_default();
+                                let source_ae = entry.source_ae.clone().unwrap_or_default();
+                                let date = entry.date_label();
+                                let time = entry.time_label();
+                                let viewer_url = entry.study_uid.as_ref().map(|study_uid| dicomweb::viewer_url(study_uid));
+                                let to_show = text_filters.matches(entry);
                                 let navigator = navigator.clone();
+                                let is_new = entry.study_uid.as_ref().is_some_and(|study_uid| new_studies.contains(study_uid));
+                                let unread = match (&*read, &entry.study_uid) {
+                                    (Some(read), Some(study_uid)) => !read.contains(study_uid),
+                                    _ => false,
+                                };
+                                let on_open = {
+                                    let patient_id = entry.patient.id.clone();
+                                    let patient_name = entry.patient.name.clone();
+                                    let study_uid = entry.study_uid.clone();
+                                    let read = read.clone();
+                                    let store = (*store).clone();
+                                    // every cell but the name and the Report button links to the viewer
+                                    move |e: MouseEvent| {
+                                        let in_viewer_link = e
+                                            .target_dyn_into::<web_sys::Element>()
+                                            .and_then(|target| target.closest("a[target=_blank]").ok().flatten())
+                                            .is_some();
+                                        if in_viewer_link {
+                                            api::audit("study-opened", patient_id.clone(), patient_name.clone(), study_uid.clone(), String::from("viewer"));
+                                            if let (Some(store), Some(study_uid), Some(already_read)) = (store.clone(), study_uid.clone(), (*read).clone()) {
+                                                let read = read.clone();
+                                                wasm_bindgen_futures::spawn_local(async move {
+                                                    match store.mark_read(&study_uid).await {
+                                                        Ok(()) => {
+                                                            let mut now_read = already_read;
+                                                            now_read.insert(study_uid);
+                                                            read.set(Some(now_read));
+                                                        }
+                                                        Err(e) => log::warn("search", format!("could not mark the study read: {}", e)),
+                                                    }
+                                                });
+                                            }
+                                        }
+                                    }
+                                };
                                 html!{
                                     if to_show {
-                                        <tr key={id.clone().into_owned()} class="border-b dark:border-neutral-500 hover:bg-[#d01c25]">
-                                            <td><a href={format!("http://210.56.0.36:3000/Viewer/{}", study_uid.clone())} target="_blank" rel="noopener noreferrer" class="block w-full text-white font-medium">{id}</a></td>
-                                            <td><a href={format!("http://210.56.0.36:3000/Viewer/{}", study_uid.clone())} target="_blank" rel="noopener noreferrer" class="block w-full text-white">{name}</a></td>
-                                            <td><a href={format!("http://210.56.0.36:3000/Viewer/{}", study_uid.clone())} target="_blank" rel="noopener noreferrer" class="block w-full text-white">{accession}</a></td>
-                                            <td><a href={format!("http://210.56.0.36:3000/Viewer/{}", study_uid.clone())} target="_blank" rel="noopener noreferrer" class="block w-full text-white">{modalities.clone()}</a></td>
-                                            <td><a href={format!("http://210.56.0.36:3000/Viewer/{}", study_uid.clone())} target="_blank" rel="noopener noreferrer" class="block w-full text-white">{description}</a></td>
-                                            <td><a href={format!("http://210.56.0.36:3000/Viewer/{}", study_uid.clone())} target="_blank" rel="noopener noreferrer" class="block w-full text-white">{source_ae}</a></td>
-                                            <td><a href={format!("http://210.56.0.36:3000/Viewer/{}", study_uid.clone())} target="_blank" rel="noopener noreferrer" class="block w-full text-white">{date}{" "}{time}</a></td>
+                                        <tr onclick={on_open} key={entry.study_uid.clone().unwrap_or_else(|| format!("{}{}{}", id, date, time))} class={classes!("border-b", "dark:border-neutral-500", "hover:bg-[#d01c25]", priority_row_classes(entry.priority), is_new.then_some("bg-[#ffd400]/20"))}>
+                                            <td>
+                                                <a href={viewer_url.clone()} target="_blank" rel="noopener noreferrer" class="block w-full text-white font-medium">
+                                                    if unread {
+                                                        <span title="Unread" class="inline-block w-2 h-2 mr-1 rounded-full bg-[#ffd400]"></span>
+                                                    }
+                                                    if let Some(priority @ (Priority::Stat | Priority::High)) = entry.priority {
+                                                        <span class={classes!("inline-block", "mr-1", "px-1", "text-xs", "font-bold", priority_classes(Some(priority)))}>{priority.label()}</span>
+                                                    }
+                                                    if is_new {
+                                                        <span title="Arrived since the search was opened" class="inline-block mr-1 px-1 bg-[#ffd400] text-black text-xs font-bold">{"New"}</span>
+                                                    }
+                                                    if entry.is_malformed() {
+                                                        <span title={format!("Malformed data, missing: {}", entry.missing.join(", "))} class="inline-block mr-1 px-1 bg-red text-white text-xs font-bold">{"!"}</span>
+                                                    }
+                                                    {id.clone()}
+                                                </a>
+                                            </td>
+                                            <td>
+                                                if entry.patient.id.is_some() {
+                                                    <Link<Route> to={Route::Patient { id: id.clone() }} classes="block w-full text-white underline decoration-dotted">{name}</Link<Route>>
+                                                } else {
+                                                    <a href={viewer_url.clone()} target="_blank" rel="noopener noreferrer" class="block w-full text-white">{name}</a>
+                                                }
+                                            </td>
+                                            <td><a href={viewer_url.clone()} target="_blank" rel="noopener noreferrer" class="block w-full text-white">{accession}</a></td>
+                                            <td><a href={viewer_url.clone()} target="_blank" rel="noopener noreferrer" class="block w-full text-white">{modalities.clone()}</a></td>
+                                            <td><a href={viewer_url.clone()} target="_blank" rel="noopener noreferrer" class="block w-full text-white">{description}</a></td>
+                                            <td><a href={viewer_url.clone()} target="_blank" rel="noopener noreferrer" class="block w-full text-white">{source_ae}</a></td>
+                                            <td><a href={viewer_url.clone()} target="_blank" rel="noopener noreferrer" class="block w-full text-white">{date}{" "}{time}</a></td>
                                             {
-                                                if auth_ctx.inner && !modalities.contains("SR") {
-                                                    html!{
+                                                match &entry.study_uid {
+                                                    Some(study_uid) if auth_ctx.inner && !modalities.contains("SR") => html!{
                                                         <td>
                                                             <button onclick={
                                                                 move |e: MouseEvent| {
-                                                                    let target_uid = e.target().and_then(|t| t.dyn_into::<HtmlButtonElement>().ok()).unwrap().value();
-                                                                    navigator.clone().push(&Route::Reporting { uid: target_uid });
-                                                            }} value={study_uid.clone().to_string()} type="submit" class="inline-block px-2 py-1 bg-[#ffd400] shadow-lg text-xs font-medium">
+                                                                    if let Some(button) = e.target().and_then(|t| t.dyn_into::<HtmlButtonElement>().ok()) {
+                                                                        navigator.clone().push(&Route::Reporting { uid: button.value() });
+                                                                    }
+                                                            }} value={study_uid.clone()} type="submit" class="inline-block px-2 py-1 bg-[#ffd400] shadow-lg text-xs font-medium">
                                                                 {"Report"}
                                                             </button>
                                                         </td>
-                                                    }
-                                                } else {
-                                                    html!{}
+                                                    },
+                                                    _ if auth_ctx.inner => html!{<td></td>},
+                                                    _ => html!{},
                                                 }
                                             }
                                         </tr>
                                     }
-                                    
+
                                 }
                             }).collect::<Html>()
                         }
@@ -391,67 +814,55 @@ pub fn search() -> Html {
 
     let date_filter_callback = {
         let fetch_filters = fetch_filters.clone();
+        let date_error = date_error.clone();
         Callback::from(move |e: MouseEvent| {
-            let mut new_fetch_filters = (*fetch_filters).clone();
-            new_fetch_filters.end_date = Local::now().date_naive();
-            let target = e.target();
-            let button = target
-                .and_then(|t| t.dyn_into::<HtmlButtonElement>().ok())
-                .unwrap();
-            match button.name().as_str() {
-                "1D" => {
-                    new_fetch_filters.start_date = fetch_filters
-                        .end_date
-                        .checked_sub_days(Days::new(1))
-                        .unwrap()
-                }
-                "3D" => {
-                    new_fetch_filters.start_date = fetch_filters
-                        .end_date
-                        .checked_sub_days(Days::new(3))
-                        .unwrap()
-                }
-                "1W" => {
-                    new_fetch_filters.start_date = fetch_filters
-                        .end_date
-                        .checked_sub_days(Days::new(7))
-                        .unwrap()
-                }
-                "1M" => {
-                    new_fetch_filters.start_date = fetch_filters
-                        .end_date
-                        .checked_sub_months(Months::new(1))
-                        .unwrap()
-                }
-                "1Y" => {
-                    new_fetch_filters.start_date = fetch_filters
-                        .end_date
-                        .checked_sub_months(Months::new(12))
-                        .unwrap()
+            let preset = e
+                .target_dyn_into::<HtmlButtonElement>()
+                .and_then(|button| button.name().parse::<usize>().ok())
+                .and_then(|index| date_presets().get(index).copied());
+            if let Some(preset) = preset {
+                date_error.set(None);
+                fetch_filters.set(FetchFilters {
+                    period: preset.period(Local::now().naive_local()),
+                    preset: Some(preset),
+                    ..(*fetch_filters).clone()
+                });
+            }
+        })
+    };
+    let date_shortcut_callback = {
+        let fetch_filters = fetch_filters.clone();
+        let date_error = date_error.clone();
+        Callback::from(move |e: Event| {
+            let Some(input) = e.target_dyn_into::<HtmlInputElement>() else {
+                return;
+            };
+            if input.value().trim().is_empty() {
+                date_error.set(None);
+                return;
+            }
+            match period::parse(&input.value(), Local::now().naive_local()) {
+                Ok(typed) => {
+                    date_error.set(None);
+                    input.set_value("");
+                    fetch_filters.set(FetchFilters {
+                        period: typed,
+                        preset: None,
+                        ..(*fetch_filters).clone()
+                    });
                 }
-                &_ => new_fetch_filters.start_date = NaiveDate::from_ymd_opt(1990, 1, 1).unwrap(),
+                Err(e) => date_error.set(Some(e.to_string())),
             }
-            fetch_filters.set(new_fetch_filters);
         })
     };
     let date_query_bar = {
         let fetch_filters = fetch_filters.clone();
+        let date_error = date_error.clone();
         move || -> Html {
-            let start_date = fetch_filters
-                .start_date
-                .format("%Y-%m-%d")
-                .to_string()
-                .to_owned();
-            let end_date = fetch_filters
-                .end_date
-                .format("%Y-%m-%d")
-                .to_string()
-                .to_owned();
-            let filter_duration = fetch_filters
-                .end_date
-                .signed_duration_since(fetch_filters.start_date)
-                .num_days();
-            let durations = vec![1, 3, 7, 30, 365];
+            let period = fetch_filters.period;
+            let start_date = period.start_date().format("%Y-%m-%d").to_string();
+            let end_date = period.end_date().format("%Y-%m-%d").to_string();
+            let presets = date_presets();
             let base_styles = vec![
                 "px-2",
                 "py-1",
@@ -463,37 +874,36 @@ pub fn search() -> Html {
                 <>
                     <div class={classes!(String::from("flex items-center"))}>
                         <input type={"date"} class={classes!(String::from("px-2 py-1 border"))} value={start_date} max={end_date.clone()} ref={&filter_node_refs[6]} onchange={&filter_callback} />
-                        // <input type={"date"} class={classes!(String::from("px-2 py-1 border"))} value={start_date} ref={&filter_node_refs[6]} onchange={&filter_callback} />
                         <span class={classes!(String::from("mx-4 text-gray-500"))}>{"to"}</span>
                         <input type={"date"} class={classes!(String::from("px-2 py-1 border"))} value={end_date} max={Local::now().date_naive().format("%Y-%m-%d").to_string()} ref={&filter_node_refs[7]} onchange={&filter_callback} />
-                        // <input type={"date"} class={classes!(String::from("px-2 py-1 border"))} value={end_date} ref={&filter_node_refs[7]} onchange={&filter_callback} />
+                        <input type="text" placeholder="-3d" title="A date, two dates joined by .., or e.g. -3d, -12h, -2w, today" onchange={&date_shortcut_callback} class={classes!("ml-2", "w-20", "px-2", "py-1", "border", "bg-transparent", "text-white", date_error.is_some().then_some("border-red"))} />
                     </div>
+                    if !period.is_whole_days() {
+                        <span class="ml-2 text-xs text-grey">{format!("{} to {}", period.start.format("%d %b %H:%M"), period.end.format("%d %b %H:%M"))}</span>
+                    }
+                    if let Some(error) = &*date_error {
+                        <span class="ml-2 text-xs text-red">{error.clone()}</span>
+                    }
                     <div class={classes!(String::from("flex m-2"))}>
                         {
-                            durations.iter().enumerate().map(|(idx, duration)| {
+                            presets.iter().enumerate().map(|(idx, preset)| {
                                 let mut needed_styles = base_styles.clone();
                                 if idx == 0 {
                                     needed_styles.push("rounded-l");
                                 }
-                                if *duration == filter_duration {
+                                if idx + 1 == presets.len() {
+                                    needed_styles.push("rounded-r");
+                                }
+                                if fetch_filters.preset == Some(*preset) {
                                     needed_styles.push("bg-[#ffd400] text-black");
                                 } else {
                                     needed_styles.push("text-white dark:text-white");
                                 }
-                                let label = match duration {
-                                    1 => "1D",
-                                    3 => "3D",
-                                    7 => "1W",
-                                    30 => "1M",
-                                    365 => "1Y",
-                                    _ => "Any",
-                                };
                                 html!{
-                                    <button name={label.clone()} onclick={&date_filter_callback} class={classes!(needed_styles)}>{label.clone()}</button>
+                                    <button name={idx.to_string()} onclick={&date_filter_callback} class={classes!(needed_styles, "whitespace-nowrap")}>{preset.label()}</button>
                                 }
                             }).collect::<Html>()
                         }
-                        <button name={"ANY"} onclick={&date_filter_callback} class={classes!(base_styles, "rounded-r", "text-white")}>{"Any"}</button>
                     </div>
                 </>
             }
@@ -502,6 +912,7 @@ pub fn search() -> Html {
 
     let modality_filter_callback = {
         let fetch_filters = fetch_filters.clone();
+        let available_modalities = available_modalities.clone();
         Callback::from(move |e: MouseEvent| {
             let target = e.target();
             let button = target
@@ -509,23 +920,25 @@ pub fn search() -> Html {
                 .unwrap();
             let requested_filter = button.name();
             let mut filtered_modalities = (*fetch_filters).clone().modalities;
-            if requested_filter == String::from("ANY") {
-                for (_, val) in filtered_modalities.iter_mut() {
-                    *val = false;
-                }
+            if requested_filter == "ANY" {
+                filtered_modalities.clear();
+            } else if filtered_modalities.contains(&requested_filter) {
+                filtered_modalities.retain(|modality| *modality != requested_filter);
             } else {
-                let current_filter_status = *filtered_modalities.get(&requested_filter).unwrap();
-                filtered_modalities.insert(requested_filter, !current_filter_status);
+                filtered_modalities.push(requested_filter);
+                // keep the query in the order of the buttons
+                filtered_modalities.sort_by_key(|modality| available_modalities.iter().position(|available| available == modality));
             }
             fetch_filters.set(FetchFilters {
-                start_date: fetch_filters.start_date,
-                end_date: fetch_filters.end_date,
                 modalities: filtered_modalities,
+                ..(*fetch_filters).clone()
             });
         })
     };
     let modality_query_bar = {
         let fetch_filters = fetch_filters.clone();
+        let available_modalities = available_modalities.clone();
+        let images_only = images_only.clone();
         move || -> Html {
             let base_styles = vec![
                 "px-2",
@@ -534,19 +947,23 @@ pub fn search() -> Html {
                 "hover:bg-yellow",
                 "hover:text-black",
             ];
-            let is_any = match fetch_filters.modalities.values().all(|v| *v == false) {
+            let is_any = match fetch_filters.modalities.is_empty() {
+                true => "bg-[#ffd400] text-black",
+                false => "text-white",
+            };
+            let images_only_styles = match *images_only {
                 true => "bg-[#ffd400] text-black",
                 false => "text-white",
             };
             html! {
                 <div class={classes!(String::from("flex m-2"))}>
                 {
-                    fetch_filters.modalities.clone().iter().enumerate().map(|(idx, (filter, state))| {
+                    available_modalities.iter().enumerate().map(|(idx, filter)| {
                         let mut needed_styles = base_styles.clone();
                         if idx == 0 {
                             needed_styles.push("rounded-l");
                         }
-                        if *state {
+                        if fetch_filters.modalities.contains(filter) {
                             needed_styles.push("bg-[#ffd400] text-black dark:text-black")
                         } else {
                             needed_styles.push("text-white dark:text-white");
@@ -554,11 +971,64 @@ pub fn search() -> Html {
                         html!{<button name={filter.clone()} onclick={&modality_filter_callback} class={classes!(needed_styles)}>{filter.clone()}</button>}
                     }).collect::<Html>()
                 }
-                    <button name={"ANY"} onclick={&modality_filter_callback} class={classes!(base_styles, "rounded-r", is_any)}>{"Any"}</button>
+                    <button name={"ANY"} onclick={&modality_filter_callback} class={classes!(base_styles.clone(), "rounded-r", is_any)}>{"Any"}</button>
+                    <button onclick={
+                        let images_only = images_only.clone();
+                        move |_: MouseEvent| images_only.set(!*images_only)
+                    } title="Hide studies with only reports, presentation states or key images" class={classes!(base_styles, "ml-2", "rounded", images_only_styles)}>{"Images only"}</button>
                 </div>
             }
         }
     };
+    let export_callback = {
+        let studies = sorted_studies.clone();
+        let fetch_filters = fetch_filters.clone();
+        let text_filters = TextFilters {
+            id: (*id_filter).clone(),
+            name: (*name_filter).clone(),
+            accession: (*accession_filter).clone(),
+            modality: (*modality_filter).clone(),
+            description: (*description_filter).clone(),
+            source_ae: (*source_ae_filter).clone(),
+            images_only: *images_only,
+            priority: *priority_filter,
+        };
+        Callback::from(move |e: MouseEvent| {
+            let Some(button) = e.target().and_then(|t| t.dyn_into::<HtmlButtonElement>().ok()) else {
+                return;
+            };
+            let rows = export::rows(&studies, &text_filters);
+            let file_name = format!(
+                "studies-{}-{}",
+                fetch_filters.period.start_date().format("%Y%m%d"),
+                fetch_filters.period.end_date().format("%Y%m%d")
+            );
+            let saved = match button.name().as_str() {
+                "XLSX" => export::xlsx(&rows)
+                    .map_err(|e| e.to_string())
+                    .and_then(|bytes| download::save(&format!("{}.xlsx", file_name), pacsportal_core::xlsx::CONTENT_TYPE, &bytes)),
+                _ => download::save(&format!("{}.csv", file_name), "text/csv;charset=utf-8", export::csv(&rows).as_bytes()),
+            };
+            match saved {
+                Ok(()) => api::audit("export", None, None, None, format!("search results, {} studies, {}", rows.len() - 1, button.name())),
+                Err(e) => log::error("search", format!("export failed: {}", e)),
+            }
+        })
+    };
+    let on_notify_stat = {
+        let notify_stat = notify_stat.clone();
+        move |_: MouseEvent| {
+            let enabled = !*notify_stat;
+            // the browser asks the user once; a refusal leaves the alerts silent
+            if enabled && Notification::permission() == NotificationPermission::Default {
+                let _ = Notification::request_permission();
+            }
+            if let Err(e) = LocalStorage::set(NOTIFY_STAT_KEY, enabled) {
+                log::warn("search", format!("could not keep the STAT alerts setting: {}", e));
+            }
+            notify_stat.set(enabled);
+        }
+    };
     /* Colors
     NATURAL GRAY #8A8887
     ALIZARIN CRIMSON #D41C24
@@ -577,15 +1047,85 @@ pub fn search() -> Html {
                 <div class="flex items-center justify-between">
                     {date_query_bar()}
                     {modality_query_bar()}
+                    if *is_loaded {
+                        <div class="flex m-2">
+                            <button name="CSV" onclick={&export_callback} title="Export the shown studies" class="px-2 py-1 border rounded-l text-white hover:bg-yellow hover:text-black">{"CSV"}</button>
+                            <button name="XLSX" onclick={&export_callback} title="Export the shown studies" class="px-2 py-1 border rounded-r text-white hover:bg-yellow hover:text-black">{"XLSX"}</button>
+                        </div>
+                    }
+                    if !new_studies.is_empty() {
+                        <button onclick={
+                            let new_studies = new_studies.clone();
+                            move |_: MouseEvent| new_studies.set(HashSet::new())
+                        } type="button" title="Clear the highlight of the new studies" class="m-2 px-2 py-1 rounded bg-[#ffd400] text-black text-xs font-semibold">{format!("{} new", new_studies.len())}</button>
+                    }
+                    <button onclick={
+                        let auto_refresh = auto_refresh.clone();
+                        move |_: MouseEvent| auto_refresh.set(!*auto_refresh)
+                    } type="button" title={if *live { "Kept up to date by the server" } else { "Search again every minute for new studies" }} class={classes!("m-2", "px-2", "py-1", "border", "rounded", "text-xs", "hover:bg-yellow", "hover:text-black", if *auto_refresh { "bg-[#ffd400] text-black" } else { "text-white" })}>{"Auto-refresh"}</button>
+                    <button onclick={on_notify_stat} type="button" title="Notify me of new STAT studies" class={classes!("m-2", "px-2", "py-1", "border", "rounded", "text-xs", "hover:bg-yellow", "hover:text-black", if *notify_stat { "bg-[#ffd400] text-black" } else { "text-white" })}>{"STAT alerts"}</button>
+                    <select onchange={on_priority_filter} title="Least urgent priority shown" class="m-2 px-2 py-1 border bg-black text-white">
+                        {
+                            PRIORITY_FILTERS.iter().map(|(priority, label)| html! {
+                                <option value={priority.map(|priority| priority.label()).unwrap_or_default()} selected={*priority == *priority_filter}>{*label}</option>
+                            }).collect::<Html>()
+                        }
+                    </select>
+                    <select onchange={on_sort} title="Order of the studies" class="m-2 px-2 py-1 border bg-black text-white">
+                        {
+                            Sort::ALL.iter().map(|option| html! {
+                                <option value={option.key()} selected={*option == *sort}>{option.label()}</option>
+                            }).collect::<Html>()
+                        }
+                    </select>
                     <button onclick={
                         let navigator = navigator.clone();
                         move |_: MouseEvent| {
+                            navigator.push(&Route::CriticalResults);
+                        }
+                    } type="button" class="flex justify-center rounded-sm border border-red px-3 py-1.5 mr-2 text-sm font-semibold leading-6 text-white shadow-sm hover:bg-red">{"Critical"}</button>
+                    if auth_ctx.inner {
+                        <button onclick={
+                            let navigator = navigator.clone();
+                            move |_: MouseEvent| {
+                                navigator.push(&Route::Worklist);
+                            }
+                        } type="button" class="flex justify-center rounded-sm border px-3 py-1.5 mr-2 text-sm font-semibold leading-6 text-white shadow-sm hover:bg-yellow hover:text-black">{"Worklist"}</button>
+                        <button onclick={
+                            let navigator = navigator.clone();
+                            move |_: MouseEvent| {
+                                navigator.push(&Route::Macros);
+                            }
+                        } type="button" class="flex justify-center rounded-sm border px-3 py-1.5 mr-2 text-sm font-semibold leading-6 text-white shadow-sm hover:bg-yellow hover:text-black">{"Macros"}</button>
+                    }
+                    if auth_ctx.admin {
+                        <button onclick={
+                            let navigator = navigator.clone();
+                            move |_: MouseEvent| {
+                                navigator.push(&Route::Audit);
+                            }
+                        } type="button" class="flex justify-center rounded-sm border px-3 py-1.5 mr-2 text-sm font-semibold leading-6 text-white shadow-sm hover:bg-yellow hover:text-black">{"Audit"}</button>
+                        <button onclick={
+                            let navigator = navigator.clone();
+                            move |_: MouseEvent| {
+                                navigator.push(&Route::Turnaround);
+                            }
+                        } type="button" class="flex justify-center rounded-sm border px-3 py-1.5 mr-2 text-sm font-semibold leading-6 text-white shadow-sm hover:bg-yellow hover:text-black">{"Turnaround"}</button>
+                    }
+                    <button onclick={
+                        let navigator = navigator.clone();
+                        move |_: MouseEvent| {
+                            if let Some(base) = api::API_BASE {
+                                wasm_bindgen_futures::spawn_local(api::log_out(base));
+                            }
                             navigator.replace(&Route::Login);
                         }
                     } type="submit" class="flex w-full justify-center rounded-sm bg-red px-3 py-1.5 text-sm font-semibold leading-6 text-white shadow-sm hover:bg-red-600">{"Logout"}</button>
                 </div>
             </nav>
-            <div class="h-4/5 overflow-x-auto">
+            <div class="h-4/5 flex">
+                <SavedSearchList current={current_search} on_apply={apply_search} />
+                <div class="flex-1 overflow-x-auto">
                 <div class="inline-block min-w-full py-2 sm:px-6 lg:px-8">
                     <div class="container">
                         <table id="myTable" class="w-full text-left text-sm font-light">
@@ -600,6 +1140,7 @@ pub fn search() -> Html {
                         // </script>
                     </div>
                 </div>
+                </div>
             </div>
         </div>
     }
@@ -0,0 +1,158 @@
+use chrono::DateTime;
+use web_sys::HtmlSelectElement;
+use yew::prelude::*;
+
+use crate::api::{self, ShareLink};
+use crate::AuthorizedContext;
+
+/// How long a new link may be valid for; the server may allow less.
+const DAYS: [u32; 4] = [1, 3, 7, 14];
+
+/// An RFC 3339 time from the server in local time, e.g. `2023-07-24 09:05`.
+pub fn local_time(time: &str) -> String {
+    DateTime::parse_from_rfc3339(time)
+        .map(|time| time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|_| time.to_owned())
+}
+
+fn link_url(token: &str) -> String {
+    let origin = gloo::utils::window().location().origin().unwrap_or_default();
+    format!("{}/share/{}", origin, token)
+}
+
+#[derive(Properties, PartialEq)]
+pub struct ShareLinksProps {
+    pub study_uid: String,
+}
+
+/// Expiring links that give consultants outside the hospital the report and
+/// images of a study without a login. Radiologists create and revoke them;
+/// only shown when the portal is served by `pacsportal-server`.
+#[function_component(ShareLinks)]
+pub fn share_links(props: &ShareLinksProps) -> Html {
+    let open = use_state(|| false);
+    let links = use_state(Vec::<ShareLink>::new);
+    let days = use_state(|| 7u32);
+    let status = use_state(|| String::from(""));
+    let version = use_state(|| 0u32);
+    let auth_ctx = use_context::<AuthorizedContext>().unwrap();
+
+    use_effect_with_deps(
+        {
+            let links = links.clone();
+            let status = status.clone();
+            move |(study_uid, open, _): &(String, bool, u32)| {
+                if let (Some(base), true) = (api::API_BASE, *open) {
+                    let study_uid = study_uid.clone();
+                    wasm_bindgen_futures::spawn_local(async move {
+                        match api::share_links(base, &study_uid).await {
+                            Ok(fetched) => links.set(fetched),
+                            Err(e) => status.set(e),
+                        }
+                    });
+                }
+            }
+        },
+        (props.study_uid.clone(), *open, *version),
+    );
+
+    let (Some(base), true) = (api::API_BASE, auth_ctx.inner) else {
+        return html! {};
+    };
+    if !*open {
+        return html! {
+            <button onclick={move |_: MouseEvent| open.set(true)} type="button" class="mt-1 ml-4 text-xs text-grey hover:text-white">{"Share..."}</button>
+        };
+    }
+
+    let on_create = {
+        let days = days.clone();
+        let status = status.clone();
+        let version = version.clone();
+        let study_uid = props.study_uid.clone();
+        move |_: MouseEvent| {
+            let days = *days;
+            let status = status.clone();
+            let version = version.clone();
+            let study_uid = study_uid.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match api::create_share_link(base, &study_uid, days).await {
+                    Ok(_) => {
+                        status.set(String::from(""));
+                        version.set(*version + 1);
+                    }
+                    Err(e) => status.set(e),
+                }
+            });
+        }
+    };
+
+    html! {
+        <div class="mt-2 p-3 max-w-xl border border-white/20 text-sm text-white">
+            <p class="text-xs text-grey">{"Anyone with a link sees the report and images of this study until it expires or is revoked. Every opening is audited."}</p>
+            <div class="mt-2 flex items-center gap-x-2">
+                <select onchange={
+                    let days = days.clone();
+                    move |e: Event| {
+                        if let Some(value) = e.target_dyn_into::<HtmlSelectElement>().and_then(|select| select.value().parse().ok()) {
+                            days.set(value);
+                        }
+                    }
+                } class="bg-black border border-white/20 px-2 py-1 text-xs">
+                    {
+                        DAYS.iter().map(|option| html! {
+                            <option value={option.to_string()} selected={*option == *days}>{format!("Valid for {} day{}", option, if *option == 1 { "" } else { "s" })}</option>
+                        }).collect::<Html>()
+                    }
+                </select>
+                <button onclick={on_create} type="button" class="inline-block px-2 py-1 bg-[#ffd400] text-black text-xs font-medium">{"Create link"}</button>
+                <button onclick={move |_: MouseEvent| open.set(false)} type="button" class="text-xs text-grey">{"Close"}</button>
+            </div>
+            <ul class="mt-2">
+                {
+                    links.iter().map(|link| {
+                        let state = match (&link.token, &link.revoked) {
+                            (_, Some(revoked)) => format!("revoked {}", local_time(revoked)),
+                            (Some(_), None) => format!("valid until {}", local_time(&link.expires)),
+                            (None, None) => format!("expired {}", local_time(&link.expires)),
+                        };
+                        let on_revoke = {
+                            let status = status.clone();
+                            let version = version.clone();
+                            let id = link.id.clone();
+                            move |_: MouseEvent| {
+                                let status = status.clone();
+                                let version = version.clone();
+                                let id = id.clone();
+                                wasm_bindgen_futures::spawn_local(async move {
+                                    match api::revoke_share_link(base, &id).await {
+                                        Ok(()) => version.set(*version + 1),
+                                        Err(e) => status.set(e),
+                                    }
+                                });
+                            }
+                        };
+                        html! {
+                            <li key={link.id.clone()} class="mt-2 text-xs">
+                                <span class="text-grey">{format!("By {} on {}, {}", link.created_by, local_time(&link.created), state)}</span>
+                                if let Some(token) = &link.token {
+                                    <div class="flex items-center gap-x-2">
+                                        <input type="text" readonly=true value={link_url(token)} onfocus={|e: FocusEvent| {
+                                            if let Some(input) = e.target_dyn_into::<web_sys::HtmlInputElement>() {
+                                                input.select();
+                                            }
+                                        }} class="flex-1 bg-black border border-white/20 px-2 py-1" />
+                                        <button onclick={on_revoke} type="button" class="px-2 py-1 border border-red text-red">{"Revoke"}</button>
+                                    </div>
+                                }
+                            </li>
+                        }
+                    }).collect::<Html>()
+                }
+            </ul>
+            if !status.is_empty() {
+                <p class="mt-2 text-xs">{(*status).clone()}</p>
+            }
+        </div>
+    }
+}
@@ -0,0 +1,180 @@
+use pacsportal_core::model::{Instance, Series};
+use yew::prelude::*;
+
+use crate::api::{self, SharedStudy};
+use crate::dicomweb;
+use crate::pages::share::local_time;
+
+#[derive(Properties, PartialEq)]
+pub struct SharedProps {
+    pub token: String,
+}
+
+/// The page behind a share link: the report and a simple viewer of one
+/// study, without a login. Everything goes through the link's own DICOMweb
+/// root on the server, which checks the signature, expiry and revocation of
+/// the link on every request.
+#[function_component(Shared)]
+pub fn shared(props: &SharedProps) -> Html {
+    let study = use_state(|| Option::<SharedStudy>::None);
+    let series = use_state(Vec::<Series>::new);
+    let open_series = use_state(|| Option::<String>::None);
+    let instances = use_state(Vec::<String>::new);
+    let index = use_state(|| 0usize);
+    let reports = use_state(Vec::<String>::new);
+    let status = use_state(|| String::from("Opening the shared study..."));
+    let base = api::API_BASE.map(|base| format!("{}/shared/{}/dicomweb", base, props.token));
+
+    use_effect_with_deps(
+        {
+            let study = study.clone();
+            let series = series.clone();
+            let open_series = open_series.clone();
+            let reports = reports.clone();
+            let status = status.clone();
+            move |token: &String| {
+                let Some(api_base) = api::API_BASE else {
+                    status.set(String::from("Share links are only available through the portal server."));
+                    return;
+                };
+                let token = token.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    let opened = match api::shared_study(api_base, &token).await {
+                        Ok(opened) => opened,
+                        Err(e) => {
+                            status.set(e);
+                            return;
+                        }
+                    };
+                    status.set(String::from(""));
+                    let base = format!("{}/shared/{}/dicomweb", api_base, token);
+                    let fetched = dicomweb::fetch_json(&format!(
+                        "{}/studies/{}/series?includefield=SeriesDescription",
+                        base, opened.study_uid
+                    ))
+                    .await
+                    .unwrap_or_default();
+                    let mut fetched: Vec<Series> = fetched
+                        .iter()
+                        .map(Series::from_dicom)
+                        .filter(|s| s.is_image_series())
+                        .collect();
+                    fetched.sort_by_key(|s| s.number.unwrap_or_default());
+                    open_series.set(fetched.first().and_then(|s| s.series_uid.clone()));
+                    series.set(fetched);
+                    reports.set(dicomweb::fetch_reports_from(&base, &opened.study_uid).await);
+                    study.set(Some(opened));
+                });
+            }
+        },
+        props.token.clone(),
+    );
+
+    use_effect_with_deps(
+        {
+            let instances = instances.clone();
+            let index = index.clone();
+            let base = base.clone();
+            move |(study_uid, open_series): &(Option<String>, Option<String>)| {
+                instances.set(Vec::new());
+                index.set(0);
+                if let (Some(base), Some(study_uid), Some(series_uid)) = (base, study_uid.clone(), open_series.clone()) {
+                    wasm_bindgen_futures::spawn_local(async move {
+                        let fetched = dicomweb::fetch_json(&format!(
+                            "{}/studies/{}/series/{}/instances?includefield=InstanceNumber",
+                            base, study_uid, series_uid
+                        ))
+                        .await
+                        .unwrap_or_default();
+                        let mut fetched: Vec<Instance> = fetched.iter().map(Instance::from_dicom).collect();
+                        fetched.sort_by_key(|instance| instance.number.unwrap_or_default());
+                        instances.set(fetched.into_iter().filter_map(|instance| instance.sop_uid).collect());
+                    });
+                }
+            }
+        },
+        (study.as_ref().map(|study| study.study_uid.clone()), (*open_series).clone()),
+    );
+
+    let (Some(base), Some(opened)) = (base, &*study) else {
+        return html! {
+            <div class="min-h-screen bg-black p-8 text-white">
+                <p>{(*status).clone()}</p>
+            </div>
+        };
+    };
+
+    let step = {
+        let index = index.clone();
+        let count = instances.len();
+        move |forward: bool| {
+            if forward && *index + 1 < count {
+                index.set(*index + 1);
+            } else if !forward && *index > 0 {
+                index.set(*index - 1);
+            }
+        }
+    };
+    let image = match (&*open_series, instances.get(*index)) {
+        (Some(series_uid), Some(sop_uid)) => Some(format!(
+            "{}/studies/{}/series/{}/instances/{}/rendered",
+            base, opened.study_uid, series_uid, sop_uid
+        )),
+        _ => None,
+    };
+
+    html! {
+        <div class="min-h-screen bg-black p-8 text-white">
+            <h1 class="text-xl font-bold">{opened.patient_name.clone().unwrap_or_default().replace('^', " ")}</h1>
+            <p class="text-sm text-grey">
+                {format!("{} {}", opened.study_date.clone().unwrap_or_default(), opened.description.clone().unwrap_or_default())}
+            </p>
+            <p class="mt-1 text-xs text-grey">{format!("Shared with you until {}. Please do not pass this link on.", local_time(&opened.expires))}</p>
+            <div class="mt-4 flex flex-wrap gap-2">
+                {
+                    series.iter().map(|s| {
+                        let series_uid = s.series_uid.clone().unwrap_or_default();
+                        let is_open = open_series.as_deref() == Some(series_uid.as_str());
+                        let onclick = {
+                            let open_series = open_series.clone();
+                            move |_: MouseEvent| open_series.set(Some(series_uid.clone()))
+                        };
+                        html! {
+                            <button {onclick} type="button" class={classes!("px-2", "py-1", "border", "text-xs", if is_open { "bg-[#ffd400] text-black" } else { "text-white" })}>{s.label()}</button>
+                        }
+                    }).collect::<Html>()
+                }
+            </div>
+            if let Some(image) = image {
+                <div class="mt-2">
+                    <img src={image} alt="Image" onwheel={
+                        let step = step.clone();
+                        move |e: WheelEvent| {
+                            e.prevent_default();
+                            step(e.delta_y() > 0.0);
+                        }
+                    } class="h-[32rem] w-[32rem] object-contain bg-black border border-white/20" />
+                    <div class="mt-1 flex items-center gap-x-4 text-xs">
+                        <button onclick={let step = step.clone(); move |_: MouseEvent| step(false)} type="button" class="px-2 py-1 border">{"Previous"}</button>
+                        <span>{format!("{} / {}", *index + 1, instances.len())}</span>
+                        <button onclick={move |_: MouseEvent| step(true)} type="button" class="px-2 py-1 border">{"Next"}</button>
+                    </div>
+                </div>
+            }
+            <div class="mt-4 max-w-3xl">
+                <h2 class="font-bold">{"Report"}</h2>
+                if reports.is_empty() {
+                    <p class="text-sm text-grey">{"No report has been made for this study yet."}</p>
+                }
+                {
+                    reports.iter().map(|report| html! {
+                        <p class="mt-2 text-sm whitespace-pre-wrap">{report.clone()}</p>
+                    }).collect::<Html>()
+                }
+            </div>
+            if !status.is_empty() {
+                <p class="mt-2 text-xs">{(*status).clone()}</p>
+            }
+        </div>
+    }
+}
@@ -0,0 +1,165 @@
+use chrono::{Days, Local, NaiveDate};
+use data_encoding::BASE64;
+use dicom::object::InMemDicomObject;
+use pacsportal_core::model::Study;
+use pacsportal_core::turnaround::{self, Grouping, Turnaround as Reported};
+use pacsportal_core::worklist::duration_label;
+use web_sys::{HtmlInputElement, HtmlSelectElement};
+use yew::prelude::*;
+use yew_router::prelude::use_navigator;
+
+use crate::dicomweb;
+use crate::log;
+use crate::Route;
+
+/// Days shown when the dashboard is opened.
+const DEFAULT_DAYS: u64 = 30;
+
+async fn query(path: &str) -> Option<Vec<InMemDicomObject>> {
+    log::debug("turnaround", format!("QIDO {}", log::redact::query(path)));
+    dicomweb::fetch_json(&format!("{}/{}", dicomweb::RS_BASE, path)).await
+}
+
+#[function_component(Turnaround)]
+pub fn turnaround() -> Html {
+    let reported = use_state(Vec::<Reported>::new);
+    let loaded_status = use_state(|| String::from("Loading..."));
+    let range = use_state(|| {
+        let today = Local::now().date_naive();
+        (today.checked_sub_days(Days::new(DEFAULT_DAYS)).unwrap_or(today), today)
+    });
+    let grouping = use_state(|| Grouping::Modality);
+    let navigator = use_navigator().unwrap();
+
+    use_effect_with_deps(
+        {
+            let reported = reported.clone();
+            let loaded_status = loaded_status.clone();
+            move |(from, to): &(NaiveDate, NaiveDate)| {
+                let dates = format!("StudyDate={}-{}", from.format("%Y%m%d"), to.format("%Y%m%d"));
+                loaded_status.set(String::from("Loading..."));
+                wasm_bindgen_futures::spawn_local(async move {
+                    let studies = query(&format!("studies?{}", dates)).await;
+                    let reports = query(&format!(
+                        "instances?Modality=SR&{}&includefield=ContentDate&includefield=ContentTime&includefield=ContentCreatorName&includefield=VerifyingObserverSequence&includefield=ConceptNameCodeSequence",
+                        dates
+                    ))
+                    .await;
+                    match studies {
+                        Some(studies) => {
+                            let studies: Vec<Study> = studies.iter().map(Study::from_dicom).collect();
+                            // no SRs in the period is no reports
+                            reported.set(turnaround::turnarounds(&studies, &reports.unwrap_or_default()));
+                            loaded_status.set(String::new());
+                        }
+                        None => loaded_status.set(String::from("There are no studies in this period, or the archive could not be searched. Please try again later or contact your system administrator.")),
+                    }
+                });
+            }
+        },
+        *range,
+    );
+
+    let on_date = |set_to: bool| {
+        let range = range.clone();
+        move |e: Event| {
+            let Some(input) = e.target_dyn_into::<HtmlInputElement>() else {
+                return;
+            };
+            if let Ok(date) = NaiveDate::parse_from_str(&input.value(), "%Y-%m-%d") {
+                let (from, to) = *range;
+                range.set(if set_to { (from, date) } else { (date, to) });
+            }
+        }
+    };
+
+    let summaries = turnaround::summarize(&reported, *grouping);
+    let longest = summaries.iter().map(|summary| summary.p90).max().unwrap_or(0).max(1);
+    let bars = summaries
+        .iter()
+        .map(|summary| {
+            let width = |minutes: i64| format!("width: {:.1}%", minutes as f64 * 100.0 / longest as f64);
+            html! {
+                <tr class="border-b dark:border-neutral-500">
+                    <td class="px-2 py-1 text-white whitespace-nowrap">{summary.key.clone()}</td>
+                    <td class="px-2 py-1 text-grey">{summary.count}</td>
+                    <td class="px-2 py-1 text-white whitespace-nowrap">{duration_label(summary.median)}</td>
+                    <td class="px-2 py-1 text-white whitespace-nowrap">{duration_label(summary.p90)}</td>
+                    <td class="px-2 py-1 w-1/2">
+                        <div class="h-2 bg-[#ffd400]" style={width(summary.median)}></div>
+                        <div class="mt-1 h-2 bg-grey" style={width(summary.p90)}></div>
+                    </td>
+                </tr>
+            }
+        })
+        .collect::<Html>();
+
+    let (from, to) = *range;
+    let export = format!("data:text/csv;base64,{}", BASE64.encode(turnaround::csv(&reported).as_bytes()));
+
+    html! {
+        <div class="min-h-screen bg-black px-6 md:px-12 py-6">
+            <div class="flex items-center justify-between border-b border-white/10 pb-6">
+                <div>
+                    <h1 class="text-white text-base font-semibold leading-7">{"Turnaround"}</h1>
+                    <p class="mt-1 text-sm leading-6 text-gray-500">{"Time from acquisition to the verification of the first report."}</p>
+                </div>
+                <div class="flex items-center gap-x-6">
+                    if !reported.is_empty() {
+                        <a href={export} download={format!("turnaround-{}-{}.csv", from.format("%Y%m%d"), to.format("%Y%m%d"))} class="text-sm font-semibold leading-6 text-white">{"Export CSV"}</a>
+                    }
+                    <button onclick={
+                        move |_: MouseEvent| {
+                            navigator.push(&Route::Search);
+                        }
+                    } type="button" class="text-sm font-semibold leading-6 text-gray-500">{"Back"}</button>
+                </div>
+            </div>
+            <div class="mt-6 flex flex-wrap items-center gap-x-4 gap-y-2 text-sm">
+                <label class="text-grey">{"Studies from "}
+                    <input onchange={on_date(false)} type="date" value={from.format("%Y-%m-%d").to_string()} class="bg-black border px-2 py-1 text-white" />
+                </label>
+                <label class="text-grey">{"To "}
+                    <input onchange={on_date(true)} type="date" value={to.format("%Y-%m-%d").to_string()} class="bg-black border px-2 py-1 text-white" />
+                </label>
+                <select onchange={
+                    let grouping = grouping.clone();
+                    move |e: Event| {
+                        if let Some(select) = e.target_dyn_into::<HtmlSelectElement>() {
+                            let chosen = Grouping::ALL.into_iter().find(|grouping| grouping.label() == select.value());
+                            grouping.set(chosen.unwrap_or(Grouping::Modality));
+                        }
+                    }
+                } class="bg-black border px-2 py-1 text-white">
+                    {
+                        Grouping::ALL.iter().map(|option| html! {
+                            <option value={option.label()} selected={*option == *grouping}>{format!("By {}", option.label().to_lowercase())}</option>
+                        }).collect::<Html>()
+                    }
+                </select>
+                <span class="flex items-center gap-x-1 text-grey"><span class="inline-block w-3 h-2 bg-[#ffd400]"></span>{"Median"}</span>
+                <span class="flex items-center gap-x-1 text-grey"><span class="inline-block w-3 h-2 bg-grey"></span>{"90th percentile"}</span>
+            </div>
+            if !loaded_status.is_empty() {
+                <p class="mt-6 text-white">{(*loaded_status).clone()}</p>
+            } else if reported.is_empty() {
+                <p class="mt-6 text-white">{"No reported studies in this period."}</p>
+            } else {
+                <table class="mt-6 w-full text-left text-sm font-light">
+                    <thead class="border-b font-medium dark:border-neutral-500">
+                        <tr>
+                            <th scope="col" class="px-2 py-1 text-grey">{grouping.label()}</th>
+                            <th scope="col" class="px-2 py-1 text-grey">{"Reports"}</th>
+                            <th scope="col" class="px-2 py-1 text-grey">{"Median"}</th>
+                            <th scope="col" class="px-2 py-1 text-grey">{"90th percentile"}</th>
+                            <th scope="col" class="px-2 py-1"></th>
+                        </tr>
+                    </thead>
+                    <tbody>
+                        {bars}
+                    </tbody>
+                </table>
+            }
+        </div>
+    }
+}
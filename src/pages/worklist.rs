@@ -0,0 +1,465 @@
+use std::collections::HashMap;
+
+use chrono::{Days, Local};
+use dicom::object::InMemDicomObject;
+use gloo::net::http::Request;
+use gloo::timers::callback::Interval;
+use pacsportal_core::model::{set_priorities, Priority, Study};
+use pacsportal_core::search::INCLUDE_FIELDS;
+use pacsportal_core::worklist::{self, acquired_at, waiting_label};
+use yew::prelude::*;
+use yew_router::prelude::use_navigator;
+
+use crate::dicomweb;
+use crate::feed;
+use crate::log;
+use crate::store::{self, Assignment, Claim, Lock, LOCK_TIMEOUT_MINUTES};
+use crate::{AuthorizedContext, Route};
+
+/// How often the locks and assignments of others are refreshed.
+const LOCK_REFRESH_MILLIS: u32 = 30_000;
+
+pub fn priority_classes(priority: Option<Priority>) -> &'static str {
+    match priority {
+        Some(Priority::Stat) => "bg-red text-white",
+        Some(Priority::High) => "bg-[#ffd400] text-black",
+        _ => "text-grey",
+    }
+}
+
+#[function_component(Worklist)]
+pub fn worklist() -> Html {
+    let studies = use_state(Vec::<Study>::new);
+    let locks = use_state(HashMap::<String, Lock>::new);
+    let assignments = use_state(HashMap::<String, Assignment>::new);
+    let assigned_to_me = use_state(|| false);
+    let loaded_status = use_state(|| String::from("Loading..."));
+    let action_status = use_state(String::new);
+    let days = use_state(|| 7u64);
+    let lock_refresh = use_state(|| 0u32);
+    // priorities set in the store, which win over those of the requests
+    let priorities = use_state(HashMap::<String, Priority>::new);
+    // bumped by the live feed when studies arrive or reports are stored
+    let changes = use_state(|| 0u32);
+    let auth_ctx = use_context::<AuthorizedContext>().unwrap();
+    let navigator = use_navigator().unwrap();
+    let store = use_memo(store::for_user, (*auth_ctx).clone());
+
+    use_effect_with_deps(
+        {
+            let studies = studies.clone();
+            let loaded_status = loaded_status.clone();
+            move |(days, changes): &(u64, u32)| {
+                let today = Local::now().date_naive();
+                let from = today.checked_sub_days(Days::new(*days)).unwrap_or(today);
+                let query = format!("StudyDate={}-{}&{}", from.format("%Y%m%d"), today.format("%Y%m%d"), INCLUDE_FIELDS);
+                if *changes == 0 {
+                    loaded_status.set(String::from("Loading..."));
+                }
+                wasm_bindgen_futures::spawn_local(async move {
+                    log::debug("worklist", format!("QIDO studies?{}", log::redact::query(&query)));
+                    match Request::get(&format!("{}/studies?{}", dicomweb::RS_BASE, query)).send().await {
+                        Ok(res) if res.status() == 204 => {
+                            studies.set(Vec::new());
+                            loaded_status.set(String::new());
+                        }
+                        Ok(res) if res.status() == 200 => match res.json::<Vec<serde_json::Value>>().await {
+                            Ok(data) => {
+                                let fetched: Vec<Study> = data
+                                    .into_iter()
+                                    .map(|study| Study::from_dicom(&dicom_json::from_value::<InMemDicomObject>(study).unwrap_or_else(|_| InMemDicomObject::new_empty())))
+                                    .collect();
+                                studies.set(fetched);
+                                loaded_status.set(String::new());
+                            }
+                            Err(_) => loaded_status.set(String::from("Unable to parse data from server. Please report this to your system administrator.")),
+                        },
+                        Ok(res) => loaded_status.set(format!("The server sent back an error: {}. Please report this to your system administrator.", res.status())),
+                        Err(_) => loaded_status.set(String::from("Unable to reach the server. Please try again later or contact your system administrator.")),
+                    }
+                });
+            }
+        },
+        (*days, *changes),
+    );
+
+    use_effect_with_deps(
+        {
+            let changes = changes.clone();
+            move |_| {
+                let counter = std::rc::Rc::new(std::cell::Cell::new(*changes));
+                let subscription = feed::subscribe(
+                    Callback::from(move |()| {
+                        counter.set(counter.get() + 1);
+                        changes.set(counter.get());
+                    }),
+                    Callback::noop(),
+                );
+                move || drop(subscription)
+            }
+        },
+        (),
+    );
+
+    use_effect_with_deps(
+        {
+            let locks = locks.clone();
+            let assignments = assignments.clone();
+            let store = store.clone();
+            move |_: &u32| {
+                if let Some(store) = (*store).clone() {
+                    wasm_bindgen_futures::spawn_local(async move {
+                        match store.locks().await {
+                            Ok(found) => locks.set(found.into_iter().map(|lock| (lock.study_uid.clone(), lock)).collect()),
+                            Err(e) => log::warn("worklist", format!("could not load the locks: {}", e)),
+                        }
+                        match store.assignments().await {
+                            Ok(found) => assignments.set(found.into_iter().map(|assignment| (assignment.study_uid.clone(), assignment)).collect()),
+                            Err(e) => log::warn("worklist", format!("could not load the assignments: {}", e)),
+                        }
+                    });
+                }
+            }
+        },
+        *lock_refresh,
+    );
+
+    use_effect_with_deps(
+        {
+            let lock_refresh = lock_refresh.clone();
+            move |_| {
+                let counter = std::rc::Rc::new(std::cell::Cell::new(*lock_refresh));
+                let interval = Interval::new(LOCK_REFRESH_MILLIS, move || {
+                    counter.set(counter.get() + 1);
+                    lock_refresh.set(counter.get());
+                });
+                move || drop(interval)
+            }
+        },
+        (),
+    );
+
+    use_effect_with_deps(
+        {
+            let priorities = priorities.clone();
+            let store = store.clone();
+            move |studies: &Vec<Study>| {
+                let study_uids: Vec<String> = studies.iter().filter_map(|study| study.study_uid.clone()).collect();
+                if let (Some(store), false) = ((*store).clone(), study_uids.is_empty()) {
+                    wasm_bindgen_futures::spawn_local(async move {
+                        match store.priorities(&study_uids).await {
+                            Ok(found) => priorities.set(store::priorities(found)),
+                            Err(e) => log::warn("worklist", format!("could not load the priorities: {}", e)),
+                        }
+                    });
+                }
+            }
+        },
+        (*studies).clone(),
+    );
+
+    let set_priority = {
+        let store = store.clone();
+        let priorities = priorities.clone();
+        let action_status = action_status.clone();
+        move |study_uid: String| -> Callback<Event> {
+            let store = store.clone();
+            let priorities = priorities.clone();
+            let action_status = action_status.clone();
+            Callback::from(move |e: Event| {
+                let (Some(store), Some(select)) = ((*store).clone(), e.target_dyn_into::<web_sys::HtmlSelectElement>()) else {
+                    return;
+                };
+                // the empty choice goes back to the priority of the request
+                let chosen = Priority::parse(&select.value());
+                let study_uid = study_uid.clone();
+                let priorities = priorities.clone();
+                let action_status = action_status.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    let result = match chosen {
+                        Some(priority) => store.set_priority(&study_uid, priority.label()).await,
+                        None => store.clear_priority(&study_uid).await,
+                    };
+                    // only whoever set a priority may change it; clearing
+                    // someone else's leaves it as it is
+                    let refused = String::from("Only whoever set the priority of this study may change it.");
+                    match result {
+                        Ok(()) => action_status.set(String::new()),
+                        Err(store::Error::Status(403, _)) => action_status.set(refused.clone()),
+                        Err(e) => {
+                            log::error("worklist", &e);
+                            action_status.set(String::from("Unable to reach the metadata store. Please try again later or contact your system administrator."));
+                            return;
+                        }
+                    }
+                    match store.priorities(std::slice::from_ref(&study_uid)).await {
+                        Ok(found) => {
+                            let now = store::priorities(found).remove(&study_uid);
+                            if chosen.is_none() && now.is_some() {
+                                action_status.set(refused);
+                            }
+                            let mut now_set = (*priorities).clone();
+                            match now {
+                                Some(priority) => now_set.insert(study_uid, priority),
+                                None => now_set.remove(&study_uid),
+                            };
+                            priorities.set(now_set);
+                        }
+                        Err(e) => log::warn("worklist", format!("could not load the priorities: {}", e)),
+                    }
+                });
+            })
+        }
+    };
+
+    // an empty name takes the study off whoever it was assigned to
+    let assign = {
+        let store = store.clone();
+        let lock_refresh = lock_refresh.clone();
+        let action_status = action_status.clone();
+        move |study_uid: String| -> Callback<Event> {
+            let store = store.clone();
+            let lock_refresh = lock_refresh.clone();
+            let action_status = action_status.clone();
+            Callback::from(move |e: Event| {
+                let (Some(store), Some(input)) = ((*store).clone(), e.target_dyn_into::<web_sys::HtmlInputElement>()) else {
+                    return;
+                };
+                let radiologist = input.value().trim().to_owned();
+                let study_uid = study_uid.clone();
+                let lock_refresh = lock_refresh.clone();
+                let action_status = action_status.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    let result = if radiologist.is_empty() {
+                        store.unassign(&study_uid).await
+                    } else {
+                        store.assign(&study_uid, &radiologist).await
+                    };
+                    match result {
+                        Ok(()) => action_status.set(String::new()),
+                        Err(store::Error::Status(403, _)) => {
+                            action_status.set(String::from("Only whoever assigned this study, or the radiologist it is assigned to, may change its assignment."));
+                        }
+                        Err(e) => {
+                            log::error("worklist", &e);
+                            action_status.set(String::from("Unable to reach the metadata store. Please try again later or contact your system administrator."));
+                        }
+                    }
+                    lock_refresh.set(*lock_refresh + 1);
+                });
+            })
+        }
+    };
+
+    let shown = {
+        let mut fetched = (*studies).clone();
+        set_priorities(&mut fetched, &priorities);
+        let mut shown = worklist::worklist(&fetched);
+        if *assigned_to_me {
+            shown.retain(|study| {
+                study
+                    .study_uid
+                    .as_ref()
+                    .and_then(|study_uid| assignments.get(study_uid))
+                    .is_some_and(|assignment| assignment.radiologist == auth_ctx.username)
+            });
+        }
+        shown
+    };
+
+    // everyone seen assigning, assigned or reporting, to pick from
+    let radiologists = {
+        let mut names: Vec<String> = assignments
+            .values()
+            .flat_map(|assignment| [assignment.radiologist.clone(), assignment.assigned_by.clone()])
+            .chain(locks.values().map(|lock| lock.username.clone()))
+            .chain([auth_ctx.username.clone()])
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    };
+
+    let claim = {
+        let store = store.clone();
+        let lock_refresh = lock_refresh.clone();
+        let action_status = action_status.clone();
+        move |study_uid: String, release: bool| -> Callback<MouseEvent> {
+            let store = store.clone();
+            let lock_refresh = lock_refresh.clone();
+            let action_status = action_status.clone();
+            Callback::from(move |_: MouseEvent| {
+                let Some(store) = (*store).clone() else {
+                    return;
+                };
+                let study_uid = study_uid.clone();
+                let lock_refresh = lock_refresh.clone();
+                let action_status = action_status.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    let result = if release {
+                        store.release(&study_uid).await.map(|_| Claim::Claimed)
+                    } else {
+                        store.claim(&study_uid).await
+                    };
+                    match result {
+                        Ok(Claim::Claimed | Claim::Kept) => action_status.set(String::new()),
+                        Ok(Claim::HeldBy(lock)) => action_status.set(format!("{} claimed this study first.", lock.username)),
+                        Err(e) => {
+                            log::error("worklist", &e);
+                            action_status.set(String::from("Unable to reach the metadata store. Please try again later or contact your system administrator."));
+                        }
+                    }
+                    lock_refresh.set(*lock_refresh + 1);
+                });
+            })
+        }
+    };
+
+    let rows = {
+        let now = Local::now().naive_local();
+        shown
+            .iter()
+            .map(|study| {
+                let study_uid = study.study_uid.clone().unwrap_or_default();
+                let waiting = acquired_at(study).map(|at| waiting_label(at, now)).unwrap_or_default();
+                let lock = locks.get(&study_uid);
+                let mine = lock.is_some_and(|lock| lock.username == auth_ctx.username);
+                let status = match lock {
+                    Some(_) if mine => html! { <span class="text-[#ffd400]">{"Claimed by you"}</span> },
+                    Some(lock) => html! {
+                        <span class="text-red font-semibold">
+                            {format!("Being reported by {} since {}", lock.username, lock.claimed_at.with_timezone(&Local).format("%H:%M"))}
+                        </span>
+                    },
+                    None => html! { <span class="text-grey">{"Unclaimed"}</span> },
+                };
+                let report = {
+                    let navigator = navigator.clone();
+                    let study_uid = study_uid.clone();
+                    move |_: MouseEvent| navigator.push(&Route::Reporting { uid: study_uid.clone() })
+                };
+                html! {
+                    <tr key={study_uid.clone()} class="border-b dark:border-neutral-500">
+                        <td class="px-2 py-1">
+                            if store.is_some() {
+                                <select onchange={set_priority(study_uid.clone())} title="Priority of this study" class={classes!("px-1", "text-xs", "font-bold", (study.urgency() > Priority::High).then_some("bg-black"), priority_classes(study.priority))}>
+                                    {
+                                        Priority::ALL.iter().map(|priority| html! {
+                                            <option value={priority.label()} selected={*priority == study.urgency()}>{priority.label()}</option>
+                                        }).collect::<Html>()
+                                    }
+                                    if priorities.contains_key(&study_uid) {
+                                        <option value="">{"As requested"}</option>
+                                    }
+                                </select>
+                            } else {
+                                <span class={classes!("px-1", "text-xs", "font-bold", priority_classes(study.priority))}>
+                                    {study.urgency().label()}
+                                </span>
+                            }
+                        </td>
+                        <td class="px-2 py-1 text-white whitespace-nowrap">{waiting}</td>
+                        <td class="px-2 py-1 text-white font-medium">{study.patient.id.clone().unwrap_or_default()}</td>
+                        <td class="px-2 py-1 text-white">{study.patient.name.clone().unwrap_or_default()}</td>
+                        <td class="px-2 py-1 text-white">{study.modalities_label()}</td>
+                        <td class="px-2 py-1 text-white">{study.description.clone().unwrap_or_default()}</td>
+                        <td class="px-2 py-1">{status}</td>
+                        <td class="px-2 py-1">
+                            if store.is_some() {
+                                <input onchange={assign(study_uid.clone())} list="radiologists" placeholder="Unassigned" title="Radiologist to report this study"
+                                    value={assignments.get(&study_uid).map(|assignment| assignment.radiologist.clone()).unwrap_or_default()}
+                                    class="w-32 bg-black border px-1 text-xs text-white" />
+                            }
+                        </td>
+                        <td class="px-2 py-1 whitespace-nowrap">
+                            if store.is_some() && lock.is_none() {
+                                <button onclick={claim(study_uid.clone(), false)} type="button" class="mr-2 inline-block px-2 py-1 border text-xs font-medium text-white hover:bg-yellow hover:text-black">{"Claim"}</button>
+                            }
+                            if mine {
+                                <button onclick={claim(study_uid.clone(), true)} type="button" class="mr-2 inline-block px-2 py-1 border text-xs font-medium text-white hover:bg-yellow hover:text-black">{"Release"}</button>
+                            }
+                            if lock.is_none() || mine {
+                                <button onclick={report} type="button" class="inline-block px-2 py-1 bg-[#ffd400] shadow-lg text-xs font-medium">{"Report"}</button>
+                            }
+                        </td>
+                    </tr>
+                }
+            })
+            .collect::<Html>()
+    };
+
+    html! {
+        <div class="min-h-screen bg-black px-6 md:px-12 py-6">
+            <div class="flex items-center justify-between border-b border-white/10 pb-6">
+                <div>
+                    <h1 class="text-white text-base font-semibold leading-7">{"Worklist"}</h1>
+                    <p class="mt-1 text-sm leading-6 text-gray-500">
+                        {format!("Unreported studies, most urgent and longest waiting first. Claims are released after {} minutes without activity.", LOCK_TIMEOUT_MINUTES)}
+                    </p>
+                </div>
+                <div class="flex items-center gap-x-6">
+                    if store.is_some() {
+                        <label class="text-sm text-white">
+                            <input type="checkbox" class="mr-2" checked={*assigned_to_me} onclick={
+                                let assigned_to_me = assigned_to_me.clone();
+                                move |_: MouseEvent| assigned_to_me.set(!*assigned_to_me)
+                            } />
+                            {"Assigned to me"}
+                        </label>
+                    }
+                    <select onchange={
+                        let days = days.clone();
+                        move |e: Event| {
+                            if let Some(select) = e.target_dyn_into::<web_sys::HtmlSelectElement>() {
+                                days.set(select.value().parse().unwrap_or(7));
+                            }
+                        }
+                    } class="bg-black border px-2 py-1 text-sm text-white">
+                        <option value="1" selected={*days == 1}>{"Last day"}</option>
+                        <option value="3" selected={*days == 3}>{"Last 3 days"}</option>
+                        <option value="7" selected={*days == 7}>{"Last week"}</option>
+                        <option value="30" selected={*days == 30}>{"Last month"}</option>
+                    </select>
+                    <button onclick={
+                        move |_: MouseEvent| {
+                            navigator.push(&Route::Search);
+                        }
+                    } type="button" class="text-sm font-semibold leading-6 text-gray-500">{"Back"}</button>
+                </div>
+            </div>
+            if store.is_none() {
+                <p class="mt-4 text-sm text-gray-500">{"Claiming and assigning studies needs the metadata store, which this build does not use."}</p>
+            }
+            if !action_status.is_empty() {
+                <p class="mt-4 text-sm text-red">{(*action_status).clone()}</p>
+            }
+            if !loaded_status.is_empty() {
+                <p class="mt-6 text-white">{(*loaded_status).clone()}</p>
+            } else if shown.is_empty() {
+                <p class="mt-6 text-white">{"Every study has been reported."}</p>
+            } else {
+                <table class="mt-6 w-full text-left text-sm font-light">
+                    <thead class="border-b font-medium dark:border-neutral-500">
+                        <tr>
+                            <th scope="col" class="px-2 py-1 text-grey">{"Priority"}</th>
+                            <th scope="col" class="px-2 py-1 text-grey">{"Waiting"}</th>
+                            <th scope="col" class="px-2 py-1 text-grey">{"Patient ID"}</th>
+                            <th scope="col" class="px-2 py-1 text-grey">{"Name"}</th>
+                            <th scope="col" class="px-2 py-1 text-grey">{"Modality"}</th>
+                            <th scope="col" class="px-2 py-1 text-grey">{"Description"}</th>
+                            <th scope="col" class="px-2 py-1 text-grey">{"Status"}</th>
+                            <th scope="col" class="px-2 py-1 text-grey">{"Assigned to"}</th>
+                            <th scope="col" class="px-2 py-1"></th>
+                        </tr>
+                    </thead>
+                    <tbody>
+                        {rows}
+                    </tbody>
+                </table>
+                <datalist id="radiologists">
+                    { radiologists.into_iter().map(|name| html! { <option value={name} /> }).collect::<Html>() }
+                </datalist>
+            }
+        </div>
+    }
+}
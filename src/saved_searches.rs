@@ -0,0 +1,31 @@
+use gloo::storage::{LocalStorage, Storage};
+
+pub use pacsportal_core::search::{SavedSearch, SavedSearches};
+
+use crate::store::Store;
+
+/// The preference of the metadata store holding the saved searches.
+const PREFERENCE: &str = "saved-searches";
+
+/// Without a store, saved searches are kept in the browser, per user.
+fn local_key(username: &str) -> String {
+    format!("pacsportal.saved-searches.{}", username)
+}
+
+pub async fn load(store: Option<&Store>, username: &str) -> Result<SavedSearches, String> {
+    match store {
+        Some(store) => store
+            .preference::<SavedSearches>(PREFERENCE)
+            .await
+            .map(Option::unwrap_or_default)
+            .map_err(|e| e.to_string()),
+        None => Ok(LocalStorage::get(local_key(username)).unwrap_or_default()),
+    }
+}
+
+pub async fn save(store: Option<&Store>, username: &str, saved: &SavedSearches) -> Result<(), String> {
+    match store {
+        Some(store) => store.set_preference(PREFERENCE, saved).await.map_err(|e| e.to_string()),
+        None => LocalStorage::set(local_key(username), saved).map_err(|e| e.to_string()),
+    }
+}
@@ -0,0 +1,35 @@
+use std::collections::HashMap;
+
+use pacsportal_core::model::Priority;
+pub use pacsportal_store::{Acknowledgement, Assignment, Claim, Draft, Error, Lock, Store, StoredMacro, StudyPriority, LOCK_TIMEOUT_MINUTES};
+
+use crate::Authorized;
+
+/// Root of the metadata store of `pacsportal-store`, e.g. `/store` when
+/// served by `pacsportal-server`. Set `PACSPORTAL_STORE_BASE` at build time;
+/// without it there are no drafts, assignments, read status or priorities
+/// set in the portal, and macros are kept in the browser.
+pub const STORE_BASE: Option<&str> = option_env!("PACSPORTAL_STORE_BASE");
+
+/// The store acting for the logged in user, if the build has one.
+pub fn for_user(auth: &Authorized) -> Option<Store> {
+    let base = STORE_BASE?;
+    if auth.username.is_empty() {
+        return None;
+    }
+    // the client needs an absolute URL
+    let url = if base.starts_with('/') {
+        format!("{}{}", gloo::utils::window().location().origin().ok()?, base)
+    } else {
+        base.to_owned()
+    };
+    Some(Store::new(&url, &auth.username, &auth.department))
+}
+
+/// The priorities set in the portal, by study; entries that do not parse are
+/// left out.
+pub fn priorities(set: Vec<StudyPriority>) -> HashMap<String, Priority> {
+    set.into_iter()
+        .filter_map(|row| Some((row.study_uid, Priority::parse(&row.priority)?)))
+        .collect()
+}
@@ -0,0 +1,37 @@
+use wasm_bindgen_test::wasm_bindgen_test;
+
+use super::*;
+
+async fn log_in(username: &str, password: &str) -> Mounted {
+    let app = mount(Authorized::anonymous(), Route::Login);
+    set_input(&app.root, "#username", username);
+    set_input(&app.root, "#password", password);
+    click(&app.root, "Login").await;
+    app
+}
+
+#[wasm_bindgen_test]
+async fn radiologist_reaches_search_with_macros() {
+    let app = log_in("radiologist", "ultimate_radiologist").await;
+    wait_for("the search page", || buttons(&app.root, "Logout").into_iter().next()).await;
+    assert_eq!(buttons(&app.root, "Macros").len(), 1);
+}
+
+#[wasm_bindgen_test]
+async fn doctor_reaches_search_without_macros() {
+    let app = log_in("root", "root123").await;
+    wait_for("the search page", || buttons(&app.root, "Logout").into_iter().next()).await;
+    assert!(buttons(&app.root, "Macros").is_empty());
+}
+
+#[wasm_bindgen_test]
+async fn wrong_password_is_rejected() {
+    let app = log_in("radiologist", "root123").await;
+    wait_for("the error message", || {
+        app.root
+            .text_content()
+            .filter(|text| text.contains("Incorrect username and password."))
+    })
+    .await;
+    assert!(buttons(&app.root, "Logout").is_empty());
+}
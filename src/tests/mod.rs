@@ -0,0 +1,199 @@
+//! Browser tests of the login, search and reporting pages, run headless
+//! against the mock archive in `pacsportal-mock`:
+//!
+//! ```sh
+//! cargo run -p pacsportal-mock &
+//! PACSPORTAL_RS_BASE=http://127.0.0.1:8042/dicomweb wasm-pack test --headless --firefox
+//! ```
+//!
+//! Every reporting test stores an SR, so restart the mock to get a fresh set
+//! of unreported studies.
+
+mod login;
+mod reporting;
+mod search;
+
+use gloo::net::http::Request;
+use wasm_bindgen::JsCast;
+use web_sys::{Element, HtmlElement, HtmlInputElement};
+use yew::prelude::*;
+use yew::AppHandle;
+use yew_router::history::{AnyHistory, MemoryHistory};
+use yew_router::prelude::*;
+
+use crate::dicomweb;
+use crate::{switch, Authorized, AuthorizedContext, Route};
+
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+pub fn radiologist() -> Authorized {
+    Authorized {
+        inner: true,
+        username: String::from("radiologist"),
+        name: String::from("DR WASAY JILANI"),
+        department: String::from("Radiology"),
+        admin: false,
+    }
+}
+
+pub fn doctor() -> Authorized {
+    Authorized {
+        inner: false,
+        username: String::from("root"),
+        name: String::from("root"),
+        department: String::from("Radiology"),
+        admin: false,
+    }
+}
+
+#[derive(Properties, PartialEq)]
+struct HarnessProps {
+    authorized: Authorized,
+    path: String,
+}
+
+/// The `App` with a preset login and an in-memory history starting at `path`.
+#[function_component(Harness)]
+fn harness(props: &HarnessProps) -> Html {
+    let ctx = use_reducer({
+        let authorized = props.authorized.clone();
+        move || authorized
+    });
+    let history = use_memo(
+        |path| AnyHistory::from(MemoryHistory::with_entries(vec![path.clone()])),
+        props.path.clone(),
+    );
+
+    html! {
+        <ContextProvider<AuthorizedContext> context={ctx}>
+            <Router history={(*history).clone()}>
+                <Switch<Route> render={switch} />
+            </Router>
+        </ContextProvider<AuthorizedContext>>
+    }
+}
+
+/// A rendered app, removed from the page when dropped.
+pub struct Mounted {
+    pub root: Element,
+    handle: Option<AppHandle<Harness>>,
+}
+
+impl Drop for Mounted {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.destroy();
+        }
+        self.root.remove();
+    }
+}
+
+pub fn mount(authorized: Authorized, route: Route) -> Mounted {
+    let document = gloo::utils::document();
+    let root = document.create_element("div").unwrap();
+    gloo::utils::body().append_child(&root).unwrap();
+    let props = HarnessProps {
+        authorized,
+        path: route.to_path(),
+    };
+    let handle = yew::Renderer::<Harness>::with_root_and_props(root.clone(), props).render();
+    Mounted {
+        root,
+        handle: Some(handle),
+    }
+}
+
+pub async fn sleep(millis: u32) {
+    gloo::timers::future::TimeoutFuture::new(millis).await;
+}
+
+/// Polls `check` until it returns something, failing the test after five
+/// seconds.
+pub async fn wait_for<T>(what: &str, check: impl Fn() -> Option<T>) -> T {
+    for _ in 0..100 {
+        if let Some(found) = check() {
+            return found;
+        }
+        sleep(50).await;
+    }
+    panic!("Timed out waiting for {}", what);
+}
+
+pub fn select_all(root: &Element, selector: &str) -> Vec<HtmlElement> {
+    let nodes = root.query_selector_all(selector).unwrap();
+    (0..nodes.length())
+        .filter_map(|i| nodes.item(i))
+        .filter_map(|node| node.dyn_into::<HtmlElement>().ok())
+        .collect()
+}
+
+/// Buttons labelled exactly `label`.
+pub fn buttons(root: &Element, label: &str) -> Vec<HtmlElement> {
+    select_all(root, "button")
+        .into_iter()
+        .filter(|button| button.text_content().unwrap_or_default().trim() == label)
+        .collect()
+}
+
+pub async fn click(root: &Element, label: &str) {
+    let button = wait_for(&format!("a {} button", label), || buttons(root, label).into_iter().next()).await;
+    button.click();
+}
+
+pub fn set_input(root: &Element, selector: &str, value: &str) {
+    let input = root
+        .query_selector(selector)
+        .unwrap()
+        .and_then(|input| input.dyn_into::<HtmlInputElement>().ok())
+        .unwrap_or_else(|| panic!("No input matches {}", selector));
+    input.set_value(value);
+}
+
+/// A request the mock archive received, see `GET /mock/requests`.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Recorded {
+    pub method: String,
+    pub path: String,
+    pub params: Vec<(String, String)>,
+    pub content_type: String,
+}
+
+impl Recorded {
+    /// Every value sent for the query parameter `key`.
+    pub fn values(&self, key: &str) -> Vec<&str> {
+        self.params
+            .iter()
+            .filter(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+}
+
+pub async fn clear_requests() {
+    Request::delete(&format!("{}/mock/requests", dicomweb::RS_BASE))
+        .send()
+        .await
+        .expect("the mock archive is not running");
+}
+
+pub async fn recorded_requests() -> Vec<Recorded> {
+    Request::get(&format!("{}/mock/requests", dicomweb::RS_BASE))
+        .send()
+        .await
+        .expect("the mock archive is not running")
+        .json()
+        .await
+        .unwrap()
+}
+
+/// Waits for the mock to receive a request matching `wanted`.
+pub async fn wait_for_request(what: &str, wanted: impl Fn(&Recorded) -> bool) -> Recorded {
+    for _ in 0..100 {
+        if let Some(found) = recorded_requests().await.into_iter().find(|request| wanted(request)) {
+            return found;
+        }
+        sleep(50).await;
+    }
+    panic!("The mock archive never received {}", what);
+}
@@ -0,0 +1,87 @@
+use chrono::{Days, Local};
+use dicom::dictionary_std::{tags, uids};
+use pacsportal_core::attributes::text;
+use pacsportal_core::model::{Instance, Study};
+use pacsportal_core::report::Report;
+use wasm_bindgen_test::wasm_bindgen_test;
+use web_sys::HtmlTextAreaElement;
+
+use super::*;
+
+/// A study of the last week that has no report yet.
+async fn unreported_study() -> Study {
+    let since = Local::now().date_naive() - Days::new(7);
+    let studies = dicomweb::fetch_json(&format!("{}/studies?StudyDate={}-", dicomweb::RS_BASE, since.format("%Y%m%d")))
+        .await
+        .expect("the mock archive has no recent studies");
+    studies
+        .iter()
+        .map(Study::from_dicom)
+        .find(|study| !study.modalities.iter().any(|modality| modality == "SR"))
+        .expect("every recent study is reported, restart the mock archive")
+}
+
+#[wasm_bindgen_test]
+async fn saving_stores_a_basic_text_sr() {
+    let study = unreported_study().await;
+    let study_uid = study.study_uid.clone().unwrap();
+    let findings = "Consolidation in the right lower lobe.\nNo pleural effusion.";
+
+    let app = mount(radiologist(), Route::Reporting { uid: study_uid.clone() });
+    let report = wait_for("the report form", || {
+        app.root
+            .query_selector("#report")
+            .unwrap()
+            .and_then(|textarea| textarea.dyn_into::<HtmlTextAreaElement>().ok())
+    })
+    .await;
+    report.set_value(findings);
+    app.root
+        .query_selector("#critical")
+        .unwrap()
+        .and_then(|checkbox| checkbox.dyn_into::<HtmlInputElement>().ok())
+        .unwrap()
+        .set_checked(true);
+
+    clear_requests().await;
+    click(&app.root, "Save").await;
+    let stow = wait_for_request("the STOW-RS request", |request| {
+        request.method == "POST" && request.path == "/studies"
+    })
+    .await;
+    assert_eq!(stow.content_type, dicomweb::STOW_CONTENT_TYPE);
+
+    let srs_url = format!("{}/studies/{}/instances?Modality=SR", dicomweb::RS_BASE, study_uid);
+    let mut srs = None;
+    for _ in 0..20 {
+        srs = dicomweb::fetch_json(&srs_url).await;
+        if srs.is_some() {
+            break;
+        }
+        sleep(50).await;
+    }
+    let srs = srs.expect("the report was not stored");
+    assert_eq!(srs.len(), 1);
+    let instance = Instance::from_dicom(&srs[0]);
+    let metadata = dicomweb::fetch_json(&format!(
+        "{}/studies/{}/series/{}/instances/{}/metadata",
+        dicomweb::RS_BASE,
+        study_uid,
+        instance.series_uid.unwrap(),
+        instance.sop_uid.unwrap(),
+    ))
+    .await
+    .unwrap();
+    let sr = &metadata[0];
+
+    assert_eq!(text(sr, tags::SOP_CLASS_UID).as_deref(), Some(uids::BASIC_TEXT_SR_STORAGE));
+    assert_eq!(text(sr, tags::STUDY_INSTANCE_UID), study.study_uid);
+    assert_eq!(text(sr, tags::PATIENT_ID), study.patient.id);
+    assert_eq!(text(sr, tags::ACCESSION_NUMBER), study.accession);
+    assert_eq!(text(sr, tags::MODALITY).as_deref(), Some("SR"));
+    let report = Report::from_dicom(sr);
+    assert_eq!(report.text.as_deref(), Some(findings));
+    assert_eq!(report.verifying_observer.as_deref(), Some("DR WASAY JILANI"));
+    assert!(report.is_critical);
+    assert!(report.content_date_time.is_some());
+}
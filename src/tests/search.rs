@@ -0,0 +1,85 @@
+use chrono::{Days, Local};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+use super::*;
+
+fn is_study_search(request: &Recorded) -> bool {
+    request.method == "GET" && request.path == "/studies"
+}
+
+/// Mounts the search page for the last week, which has a study for every
+/// synthetic patient.
+async fn search_last_week(authorized: Authorized) -> Mounted {
+    clear_requests().await;
+    let app = mount(authorized, Route::Search);
+    click(&app.root, "1W").await;
+    wait_for("the studies of the last week", || {
+        Some(select_all(&app.root, "tbody tr")).filter(|rows| !rows.is_empty())
+    })
+    .await;
+    app
+}
+
+#[wasm_bindgen_test]
+async fn date_and_modality_filters_become_qido_parameters() {
+    let today = Local::now().date_naive();
+    let week = format!(
+        "{}-{}",
+        (today - Days::new(7)).format("%Y%m%d"),
+        today.format("%Y%m%d")
+    );
+    let app = search_last_week(radiologist()).await;
+    let request = wait_for_request("the week search", |request| {
+        is_study_search(request) && request.values("StudyDate") == [week.as_str()]
+    })
+    .await;
+    assert!(request.values("ModalitiesInStudy").is_empty());
+    assert!(request.values("includefield").contains(&"StudyDescription"));
+
+    clear_requests().await;
+    click(&app.root, "CT").await;
+    click(&app.root, "MR").await;
+    let request = wait_for_request("the CT and MR search", |request| {
+        is_study_search(request) && request.values("ModalitiesInStudy").len() == 2
+    })
+    .await;
+    assert_eq!(request.values("StudyDate"), [week.as_str()]);
+    let mut modalities = request.values("ModalitiesInStudy");
+    modalities.sort();
+    assert_eq!(modalities, ["CT", "MR"]);
+
+    clear_requests().await;
+    // both the date and the modality bar end in an Any button
+    buttons(&app.root, "Any").last().unwrap().click();
+    let request = wait_for_request("the search for any modality", is_study_search).await;
+    assert!(request.values("ModalitiesInStudy").is_empty());
+}
+
+#[wasm_bindgen_test]
+async fn radiologists_can_report_unreported_studies() {
+    let app = search_last_week(radiologist()).await;
+    // the fourth column lists the modalities; reported studies include an SR
+    let reportable = select_all(&app.root, "tbody tr td:nth-child(4)")
+        .iter()
+        .filter(|modalities| !modalities.text_content().unwrap_or_default().contains("SR"))
+        .count();
+    assert!(reportable > 0);
+    assert_eq!(buttons(&app.root, "Report").len(), reportable);
+}
+
+#[wasm_bindgen_test]
+async fn referring_doctors_cannot_report() {
+    let app = search_last_week(doctor()).await;
+    assert!(buttons(&app.root, "Report").is_empty());
+}
+
+#[wasm_bindgen_test]
+async fn date_presets_are_worked_out_when_pressed() {
+    let yesterday = (Local::now().date_naive() - Days::new(1)).format("%Y%m%d").to_string();
+    let app = search_last_week(radiologist()).await;
+    clear_requests().await;
+    click(&app.root, "Yesterday").await;
+    let request = wait_for_request("the search of yesterday", is_study_search).await;
+    assert_eq!(request.values("StudyDate"), [format!("{}-{}", yesterday, yesterday).as_str()]);
+    assert!(request.values("StudyTime").is_empty());
+}